@@ -0,0 +1,17 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // The protoc of a crate rather than of the system, so that the feature needs nothing else
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/sunwalker_box.proto");
+        std::env::set_var(
+            "PROTOC",
+            protoc_bin_vendored::protoc_bin_path().expect("Failed to find protoc"),
+        );
+        tonic_build::configure()
+            .build_client(false)
+            .compile(&["proto/sunwalker_box.proto"], &["proto"])
+            .expect("Failed to compile proto/sunwalker_box.proto");
+    }
+}
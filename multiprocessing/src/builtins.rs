@@ -584,6 +584,9 @@ impl Object for OwnedHandle {
         Box::new(Self::deserialize_self(d))
     }
 }
+// Handles are passed via SCM_RIGHTS, so they can be sent over channels too
+#[cfg(unix)]
+impl TransmissibleObject for OwnedHandle {}
 
 impl Object for std::fs::File {
     fn serialize_self(&self, s: &mut Serializer) {
@@ -597,6 +600,8 @@ impl Object for std::fs::File {
         Box::new(Self::deserialize_self(d))
     }
 }
+#[cfg(unix)]
+impl TransmissibleObject for std::fs::File {}
 
 impl Object for tokio::fs::File {
     fn serialize_self(&self, s: &mut Serializer) {
@@ -0,0 +1,56 @@
+#!/usr/bin/env python3
+import statistics
+import subprocess
+import sys
+import time
+from test import Box, CORE
+
+
+# Measures the latency of `reset` in a few typical situations. Run from this directory as root,
+# after `make`, like test.py.
+
+
+def measure(box: Box, prepare, iterations: int) -> list[float]:
+    samples = []
+    for _ in range(iterations):
+        prepare(box)
+        start = time.perf_counter()
+        box.reset()
+        samples.append(time.perf_counter() - start)
+    return samples
+
+
+def report(name: str, samples: list[float]):
+    samples = sorted(samples)
+    p99 = samples[min(len(samples) - 1, len(samples) * 99 // 100)]
+    print(
+        f"{name:<16} min {samples[0] * 1000:7.3f} ms  median {statistics.median(samples) * 1000:7.3f} ms  p99 {p99 * 1000:7.3f} ms")
+
+
+SCENARIOS = {
+    # Nothing was modified, so /space and friends are kept
+    "idle": lambda box: None,
+    # /space has to be recreated
+    "write": lambda box: box.write("/space/input.txt", b"1 2\n"),
+    # A process has run, so the PID and IPC namespaces are dirty too
+    "run": lambda box: box.run(["/bin/true"], processes_limit=1),
+}
+
+
+def main():
+    iterations = int(sys.argv[1]) if len(sys.argv) > 1 else 200
+
+    subprocess.run(["../sunwalker_box", "isolate",
+                   "--core", str(CORE)], check=True)
+    try:
+        with Box() as box:
+            box.reset()
+            for name, prepare in SCENARIOS.items():
+                report(name, measure(box, prepare, iterations))
+    finally:
+        subprocess.run(["../sunwalker_box", "free",
+                       "--core", str(CORE)], check=True)
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,25 @@
+"""
+description: File commands are refused while an asynchronous run is in progress
+"""
+
+from __main__ import expect_error
+
+
+def run(box):
+    box.write("/space/file.txt", b"original")
+    run_id = box.run(["/bin/sleep", "10"], real_time_limit=20, is_async=True)["id"]
+
+    expect_error(lambda: box.cat("/space/file.txt"), "A run is in progress")
+    # The payload is consumed, so the commands after it are not confused by it
+    expect_error(lambda: box.write("/space/file.txt", b"overwritten"), "A run is in progress")
+    expect_error(lambda: box.ls("/space"), "A run is in progress")
+    expect_error(lambda: box.mkdir("/space/dir"), "A run is in progress")
+    [result] = box.batch([("cat", {"path": "/space/file.txt"})])
+    assert result["status"] == "error" and "A run is in progress" in result["error"], result
+
+    assert box.poll(run_id) is None, "The run has finished too early"
+    box.kill(run_id)
+    box.wait(run_id)
+
+    assert box.cat("/space/file.txt") == b"original"
+    assert "dir" not in box.ls("/space")
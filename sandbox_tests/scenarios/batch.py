@@ -0,0 +1,51 @@
+"""
+description: batch runs commands in order, stops at the first error, and keeps payloads in sync
+"""
+
+
+def run(box):
+    # Later commands see the effects of earlier ones, and the payloads follow the batch line in the
+    # order of the commands
+    results = box.batch([
+        ("mkdir", "/space/dir"),
+        ("write", {"path": "/space/dir/a", "len": 3}),
+        ("write", {"path": "/space/dir/b", "len": 5}),
+        ("cat", "/space/dir/a"),
+        ("cat", "/space/dir/b"),
+        ("ls", "/space/dir"),
+    ], b"aaa" + b"bbbbb")
+    assert [result["status"] for result in results] == ["ok"] * 6, results
+    assert bytes(results[3]["result"]) == b"aaa", results
+    assert bytes(results[4]["result"]) == b"bbbbb", results
+    assert results[5]["result"].keys() == {"a", "b"}, results
+
+    # Execution stops at the failed command, and the payloads of the skipped commands are consumed
+    results = box.batch([
+        ("write", {"path": "/space/c", "len": 3}),
+        ("cat", "/space/missing"),
+        ("write", {"path": "/space/d", "len": 4}),
+        ("mkdir", "/space/e"),
+    ], b"ccc" + b"dddd")
+    assert len(results) == 2, results
+    assert results[0]["status"] == "ok", results
+    assert results[1]["status"] == "error", results
+    assert box.ls("/space").keys() == {"dir", "c"}, box.ls("/space")
+    assert box.cat("/space/c") == b"ccc"
+
+    # The payloads of raw cat follow the reply, in the order of the commands
+    results = box.batch([
+        ("cat", {"path": "/space/dir/b", "at": 0, "len": 0, "raw": True}),
+        ("cat", {"path": "/space/dir/a", "at": 0, "len": 0, "raw": True}),
+    ])
+    assert [result["result"]["len"] for result in results] == [5, 3], results
+    assert box.proc.stdout.read(8) == b"bbbbb" + b"aaa"
+
+    # The attachments of a command that fails are dropped along with it
+    results = box.batch([
+        ("cat", {"path": "/space/dir/a", "at": 0, "len": 0, "raw": True}),
+        ("batch", []),
+    ])
+    assert [result["status"] for result in results] == ["ok", "error"], results
+    assert "cannot be nested" in results[1]["error"], results
+    assert box.proc.stdout.read(3) == b"aaa"
+    assert box.cat("/space/c") == b"ccc"
@@ -0,0 +1,38 @@
+"""
+description: bind creates missing mount points in the read-only image, except at the top level
+"""
+
+import os
+import shutil
+import tempfile
+
+from __main__ import expect_error
+
+
+host_dir = None
+
+
+def setup():
+    global host_dir
+    host_dir = tempfile.mkdtemp()
+    with open(os.path.join(host_dir, "checker"), "wb") as f:
+        f.write(b"checker")
+
+
+def teardown():
+    shutil.rmtree(host_dir)
+
+
+def run(box):
+    checker = os.path.join(host_dir, "checker")
+    box.bind(checker, "/usr/judge/bin/checker", readonly=True)
+    assert box.cat("/usr/judge/bin/checker") == b"checker"
+    # The shim is read-only, too
+    result = box.run(["/bin/mkdir", "/usr/judge/bin/other"], real_time_limit=10)
+    assert result["verdict"]["kind"] != "OK", result
+
+    box.reset()
+    assert "judge" not in box.ls("/usr"), box.ls("/usr")
+
+    expect_error(lambda: box.bind(checker, "/judge/bin/checker", readonly=True),
+                 "missing top-level directories cannot be created")
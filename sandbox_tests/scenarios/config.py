@@ -0,0 +1,58 @@
+"""
+description: --config reads start options, run defaults, and volumes from a TOML file on the host
+box_options: ["--config", "build/config.toml"]
+"""
+
+import os
+import shutil
+import tempfile
+
+
+host_dir = None
+
+
+def setup():
+    global host_dir
+    host_dir = tempfile.mkdtemp()
+    with open(os.path.join(host_dir, "data"), "wb") as f:
+        f.write(b"from the host")
+    with open("build/config.toml", "w") as f:
+        f.write(f"""
+boottime_offset = 100.5
+
+[run]
+real_time_limit = 1.0
+env = {{ GREETING = "hello" }}
+
+[[volume]]
+external = "{host_dir}"
+internal = "/volume"
+ro = true
+""")
+
+
+def teardown():
+    shutil.rmtree(host_dir)
+    os.unlink("build/config.toml")
+
+
+def run(box):
+    # Floats are accepted for options that take seconds
+    result = box.run(["/bin/sh", "-c", "cat /proc/uptime > /space/uptime"])
+    assert result["verdict"]["kind"] == "OK", result
+    uptime = float(box.cat("/space/uptime").split()[0])
+    assert 100.5 <= uptime < 110, uptime
+
+    # The run defaults apply unless a run overrides them
+    result = box.run(["/bin/sh", "-c", "echo $GREETING > /space/greeting"])
+    assert result["verdict"]["kind"] == "OK", result
+    assert box.cat("/space/greeting") == b"hello\n"
+    result = box.run(["/bin/sleep", "3"])
+    assert result["verdict"]["kind"] == "RealTimeLimitExceeded", result
+    result = box.run(["/bin/sleep", "2"], real_time_limit=10)
+    assert result["verdict"]["kind"] == "OK", result
+
+    # The volume is added right after the box starts and survives reset
+    assert box.cat("/volume/data") == b"from the host"
+    box.reset()
+    assert box.cat("/volume/data") == b"from the host"
@@ -0,0 +1,16 @@
+"""
+description: cpus pins a run to cores of the box and rejects other cores up front
+"""
+
+import __main__
+from __main__ import expect_error
+
+
+def run(box):
+    argv = ["/bin/sh", "-c", "grep Cpus_allowed_list /proc/self/status > /space/cpus"]
+    result = box.cmd("run", {"argv": argv, "real_time_limit": 10, "cpus": [__main__.CORE]})
+    assert result["verdict"]["kind"] == "OK", result
+    assert box.cat("/space/cpus").split() == [b"Cpus_allowed_list:", str(__main__.CORE).encode()]
+
+    expect_error(lambda: box.cmd("run", {"argv": argv, "cpus": [__main__.CORE + 1000]}),
+                 "is not a core of the box")
@@ -0,0 +1,48 @@
+"""
+description: du counts the allocated space and inodes of a tree once per inode and skips mounts
+"""
+
+import os
+import shutil
+import tempfile
+
+
+host_dir = None
+
+
+def setup():
+    global host_dir
+    host_dir = tempfile.mkdtemp()
+    with open(os.path.join(host_dir, "big"), "wb") as f:
+        f.write(b"x" * 1000000)
+
+
+def teardown():
+    shutil.rmtree(host_dir)
+
+
+def run(box):
+    box.mkdir("/space/dir")
+    box.write("/space/dir/a", b"a" * 10000)
+    box.write("/space/dir/empty", b"")
+    box.mksymlink("/space/dir/a", "/space/dir/link")
+    files = box.ls("/space/dir")
+    allocated = box.ls("/space")["dir"]["allocated"] + sum(
+        file["allocated"] for file in files.values())
+    assert allocated >= 10000, files
+
+    usage = box.du("/space/dir")
+    assert usage == {"bytes": allocated, "inodes": 4}, usage
+    assert box.du("/space/dir/a") == {"bytes": files["a"]["allocated"], "inodes": 1}
+
+    # A hard link is another name of the same inode
+    result = box.run(["/bin/ln", "/space/dir/a", "/space/dir/hardlink"], real_time_limit=10)
+    assert result["verdict"]["kind"] == "OK", result
+    assert box.du("/space/dir") == usage, box.du("/space/dir")
+
+    # du does not descend into mounts, which are other filesystems
+    box.mkdir("/space/dir/mnt")
+    usage = box.du("/space/dir")
+    box.bind(host_dir, "/space/dir/mnt", readonly=True)
+    assert box.du("/space/dir")["bytes"] < usage["bytes"] + 1000000, box.du("/space/dir")
+    assert box.du("/space/dir")["inodes"] == usage["inodes"] - 1, box.du("/space/dir")
@@ -0,0 +1,34 @@
+"""
+description: hash returns known SHA-256 digests
+"""
+
+import hashlib
+
+from __main__ import expect_error
+
+
+def run(box):
+    # Digests as printed by sha256sum
+    known_answers = [
+        (b"", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+        (b"abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+        # 56 bytes, so the padding takes a block of its own
+        (b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+         "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"),
+        (b"a" * 1000000, "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"),
+    ]
+    for content, digest in known_answers:
+        box.write("/space/file", content)
+        assert box.hash("/space/file") == digest, len(content)
+        assert box.cmd("hash", {"path": "/space/file", "algo": "sha256"}) == digest
+
+    # Lengths around the block size, where the padding is the most fragile
+    for length in [55, 56, 63, 64, 65, 119, 120, 127, 128, 129]:
+        content = bytes(i % 251 for i in range(length))
+        box.write("/space/file", content)
+        assert box.hash("/space/file") == hashlib.sha256(content).hexdigest(), length
+
+    expect_error(lambda: box.cmd("hash", {"path": "/space/file", "algo": "md5"}),
+                 "Unsupported hash algorithm")
+    box.mkdir("/space/dir")
+    expect_error(lambda: box.hash("/space/dir"), "does not refer to a regular file")
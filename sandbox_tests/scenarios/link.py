@@ -0,0 +1,67 @@
+"""
+description: link copies or hard-links host files into the box, and persistent links survive reset
+"""
+
+import os
+import shutil
+import tempfile
+
+from __main__ import expect_error
+
+
+host_dir = None
+
+
+def setup():
+    global host_dir
+    host_dir = tempfile.mkdtemp()
+    with open(os.path.join(host_dir, "input.txt"), "wb") as f:
+        f.write(b"1 2\n")
+    os.chmod(os.path.join(host_dir, "input.txt"), 0o640)
+    os.mkdir(os.path.join(host_dir, "mnt"))
+
+
+def teardown():
+    shutil.rmtree(host_dir)
+
+
+def run(box):
+    source = os.path.join(host_dir, "input.txt")
+
+    # /space is a tmpfs of its own, so neither a hard link nor a reflink is possible
+    assert box.link(source, "/space/input.txt", hardlink=True) == "copy"
+    assert box.cat("/space/input.txt") == b"1 2\n"
+    assert box.ls("/space")["input.txt"]["mode"] & 0o777 == 0o640, box.ls("/space")
+    box.write("/space/input.txt", b"modified")
+    with open(source, "rb") as f:
+        assert f.read() == b"1 2\n", "The copy is shared with the original"
+
+    # Only persistent links come back after reset, along with their parent directories
+    box.mkdir("/space/deep")
+    box.mkdir("/space/deep/er")
+    box.link(source, "/space/deep/er/persistent.txt", persistent=True)
+    box.reset()
+    assert box.ls("/space").keys() == {"deep"}, box.ls("/space")
+    assert box.cat("/space/deep/er/persistent.txt") == b"1 2\n"
+    box.reset()
+    assert box.cat("/space/deep/er/persistent.txt") == b"1 2\n"
+
+    # A writable bind mount from the same filesystem allows a hard link
+    box.mkdir("/space/mnt")
+    box.bind(os.path.join(host_dir, "mnt"), "/space/mnt")
+    assert box.link(source, "/space/mnt/input.txt", hardlink=True) == "hardlink"
+    assert os.stat(source).st_nlink == 2
+    assert box.link(source, "/space/mnt/copy.txt") in ("reflink", "copy")
+    assert os.stat(source).st_nlink == 2
+
+    # Linking over the hard link replaces it instead of writing through it to the host
+    box.link(source, "/space/mnt/input.txt")
+    assert os.stat(source).st_nlink == 1
+    with open(source, "rb") as f:
+        assert f.read() == b"1 2\n", "The original was truncated"
+    box.link(source, "/space/mnt/input.txt", hardlink=True)
+    assert os.stat(source).st_nlink == 2
+
+    expect_error(lambda: box.link(host_dir, "/space/dir"), "does not refer to a regular file")
+    expect_error(lambda: box.link(os.path.join(host_dir, "missing"), "/space/missing"),
+                 "Failed to open")
@@ -0,0 +1,36 @@
+"""
+description: A host process drives the box over the --listen socket, which is removed on exit
+box_options: ["--listen", "build/listen.sock"]
+"""
+
+import json
+import os
+import socket
+
+
+SOCKET_PATH = "build/listen.sock"
+
+
+def setup():
+    if os.path.exists(SOCKET_PATH):
+        os.unlink(SOCKET_PATH)
+
+
+def teardown():
+    assert not os.path.exists(SOCKET_PATH), "The socket has been left behind"
+
+
+def run(box):
+    with socket.socket(socket.AF_UNIX, socket.SOCK_STREAM) as client:
+        client.connect(SOCKET_PATH)
+        stream = client.makefile("rwb")
+        stream.write(b'mkdir "/space/from_socket"\n')
+        stream.write(b'ls "/space"\n')
+        stream.flush()
+        assert stream.readline() == b"ok\n"
+        reply = stream.readline()
+        assert reply.startswith(b"ok "), reply
+        assert "from_socket" in json.loads(reply[3:]), reply
+
+    # Both clients see the same box
+    assert "from_socket" in box.ls("/space")
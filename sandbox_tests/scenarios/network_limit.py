@@ -0,0 +1,51 @@
+"""
+description: network_limit kills a program that sends too much, and traffic is reported
+box_options: ["--veth-bridge", "swtest0", "--veth-address", "10.77.0.2/24"]
+"""
+
+import subprocess
+
+
+BRIDGE = "swtest0"
+
+# Floods the host with UDP datagrams, which count as traffic whether anyone listens or not
+FLOOD = """
+import socket
+import time
+s = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+deadline = time.monotonic() + float(__import__("sys").argv[1])
+while time.monotonic() < deadline:
+    s.sendto(b"x" * 1000, ("10.77.0.1", 9))
+"""
+
+
+def setup():
+    subprocess.run(["ip", "link", "add", BRIDGE, "type", "bridge"], check=True)
+    subprocess.run(["ip", "addr", "add", "10.77.0.1/24", "dev", BRIDGE], check=True)
+    subprocess.run(["ip", "link", "set", BRIDGE, "up"], check=True)
+
+
+def teardown():
+    subprocess.run(["ip", "link", "del", BRIDGE], check=True)
+
+
+def run(box):
+    box.write("/space/flood.py", FLOOD.encode())
+
+    # Under the limit, the traffic is only reported
+    result = box.cmd("run", {"argv": ["/usr/bin/python3", "/space/flood.py", "0.1"],
+                             "real_time_limit": 10, "network_limit": 1 << 30})
+    assert result["verdict"] == {"kind": "OK"}, result
+    assert result["network"]["bytes_sent"] >= 1000, result
+    assert result["network"]["packets_sent"] >= 1, result
+
+    # Over the limit, the program is killed long before it would have finished on its own
+    box.reset()
+    box.write("/space/flood.py", FLOOD.encode())
+    result = box.cmd("run", {"argv": ["/usr/bin/python3", "/space/flood.py", "8"],
+                             "real_time_limit": 10, "network_limit": 100000})
+    assert result["limit_verdict"] == "NetworkLimitExceeded", result
+    assert result["verdict"] == {"kind": "NetworkLimitExceeded", "limit": "network_limit",
+                                 "limit_value": 100000}, result
+    assert result["network"]["bytes_sent"] > 100000, result
+    assert result["real_time"] < 5, result
@@ -0,0 +1,35 @@
+"""
+description: output_limit applies to pipes between programs, passing on the output up to the limit
+"""
+
+
+def run_pipeline(box, n_bytes: int) -> list[dict[str, ...]]:
+    writer = {
+        "argv": ["/bin/sh", "-c", f"head -c {n_bytes} /dev/zero"],
+        "stdout": {"pipe": "data"},
+        "output_limit": 1000,
+        "real_time_limit": 10,
+    }
+    reader = {
+        "argv": ["/bin/sh", "-c", "sleep 1; wc -c > /space/count"],
+        "stdin": {"pipe": "data"},
+        "real_time_limit": 10,
+    }
+    return box.run_group([writer, reader])
+
+
+def run(box):
+    results = run_pipeline(box, 1000)
+    assert [result["verdict"]["kind"] for result in results] == ["OK", "OK"], results
+    assert box.cat("/space/count") == b"1000\n"
+
+    # The writer exits long before the reader gets to the data, which is still noticed
+    results = run_pipeline(box, 1001)
+    assert results[0]["verdict"] == {"kind": "OutputLimitExceeded", "limit": "output_limit",
+                                     "limit_value": 1000}, results
+    assert results[1]["verdict"]["kind"] == "OK", results
+    assert box.cat("/space/count") == b"1000\n"
+
+    results = run_pipeline(box, 10000000)
+    assert results[0]["verdict"]["kind"] == "OutputLimitExceeded", results
+    assert box.cat("/space/count") == b"1000\n"
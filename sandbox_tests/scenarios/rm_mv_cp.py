@@ -0,0 +1,47 @@
+"""
+description: rm, mv, and cp manipulate files, symlinks, and directories
+"""
+
+from __main__ import expect_error
+
+
+def run(box):
+    box.mkdir("/space/dir")
+    box.write("/space/dir/file", b"hello", mode=0o640)
+    box.mksymlink("file", "/space/dir/link")
+
+    # cp copies files with their permissions and symlinks as is
+    box.cp("/space/dir/file", "/space/copy")
+    assert box.cat("/space/copy") == b"hello"
+    assert box.cmd("stat", "/space/copy")["mode"] & 0o777 == 0o640
+    box.cp("/space/dir/link", "/space/link")
+    assert box.ls("/space")["link"]["file_type"] == "symlink", box.ls("/space")
+    expect_error(lambda: box.cp("/space/dir", "/space/dir2"), "is a directory")
+    box.cp("/space/dir", "/space/dir2", recursive=True)
+    assert box.ls("/space/dir2").keys() == {"file", "link"}
+    assert box.cat("/space/dir2/file") == b"hello"
+
+    # mv renames, and the source disappears
+    box.mv("/space/copy", "/space/moved")
+    assert box.cat("/space/moved") == b"hello"
+    assert "copy" not in box.ls("/space")
+    box.mv("/space/dir2", "/space/dir3")
+    assert box.ls("/space/dir3").keys() == {"file", "link"}
+
+    # A path can't be moved or copied to itself, be it a file or a directory
+    expect_error(lambda: box.mv("/space/moved", "/space/moved"), "to itself")
+    expect_error(lambda: box.cp("/space/moved", "/space/moved"), "to itself")
+    expect_error(lambda: box.mv("/space/dir", "/space/dir"), "to itself")
+    expect_error(lambda: box.mv("/space/dir", "/space/dir/sub"), "a directory into itself")
+    expect_error(lambda: box.cp("/space/dir", "/space/dir/sub", recursive=True),
+                 "a directory into itself")
+    assert box.cat("/space/moved") == b"hello"
+
+    # rm removes symlinks rather than their targets, and only removes non-empty directories in
+    # recursive mode
+    box.rm("/space/dir/link")
+    assert box.cat("/space/dir/file") == b"hello"
+    expect_error(lambda: box.rm("/space/dir"), "Failed to rmdir")
+    box.rm("/space/dir", recursive=True)
+    box.rm("/space/moved")
+    assert box.ls("/space").keys() == {"link", "dir3"}, box.ls("/space")
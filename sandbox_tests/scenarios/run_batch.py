@@ -0,0 +1,38 @@
+"""
+description: run_batch runs a program over several tests, with per-test overrides and early stops
+"""
+
+from __main__ import expect_error
+
+
+def run(box):
+    program = {
+        "argv": ["/bin/sh", "-c", "read a b; echo $((a + b))"],
+        "real_time_limit": 10,
+    }
+    for i, test in enumerate([b"1 2\n", b"3 4\n", b"5 6\n"]):
+        box.write(f"/space/{i}.in", test)
+    tests = [{"stdin": f"/space/{i}.in", "stdout": f"/space/{i}.out"} for i in range(3)]
+
+    results = box.run_batch(program, tests)
+    assert [result["status"] for result in results] == ["ok"] * 3, results
+    assert [result["result"]["verdict"]["kind"] for result in results] == ["OK"] * 3, results
+    assert [box.cat(f"/space/{i}.out") for i in range(3)] == [b"3\n", b"7\n", b"11\n"]
+
+    # A test overrides options of the program, here to make it fail
+    tests[1]["argv"] = ["/bin/sh", "-c", "exit 1"]
+    results = box.run_batch(program, tests)
+    assert [result["result"]["verdict"]["kind"] for result in results] == \
+        ["OK", "RuntimeError", "OK"], results
+    results = box.run_batch(program, tests, stop_on_failure=True)
+    assert [result["result"]["verdict"]["kind"] for result in results] == \
+        ["OK", "RuntimeError"], results
+
+    # A test that cannot be run stops the batch regardless of stop_on_failure
+    results = box.run_batch(program, [tests[0], {"async": True}, tests[2]])
+    assert [result["status"] for result in results] == ["ok", "error"], results
+    assert "cannot be asynchronous or groups" in results[1]["error"], results
+    results = box.run_batch(program, [5, tests[0]])
+    assert [result["status"] for result in results] == ["error"], results
+
+    expect_error(lambda: box.run_batch(program, []), "Invalid 'tests' argument")
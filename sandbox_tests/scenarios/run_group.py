@@ -0,0 +1,42 @@
+"""
+description: A group of programs talks over pipes, and killing one member doesn't kill the others
+"""
+
+
+def run(box):
+    # A typical interactive problem: the interactor asks a question and checks the answer
+    interactor = {
+        "argv": ["/bin/sh", "-c", 'echo 2 3; read x; [ "$x" = 5 ] && echo correct >&2'],
+        "stdin": {"pipe": "to_interactor"},
+        "stdout": {"pipe": "to_solution"},
+        "stderr": "/space/verdict.txt",
+        "real_time_limit": 10,
+    }
+    solution = {
+        "argv": ["/bin/sh", "-c", "read a b; echo $((a + b))"],
+        "stdin": {"pipe": "to_solution"},
+        "stdout": {"pipe": "to_interactor"},
+        "real_time_limit": 10,
+    }
+    results = box.run_group([interactor, solution])
+    assert [result["verdict"]["kind"] for result in results] == ["OK", "OK"], results
+    assert box.cat("/space/verdict.txt") == b"correct\n"
+
+    # The reader is still running when the writer is killed, and notices the pipe has been closed
+    reader = {
+        "argv": ["/bin/sh", "-c", "read x"],
+        "stdin": {"pipe": "data"},
+        "real_time_limit": 10,
+    }
+    writer = {
+        "argv": ["/bin/sleep", "30"],
+        "stdout": {"pipe": "data"},
+        "real_time_limit": 20,
+    }
+    run_id = box.run_group([reader, writer], is_async=True)["id"]
+    assert box.poll(run_id) is None, "The group has finished too early"
+    box.kill(run_id, program=1)
+    results = box.wait(run_id)
+    assert results[0]["limit_verdict"] == "OK" and results[0]["wait_status"] == 1, results
+    assert results[1]["limit_verdict"] == "Signaled" and results[1]["wait_status"] == -9, results
+    assert results[1]["verdict"] == {"kind": "RuntimeError", "signal": 9}, results
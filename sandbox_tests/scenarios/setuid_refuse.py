@@ -0,0 +1,25 @@
+"""
+description: The refuse setuid policy fails to start the box if the root environment has setuid files
+box_options: ["--setuid-policy", "refuse"]
+root:
+  usr:
+    bin:
+      setuid: ""
+"""
+
+import os
+
+
+def prepare_root(root_dir):
+    os.chmod(os.path.join(root_dir, "usr/bin/setuid"), 0o4755)
+
+
+def run(box):
+    try:
+        box.cmd("setuid_files")
+    except (ValueError, BrokenPipeError):
+        # The box has exited before or while reading the command
+        pass
+    else:
+        assert False, "The box has started despite a setuid file"
+    assert box.proc.wait() != 0, "The box has exited successfully"
@@ -0,0 +1,29 @@
+"""
+description: The report setuid policy lists setuid and setgid files of the root environment
+box_options: ["--setuid-policy", "report"]
+root:
+  usr:
+    bin:
+      setuid: ""
+      setgid: ""
+      plain: ""
+"""
+
+import os
+import stat
+
+
+def prepare_root(root_dir):
+    os.chmod(os.path.join(root_dir, "usr/bin/setuid"), 0o4755)
+    os.chmod(os.path.join(root_dir, "usr/bin/setgid"), 0o2755)
+    os.chmod(os.path.join(root_dir, "usr/bin/plain"), 0o755)
+
+
+def run(box):
+    files = {file["path"]: file for file in box.cmd("setuid_files")}
+    assert set(files) == {"/usr/bin/setuid", "/usr/bin/setgid"}, files
+    assert files["/usr/bin/setuid"]["mode"] == stat.S_IFREG | 0o4755, files
+    assert files["/usr/bin/setgid"]["mode"] == stat.S_IFREG | 0o2755, files
+    # The files are only listed, so the box works as usual
+    box.write("/space/file.txt", b"hello")
+    assert box.cat("/space/file.txt") == b"hello"
@@ -0,0 +1,46 @@
+"""
+description: reset restores /space to the snapshot, and drop_snapshot empties it again
+"""
+
+from __main__ import expect_error
+
+
+def run(box):
+    box.mkdir("/space/dir")
+    box.write("/space/dir/file", b"compiled")
+    box.write("/tmp/scratch", b"temporary")
+    box.snapshot()
+    # Taking a snapshot resets the box, except that /space is restored rather than emptied
+    assert box.ls("/space").keys() == {"dir"}, box.ls("/space")
+    assert box.cat("/space/dir/file") == b"compiled"
+    assert box.ls("/tmp") == {}, box.ls("/tmp")
+
+    # Modifications after the snapshot are discarded on reset, and the snapshot itself is intact
+    box.write("/space/dir/file", b"overwritten")
+    box.write("/space/new", b"new")
+    box.rm("/space/dir", recursive=True)
+    box.reset()
+    assert box.ls("/space").keys() == {"dir"}, box.ls("/space")
+    assert box.cat("/space/dir/file") == b"compiled"
+
+    # A snapshot on top of the previous one keeps both
+    box.write("/space/second", b"second")
+    box.snapshot()
+    box.rm("/space/second")
+    box.reset()
+    assert box.ls("/space").keys() == {"dir", "second"}, box.ls("/space")
+    assert box.cat("/space/dir/file") == b"compiled"
+    assert box.cat("/space/second") == b"second"
+
+    # A third level is rejected before anything changes
+    box.write("/space/third", b"third")
+    expect_error(lambda: box.snapshot(), "cannot be nested")
+    assert box.cat("/space/third") == b"third"
+    box.reset()
+    assert box.ls("/space").keys() == {"dir", "second"}, box.ls("/space")
+
+    box.drop_snapshot()
+    assert box.ls("/space") == {}, box.ls("/space")
+    box.write("/space/file", b"")
+    box.reset()
+    assert box.ls("/space") == {}, box.ls("/space")
@@ -0,0 +1,69 @@
+"""
+description: The state of a box is recorded on the host, so that resume and cleanup can find it
+box_options: ["--state-dir", "build/state", "--listen", "build/state.sock"]
+"""
+
+import json
+import os
+import shutil
+import signal
+import subprocess
+import time
+
+import __main__
+
+
+STATE_DIR = "build/state"
+SOCKET_PATH = "build/state.sock"
+
+
+def sunwalker(*args: str, **kwargs) -> subprocess.CompletedProcess:
+    return subprocess.run(__main__.sunwalker_prefix + ["../sunwalker_box", *args], **kwargs)
+
+
+def setup():
+    shutil.rmtree(STATE_DIR, ignore_errors=True)
+    if os.path.exists(SOCKET_PATH):
+        os.unlink(SOCKET_PATH)
+
+
+def teardown():
+    shutil.rmtree(STATE_DIR, ignore_errors=True)
+
+
+def run(box):
+    with open(os.path.join(STATE_DIR, "state.json")) as f:
+        state = json.load(f)
+    assert state["core"] == __main__.CORE, state
+    assert state["listen"] == os.path.abspath(SOCKET_PATH), state
+
+    # A box with the same state directory refuses to start while this one is alive
+    proc = sunwalker("start", "--core", str(__main__.CORE), "--state-dir", STATE_DIR,
+                     stdin=subprocess.DEVNULL, stdout=subprocess.DEVNULL, stderr=subprocess.PIPE)
+    assert proc.returncode != 0
+    assert b"is running" in proc.stderr, proc.stderr
+
+    # resume relays its stdin and stdout to the socket of the box
+    box.mkdir("/space/before_resume")
+    proc = sunwalker("resume", "--state-dir", STATE_DIR, input=b'ls "/space"\n',
+                     stdout=subprocess.PIPE, timeout=10)
+    assert proc.returncode == 0
+    assert proc.stdout.startswith(b"ok "), proc.stdout
+    assert "before_resume" in json.loads(proc.stdout[3:]), proc.stdout
+
+    # The controller dies without cleaning up, so the state file and the socket stay behind
+    os.kill(state["pid"], signal.SIGKILL)
+    box.proc.wait(timeout=10)
+    time.sleep(0.5)
+    assert os.path.exists(os.path.join(STATE_DIR, "state.json"))
+    assert os.path.exists(SOCKET_PATH)
+
+    proc = sunwalker("resume", "--state-dir", STATE_DIR, stdin=subprocess.DEVNULL,
+                     stdout=subprocess.DEVNULL, stderr=subprocess.PIPE)
+    assert proc.returncode != 0
+    assert b"use cleanup" in proc.stderr, proc.stderr
+
+    proc = sunwalker("cleanup", "--state-dir", STATE_DIR)
+    assert proc.returncode == 0
+    assert not os.path.exists(os.path.join(STATE_DIR, "state.json"))
+    assert not os.path.exists(SOCKET_PATH)
@@ -0,0 +1,38 @@
+"""
+description: tar archives a tree in a format that Python's tarfile and untar read back
+"""
+
+import io
+import tarfile
+
+from __main__ import expect_error
+
+
+def run(box):
+    long_name = "n" * 150
+    box.mkdir("/space/dir")
+    box.mkdir("/space/dir/sub")
+    box.write("/space/dir/file", b"contents", mode=0o640)
+    box.write("/space/dir/sub/" + long_name, b"long")
+    box.mksymlink("../file", "/space/dir/sub/link")
+    result = box.run(["/usr/bin/mkfifo", "/space/dir/fifo"], real_time_limit=10)
+    assert result["verdict"]["kind"] == "OK", result
+
+    archive = box.tar("/space/dir")
+    with tarfile.open(fileobj=io.BytesIO(archive)) as tar:
+        members = {member.name.rstrip("/"): member for member in tar.getmembers()}
+        # Paths are relative, and the entries are sorted
+        assert list(members) == ["fifo", "file", "sub", f"sub/{long_name}", "sub/link"], members
+        assert members["fifo"].isfifo()
+        assert members["file"].isfile() and members["file"].mode & 0o777 == 0o640
+        assert tar.extractfile(members["file"]).read() == b"contents"
+        assert members["sub"].isdir()
+        assert tar.extractfile(members[f"sub/{long_name}"]).read() == b"long"
+        assert members["sub/link"].issym() and members["sub/link"].linkname == "../file"
+
+    box.untar("/space/copy", archive)
+    assert box.ls("/space/copy").keys() == {"fifo", "file", "sub"}, box.ls("/space/copy")
+    assert box.cat("/space/copy/sub/" + long_name) == b"long"
+    assert box.ls("/space/copy")["fifo"]["file_type"] == "fifo", box.ls("/space/copy")
+
+    expect_error(lambda: box.tar("/space/dir/file"), "does not refer to a directory")
@@ -0,0 +1,62 @@
+"""
+description: Additional tmpfs mounts are emptied on reset, and bad --tmpfs paths fail the start
+box_options: ["--tmpfs", "/var/tmp:1048576:64"]
+root:
+  etc:
+    passwd: ""
+  var:
+    tmp: {}
+    cache: {}
+    shared: "-> /tmp"
+"""
+
+import subprocess
+
+import __main__
+
+
+root_dir = None
+
+
+def prepare_root(path):
+    global root_dir
+    root_dir = path
+
+
+def expect_start_error(tmpfs: list[str], message: str):
+    opts = []
+    for option in tmpfs:
+        opts += ["--tmpfs", option]
+    proc = subprocess.run(
+        __main__.sunwalker_prefix + ["../sunwalker_box", "start", "--core", str(__main__.CORE),
+                                     "--root", root_dir, *opts],
+        stdin=subprocess.DEVNULL,
+        stdout=subprocess.DEVNULL,
+        stderr=subprocess.PIPE
+    )
+    assert proc.returncode != 0, f"The box has started with {tmpfs}"
+    stderr = proc.stderr.decode(errors="replace")
+    assert message in stderr, f"Expected error containing {message!r} for {tmpfs}, actual: {stderr}"
+
+
+# The box of the scenario occupies the core, so the failing boxes are started before it
+def setup():
+    expect_start_error(["/space/dir:1048576:64"], "because /space is managed by the box itself")
+    expect_start_error(["/tmp:1048576:64"], "because /tmp is managed by the box itself")
+    expect_start_error(["/dev/shm/x:1048576:64"], "because /dev/shm is managed by the box itself")
+    expect_start_error(["/var/../space:1048576:64"], "must not contain '..'")
+    expect_start_error(["/:1048576:64"], "cannot be mounted on the root")
+    expect_start_error(["/var:1048576:64", "/var/cache/:1048576:64"],
+                       "tmpfs on \"/var/cache\" overlaps tmpfs on \"/var\"")
+    expect_start_error(["/var/tmp:1048576:64", "/var/tmp:1048576:64"],
+                       "tmpfs on \"/var/tmp\" overlaps tmpfs on \"/var/tmp\"")
+    expect_start_error(["/var/missing:1048576:64"], "because it does not exist in the root")
+    expect_start_error(["/etc/passwd:1048576:64"], "because it is not a directory in the root")
+    expect_start_error(["/var/shared:1048576:64"], "because it is inside /tmp")
+
+
+def run(box):
+    box.cmd("mkfile", "/var/tmp/file")
+    assert "file" in box.ls("/var/tmp")
+    box.cmd("reset")
+    assert box.ls("/var/tmp") == {}
@@ -0,0 +1,54 @@
+"""
+description: untar rejects archive entries that point outside of the target directory
+"""
+
+import io
+import tarfile
+
+from __main__ import expect_error
+
+
+def make_archive(entries: list[tuple[str, ...]]) -> bytes:
+    f = io.BytesIO()
+    with tarfile.open(fileobj=f, mode="w", format=tarfile.USTAR_FORMAT) as tar:
+        for kind, name, value in entries:
+            info = tarfile.TarInfo(name)
+            if kind == "file":
+                info.size = len(value)
+                tar.addfile(info, io.BytesIO(value))
+            else:
+                info.type = {"symlink": tarfile.SYMTYPE, "hardlink": tarfile.LNKTYPE}[kind]
+                info.linkname = value
+                tar.addfile(info)
+    return f.getvalue()
+
+
+def run(box):
+    box.write("/space/victim", b"original")
+
+    archives = [
+        ([("file", "../victim", b"overwritten")], "points outside of the archive"),
+        ([("file", "dir/../../victim", b"overwritten")], "points outside of the archive"),
+        ([("file", "/space/victim", b"overwritten")], "points outside of the archive"),
+        ([("symlink", "link", "/space"), ("file", "link/victim", b"overwritten")],
+         "points outside of the archive"),
+        ([("symlink", "link", ".."), ("file", "link/victim", b"overwritten")],
+         "points outside of the archive"),
+        ([("symlink", "link", "dir/../.."), ("file", "link/victim", b"overwritten")],
+         "points outside of the archive"),
+        ([("hardlink", "link", "../victim")], "points outside of the archive"),
+    ]
+    for entries, message in archives:
+        expect_error(lambda: box.untar("/space/target", make_archive(entries)), message)
+        # The archive is unpacked atomically, so not even the files before the bad entry are left
+        assert box.ls("/space").keys() == {"victim"}, box.ls("/space")
+        assert box.cat("/space/victim") == b"original"
+
+    # Symlinks inside the archive are fine, and the payload of a rejected archive hasn't confused
+    # the protocol
+    box.untar("/space/target", make_archive([
+        ("symlink", "link", "."),
+        ("file", "link/file", b"hello"),
+    ]))
+    assert box.cat("/space/target/file") == b"hello"
+    assert box.cat("/space/victim") == b"original"
@@ -0,0 +1,63 @@
+"""
+description: upload creates a directory tree that reads back as it was described, including sparse files
+"""
+
+from __main__ import expect_error
+
+
+def run(box):
+    box.upload("/space/tree", [
+        {"path": ".", "type": "dir", "mode": 0o750},
+        {"path": "bin", "type": "dir", "mode": 0o755, "owner": "root"},
+        {"path": "bin/run", "type": "file", "len": 10, "mode": 0o755},
+        {"path": "data.txt", "type": "file", "len": 5, "mode": 0o600, "owner": "user"},
+        {"path": "empty", "type": "file", "len": 0},
+        {"path": "run", "type": "symlink", "target": "bin/run"},
+    ], b"#!/bin/sh\n" + b"hello")
+
+    entries = box.cmd("ls", {"path": "/space/tree", "recursive": True})
+    assert entries.keys() == {"bin", "bin/run", "data.txt", "empty", "run"}, entries
+    assert entries["bin"]["file_type"] == "dir" and entries["bin"]["mode"] & 0o7777 == 0o755
+    assert entries["bin/run"]["mode"] & 0o7777 == 0o755, entries
+    assert entries["data.txt"]["mode"] & 0o7777 == 0o600, entries
+    assert entries["run"]["file_type"] == "symlink", entries
+    # Without "recursive", only the top level is listed
+    assert box.cmd("ls", {"path": "/space/tree"}).keys() == {"bin", "data.txt", "empty", "run"}
+    assert box.cmd("stat", "/space/tree")["mode"] & 0o7777 == 0o750
+    assert box.cat("/space/tree/bin/run") == b"#!/bin/sh\n"
+    assert box.cat("/space/tree/data.txt") == b"hello"
+    assert box.cat("/space/tree/empty") == b""
+    # Files are read via the box root, so the symlink is followed
+    assert box.cat("/space/tree/run") == b"#!/bin/sh\n"
+
+    # A sparse file inside the uploaded tree keeps its holes on the way in and out
+    size = 1 << 20
+    extents = [[0, 4096], [512 * 1024, 8192]]
+    box.cmd("write", {"path": "/space/tree/sparse", "len": 4096 + 8192, "size": size,
+                      "extents": extents}, b"a" * 4096 + b"b" * 8192)
+    stat = box.cmd("stat", "/space/tree/sparse")
+    assert stat["len"] == size and stat["allocated"] < size, stat
+    header = box.cmd("cat", {"path": "/space/tree/sparse", "at": 0, "len": 0, "raw": True,
+                             "sparse": True})
+    assert header["extents"] == extents, header
+    assert box.proc.stdout.read(header["len"]) == b"a" * 4096 + b"b" * 8192
+    # Empty and special files have no holes either
+    header = box.cmd("cat", {"path": "/space/tree/empty", "at": 0, "len": 0, "raw": True,
+                             "sparse": True})
+    assert header == {"len": 0, "extents": []}, header
+    header = box.cmd("cat", {"path": "/proc/uptime", "at": 0, "len": 0, "raw": True,
+                             "sparse": True})
+    assert header["extents"] == [[0, header["len"]]] and header["len"] > 0, header
+    box.proc.stdout.read(header["len"])
+    content = box.cat_raw("/space/tree/sparse")
+    assert content == b"a" * 4096 + bytes(512 * 1024 - 4096) + b"b" * 8192 + \
+        bytes(size - 512 * 1024 - 8192)
+
+    # The tree is created atomically, and the payload of a failed upload is consumed
+    expect_error(lambda: box.upload("/space/bad", [
+        {"path": "file", "type": "file", "len": 3},
+        {"path": "link", "type": "symlink", "target": "../tree"},
+    ], b"abc"), "outside")
+    expect_error(lambda: box.upload("/space/tree", [{"path": "file", "type": "file", "len": 0}]),
+                 "already exists")
+    assert box.ls("/space").keys() == {"tree"}, box.ls("/space")
@@ -0,0 +1,54 @@
+"""
+description: Volumes are mounted again after each reset and keep what the programs wrote to them
+"""
+
+import os
+import shutil
+import tempfile
+
+from __main__ import expect_error
+
+
+host_dir = None
+
+
+def setup():
+    global host_dir
+    host_dir = tempfile.mkdtemp()
+    for name in ["cache", "readonly"]:
+        os.mkdir(os.path.join(host_dir, name))
+    with open(os.path.join(host_dir, "readonly/data"), "wb") as f:
+        f.write(b"data")
+
+
+def teardown():
+    shutil.rmtree(host_dir)
+
+
+def run(box):
+    # The mount point is in /space, so it is removed on reset and has to be recreated
+    box.volume(os.path.join(host_dir, "cache"), "/space/cache", idmap=True)
+    box.volume(os.path.join(host_dir, "readonly"), "/space/readonly", readonly=True)
+
+    for expected in [b"hit\n", b"hit\nhit\n", b"hit\nhit\nhit\n"]:
+        result = box.run(["/bin/sh", "-c", "echo hit >> /space/cache/log"], real_time_limit=10)
+        assert result["verdict"]["kind"] == "OK", result
+        assert box.cat("/space/cache/log") == expected
+        assert box.cat("/space/readonly/data") == b"data"
+        box.reset()
+
+    with open(os.path.join(host_dir, "cache/log"), "rb") as f:
+        assert f.read() == b"hit\nhit\nhit\n"
+
+    result = box.run(["/bin/sh", "-c", "echo x > /space/readonly/data"], real_time_limit=10)
+    assert result["verdict"]["kind"] != "OK", result
+    with open(os.path.join(host_dir, "readonly/data"), "rb") as f:
+        assert f.read() == b"data"
+
+    expect_error(lambda: box.cmd("volume", {
+        "external": os.path.join(host_dir, "cache"),
+        "internal": "/space/quota",
+        "ro": False,
+        "idmap": False,
+        "quota": 1024
+    }), "Mounts cannot have a quota")
@@ -0,0 +1,24 @@
+"""
+description: warmup reads files and trees once per inode, skips missing paths, and obeys max_bytes
+"""
+
+
+def run(box):
+    box.mkdir("/space/dir")
+    box.write("/space/dir/a", b"a" * 1000)
+    box.write("/space/dir/b", b"b" * 3000)
+    box.mksymlink("/space/dir/a", "/space/dir/link")
+    result = box.run(["/bin/ln", "/space/dir/a", "/space/dir/hardlink"], real_time_limit=10)
+    assert result["verdict"]["kind"] == "OK", result
+
+    # The symlink is not followed, and the hard link shares the page cache with its target
+    assert box.warmup(["/space/dir", "/space/missing"]) == {"files": 2, "bytes": 4000}
+    assert box.warmup(["/space/dir/a", "/space/dir/hardlink"]) == {"files": 1, "bytes": 1000}
+
+    # Whichever file comes first, reading stops exactly at the limit
+    assert box.warmup(["/space/dir"], max_bytes=1500)["bytes"] == 1500
+
+    assert box.warmup(["/proc", "/dev"]) == {"files": 0, "bytes": 0}
+
+    usage = box.warmup(["/usr/bin"])
+    assert usage["files"] > 0 and usage["bytes"] > 0, usage
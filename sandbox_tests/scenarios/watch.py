@@ -0,0 +1,30 @@
+"""
+description: watch reports what programs do to the watched files, until reset
+"""
+
+
+def events_of(events: list[dict[str, ...]], path: str) -> set[str]:
+    return {name for event in events if event["path"] == path for name in event["events"]}
+
+
+def run(box):
+    assert box.watch_events() == []
+
+    box.write("/space/input.txt", b"1 2\n")
+    box.watch("/space")
+    box.watch("/space/input.txt")
+    result = box.run(["/bin/sh", "-c", "echo 3 > /space/output.txt; rm /space/input.txt"],
+                     real_time_limit=10)
+    assert result["verdict"]["kind"] == "OK", result
+
+    events = box.watch_events(timeout=1)
+    assert {"create", "modify", "close_write"} <= events_of(events, "/space/output.txt"), events
+    assert "delete" in events_of(events, "/space/input.txt"), events
+    assert "delete_self" in events_of(events, "/space/input.txt"), events
+    # The events are returned once
+    assert box.watch_events() == []
+
+    # The watches are gone after reset
+    box.reset()
+    box.write("/space/output.txt", b"")
+    assert box.watch_events(timeout=0.1) == []
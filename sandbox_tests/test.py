@@ -1,6 +1,7 @@
 #!/usr/bin/env python3
 import abc
 import contextlib
+import importlib.util
 import io
 import json
 import os
@@ -81,6 +82,8 @@ class Box:
     def __init__(self, opts: list[str] = []):
         self.proc = subprocess.Popen(sunwalker_prefix + ["../sunwalker_box", "start", "--core", str(
             CORE), *opts], stdin=subprocess.PIPE, stdout=subprocess.PIPE)
+        # Events of runs with events=True, in the order they were received
+        self.events = []
 
     def __enter__(self):
         return self
@@ -90,10 +93,14 @@ class Box:
         with self.proc:
             pass
 
-    def cmd(self, name: str, arg=None):
+    def cmd(self, name: str, arg=None, payload: bytes = b""):
         self.proc.stdin.write(f"{name} {json.dumps(arg)}\n".encode())
+        self.proc.stdin.write(payload)
         self.proc.stdin.flush()
         line = self.proc.stdout.readline().strip().decode()
+        while line.startswith("event "):
+            self.events.append(json.loads(line[6:]))
+            line = self.proc.stdout.readline().strip().decode()
         if line == "ok":
             return None
         elif line.startswith("ok "):
@@ -112,21 +119,95 @@ class Box:
     def cat(self, path: str, at: int = 0, len: int = 0) -> bytes:
         return bytes(self.cmd("cat", {"path": path, "at": at, "len": len}))
 
-    def mkfile(self, path: str, content: bytes = b""):
-        return self.cmd("mkfile", {"path": path, "content": list(content)})
+    def cat_raw(self, path: str, at: int = 0, len: int = 0) -> bytes:
+        header = self.cmd(
+            "cat", {"path": path, "at": at, "len": len, "raw": True})
+        return self.proc.stdout.read(header["len"])
+
+    def du(self, path: str) -> dict[str, int]:
+        return self.cmd("du", path)
+
+    def warmup(self, paths: list[str], max_bytes: int | None = None) -> dict[str, int]:
+        return self.cmd("warmup", {"paths": paths, "max_bytes": max_bytes})
+
+    def hash(self, path: str) -> str:
+        return self.cmd("hash", path)
+
+    def tar(self, path: str) -> bytes:
+        header = self.cmd("tar", path)
+        return self.proc.stdout.read(header["len"])
+
+    def watch(self, path: str) -> int:
+        return self.cmd("watch", path)["wd"]
+
+    def watch_events(self, timeout: float = 0) -> list[dict[str, ...]]:
+        return self.cmd("watch_events", {"timeout": timeout})
+
+    def mkfile(self, path: str, content: bytes = b"", mode: int | None = None, owner: str | None = None):
+        return self.cmd("mkfile", {"path": path, "content": list(content), "mode": mode, "owner": owner})
+
+    def write(self, path: str, content: bytes, mode: int | None = None, owner: str | None = None):
+        return self.cmd("write", {"path": path, "len": len(content), "mode": mode, "owner": owner}, content)
+
+    def untar(self, path: str, archive: bytes):
+        return self.cmd("untar", {"path": path, "len": len(archive)}, archive)
+
+    def upload(self, path: str, entries: list, contents: bytes = b""):
+        return self.cmd("upload", {"path": path, "len": len(contents), "entries": entries}, contents)
+
+    def rm(self, path: str, recursive: bool = False):
+        return self.cmd("rm", {"path": path, "recursive": recursive})
+
+    def mv(self, source: str, target: str):
+        return self.cmd("mv", {"from": source, "to": target})
+
+    def cp(self, source: str, target: str, recursive: bool = False):
+        return self.cmd("cp", {"from": source, "to": target, "recursive": recursive})
 
     def mksymlink(self, target: str, link: str):
         return self.cmd("mksymlink", {"target": target, "link": link})
 
-    def bind(self, source: str, mountpoint: str, readonly: bool = False):
-        return self.cmd("bind", {"external": source, "internal": mountpoint, "ro": readonly})
+    def bind(self, source: str, mountpoint: str, readonly: bool = False, idmap: bool = False):
+        return self.cmd("bind", {"external": source, "internal": mountpoint, "ro": readonly, "idmap": idmap})
+
+    def volume(self, source: str, mountpoint: str, readonly: bool = False, idmap: bool = False):
+        return self.cmd("volume", {"external": source, "internal": mountpoint, "ro": readonly, "idmap": idmap})
+
+    def link(self, source: str, target: str, hardlink: bool = False, persistent: bool = False) -> str:
+        return self.cmd("link", {"external": source, "internal": target, "hardlink": hardlink, "persistent": persistent})["method"]
+
+    def batch(self, commands: list[tuple[str, ...]], payload: bytes = b"") -> list[dict[str, ...]]:
+        return self.cmd("batch", [{"command": name, "argument": arg} for name, arg in commands], payload)
+
+    def run_batch(self, program: dict[str, ...], tests: list[dict[str, ...]], stop_on_failure: bool = False) -> list[dict[str, ...]]:
+        return self.cmd("run_batch", {"program": program, "tests": tests, "stop_on_failure": stop_on_failure})
+
+    def run_group(self, programs: list[dict[str, ...]], is_async: bool = False) -> list[dict[str, ...]]:
+        return self.cmd("run", {"programs": programs, "async": is_async})
+
+    def wait(self, run_id: int) -> dict[str, ...]:
+        return self.cmd("wait", run_id)
+
+    def poll(self, run_id: int) -> dict[str, ...] | None:
+        return self.cmd("poll", run_id)
+
+    def kill(self, run_id: int, signal: int | None = None, program: int | None = None):
+        return self.cmd("kill", {"id": run_id, "signal": signal, "program": program})
 
     def reset(self):
         return self.cmd("reset")
 
+    def snapshot(self):
+        return self.cmd("snapshot")
+
+    def drop_snapshot(self):
+        return self.cmd("drop_snapshot")
+
     def run(
         self,
         argv: list[str],
+        argv0: Optional[str] = None,
+        cwd: Optional[str] = None,
         stdin: Optional[str] = None,
         stdout: Optional[str] = None,
         stderr: Optional[str] = None,
@@ -134,11 +215,30 @@ class Box:
         cpu_time_limit: Optional[float] = None,
         idleness_time_limit: Optional[float] = None,
         memory_limit: Optional[int] = None,
+        swap_limit: Optional[int] = None,
         processes_limit: Optional[int] = None,
-        env: dict[str, str] = None
+        output_limit: Optional[int] = None,
+        stack_limit: Optional[int | str] = None,
+        rlimits: Optional[dict[str, int | str]] = None,
+        cpu_quota: Optional[float] = None,
+        transparent_hugepages: Optional[bool] = None,
+        capabilities: Optional[list[str]] = None,
+        landlock: Optional[bool | dict] = None,
+        seccomp_profile: Optional[str | dict] = None,
+        intercept: Optional[list[dict]] = None,
+        syscall_audit: Optional[bool | list[str]] = None,
+        exec_deny: Optional[list[dict]] = None,
+        core_dump: Optional[dict] = None,
+        socket_isolation: Optional[dict] = None,
+        realtime: Optional[dict] = None,
+        env: dict[str, str] = None,
+        is_async: bool = False,
+        events: bool = False
     ) -> dict[str, ...]:
         return self.cmd("run", {
             "argv": argv,
+            "argv0": argv0,
+            "cwd": cwd,
             "stdin": stdin,
             "stdout": stdout,
             "stderr": stderr,
@@ -146,8 +246,25 @@ class Box:
             "cpu_time_limit": cpu_time_limit,
             "idleness_time_limit": idleness_time_limit,
             "memory_limit": memory_limit,
+            "swap_limit": swap_limit,
             "processes_limit": processes_limit,
-            "env": env
+            "output_limit": output_limit,
+            "stack_limit": stack_limit,
+            "rlimits": rlimits,
+            "cpu_quota": cpu_quota,
+            "transparent_hugepages": transparent_hugepages,
+            "capabilities": capabilities,
+            "landlock": landlock,
+            "seccomp_profile": seccomp_profile,
+            "intercept": intercept,
+            "syscall_audit": syscall_audit,
+            "exec_deny": exec_deny,
+            "core_dump": core_dump,
+            "socket_isolation": socket_isolation,
+            "realtime": realtime,
+            "env": env,
+            "async": is_async,
+            "events": events
         })
 
 
@@ -194,7 +311,25 @@ class SimpleTest(Test):
         static: bool = False,
         outer_env: dict[str, str] = {},
         env: dict[str, str] = DEFAULT_ENV,
+        argv0: Optional[str] = None,
+        cwd: Optional[str] = None,
+        merge_stderr: bool = False,
+        cpu_quota: Optional[float] = None,
+        transparent_hugepages: Optional[bool] = None,
+        capabilities: Optional[list[str]] = None,
+        landlock: Optional[bool | dict] = None,
+        seccomp_profile: Optional[str | dict] = None,
+        intercept: Optional[list[dict]] = None,
+        syscall_audit: Optional[bool | list[str]] = None,
+        exec_deny: Optional[list[dict]] = None,
+        rlimits: Optional[dict[str, int | str]] = None,
+        core_dump: Optional[dict] = None,
+        socket_isolation: Optional[dict] = None,
+        realtime: Optional[dict] = None,
         quotas: dict[str, ...] = {},
+        loopback: bool = False,
+        monotonic_offset: Optional[float] = None,
+        boottime_offset: Optional[float] = None,
         input: Optional[str] = None,
         expect: dict[str, ...] = {},
         limits: dict[str, ...] = {}
@@ -209,7 +344,25 @@ class SimpleTest(Test):
         self.static = static
         self.outer_env = outer_env
         self.env = env
+        self.argv0 = argv0
+        self.cwd = cwd
+        self.merge_stderr = merge_stderr
+        self.cpu_quota = cpu_quota
+        self.transparent_hugepages = transparent_hugepages
+        self.capabilities = capabilities
+        self.landlock = landlock
+        self.seccomp_profile = seccomp_profile
+        self.intercept = intercept
+        self.syscall_audit = syscall_audit
+        self.exec_deny = exec_deny
+        self.rlimits = rlimits
+        self.core_dump = core_dump
+        self.socket_isolation = socket_isolation
+        self.realtime = realtime
         self.quotas = quotas
+        self.loopback = loopback
+        self.monotonic_offset = monotonic_offset
+        self.boottime_offset = boottime_offset
         self.input = input
         self.expect = expect
         self.limits = limits
@@ -242,6 +395,12 @@ class SimpleTest(Test):
             opts += ["--quota-space", str(self.quotas["space"])]
         if "inodes" in self.quotas:
             opts += ["--quota-inodes", str(self.quotas["inodes"])]
+        if self.loopback:
+            opts.append("--loopback")
+        if self.monotonic_offset is not None:
+            opts += ["--monotonic-offset", str(self.monotonic_offset)]
+        if self.boottime_offset is not None:
+            opts += ["--boottime-offset", str(self.boottime_offset)]
         for key, value in self.outer_env.items():
             os.environ[key] = value
         if self.root is not None:
@@ -258,7 +417,7 @@ class SimpleTest(Test):
                     stdin = None
                 else:
                     stdin = "/space/stdin.txt"
-                    box.mkfile(f"/space/stdin.txt", self.input.encode())
+                    box.write(f"/space/stdin.txt", self.input.encode())
 
                 for cmd in self.preexec:
                     args = cmd.split()
@@ -301,7 +460,10 @@ class SimpleTest(Test):
                     ("idleness_time", float),
                     ("real_time", float),
                     ("memory", parse_size),
-                    ("processes", int)
+                    ("swap", parse_size),
+                    ("processes", int),
+                    ("output", parse_size),
+                    ("stack", lambda s: s if s == "unlimited" else parse_size(s))
                 ]:
                     if key in self.limits:
                         limits[f"{key}_limit"] = parser(self.limits[key])
@@ -315,24 +477,54 @@ class SimpleTest(Test):
                     argv,
                     stdin=stdin,
                     stdout="/space/stdout.txt",
-                    stderr="/space/stderr.txt",
+                    stderr="/space/stdout.txt" if self.merge_stderr else "/space/stderr.txt",
                     env=self.env,
+                    argv0=self.argv0,
+                    cwd=self.cwd,
+                    cpu_quota=self.cpu_quota,
+                    transparent_hugepages=self.transparent_hugepages,
+                    capabilities=self.capabilities,
+                    landlock=self.landlock,
+                    seccomp_profile=self.seccomp_profile,
+                    intercept=self.intercept,
+                    syscall_audit=self.syscall_audit,
+                    exec_deny=self.exec_deny,
+                    rlimits=self.rlimits,
+                    core_dump=self.core_dump,
+                    socket_isolation=self.socket_isolation,
+                    realtime=self.realtime,
                     **limits
                 )
 
                 for key, default_value in [
                     ("limit_verdict", "OK"),
-                    ("exit_code", 0 if result["limit_verdict"] == "OK" else -1)
+                    ("exit_code", 0 if result["limit_verdict"] == "OK" else -1),
+                    ("verdict", None),
+                    ("processes_limit_hit", None),
+                    ("syscalls", None),
+                    ("crash", None),
+                    ("core_dumps", None),
+                    ("swap", None),
+                    ("security_report", None)
                 ]:
-                    value = result[key]
+                    value = result.get(key)
                     expected_value = self.expect.get(key, default_value)
+                    if key == "crash" and value is not None and expected_value is not None:
+                        # Registers and addresses vary, so only the listed properties are compared
+                        value = {k: value.get(k) for k in expected_value}
+                    if key == "core_dumps" and value is not None and expected_value is not None:
+                        # So do PIDs and the paths from core_pattern
+                        value = [{k: dump.get(k) for k in expected} for dump, expected in zip(value, expected_value)] + value[len(expected_value):]
+                    if key == "security_report" and value is not None:
+                        # PIDs depend on what the runtime spawns
+                        value = {"sockets": [{k: v for k, v in socket.items() if k != "pid"} for socket in value["sockets"]]}
                     if expected_value is not None:
                         stdout = box.cat("/space/stdout.txt").decode()
-                        stderr = box.cat("/space/stderr.txt").decode()
+                        stderr = "" if self.merge_stderr else box.cat("/space/stderr.txt").decode()
                         assert value == expected_value, f"Expected {key}: {expected_value}, actual: {value}\n\nstdout:\n{stdout}\nstderr:\n{stderr}"
 
-                for key in ("stdout", "stderr"):
-                    value = box.cat(f"/space/{key}.txt")
+                for key in ("stdout",) if self.merge_stderr else ("stdout", "stderr"):
+                    value = box.cat_raw(f"/space/{key}.txt")
 
                     patched_value = value
                     if self.expect.get(f"unordered_{key}"):
@@ -373,6 +565,8 @@ class SimpleTest(Test):
 
                 for (key, parser) in [
                     ("cpu_time", float),
+                    ("user_time", float),
+                    ("system_time", float),
                     ("idleness_time", float),
                     ("real_time", float),
                     ("memory", parse_size)
@@ -414,6 +608,64 @@ class PyTest(SimpleTest):
         return box.run(["/usr/bin/python3", f"/space/{self.slug}.py"] + argv, **kwargs)
 
 
+# Drives the box through its commands rather than runs a single program, e.g. to check how commands
+# interact with each other
+class ScenarioTest(Test):
+    def __init__(
+        self,
+        slug: str,
+        description: str,
+        source_path: str,
+        box_options: list[str] = [],
+        root: Optional[dict[str, ...]] = None
+    ):
+        self.slug = slug
+        self.description = description
+        self.source_path = source_path
+        self.box_options = box_options
+        self.root = root
+        self.root_dir = None
+
+        spec = importlib.util.spec_from_file_location(slug, source_path)
+        self.module = importlib.util.module_from_spec(spec)
+        spec.loader.exec_module(self.module)
+
+    def prepare(self, tester):
+        if self.root is not None:
+            self.root_dir = f"build/roots/{self.slug}"
+            if os.path.isdir(self.root_dir):
+                shutil.rmtree(self.root_dir)
+            os.mkdir(self.root_dir)
+
+            create_dirs(self.root, self.root_dir)
+            # Lets the scenario set up what the YAML header can't express, e.g. file modes
+            if hasattr(self.module, "prepare_root"):
+                self.module.prepare_root(self.root_dir)
+
+    def run(self, tester):
+        opts = list(self.box_options)
+        if self.root is not None:
+            opts += ["--root", self.root_dir]
+        # Lets the scenario set up and tear down what the box needs on the host, e.g. a bridge
+        if hasattr(self.module, "setup"):
+            self.module.setup()
+        try:
+            with Box(opts) as box:
+                self.module.run(box)
+        finally:
+            if hasattr(self.module, "teardown"):
+                self.module.teardown()
+
+
+def expect_error(action: Callable[[], ...], message: str):
+    try:
+        action()
+    except RuntimeError as e:
+        assert message in str(e), f"Expected error containing {message!r}, actual: {e}"
+    else:
+        assert False, f"Expected error containing {message!r}, but the command succeeded"
+
+
 class Tester:
     def __init__(self, f_makefile: io.TextIOBase):
         self.f_makefile = f_makefile
@@ -439,6 +691,16 @@ class Tester:
 
         self.tests.append(PyTest(slug, **header))
 
+    def register_scenario_test(self, source_path: str):
+        with open(source_path) as f:
+            yaml_header = re.match(
+                r"\"\"\"([\s\S]+?)\"\"\"", f.read()).group(1)
+        header = yaml.unsafe_load(yaml_header)
+
+        slug = os.path.basename(source_path).removesuffix(".py")
+
+        self.tests.append(ScenarioTest(slug, source_path=source_path, **header))
+
     def prepare(self):
         for test in self.tests:
             test.prepare(self)
@@ -531,6 +793,11 @@ def main():
             elif test_file.endswith(".py"):
                 tester.register_py_test(os.path.join("tests", test_file))
 
+        for test_file in sorted(os.listdir("scenarios")):
+            if test_file.endswith(".py"):
+                tester.register_scenario_test(
+                    os.path.join("scenarios", test_file))
+
         tester.prepare()
         tester.run()
     finally:
@@ -0,0 +1,13 @@
+/*
+description: argv[0] can differ from the path to the executable
+argv0: custom-name
+expect:
+  stdout: "custom-name\n"
+*/
+
+#include <stdio.h>
+
+int main(int argc, char **argv) {
+  printf("%s\n", argv[0]);
+  return 0;
+}
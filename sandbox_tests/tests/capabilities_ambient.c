@@ -0,0 +1,25 @@
+/*
+description: Allowed capabilities are kept as ambient capabilities across execve
+capabilities: [CAP_SYS_PTRACE]
+expect:
+  stdout: "CapEff:\t0000000000080000\nCapAmb:\t0000000000080000\n"
+*/
+
+#include <stdio.h>
+#include <string.h>
+
+int main() {
+  FILE *f = fopen("/proc/self/status", "r");
+  if (f == NULL) {
+    perror("Failed to open /proc/self/status");
+    return 1;
+  }
+  char line[256];
+  while (fgets(line, sizeof(line), f) != NULL) {
+    if (strncmp(line, "CapEff:", 7) == 0 || strncmp(line, "CapAmb:", 7) == 0) {
+      fputs(line, stdout);
+    }
+  }
+  fclose(f);
+  return 0;
+}
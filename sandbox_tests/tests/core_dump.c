@@ -0,0 +1,15 @@
+/*
+description: The core dump of a crashing process is kept, truncated to the limit
+core_dump:
+  limit: 4096
+expect:
+  limit_verdict: Signaled
+  exit_code: -11
+  core_dumps:
+    - truncated: true
+*/
+
+int main() {
+  volatile int *p = (int *)0x10;
+  return *p;
+}
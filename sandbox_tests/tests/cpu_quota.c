@@ -0,0 +1,15 @@
+/*
+description: A CPU quota slows the program down without affecting CPU time
+cpu_quota: 0.5
+expect:
+    cpu_time: 0.2 +- 0.02
+    real_time: 0.4 +- 0.05
+*/
+
+#include <time.h>
+
+int main() {
+  while (clock() < CLOCKS_PER_SEC / 5) {
+  }
+  return 0;
+}
@@ -0,0 +1,15 @@
+/*
+description: A crash is reported with its signal, code, and faulting address
+expect:
+  limit_verdict: Signaled
+  exit_code: -11
+  crash:
+    signal: 11
+    code: SEGV_MAPERR
+    address: "0x10"
+*/
+
+int main() {
+  volatile int *p = (int *)0x10;
+  return *p;
+}
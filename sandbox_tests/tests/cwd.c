@@ -0,0 +1,20 @@
+/*
+description: Working directory can be changed
+preexec:
+  - ~0 mkdir /space/dir
+cwd: dir
+expect:
+  stdout: "/space/dir\n"
+*/
+
+#include <stdio.h>
+#include <unistd.h>
+
+int main() {
+  char buf[256];
+  if (getcwd(buf, sizeof(buf)) == NULL) {
+    return 1;
+  }
+  printf("%s\n", buf);
+  return 0;
+}
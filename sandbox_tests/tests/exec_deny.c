@@ -0,0 +1,23 @@
+/*
+description: Executing a denied file kills the process and yields a security violation
+exec_deny: [{path: /bin/sh}]
+limits:
+  processes: 2
+expect:
+  verdict:
+    kind: SecurityViolation
+*/
+
+#include <sys/wait.h>
+#include <unistd.h>
+
+int main() {
+  pid_t pid = fork();
+  if (pid == 0) {
+    execl("/bin/sh", "sh", "-c", "exit 0", NULL);
+    _exit(1);
+  }
+  int wstatus;
+  waitpid(pid, &wstatus, 0);
+  return 0;
+}
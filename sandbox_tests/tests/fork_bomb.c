@@ -2,6 +2,8 @@
 description: Can start exactly as many processes as allowed
 limits:
   processes: 100
+expect:
+  processes_limit_hit: true
 */
 
 #include <stddef.h>
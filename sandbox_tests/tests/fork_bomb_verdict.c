@@ -0,0 +1,27 @@
+/*
+description: A fork bomb crashing on a failed fork is reported as hitting the processes limit
+limits:
+  processes: 16
+expect:
+  exit_code: 1
+  verdict:
+    kind: RuntimeError
+    exit_code: 1
+    limit: processes_limit
+  processes_limit_hit: true
+*/
+
+#include <unistd.h>
+
+int main() {
+  for (;;) {
+    switch (fork()) {
+    case 0:
+      for (;;) {
+        pause();
+      }
+    case -1:
+      return 1;
+    }
+  }
+}
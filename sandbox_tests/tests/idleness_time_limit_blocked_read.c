@@ -0,0 +1,28 @@
+/*
+description: Idleness time limit terminates a process blocked on I/O before the real time limit
+limits:
+  idleness_time: 0.2
+  real_time: 5
+expect:
+  limit_verdict: IdlenessTimeLimitExceeded
+  verdict:
+    kind: TimeLimitExceeded
+    limit: idleness_time_limit
+    limit_value: 0.2
+  real_time: 0.2 +- 0.07
+  cpu_time: 0 +- 0.01
+  idleness_time: 0.2 +- 0.07
+*/
+
+#include <unistd.h>
+
+int main() {
+  // Nobody is going to write to the pipe, so read blocks without consuming CPU time
+  int fds[2];
+  if (pipe(fds) == -1) {
+    return 1;
+  }
+  char c;
+  read(fds[0], &c, 1);
+  return 0;
+}
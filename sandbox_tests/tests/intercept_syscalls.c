@@ -0,0 +1,28 @@
+/*
+description: Intercepted syscalls can be failed or stubbed out, optionally by path
+intercept:
+  - syscalls: [openat]
+    path_prefix: /etc/
+    action:
+      errno: 13
+  - syscalls: [getppid]
+    action:
+      return: 42
+expect:
+  stdout: "-1 13\n0\n42\n"
+*/
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+int main() {
+  int fd = open("/etc/passwd", O_RDONLY);
+  printf("%d %d\n", fd, errno);
+  fd = open("/dev/null", O_RDONLY);
+  printf("%d\n", fd >= 0 ? 0 : errno);
+  printf("%ld\n", syscall(SYS_getppid));
+  return 0;
+}
@@ -0,0 +1,20 @@
+/*
+description: Landlock denies writes outside of the read-write paths even where the mounts allow them
+landlock:
+  read_only: [/]
+  read_write: [/tmp, /dev]
+expect:
+  stdout: "0 13 0\n"
+*/
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+
+int main() {
+  int read_space = open("/space", O_RDONLY | O_DIRECTORY) >= 0 ? 0 : errno;
+  int write_space = open("/space/file", O_WRONLY | O_CREAT, 0644) >= 0 ? 0 : errno;
+  int write_tmp = open("/tmp/file", O_WRONLY | O_CREAT, 0644) >= 0 ? 0 : errno;
+  printf("%d %d %d\n", read_space, write_space, write_tmp);
+  return 0;
+}
@@ -0,0 +1,18 @@
+/*
+description: With the default Landlock ruleset, the image is readable and /space is writable
+landlock: true
+expect:
+  stdout: "0 0 0\n"
+*/
+
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+
+int main() {
+  int read_image = open("/etc/passwd", O_RDONLY) >= 0 ? 0 : errno;
+  int write_space = open("/space/file", O_WRONLY | O_CREAT, 0644) >= 0 ? 0 : errno;
+  int write_null = open("/dev/null", O_WRONLY) >= 0 ? 0 : errno;
+  printf("%d %d %d\n", read_image, write_space, write_null);
+  return 0;
+}
@@ -0,0 +1,24 @@
+"""
+description: With --loopback, programs can connect to each other over 127.0.0.1
+loopback: true
+limits:
+  processes: 2
+"""
+
+import os
+import socket
+
+server = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+server.bind(("127.0.0.1", 0))
+server.listen(1)
+port = server.getsockname()[1]
+
+if os.fork() == 0:
+    client = socket.create_connection(("127.0.0.1", port))
+    client.sendall(b"ping")
+    client.close()
+    os._exit(0)
+
+connection, _ = server.accept()
+assert connection.recv(4) == b"ping", "Unexpected message"
+os.wait()
@@ -0,0 +1,43 @@
+/*
+description: An OOM kill of a child process is reported as MLE rather than SIGKILL
+limits:
+  memory: 200 MB
+  processes: 2
+expect:
+  limit_verdict: MemoryLimitExceeded
+  verdict:
+    kind: MemoryLimitExceeded
+    limit: memory_limit
+    limit_value: 200000000
+*/
+
+#include <stddef.h>
+#include <stdio.h>
+#include <sys/mman.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+int main() {
+  pid_t pid = fork();
+  if (pid == -1) {
+    perror("fork");
+    return 1;
+  }
+  if (pid == 0) {
+    size_t size = 300 * 1000 * 1000;
+    char *p = mmap(NULL, size, PROT_READ | PROT_WRITE,
+                   MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    if (p == MAP_FAILED) {
+      perror("mmap");
+      return 1;
+    }
+    for (size_t i = 0; i < size; i += 4096) {
+      p[i] = '\0';
+    }
+    return 0;
+  }
+  // Even if only the child were killed, the parent would exit normally
+  int status;
+  waitpid(pid, &status, 0);
+  return 0;
+}
@@ -0,0 +1,25 @@
+/*
+description: Writing past the output limit is caught as a verdict
+limits:
+  output: 100000
+expect:
+  limit_verdict: OutputLimitExceeded
+  verdict:
+    kind: OutputLimitExceeded
+    limit: output_limit
+    limit_value: 100000
+*/
+
+#include <string.h>
+#include <unistd.h>
+
+int main() {
+  char buf[1000];
+  memset(buf, 'a', sizeof(buf));
+  for (int i = 0; i < 200; i++) {
+    if (write(1, buf, sizeof(buf)) == -1) {
+      return 1;
+    }
+  }
+  return 0;
+}
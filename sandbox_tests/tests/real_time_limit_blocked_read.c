@@ -0,0 +1,26 @@
+/*
+description: Real time limit applies to a process blocked on I/O
+limits:
+  real_time: 0.2
+expect:
+  limit_verdict: RealTimeLimitExceeded
+  verdict:
+    kind: TimeLimitExceeded
+    limit: real_time_limit
+    limit_value: 0.2
+  real_time: 0.2 +- 0.07
+  cpu_time: 0 +- 0.01
+*/
+
+#include <unistd.h>
+
+int main() {
+  // Nobody is going to write to the pipe, so read blocks forever
+  int fds[2];
+  if (pipe(fds) == -1) {
+    return 1;
+  }
+  char c;
+  read(fds[0], &c, 1);
+  return 0;
+}
@@ -0,0 +1,45 @@
+/*
+description: CLOCK_REALTIME can be frozen at a fixed time
+runs: 2
+realtime:
+  start: 1000000000.25
+  frozen: true
+expect:
+  stdout: "1000000000.250000000\n1000000000.250000000\n1000000000.250000\n1000000000\n1000000000\n"
+  matching_stdout: true
+*/
+
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <sys/time.h>
+#include <time.h>
+#include <unistd.h>
+
+int main() {
+  clockid_t clocks[] = {CLOCK_REALTIME, CLOCK_REALTIME_COARSE};
+  for (int i = 0; i < 2; i++) {
+    struct timespec ts;
+    if (clock_gettime(clocks[i], &ts) == -1) {
+      perror("clock_gettime");
+      return 1;
+    }
+    printf("%ld.%09ld\n", ts.tv_sec, ts.tv_nsec);
+  }
+
+  if (usleep(100000) == -1) {
+    perror("usleep");
+    return 1;
+  }
+
+  struct timeval tv;
+  if (gettimeofday(&tv, NULL) == -1) {
+    perror("gettimeofday");
+    return 1;
+  }
+  printf("%ld.%06ld\n", tv.tv_sec, tv.tv_usec);
+
+  time_t t;
+  printf("%ld\n", (long)syscall(SYS_time, &t));
+  printf("%ld\n", (long)t);
+  return 0;
+}
@@ -0,0 +1,44 @@
+/*
+description: CLOCK_REALTIME starts from the given time and advances
+realtime:
+  start: 1500000000
+*/
+
+#include <stdio.h>
+#include <time.h>
+#include <unistd.h>
+
+double now(clockid_t clock) {
+  struct timespec ts;
+  if (clock_gettime(clock, &ts) == -1) {
+    perror("clock_gettime");
+    return -1;
+  }
+  return ts.tv_sec + ts.tv_nsec / 1e9;
+}
+
+int main() {
+  double start = now(CLOCK_REALTIME);
+  if (!(1500000000 <= start && start <= 1500000000.1)) {
+    fprintf(stderr, "Started at %f\n", start);
+    return 1;
+  }
+
+  if (usleep(300000) == -1) {
+    perror("usleep");
+    return 1;
+  }
+
+  double diff = now(CLOCK_REALTIME) - start;
+  if (!(0.25 <= diff && diff <= 0.35)) {
+    fprintf(stderr, "Advanced by %f, [0.25; 0.35] was expected\n", diff);
+    return 1;
+  }
+
+  // Other clocks are left alone
+  if (now(CLOCK_MONOTONIC) > 1000) {
+    fprintf(stderr, "CLOCK_MONOTONIC is off\n");
+    return 1;
+  }
+  return 0;
+}
@@ -0,0 +1,22 @@
+/*
+description: Resource limits are set as both the soft and the hard limit, core dumps are disabled by default
+rlimits: {nofile: 100, memlock: 65536}
+expect:
+  stdout: "nofile 100 100\nmemlock 65536 65536\ncore 0\n"
+*/
+
+#include <stdio.h>
+#include <sys/resource.h>
+
+int main() {
+  struct rlimit limit;
+  getrlimit(RLIMIT_NOFILE, &limit);
+  printf("nofile %llu %llu\n", (unsigned long long)limit.rlim_cur,
+         (unsigned long long)limit.rlim_max);
+  getrlimit(RLIMIT_MEMLOCK, &limit);
+  printf("memlock %llu %llu\n", (unsigned long long)limit.rlim_cur,
+         (unsigned long long)limit.rlim_max);
+  getrlimit(RLIMIT_CORE, &limit);
+  printf("core %llu\n", (unsigned long long)limit.rlim_cur);
+  return 0;
+}
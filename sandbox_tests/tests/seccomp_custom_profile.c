@@ -0,0 +1,21 @@
+/*
+description: A custom seccomp profile can make syscalls fail with the given errno
+seccomp_profile:
+  rules:
+    - syscalls: [getppid]
+      action:
+        errno: 13
+expect:
+  stdout: "-1 13\n"
+*/
+
+#include <errno.h>
+#include <stdio.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+int main() {
+  long ret = syscall(SYS_getppid);
+  printf("%ld %d\n", ret, errno);
+  return 0;
+}
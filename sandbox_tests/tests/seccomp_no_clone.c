@@ -0,0 +1,16 @@
+/*
+description: The no-clone seccomp profile forbids spawning processes
+seccomp_profile: no-clone
+expect:
+  stdout: "-1 1\n"
+*/
+
+#include <errno.h>
+#include <stdio.h>
+#include <unistd.h>
+
+int main() {
+  pid_t pid = fork();
+  printf("%d %d\n", pid, errno);
+  return 0;
+}
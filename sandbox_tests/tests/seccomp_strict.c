@@ -0,0 +1,17 @@
+/*
+description: The strict seccomp profile kills programs using unusual syscalls
+seccomp_profile: strict
+expect:
+  limit_verdict: Signaled
+  exit_code: -31
+  verdict:
+    kind: SecurityViolation
+*/
+
+#include <sys/syscall.h>
+#include <unistd.h>
+
+int main() {
+  syscall(SYS_getppid);
+  return 0;
+}
@@ -0,0 +1,14 @@
+/*
+description: A syscall forbidden by seccomp is classified as a security violation
+expect:
+  limit_verdict: Signaled
+  exit_code: -31
+  verdict:
+    kind: SecurityViolation
+*/
+
+int main() {
+  // getpid via the 32-bit ABI
+  asm volatile("int $0x80" : : "a"(20));
+  return 0;
+}
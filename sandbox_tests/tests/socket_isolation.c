@@ -0,0 +1,44 @@
+/*
+description: Abstract Unix sockets and netlink sockets are reported, and denied if asked to
+socket_isolation:
+  abstract_unix: deny
+  netlink: report
+expect:
+  stdout: "EACCES\nnetlink ok\n"
+  security_report:
+    sockets:
+      - syscall: connect
+        denied: true
+        kind: abstract_unix
+        name: "@sunwalker-test"
+      - syscall: socket
+        denied: false
+        kind: netlink
+        protocol: 15
+*/
+
+#include <errno.h>
+#include <linux/netlink.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/un.h>
+#include <unistd.h>
+
+int main() {
+  int fd = socket(AF_UNIX, SOCK_STREAM, 0);
+  struct sockaddr_un addr = {.sun_family = AF_UNIX};
+  const char name[] = "sunwalker-test";
+  memcpy(addr.sun_path + 1, name, sizeof(name) - 1);
+  socklen_t len = sizeof(addr.sun_family) + 1 + sizeof(name) - 1;
+  if (connect(fd, (struct sockaddr *)&addr, len) == -1 && errno == EACCES) {
+    puts("EACCES");
+  } else {
+    puts("connected or failed differently");
+  }
+  close(fd);
+
+  int nl = socket(AF_NETLINK, SOCK_RAW, NETLINK_KOBJECT_UEVENT);
+  puts(nl == -1 ? "netlink failed" : "netlink ok");
+  return 0;
+}
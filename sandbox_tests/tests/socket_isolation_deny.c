@@ -0,0 +1,66 @@
+/*
+description: Denied abstract Unix and netlink sockets fail with EACCES, but Unix sockets with paths don't
+socket_isolation:
+  abstract_unix: deny
+  netlink: deny
+expect:
+  stdout: "bind EACCES\nsendto EACCES\nnetlink EACCES\npath ok\n"
+  security_report:
+    sockets:
+      - syscall: bind
+        denied: true
+        kind: abstract_unix
+        name: "@sunwalker-bind"
+      - syscall: sendto
+        denied: true
+        kind: abstract_unix
+        name: "@sunwalker-sendto"
+      - syscall: socket
+        denied: true
+        kind: netlink
+        protocol: 0
+*/
+
+#include <errno.h>
+#include <linux/netlink.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <sys/un.h>
+#include <unistd.h>
+
+static socklen_t abstract_address(struct sockaddr_un *addr, const char *name) {
+  memset(addr, 0, sizeof(*addr));
+  addr->sun_family = AF_UNIX;
+  memcpy(addr->sun_path + 1, name, strlen(name));
+  return sizeof(addr->sun_family) + 1 + strlen(name);
+}
+
+static void report(const char *what, int ret) {
+  printf("%s %s\n", what, ret == -1 && errno == EACCES ? "EACCES" : "not denied");
+}
+
+int main() {
+  struct sockaddr_un addr;
+  socklen_t len;
+
+  int fd = socket(AF_UNIX, SOCK_STREAM, 0);
+  len = abstract_address(&addr, "sunwalker-bind");
+  report("bind", bind(fd, (struct sockaddr *)&addr, len));
+  close(fd);
+
+  fd = socket(AF_UNIX, SOCK_DGRAM, 0);
+  len = abstract_address(&addr, "sunwalker-sendto");
+  report("sendto", sendto(fd, "x", 1, 0, (struct sockaddr *)&addr, len));
+  close(fd);
+
+  report("netlink", socket(AF_NETLINK, SOCK_RAW, NETLINK_ROUTE));
+
+  fd = socket(AF_UNIX, SOCK_STREAM, 0);
+  memset(&addr, 0, sizeof(addr));
+  addr.sun_family = AF_UNIX;
+  strcpy(addr.sun_path, "/tmp/socket");
+  puts(bind(fd, (struct sockaddr *)&addr, sizeof(addr)) == 0 ? "path ok" : "path failed");
+  close(fd);
+  return 0;
+}
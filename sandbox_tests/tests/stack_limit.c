@@ -0,0 +1,25 @@
+/*
+description: Deep recursion works with a large stack limit
+limits:
+  stack: 256 MiB
+expect:
+  stdout: "ok\n"
+*/
+
+#include <stdio.h>
+
+// Uses about 128 MiB of stack, much more than the default 8 MiB
+static int recurse(int depth) {
+  volatile char frame[1024];
+  frame[0] = (char)depth;
+  if (depth == 0) {
+    return frame[0];
+  }
+  return recurse(depth - 1) + frame[0];
+}
+
+int main() {
+  recurse(128 * 1024);
+  printf("ok\n");
+  return 0;
+}
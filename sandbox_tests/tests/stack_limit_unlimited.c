@@ -0,0 +1,15 @@
+/*
+description: Stack limit can be lifted
+limits:
+  stack: unlimited
+*/
+
+#include <sys/resource.h>
+
+int main() {
+  struct rlimit limit;
+  if (getrlimit(RLIMIT_STACK, &limit) == -1) {
+    return 1;
+  }
+  return limit.rlim_cur == RLIM_INFINITY ? 0 : 2;
+}
@@ -0,0 +1,15 @@
+/*
+description: stdout and stderr redirected to the same file interleave
+merge_stderr: true
+expect:
+  stdout: "out\nerr\nout\n"
+*/
+
+#include <unistd.h>
+
+int main() {
+  write(1, "out\n", 4);
+  write(2, "err\n", 4);
+  write(1, "out\n", 4);
+  return 0;
+}
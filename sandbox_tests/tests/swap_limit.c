@@ -0,0 +1,29 @@
+/*
+description: Memory cannot be paged out to exceed the memory limit when swap is disallowed
+limits:
+  memory: 100 MB
+  swap: 0
+expect:
+  limit_verdict: MemoryLimitExceeded
+  swap: 0
+*/
+
+#include <stddef.h>
+#include <stdio.h>
+#include <sys/mman.h>
+
+int main() {
+  size_t size = 150 * 1000 * 1000;
+  char *p = mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS,
+                 -1, 0);
+  if (p == MAP_FAILED) {
+    fprintf(stderr, "Failed to map 150 MB\n");
+    return 1;
+  }
+  for (size_t i = 0; i < size; i += 4096) {
+    p[i] = '\0';
+  }
+  fprintf(stderr, "Program was not killed despite using more memory than "
+                  "allowed");
+  return 1;
+}
@@ -0,0 +1,24 @@
+/*
+description: Audited syscalls are counted by name, including those made by children
+syscall_audit: [getppid]
+limits:
+  processes: 2
+expect:
+  syscalls:
+    getppid: 3
+*/
+
+#include <sys/syscall.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+int main() {
+  syscall(SYS_getppid);
+  if (fork() == 0) {
+    syscall(SYS_getppid);
+    _exit(0);
+  }
+  wait(NULL);
+  syscall(SYS_getppid);
+  return 0;
+}
@@ -0,0 +1,24 @@
+/*
+description: CPU time is split into user and system time
+expect:
+    cpu_time: 0.2 +- 0.02
+    user_time: 0.1 +- 0.03
+    system_time: 0.1 +- 0.03
+*/
+
+#include <fcntl.h>
+#include <time.h>
+#include <unistd.h>
+
+static char buf[1 << 20];
+
+int main() {
+  while (clock() < CLOCKS_PER_SEC / 10) {
+  }
+  // Reading /dev/zero is spent almost entirely in the kernel clearing the buffer
+  int fd = open("/dev/zero", O_RDONLY);
+  while (clock() < CLOCKS_PER_SEC / 5) {
+    read(fd, buf, sizeof(buf));
+  }
+  return 0;
+}
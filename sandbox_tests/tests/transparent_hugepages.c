@@ -0,0 +1,14 @@
+/*
+description: Transparent hugepages can be disabled
+transparent_hugepages: false
+expect:
+  stdout: "1\n"
+*/
+
+#include <stdio.h>
+#include <sys/prctl.h>
+
+int main() {
+  printf("%d\n", prctl(PR_GET_THP_DISABLE, 0, 0, 0, 0));
+  return 0;
+}
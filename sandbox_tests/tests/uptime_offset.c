@@ -0,0 +1,40 @@
+/*
+description: Clocks should start from the configured offsets
+runs: 2
+monotonic_offset: 1000
+boottime_offset: 86400.5
+expect:
+  matching_stdout: +- 0.1
+*/
+
+#include <stdio.h>
+#include <time.h>
+
+struct {
+  clockid_t clock;
+  double expected;
+} clocks[] = {
+    {CLOCK_MONOTONIC, 1000},       {CLOCK_MONOTONIC_RAW, 1000},
+    {CLOCK_MONOTONIC_COARSE, 1000}, {CLOCK_BOOTTIME, 86400.5},
+    {CLOCK_BOOTTIME_ALARM, 86400.5},
+};
+
+#define N_CLOCKS (sizeof(clocks) / sizeof(clocks[0]))
+
+int main() {
+  for (int i = 0; i < N_CLOCKS; i++) {
+    struct timespec ts;
+    if (clock_gettime(clocks[i].clock, &ts) == -1) {
+      perror("clock_gettime");
+      return 1;
+    }
+    printf("%ld.%09ld\n", ts.tv_sec, ts.tv_nsec);
+    double value = ts.tv_sec + ts.tv_nsec / 1e9;
+    if (!(clocks[i].expected <= value && value <= clocks[i].expected + 0.1)) {
+      fprintf(stderr, "Clock #%d is %f, %f was expected\n", i, value,
+              clocks[i].expected);
+      return 1;
+    }
+  }
+  return 0;
+}
@@ -8,12 +8,20 @@ pub struct CLIArgs {
     pub command: CLICommand,
 }
 
+// Parsed once, so the size of the start options doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(FromArgs)]
 #[argh(subcommand)]
 pub enum CLICommand {
     Isolate(CLIIsolateCommand),
     Free(CLIFreeCommand),
     Start(CLIStartCommand),
+    ImportImage(CLIImportImageCommand),
+    Daemon(CLIDaemonCommand),
+    Grpc(CLIGrpcCommand),
+    Cleanup(CLICleanupCommand),
+    Resume(CLIResumeCommand),
+    Selftest(CLISelftestCommand),
 }
 
 #[derive(FromArgs)]
@@ -34,6 +42,78 @@ pub struct CLIFreeCommand {
     pub core: u64,
 }
 
+#[derive(FromArgs)]
+/// Converts an OCI image or a `docker save` archive to a directory usable as the root environment
+#[argh(subcommand, name = "import-image")]
+pub struct CLIImportImageCommand {
+    /// path to an OCI image layout or a `docker save` result, either a directory or a tarball
+    #[argh(option, short = 'i')]
+    pub image: String,
+
+    /// directory to create
+    #[argh(option, short = 'o')]
+    pub output: String,
+}
+
+#[derive(FromArgs)]
+/// Serves several boxes over stdin/stdout, see README for the protocol
+#[argh(subcommand, name = "daemon")]
+pub struct CLIDaemonCommand {
+    /// the most verbose level of messages printed to stderr: off, error, warn, info, debug, or
+    /// trace. Also applies to the boxes, unless passed to them explicitly
+    #[argh(option, default = "\"warn\".to_string()")]
+    pub log_level: String,
+
+    /// the format of messages printed to stderr: text or json, one object per line
+    #[argh(option, default = "\"text\".to_string()")]
+    pub log_format: String,
+}
+
+#[derive(FromArgs)]
+/// Serves boxes over gRPC on a Unix socket, see proto/sunwalker_box.proto. Needs the grpc feature
+#[argh(subcommand, name = "grpc")]
+pub struct CLIGrpcCommand {
+    /// path to the Unix socket to listen on, which must not exist
+    #[argh(option)]
+    pub listen: String,
+
+    /// the most verbose level of messages printed to stderr: off, error, warn, info, debug, or
+    /// trace. Also applies to the boxes, unless passed to them explicitly
+    #[argh(option, default = "\"warn\".to_string()")]
+    pub log_level: String,
+
+    /// the format of messages printed to stderr: text or json, one object per line
+    #[argh(option, default = "\"text\".to_string()")]
+    pub log_format: String,
+}
+
+#[derive(FromArgs)]
+/// Kills a box and removes what it has left behind, given its state directory
+#[argh(subcommand, name = "cleanup")]
+pub struct CLICleanupCommand {
+    /// the directory passed to `start --state-dir`
+    #[argh(option)]
+    pub state_dir: String,
+}
+
+#[derive(FromArgs)]
+/// Re-attaches stdin and stdout to a running box, given its state directory
+#[argh(subcommand, name = "resume")]
+pub struct CLIResumeCommand {
+    /// the directory passed to `start --state-dir`
+    #[argh(option)]
+    pub state_dir: String,
+}
+
+#[derive(FromArgs)]
+/// Checks that the host supports everything the box needs and prints a JSON report
+#[argh(subcommand, name = "selftest")]
+pub struct CLISelftestCommand {
+    /// also start a box on this core, which must be isolated, and run a program in it
+    #[argh(option, short = 'c')]
+    pub core: Option<u64>,
+}
+
 #[derive(FromArgs, Object)]
 /// Starts a new box
 #[argh(subcommand, name = "start")]
@@ -42,10 +122,122 @@ pub struct CLIStartCommand {
     #[argh(option, short = 'c')]
     pub core: u64,
 
+    /// another core the runs of the box may use, see the 'cpus' option of run. Unlike the main
+    /// core, it must not be isolated with `isolate`, as the box isolates it by itself. May be
+    /// passed several times
+    #[argh(option)]
+    pub extra_core: Vec<u64>,
+
     /// directory to use as new root environment
     #[argh(option, short = 'r', default = "\"/\".to_string()")]
     pub root: String,
 
+    /// directory to stack on top of the root environment, the files in which take precedence. May
+    /// be passed several times, the last layer being the topmost
+    #[argh(option)]
+    pub layer: Vec<String>,
+
+    /// construct the root with overlayfs if the root environment and the layers contain no mount
+    /// points, which is faster than bind-mounting each top-level directory
+    #[argh(switch)]
+    pub overlay_root: bool,
+
+    /// make the root environment writable, storing up to this many bytes of modifications in
+    /// memory until the next reset. The root environment must not contain mount points
+    #[argh(option)]
+    pub writable_root: Option<u64>,
+
+    /// path inside the box to hide behind an empty file or directory, e.g. a directory with private
+    /// keys that came with the image. May be passed several times
+    #[argh(option)]
+    pub mask: Vec<String>,
+
+    /// replace /etc/passwd, /etc/group, /etc/hostname, and /etc/hosts of the image with minimal
+    /// files describing the box
+    #[argh(switch)]
+    pub synthesize_etc: bool,
+
+    /// maximum number of pseudoterminals open at once (default: unlimited)
+    #[argh(option)]
+    pub max_ptys: Option<u64>,
+
+    /// expose /dev/fuse to the box, so that FUSE filesystems can be mounted by setuid helpers like
+    /// fusermount
+    #[argh(switch)]
+    pub allow_fuse: bool,
+
+    /// bring up the loopback interface, so that programs can communicate over 127.0.0.1 and ::1
+    #[argh(switch)]
+    pub loopback: bool,
+
+    /// path to slirp4netns on the host, to give the box outbound access to the hosts passed with
+    /// --allow-host
+    #[argh(option)]
+    pub slirp4netns: Option<String>,
+
+    /// address, CIDR range, or host name the box may connect to via slirp4netns. May be passed
+    /// several times
+    #[argh(option)]
+    pub allow_host: Vec<String>,
+
+    /// bridge on the host to attach a veth pair to, so that the box gets an eth0 interface, e.g.
+    /// to let several boxes talk to each other
+    #[argh(option)]
+    pub veth_bridge: Option<String>,
+
+    /// network namespace of the bridge as a path, e.g. /run/netns/NAME, if it's not that of the
+    /// host
+    #[argh(option)]
+    pub veth_netns: Option<String>,
+
+    /// address of eth0 in the form ADDRESS/PREFIX_LEN, e.g. 10.1.0.2/24
+    #[argh(option)]
+    pub veth_address: Option<String>,
+
+    /// default gateway of the box via eth0
+    #[argh(option)]
+    pub veth_gateway: Option<String>,
+
+    /// bandwidth limit of eth0 in bytes per second, in each direction
+    #[argh(option)]
+    pub veth_rate: Option<u32>,
+
+    /// delay of packets sent over eth0 in seconds, in each direction
+    #[argh(option)]
+    pub veth_delay: Option<f64>,
+
+    /// random variation of the delay in seconds
+    #[argh(option)]
+    pub veth_jitter: Option<f64>,
+
+    /// percentage of packets sent over eth0 to drop, in each direction
+    #[argh(option)]
+    pub veth_loss: Option<f64>,
+
+    /// extra flags of a top-level directory in the form PATH:FLAGS, where FLAGS is a comma-separated
+    /// list of noexec, nodev, and nosuid, e.g. /space:noexec. May be passed several times
+    #[argh(option)]
+    pub mount_flags: Vec<String>,
+
+    /// what to do with setuid and setgid files of the root environment: allow (default), nosuid to
+    /// remount the directories containing them with nosuid, report to only list them, or refuse to
+    /// start
+    #[argh(option, default = "\"allow\".to_string()")]
+    pub setuid_policy: String,
+
+    /// what CLOCK_MONOTONIC reads at the start of each run, in seconds (default: 0)
+    #[argh(option)]
+    pub monotonic_offset: Option<f64>,
+
+    /// what CLOCK_BOOTTIME reads at the start of each run, in seconds, at least the monotonic
+    /// offset (default: the monotonic offset)
+    #[argh(option)]
+    pub boottime_offset: Option<f64>,
+
+    /// path to a Unix socket to accept commands from, in addition to stdin
+    #[argh(option)]
+    pub listen: Option<String>,
+
     /// how much disk space the box may use, in bytes
     #[argh(option, default = "32 * 1024 * 1024")]
     pub quota_space: u64,
@@ -54,14 +246,123 @@ pub struct CLIStartCommand {
     #[argh(option, default = "1024")]
     pub quota_inodes: u64,
 
+    /// an additional tmpfs to mount inside the box, in the format PATH:SIZE:INODES[:MODE], e.g.
+    /// /var/tmp:16777216:256:1777. May be passed several times
+    #[argh(option)]
+    pub tmpfs: Vec<String>,
+
     /// insecure: don't abort preemptively if a non-CLOEXEC file descriptor is found. This should
     /// only be used for benchmarking.
     #[argh(switch)]
     pub ignore_non_cloexec: bool,
+
+    /// directory to keep the state of the box in, so that it can be cleaned up with `cleanup` if
+    /// the box is killed abruptly. Only one box may use a directory at a time
+    #[argh(option)]
+    pub state_dir: Option<String>,
+
+    /// directory on the host to store the outputs of runs with the 'cache' option in, see README.
+    /// May be shared by several boxes
+    #[argh(option)]
+    pub cache_dir: Option<String>,
+
+    /// path to a TOML file with the options of the box, default run options, and volumes, see
+    /// README. Options passed on the command line take precedence
+    #[argh(option)]
+    pub config: Option<String>,
+
+    /// the most verbose level of messages printed to stderr: off, error, warn, info, debug, or
+    /// trace
+    #[argh(option, default = "\"warn\".to_string()")]
+    pub log_level: String,
+
+    /// the format of messages printed to stderr: text or json, one object per line
+    #[argh(option, default = "\"text\".to_string()")]
+    pub log_format: String,
 }
 
 #[cfg(target_os = "linux")]
 pub fn main() {
-    let cli_args = argh::from_env();
+    let args: Vec<String> = std::env::args().collect();
+    let args = expand_config_file(args).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    });
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    // This is what argh::from_env does
+    let cmd = std::path::Path::new(args[0])
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(args[0]);
+    let cli_args = CLIArgs::from_args(&[cmd], &args[1..]).unwrap_or_else(|early_exit| {
+        std::process::exit(match early_exit.status {
+            Ok(()) => {
+                println!("{}", early_exit.output);
+                0
+            }
+            Err(()) => {
+                eprintln!(
+                    "{}\nRun {cmd} --help for more information.",
+                    early_exit.output
+                );
+                1
+            }
+        })
+    });
     crate::linux::entry::main(cli_args);
 }
+
+// The top-level keys of the config file passed to `start --config` are options of `start`, which
+// are inserted into the command line unless already present there. The [run] and [[volume]] tables
+// are handled by the box itself.
+fn expand_config_file(mut args: Vec<String>) -> Result<Vec<String>, String> {
+    if args.get(1).map(|arg| arg.as_str()) != Some("start") {
+        return Ok(args);
+    }
+    let Some(i) = args.iter().position(|arg| arg == "--config") else {
+        return Ok(args);
+    };
+    let path = args.get(i + 1).ok_or("--config requires a path")?;
+
+    let config = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config file {path}: {e}"))?;
+    let config: toml::value::Table =
+        toml::from_str(&config).map_err(|e| format!("Invalid config file {path}: {e}"))?;
+
+    let mut options = Vec::new();
+    for (key, value) in config {
+        if key == "run" || key == "volume" {
+            continue;
+        }
+        let option = format!("--{}", key.replace('_', "-"));
+        let short_option = match key.as_str() {
+            "core" => Some("-c"),
+            "root" => Some("-r"),
+            _ => None,
+        };
+        if args
+            .iter()
+            .any(|arg| *arg == option || Some(arg.as_str()) == short_option)
+        {
+            continue;
+        }
+        let values = match value {
+            toml::Value::Array(array) => array,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => options.push(option.clone()),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(value) => options.extend([option.clone(), value]),
+                toml::Value::Integer(value) => options.extend([option.clone(), value.to_string()]),
+                toml::Value::Float(value) => options.extend([option.clone(), value.to_string()]),
+                _ => return Err(format!("Invalid value of '{key}' in config file {path}")),
+            }
+        }
+    }
+
+    args.splice(2..2, options);
+    Ok(args)
+}
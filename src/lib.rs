@@ -9,22 +9,46 @@
 
 pub mod entry;
 
+#[cfg(target_os = "linux")]
+pub use linux::api::{Box, Config, RunResult, RunSpec, Verdict};
+
+#[cfg(all(target_os = "linux", feature = "python"))]
+mod python;
+
 #[cfg(target_os = "linux")]
 mod linux {
+    pub mod api;
+    mod archive;
+    mod cache;
     mod cgroups;
     mod controller;
+    mod daemon;
+    mod dns;
     pub mod entry;
+    mod exporter;
+    #[cfg(feature = "grpc")]
+    mod grpc;
     mod ids;
+    mod image;
     mod ipc;
+    mod landlock;
+    mod logging;
     mod manager;
     mod mountns;
+    mod network;
     mod procs;
     mod reaper;
     mod rootfs;
     mod running;
     mod sandbox;
+    mod seccomp;
+    mod selftest;
+    mod sha256;
+    mod sparse;
+    mod state;
     mod system;
     mod timens;
     mod tracing;
     mod userns;
+    mod watcher;
 }
@@ -0,0 +1,374 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::{io::OwnedFd, net::UnixStream};
+use std::path::PathBuf;
+use std::process::{Child, Stdio};
+use std::time::Duration;
+
+// A typed interface for Rust judges. The box still lives in a process of its own:
+//
+// - The controller can't run in the process of the judge. It unshares the mount, user, and network
+//   namespaces of its own process, pivots into the root of the box, and becomes the parent of the
+//   reaper in a new PID namespace, none of which can be undone, and then only one box would fit in
+//   a process. Calling the controller directly would therefore still mean forking a process for it
+//   and inventing a way to talk to it.
+// - The process is started from the executable rather than forked from the judge, because the box
+//   needs root: an unprivileged judge points `executable` at a setuid wrapper, which a fork can't
+//   do. Forking would also require the judge to be built around multiprocessing::main, since the
+//   helpers of the box are spawned by re-executing the current executable, and the Python bindings
+//   have no such executable at all.
+//
+// The communication is the versioned JSON protocol, so the library goes through exactly the code
+// that is tested and used by other judges. The judge doesn't see any of it: commands and results
+// are typed here, and payloads are streamed rather than JSON-encoded.
+
+pub struct Config {
+    // Path to the sunwalker_box executable
+    pub executable: PathBuf,
+    pub core: u64,
+    pub root: String,
+    pub quota_space: u64,
+    pub quota_inodes: u64,
+    // Other options of `start`, e.g. ["--layer", "/opt/python"]
+    pub extra_args: Vec<String>,
+}
+
+impl Config {
+    pub fn new(executable: impl Into<PathBuf>, core: u64) -> Self {
+        Self {
+            executable: executable.into(),
+            core,
+            root: "/".to_string(),
+            quota_space: 32 * 1024 * 1024,
+            quota_inodes: 1024,
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+pub struct RunSpec {
+    pub argv: Vec<String>,
+    // What the program sees as argv[0], if not argv[0]
+    pub argv0: Option<String>,
+    pub cwd: Option<String>,
+    pub stdin: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub real_time_limit: Option<Duration>,
+    pub cpu_time_limit: Duration,
+    pub idleness_time_limit: Option<Duration>,
+    pub memory_limit: Option<u64>,
+    // Swapping is disabled if None
+    pub swap_limit: Option<u64>,
+    pub processes_limit: u64,
+    pub output_limit: Option<u64>,
+    // u64::MAX for unlimited
+    pub stack_limit: Option<u64>,
+    // A subset of the cores of the box
+    pub cpus: Option<Vec<u64>>,
+    pub env: Option<HashMap<String, String>>,
+}
+
+impl RunSpec {
+    // The CPU time and process limits are mandatory to prevent DoS
+    pub fn new(argv: Vec<String>, cpu_time_limit: Duration, processes_limit: u64) -> Self {
+        Self {
+            argv,
+            argv0: None,
+            cwd: None,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            real_time_limit: None,
+            cpu_time_limit,
+            idleness_time_limit: None,
+            memory_limit: None,
+            swap_limit: None,
+            processes_limit,
+            output_limit: None,
+            stack_limit: None,
+            cpus: None,
+            env: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    ExitCode(i32),
+    Signaled(i32),
+    // A syscall forbidden by the sandbox was made
+    SecurityViolation,
+    CPUTimeLimitExceeded,
+    RealTimeLimitExceeded,
+    IdlenessTimeLimitExceeded,
+    MemoryLimitExceeded,
+    OutputLimitExceeded,
+    NetworkLimitExceeded,
+}
+
+#[derive(Clone, Debug)]
+pub struct RunResult {
+    pub verdict: Verdict,
+    pub real_time: Duration,
+    pub cpu_time: Duration,
+    pub user_time: Duration,
+    pub system_time: Duration,
+    pub idleness_time: Duration,
+    pub memory: u64,
+    // "memory.peak" or "sampling"
+    pub memory_source: String,
+    pub swap: u64,
+    // Whether the program failed to start a process because of processes_limit
+    pub processes_limit_hit: bool,
+}
+
+pub struct Box {
+    child: Child,
+    input: UnixStream,
+    output: BufReader<UnixStream>,
+    next_id: u64,
+}
+
+impl Box {
+    pub fn new(config: Config) -> Result<Self> {
+        // A socket rather than pipes, so that the box can pass file descriptors
+        let (ours, theirs) = UnixStream::pair().context("Failed to create socket pair")?;
+        let theirs = OwnedFd::from(theirs);
+        let child = std::process::Command::new(&config.executable)
+            .arg("start")
+            .arg("--core")
+            .arg(config.core.to_string())
+            .arg("--root")
+            .arg(&config.root)
+            .arg("--quota-space")
+            .arg(config.quota_space.to_string())
+            .arg("--quota-inodes")
+            .arg(config.quota_inodes.to_string())
+            .args(&config.extra_args)
+            .stdin(Stdio::from(
+                theirs.try_clone().context("Failed to duplicate socket")?,
+            ))
+            .stdout(Stdio::from(theirs))
+            .spawn()
+            .with_context(|| format!("Failed to start {:?}", config.executable))?;
+
+        let mut sandbox = Self {
+            child,
+            input: ours.try_clone().context("Failed to duplicate socket")?,
+            output: BufReader::new(ours),
+            next_id: 0,
+        };
+
+        // This also waits until the box is ready
+        sandbox
+            .input
+            .write_all(b"protocol {\"format\": \"json\", \"version\": 1}\n")
+            .context("Box terminated during startup")?;
+        let reply = sandbox
+            .recv_line()
+            .context("Box terminated during startup")?;
+        if !reply.starts_with("ok") {
+            bail!("Box failed to start: {reply}");
+        }
+        Ok(sandbox)
+    }
+
+    // Sends an arbitrary command, see README for the list
+    pub fn command(&mut self, command: &str, argument: json::JsonValue) -> Result<json::JsonValue> {
+        self.command_with_payload(command, argument, &[])
+    }
+
+    pub fn run(&mut self, spec: &RunSpec) -> Result<RunResult> {
+        let mut argument = json::object! {
+            argv: spec.argv.clone(),
+            cpu_time_limit: spec.cpu_time_limit.as_secs_f64(),
+            processes_limit: spec.processes_limit,
+        };
+        for (name, path) in [
+            ("argv0", &spec.argv0),
+            ("cwd", &spec.cwd),
+            ("stdin", &spec.stdin),
+            ("stdout", &spec.stdout),
+            ("stderr", &spec.stderr),
+        ] {
+            if let Some(path) = path {
+                argument[name] = path.as_str().into();
+            }
+        }
+        if let Some(limit) = spec.real_time_limit {
+            argument["real_time_limit"] = limit.as_secs_f64().into();
+        }
+        if let Some(limit) = spec.idleness_time_limit {
+            argument["idleness_time_limit"] = limit.as_secs_f64().into();
+        }
+        if let Some(limit) = spec.memory_limit {
+            argument["memory_limit"] = limit.into();
+        }
+        if let Some(limit) = spec.swap_limit {
+            argument["swap_limit"] = limit.into();
+        }
+        if let Some(limit) = spec.output_limit {
+            argument["output_limit"] = limit.into();
+        }
+        match spec.stack_limit {
+            Some(u64::MAX) => argument["stack_limit"] = "unlimited".into(),
+            Some(limit) => argument["stack_limit"] = limit.into(),
+            None => {}
+        }
+        if let Some(cpus) = &spec.cpus {
+            argument["cpus"] = cpus.clone().into();
+        }
+        if let Some(env) = &spec.env {
+            let mut object = json::object! {};
+            for (key, value) in env {
+                object[key.as_str()] = value.as_str().into();
+            }
+            argument["env"] = object;
+        }
+
+        let result = self.command("run", argument)?;
+        let exit_code = result["exit_code"]
+            .as_i32()
+            .context("Invalid 'exit_code' in reply")?;
+        let verdict = match result["limit_verdict"].as_str() {
+            _ if result["verdict"]["kind"] == "SecurityViolation" => Verdict::SecurityViolation,
+            Some("OK") => Verdict::ExitCode(exit_code),
+            Some("Signaled") => Verdict::Signaled(-exit_code),
+            Some("CPUTimeLimitExceeded") => Verdict::CPUTimeLimitExceeded,
+            Some("RealTimeLimitExceeded") => Verdict::RealTimeLimitExceeded,
+            Some("IdlenessTimeLimitExceeded") => Verdict::IdlenessTimeLimitExceeded,
+            Some("MemoryLimitExceeded") => Verdict::MemoryLimitExceeded,
+            Some("OutputLimitExceeded") => Verdict::OutputLimitExceeded,
+            Some("NetworkLimitExceeded") => Verdict::NetworkLimitExceeded,
+            _ => bail!("Invalid 'limit_verdict' in reply"),
+        };
+        let duration = |name: &str| -> Result<Duration> {
+            Ok(Duration::from_secs_f64(
+                result[name]
+                    .as_f64()
+                    .with_context(|| format!("Invalid '{name}' in reply"))?,
+            ))
+        };
+        Ok(RunResult {
+            verdict,
+            real_time: duration("real_time")?,
+            cpu_time: duration("cpu_time")?,
+            user_time: duration("user_time")?,
+            system_time: duration("system_time")?,
+            idleness_time: duration("idleness_time")?,
+            memory: result["memory"]
+                .as_u64()
+                .context("Invalid 'memory' in reply")?,
+            memory_source: result["memory_source"]
+                .as_str()
+                .context("Invalid 'memory_source' in reply")?
+                .to_string(),
+            swap: result["swap"].as_u64().context("Invalid 'swap' in reply")?,
+            processes_limit_hit: result["processes_limit_hit"]
+                .as_bool()
+                .context("Invalid 'processes_limit_hit' in reply")?,
+        })
+    }
+
+    pub fn reset(&mut self) -> Result<()> {
+        self.command("reset", json::JsonValue::Null)?;
+        Ok(())
+    }
+
+    pub fn mkdir(&mut self, path: &str) -> Result<()> {
+        self.command("mkdir", path.into())?;
+        Ok(())
+    }
+
+    pub fn write_file(&mut self, path: &str, content: &[u8]) -> Result<()> {
+        self.command_with_payload(
+            "write",
+            json::object! { path: path, len: content.len() },
+            content,
+        )?;
+        Ok(())
+    }
+
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>> {
+        let result = self.command(
+            "cat",
+            json::object! { path: path, at: 0, len: 0, raw: true },
+        )?;
+        let len = result["len"].as_u64().context("Invalid 'len' in reply")?;
+        let mut content = Vec::new();
+        self.output
+            .by_ref()
+            .take(len)
+            .read_to_end(&mut content)
+            .context("Failed to receive file")?;
+        if content.len() as u64 != len {
+            bail!("Box terminated");
+        }
+        Ok(content)
+    }
+
+    pub fn bind(&mut self, external: &str, internal: &str, ro: bool) -> Result<()> {
+        self.command(
+            "bind",
+            json::object! { external: external, internal: internal, ro: ro },
+        )?;
+        Ok(())
+    }
+
+    fn command_with_payload(
+        &mut self,
+        command: &str,
+        argument: json::JsonValue,
+        payload: &[u8],
+    ) -> Result<json::JsonValue> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json::object! {
+            version: 1,
+            id: id,
+            command: command,
+            argument: argument,
+        };
+        self.input
+            .write_all(format!("{}\n", request.dump()).as_bytes())
+            .and_then(|()| self.input.write_all(payload))
+            .context("Box terminated")?;
+
+        let mut reply = json::parse(&self.recv_line()?).context("Invalid reply")?;
+        if reply["id"] != id {
+            bail!("Reply to an unexpected request");
+        }
+        if reply["status"] == "ok" {
+            Ok(reply["result"].take())
+        } else {
+            Err(anyhow!(
+                "{}",
+                reply["error"].as_str().context("Invalid reply")?
+            ))
+        }
+    }
+
+    fn recv_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self
+            .output
+            .read_line(&mut line)
+            .context("Failed to receive reply")?
+            == 0
+        {
+            bail!("Box terminated");
+        }
+        line.pop();
+        Ok(line)
+    }
+}
+
+impl Drop for Box {
+    fn drop(&mut self) {
+        // The box terminates when its stdin is closed
+        let _ = self.input.shutdown(std::net::Shutdown::Both);
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,674 @@
+use crate::linux::rootfs;
+use anyhow::{bail, Context, Result};
+use nix::{
+    libc,
+    sys::{
+        stat::{utimensat, Mode, UtimensatFlags},
+        time::TimeSpec,
+    },
+    unistd::mkfifo,
+};
+use rand::Rng;
+use std::ffi::OsStr;
+use std::fs::{File, Metadata, Permissions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::{
+    ffi::OsStrExt,
+    fs::{FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt},
+};
+use std::path::{Component, Path, PathBuf};
+
+// A minimal writer of tar archives in the GNU format. Only the features that the box can produce
+// are supported: regular files, directories, symlinks, FIFOs, and device nodes. Paths longer than
+// the 100 bytes allowed by the header are stored in GNU long name records, which are understood by
+// all widespread implementations. Ownership is not stored, as it is meaningless outside the box.
+//
+// The reader accepts the ustar, GNU, and pax flavors, which covers archives produced by GNU tar,
+// bsdtar, and Python's tarfile. The archives come from the judge, but they are unpacked into a
+// directory that the user program controls, so paths are resolved the same way as the paths from
+// the protocol, and nothing that points outside of the unpacked directory is ever created.
+//
+// Manifests are a simpler alternative to archives for judges that don't want to build tar files:
+// a list of entries in JSON accompanied by the concatenated contents of the regular files.
+
+const BLOCK_SIZE: usize = 512;
+
+pub fn write_tar(root: &Path, out: &mut impl Write) -> Result<()> {
+    let metadata =
+        std::fs::symlink_metadata(root).with_context(|| format!("Failed to stat {root:?}"))?;
+    if !metadata.is_dir() {
+        bail!("The passed path does not refer to a directory");
+    }
+    write_directory(root, b"", out)?;
+    // Two zero blocks mark the end of the archive
+    out.write_all(&[0; 2 * BLOCK_SIZE])
+        .context("Failed to write archive")?;
+    Ok(())
+}
+
+fn write_directory(path: &Path, prefix: &[u8], out: &mut impl Write) -> Result<()> {
+    let mut entries = std::fs::read_dir(path)
+        .with_context(|| format!("Failed to read directory {path:?}"))?
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read directory {path:?}"))?;
+    // Make the archive reproducible
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let entry_path = entry.path();
+        let name = [prefix, entry.file_name().as_bytes()].concat();
+        let metadata = entry
+            .metadata()
+            .with_context(|| format!("Failed to stat {entry_path:?}"))?;
+        let file_type = metadata.file_type();
+
+        if file_type.is_dir() {
+            let name = [&name[..], b"/"].concat();
+            write_header(out, &name, b'5', &metadata, 0, b"")?;
+            write_directory(&entry_path, &name, out)?;
+        } else if file_type.is_file() {
+            write_file(&entry_path, &name, &metadata, out)?;
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(&entry_path)
+                .with_context(|| format!("Failed to read symlink {entry_path:?}"))?;
+            write_header(
+                out,
+                &name,
+                b'2',
+                &metadata,
+                0,
+                target.as_os_str().as_bytes(),
+            )?;
+        } else if file_type.is_fifo() {
+            write_header(out, &name, b'6', &metadata, 0, b"")?;
+        } else if file_type.is_char_device() {
+            write_header(out, &name, b'3', &metadata, 0, b"")?;
+        } else if file_type.is_block_device() {
+            write_header(out, &name, b'4', &metadata, 0, b"")?;
+        }
+        // Sockets cannot be archived and are useless without the program that created them
+    }
+
+    Ok(())
+}
+
+fn write_file(path: &Path, name: &[u8], metadata: &Metadata, out: &mut impl Write) -> Result<()> {
+    // Don't follow symlinks in case the file was replaced after listing
+    let file = File::options()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+        .with_context(|| format!("Failed to open {path:?}"))?;
+
+    let size = metadata.len();
+    write_header(out, name, b'0', metadata, size, b"")?;
+
+    // The size is already fixed in the header, so truncate or pad the contents if the file was
+    // modified in the meantime
+    let copied = std::io::copy(&mut file.take(size), out)
+        .with_context(|| format!("Failed to archive {path:?}"))?;
+    write_zeroes(out, size - copied)?;
+    write_padding(out, size)?;
+    Ok(())
+}
+
+fn write_header(
+    out: &mut impl Write,
+    name: &[u8],
+    type_flag: u8,
+    metadata: &Metadata,
+    size: u64,
+    link_name: &[u8],
+) -> Result<()> {
+    if name.len() > 100 {
+        write_long_name(out, b'L', name)?;
+    }
+    if link_name.len() > 100 {
+        write_long_name(out, b'K', link_name)?;
+    }
+
+    let mut header = [0u8; BLOCK_SIZE];
+    put_bytes(&mut header[0..100], name);
+    put_number(&mut header[100..108], (metadata.mode() & 0o7777) as u64);
+    put_number(&mut header[108..116], 0);
+    put_number(&mut header[116..124], 0);
+    put_number(&mut header[124..136], size);
+    put_number(&mut header[136..148], metadata.mtime().max(0) as u64);
+    header[156] = type_flag;
+    put_bytes(&mut header[157..257], link_name);
+    if type_flag == b'3' || type_flag == b'4' {
+        let rdev = metadata.rdev();
+        put_number(&mut header[329..337], nix::sys::stat::major(rdev));
+        put_number(&mut header[337..345], nix::sys::stat::minor(rdev));
+    }
+    finish_header(&mut header);
+
+    out.write_all(&header).context("Failed to write archive")?;
+    Ok(())
+}
+
+fn write_long_name(out: &mut impl Write, type_flag: u8, name: &[u8]) -> Result<()> {
+    let size = name.len() as u64 + 1;
+
+    let mut header = [0u8; BLOCK_SIZE];
+    put_bytes(&mut header[0..100], b"././@LongLink");
+    put_number(&mut header[100..108], 0o644);
+    put_number(&mut header[108..116], 0);
+    put_number(&mut header[116..124], 0);
+    put_number(&mut header[124..136], size);
+    put_number(&mut header[136..148], 0);
+    header[156] = type_flag;
+    finish_header(&mut header);
+
+    out.write_all(&header).context("Failed to write archive")?;
+    out.write_all(name).context("Failed to write archive")?;
+    write_zeroes(out, 1)?;
+    write_padding(out, size)?;
+    Ok(())
+}
+
+fn finish_header(header: &mut [u8; BLOCK_SIZE]) {
+    header[257..265].copy_from_slice(b"ustar  \0");
+    // The checksum is computed as if the checksum field was filled with spaces
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|&byte| byte as u32).sum();
+    let checksum = format!("{checksum:06o}\0 ");
+    header[148..156].copy_from_slice(checksum.as_bytes());
+}
+
+fn put_bytes(field: &mut [u8], value: &[u8]) {
+    // Longer values are stored in a separate record, so truncation is fine here
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value[..len]);
+}
+
+fn put_number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    if value < 1 << (3 * digits) {
+        let value = format!("{value:0digits$o}");
+        field[..digits].copy_from_slice(value.as_bytes());
+        field[digits] = 0;
+    } else {
+        // GNU extension: big-endian base-256 with the top bit set
+        field.fill(0);
+        let len = field.len();
+        field[len - 8..].copy_from_slice(&value.to_be_bytes());
+        field[0] |= 0x80;
+    }
+}
+
+fn write_zeroes(out: &mut impl Write, count: u64) -> Result<()> {
+    std::io::copy(&mut std::io::repeat(0).take(count), out).context("Failed to write archive")?;
+    Ok(())
+}
+
+fn write_padding(out: &mut impl Write, size: u64) -> Result<()> {
+    let block_size = BLOCK_SIZE as u64;
+    write_zeroes(out, (block_size - size % block_size) % block_size)
+}
+
+pub fn extract_tar(input: &mut impl Read, target: &Path) -> Result<()> {
+    unpack_atomically(target, |staging| unpack(input, staging))
+}
+
+pub enum ManifestEntryKind {
+    Dir,
+    File { len: u64 },
+    Symlink { target: Vec<u8> },
+}
+
+pub struct ManifestEntry {
+    pub path: Vec<u8>,
+    pub kind: ManifestEntryKind,
+    pub mode: Option<u32>,
+    pub owner: Option<(u32, u32)>,
+}
+
+pub fn extract_manifest(
+    entries: &[ManifestEntry],
+    input: &mut impl Read,
+    target: &Path,
+) -> Result<()> {
+    unpack_atomically(target, |staging| unpack_manifest(entries, input, staging))
+}
+
+pub fn unpack_atomically(target: &Path, unpack: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    if std::fs::symlink_metadata(target).is_ok() {
+        bail!("File {target:?} already exists");
+    }
+    let parent = target.parent().context("Invalid target path")?;
+
+    // Unpack to a temporary directory and then move it into place, so that no one observes a
+    // partially unpacked tree
+    let mut rng = rand::thread_rng();
+    let id: String = (0..10)
+        .map(|_| rng.sample(rand::distributions::Alphanumeric) as char)
+        .collect();
+    let staging = parent.join(format!(".untar-{id}"));
+    std::fs::create_dir(&staging).with_context(|| format!("Failed to mkdir {staging:?}"))?;
+
+    let result: Result<()> = try {
+        unpack(&staging)?;
+        std::fs::rename(&staging, target)
+            .with_context(|| format!("Failed to move unpacked tree to {target:?}"))?;
+    };
+    if result.is_err() {
+        // Release the disk quota. The original error is more important than a failure here
+        let _ = std::fs::remove_dir_all(&staging);
+    }
+    result
+}
+
+fn unpack_manifest(entries: &[ManifestEntry], input: &mut impl Read, root: &Path) -> Result<()> {
+    let mut directories = Vec::new();
+
+    for entry in entries {
+        let Some(path) = resolve_entry(root, &entry.path)? else {
+            // The root directory itself
+            if let ManifestEntryKind::Dir = entry.kind {
+                directories.push((root.to_path_buf(), entry));
+                continue;
+            }
+            bail!("Only a directory can be put at the root of the tree");
+        };
+
+        match &entry.kind {
+            ManifestEntryKind::Dir => {
+                std::fs::create_dir(&path).with_context(|| format!("Failed to mkdir {path:?}"))?;
+                directories.push((path, entry));
+            }
+            ManifestEntryKind::File { len } => {
+                let mut file = File::options()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .with_context(|| format!("Failed to create {path:?}"))?;
+                let copied = std::io::copy(&mut input.by_ref().take(*len), &mut file)
+                    .with_context(|| format!("Failed to write {path:?}"))?;
+                if copied < *len {
+                    bail!("Unexpected EOF in payload");
+                }
+                if let Some((uid, gid)) = entry.owner {
+                    std::os::unix::fs::fchown(&file, Some(uid), Some(gid))
+                        .with_context(|| format!("Failed to chown {path:?}"))?;
+                }
+                if let Some(mode) = entry.mode {
+                    file.set_permissions(Permissions::from_mode(mode))
+                        .with_context(|| format!("Failed to chmod {path:?}"))?;
+                }
+            }
+            ManifestEntryKind::Symlink { target } => {
+                ensure_symlink_inside(root, &path, target)?;
+                std::os::unix::fs::symlink(OsStr::from_bytes(target), &path)
+                    .with_context(|| format!("Failed to create symlink {path:?}"))?;
+                if let Some((uid, gid)) = entry.owner {
+                    std::os::unix::fs::lchown(&path, Some(uid), Some(gid))
+                        .with_context(|| format!("Failed to chown {path:?}"))?;
+                }
+            }
+        }
+    }
+
+    // Same as with tar, a read-only directory would prevent populating it
+    for (path, entry) in directories.into_iter().rev() {
+        if let Some((uid, gid)) = entry.owner {
+            std::os::unix::fs::chown(&path, Some(uid), Some(gid))
+                .with_context(|| format!("Failed to chown {path:?}"))?;
+        }
+        if let Some(mode) = entry.mode {
+            std::fs::set_permissions(&path, Permissions::from_mode(mode))
+                .with_context(|| format!("Failed to chmod {path:?}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct Overrides {
+    name: Option<Vec<u8>>,
+    link_name: Option<Vec<u8>>,
+    size: Option<u64>,
+}
+
+pub struct EntryHeader {
+    pub type_flag: u8,
+    pub name: Vec<u8>,
+    pub link_name: Vec<u8>,
+    pub size: u64,
+    pub mode: u32,
+    pub mtime: i64,
+}
+
+// Reads the header of the next entry, handling long name and pax records. The caller is expected
+// to consume the data of the entry with skip_data or by reading exactly `size` bytes followed by
+// skip_padding
+pub fn read_entry_header(input: &mut impl Read) -> Result<Option<EntryHeader>> {
+    let mut overrides = Overrides::default();
+
+    loop {
+        let mut header = [0u8; BLOCK_SIZE];
+        input
+            .read_exact(&mut header)
+            .context("Unexpected end of archive")?;
+        if header.iter().all(|&byte| byte == 0) {
+            // The end marker is actually two zero blocks, but some writers only emit one
+            return Ok(None);
+        }
+        verify_checksum(&header)?;
+
+        let type_flag = header[156];
+        let size = get_number(&header[124..136])?;
+        match type_flag {
+            b'L' => {
+                overrides.name = Some(read_string(input, size)?);
+                continue;
+            }
+            b'K' => {
+                overrides.link_name = Some(read_string(input, size)?);
+                continue;
+            }
+            b'x' => {
+                parse_pax(&read_data(input, size)?, &mut overrides)?;
+                continue;
+            }
+            b'g' => {
+                // Global pax headers only contain metadata we ignore anyway
+                skip_data(input, size)?;
+                continue;
+            }
+            _ => {}
+        }
+
+        return Ok(Some(EntryHeader {
+            type_flag,
+            name: overrides.name.unwrap_or_else(|| header_name(&header)),
+            link_name: overrides
+                .link_name
+                .unwrap_or_else(|| get_bytes(&header[157..257]).to_vec()),
+            size: overrides.size.unwrap_or(size),
+            mode: get_number(&header[100..108])? as u32 & 0o7777,
+            mtime: get_number(&header[136..148])? as i64,
+        }));
+    }
+}
+
+fn unpack(input: &mut impl Read, root: &Path) -> Result<()> {
+    let mut directories = Vec::new();
+
+    while let Some(entry) = read_entry_header(input)? {
+        let EntryHeader {
+            type_flag,
+            name,
+            link_name,
+            size,
+            mode,
+            mtime,
+        } = entry;
+        // Don't let the judge create setuid files by accident
+        let mode = mode & 0o777;
+
+        let Some(path) = resolve_entry(root, &name)? else {
+            // The root directory itself, usually stored as "./"
+            if type_flag == b'5' {
+                directories.push((root.to_path_buf(), mode, mtime));
+            }
+            skip_data(input, size)?;
+            continue;
+        };
+
+        match type_flag {
+            b'0' | b'\0' | b'7' => {
+                // Don't follow symlinks created by previous entries
+                let mut file = File::options()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .custom_flags(libc::O_NOFOLLOW)
+                    .open(&path)
+                    .with_context(|| format!("Failed to create {path:?}"))?;
+                let copied = std::io::copy(&mut input.take(size), &mut file)
+                    .with_context(|| format!("Failed to write {path:?}"))?;
+                if copied < size {
+                    bail!("Unexpected end of archive");
+                }
+                skip_padding(input, size)?;
+                file.set_permissions(Permissions::from_mode(mode))
+                    .with_context(|| format!("Failed to chmod {path:?}"))?;
+                set_mtime(&path, mtime)?;
+            }
+            b'1' => {
+                let source = resolve_entry(root, &link_name)?
+                    .with_context(|| format!("Invalid hard link target of {path:?}"))?;
+                std::fs::hard_link(&source, &path)
+                    .with_context(|| format!("Failed to link {path:?} to {source:?}"))?;
+                skip_data(input, size)?;
+            }
+            b'2' => {
+                ensure_symlink_inside(root, &path, &link_name)?;
+                std::os::unix::fs::symlink(OsStr::from_bytes(&link_name), &path)
+                    .with_context(|| format!("Failed to create symlink {path:?}"))?;
+                set_mtime(&path, mtime)?;
+                skip_data(input, size)?;
+            }
+            b'5' => {
+                match std::fs::create_dir(&path) {
+                    Ok(()) => {}
+                    Err(e)
+                        if e.kind() == ErrorKind::AlreadyExists
+                            && std::fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir()) => {}
+                    Err(e) => {
+                        return Err(e).with_context(|| format!("Failed to mkdir {path:?}"));
+                    }
+                }
+                directories.push((path, mode, mtime));
+                skip_data(input, size)?;
+            }
+            b'6' => {
+                mkfifo(&path, Mode::from_bits_truncate(mode))
+                    .with_context(|| format!("Failed to mkfifo {path:?}"))?;
+                std::fs::set_permissions(&path, Permissions::from_mode(mode))
+                    .with_context(|| format!("Failed to chmod {path:?}"))?;
+                set_mtime(&path, mtime)?;
+                skip_data(input, size)?;
+            }
+            _ => bail!(
+                "Unsupported type {:?} of archive entry {path:?}",
+                type_flag as char
+            ),
+        }
+    }
+
+    // Creating files changes the mtime of the directory, and making a directory read-only early
+    // would prevent unpacking into it, so handle directories last, innermost first
+    for (path, mode, mtime) in directories.into_iter().rev() {
+        std::fs::set_permissions(&path, Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to chmod {path:?}"))?;
+        set_mtime(&path, mtime)?;
+    }
+
+    Ok(())
+}
+
+fn resolve_entry(root: &Path, name: &[u8]) -> Result<Option<PathBuf>> {
+    let name = Path::new(OsStr::from_bytes(name));
+
+    let mut parts = Vec::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => {}
+            _ => bail!("Archive entry {name:?} points outside of the archive"),
+        }
+    }
+    let Some(file_name) = parts.pop() else {
+        return Ok(None);
+    };
+
+    // Previous entries may have created symlinks, so resolve the parent directory just like any
+    // other path in the box and make sure it did not escape
+    let mut parent = Path::new("/").join(
+        root.strip_prefix("/newroot")
+            .context("Archive is unpacked outside of the box")?,
+    );
+    parent.extend(parts);
+    let parent = rootfs::resolve_abs_box_root(parent)?;
+    if !parent.starts_with(root) {
+        bail!("Archive entry {name:?} points outside of the archive");
+    }
+    Ok(Some(parent.join(file_name)))
+}
+
+fn ensure_symlink_inside(root: &Path, path: &Path, target: &[u8]) -> Result<()> {
+    let target = Path::new(OsStr::from_bytes(target));
+
+    // Only allow '..' at the beginning of the target. The parent directory of the symlink is
+    // resolved already, so this check is exact, and the rest of the target can only descend
+    // through directories and symlinks that have already been verified
+    let mut depth = path.strip_prefix(root).unwrap().components().count() - 1;
+    let mut descended = false;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => descended = true,
+            Component::CurDir => {}
+            Component::ParentDir if !descended && depth > 0 => depth -= 1,
+            _ => bail!("Symlink {path:?} -> {target:?} points outside of the archive"),
+        }
+    }
+    Ok(())
+}
+
+pub fn set_mtime(path: &Path, mtime: i64) -> Result<()> {
+    let mtime = TimeSpec::new(mtime, 0);
+    utimensat(None, path, &mtime, &mtime, UtimensatFlags::NoFollowSymlink)
+        .with_context(|| format!("Failed to set mtime of {path:?}"))?;
+    Ok(())
+}
+
+fn header_name(header: &[u8; BLOCK_SIZE]) -> Vec<u8> {
+    let name = get_bytes(&header[0..100]);
+    // Only POSIX ustar has a prefix field, GNU stores other data there
+    let prefix = get_bytes(&header[345..500]);
+    if &header[257..263] == b"ustar\0" && !prefix.is_empty() {
+        [prefix, b"/", name].concat()
+    } else {
+        name.to_vec()
+    }
+}
+
+fn verify_checksum(header: &[u8; BLOCK_SIZE]) -> Result<()> {
+    let expected = get_number(&header[148..156])?;
+    // The checksum is computed as if the checksum field was filled with spaces
+    let mut header = *header;
+    header[148..156].fill(b' ');
+    let unsigned: u64 = header.iter().map(|&byte| byte as u64).sum();
+    // Some ancient implementations computed the checksum over signed bytes
+    let signed: i64 = header.iter().map(|&byte| byte as i8 as i64).sum();
+    if expected != unsigned && expected as i64 != signed {
+        bail!("Invalid checksum of archive header");
+    }
+    Ok(())
+}
+
+fn parse_pax(data: &[u8], overrides: &mut Overrides) -> Result<()> {
+    // Each record is "<length> <key>=<value>\n", where the length includes itself
+    let mut data = data;
+    while !data.is_empty() {
+        let space = data
+            .iter()
+            .position(|&byte| byte == b' ')
+            .context("Invalid pax header")?;
+        let len: usize = std::str::from_utf8(&data[..space])
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .context("Invalid pax header")?;
+        if len <= space || len > data.len() || data[len - 1] != b'\n' {
+            bail!("Invalid pax header");
+        }
+        let record = &data[space + 1..len - 1];
+        data = &data[len..];
+
+        let equals = record
+            .iter()
+            .position(|&byte| byte == b'=')
+            .context("Invalid pax header")?;
+        let (key, value) = (&record[..equals], &record[equals + 1..]);
+        match key {
+            b"path" => overrides.name = Some(value.to_vec()),
+            b"linkpath" => overrides.link_name = Some(value.to_vec()),
+            b"size" => {
+                overrides.size = Some(
+                    std::str::from_utf8(value)
+                        .ok()
+                        .and_then(|size| size.parse().ok())
+                        .context("Invalid size in pax header")?,
+                )
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn get_bytes(field: &[u8]) -> &[u8] {
+    match field.iter().position(|&byte| byte == 0) {
+        Some(len) => &field[..len],
+        None => field,
+    }
+}
+
+fn get_number(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 != 0 {
+        // GNU extension: big-endian base-256
+        let mut value: u64 = (field[0] & 0x7f) as u64;
+        for &byte in &field[1..] {
+            value = value
+                .checked_mul(256)
+                .context("Too big number in archive header")?
+                | byte as u64;
+        }
+        return Ok(value);
+    }
+    let digits = std::str::from_utf8(get_bytes(field))
+        .context("Invalid number in archive header")?
+        .trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).context("Invalid number in archive header")
+}
+
+fn read_data(input: &mut impl Read, size: u64) -> Result<Vec<u8>> {
+    if size > 1 << 20 {
+        bail!("Too long archive header");
+    }
+    let mut data = vec![0u8; size as usize];
+    input
+        .read_exact(&mut data)
+        .context("Unexpected end of archive")?;
+    skip_padding(input, size)?;
+    Ok(data)
+}
+
+fn read_string(input: &mut impl Read, size: u64) -> Result<Vec<u8>> {
+    Ok(get_bytes(&read_data(input, size)?).to_vec())
+}
+
+pub fn skip_data(input: &mut impl Read, size: u64) -> Result<()> {
+    skip_bytes(input, size)?;
+    skip_padding(input, size)
+}
+
+pub fn skip_padding(input: &mut impl Read, size: u64) -> Result<()> {
+    let block_size = BLOCK_SIZE as u64;
+    skip_bytes(input, (block_size - size % block_size) % block_size)
+}
+
+fn skip_bytes(input: &mut impl Read, count: u64) -> Result<()> {
+    let skipped = std::io::copy(&mut input.take(count), &mut std::io::sink())
+        .context("Failed to read archive")?;
+    if skipped < count {
+        bail!("Unexpected end of archive");
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use crate::linux::{archive, rootfs};
+use anyhow::{bail, Context, Result};
+use rand::Rng;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// A host-side store of the directories produced by runs, e.g. compiled checkers and interactors,
+// so that identical sources are built once rather than for every submission. The judge computes
+// the key, typically from the hashes of the sources and the compiler flags, so the box never has
+// to know what a build depends on. An entry consists of KEY.json, the result of the run, and
+// KEY.tar, the archive of the directory. The archive is moved into place last, so an entry without
+// it is ignored, and several boxes can share a store.
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+pub struct CachedRun {
+    pub key: String,
+    // A directory inside the box
+    pub output: String,
+}
+
+impl CachedRun {
+    pub fn parse(arg: &json::JsonValue) -> Result<Self> {
+        let key = arg["key"]
+            .as_str()
+            .context("Invalid 'cache.key' argument")?;
+        if key.is_empty()
+            || key.len() > 128
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            bail!("Cache key must be a string of at most 128 letters, digits, '-', and '_'");
+        }
+        let output = arg["output"]
+            .as_str()
+            .context("Invalid 'cache.output' argument")?;
+        Ok(Self {
+            key: key.to_string(),
+            output: output.to_string(),
+        })
+    }
+}
+
+impl Cache {
+    // The directory is a path on the host
+    pub fn open(dir: &str) -> Result<Self> {
+        let dir = rootfs::resolve_abs_old_root(dir)
+            .with_context(|| format!("Failed to resolve {dir}"))?;
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to mkdir -p {dir:?}"))?;
+        Ok(Self { dir })
+    }
+
+    fn entry_path(&self, key: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{key}.{extension}"))
+    }
+
+    // Restores the output directory and returns the result of the run if the entry exists
+    pub fn lookup(&self, run: &CachedRun) -> Result<Option<json::JsonValue>> {
+        let tar_path = self.entry_path(&run.key, "tar");
+        let mut archive = match std::fs::File::open(&tar_path) {
+            Ok(archive) => std::io::BufReader::new(archive),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to open {tar_path:?}")),
+        };
+        let json_path = self.entry_path(&run.key, "json");
+        let result = std::fs::read_to_string(&json_path)
+            .with_context(|| format!("Failed to read {json_path:?}"))?;
+        let result =
+            json::parse(&result).with_context(|| format!("Invalid cache entry {json_path:?}"))?;
+        archive::extract_tar(&mut archive, &rootfs::resolve_abs_box_root(&run.output)?)
+            .with_context(|| format!("Failed to restore {}", run.output))?;
+        Ok(Some(result))
+    }
+
+    pub fn store(&self, run: &CachedRun, result: &json::JsonValue) -> Result<()> {
+        let source = rootfs::resolve_abs_box_root(&run.output)?;
+        write_atomically(&self.entry_path(&run.key, "json"), |file| {
+            file.write_all(result.dump().as_bytes())
+                .context("Failed to write result")
+        })?;
+        write_atomically(&self.entry_path(&run.key, "tar"), |file| {
+            let mut writer = std::io::BufWriter::new(file);
+            archive::write_tar(&source, &mut writer)?;
+            writer.flush().context("Failed to write archive")
+        })
+    }
+}
+
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut std::fs::File) -> Result<()>,
+) -> Result<()> {
+    // Another box might be storing the same entry at the same time
+    let mut rng = rand::thread_rng();
+    let id: String = (0..10)
+        .map(|_| rng.sample(rand::distributions::Alphanumeric) as char)
+        .collect();
+    let tmp_path = path.with_extension(format!("tmp-{id}"));
+
+    let result: Result<()> = try {
+        let mut file = std::fs::File::create(&tmp_path)
+            .with_context(|| format!("Failed to create {tmp_path:?}"))?;
+        write(&mut file)?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("Failed to rename {tmp_path:?} to {path:?}"))?;
+    };
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
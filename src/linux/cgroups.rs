@@ -10,37 +10,57 @@ use std::time::Duration;
 #[derive(Object)]
 pub struct Cgroup {
     core_cgroup_fd: openat::Dir,
+    // MAJ:MIN of the block devices to apply I/O limits to; sysfs is unavailable inside the box
+    disks: Vec<String>,
 }
 
 #[derive(Object)]
 pub struct ProcCgroup {
     core_cgroup_fd: openat::Dir,
     id: String,
+    disks: Vec<String>,
 }
 
 pub struct BoxCgroup {
     proc_cgroup_fd: openat::Dir,
     box_id: String,
+    disks: Vec<String>,
     dropped: bool,
 }
 
+// Per device, None means unlimited
+#[derive(Clone, Default, Object)]
+pub struct IoLimit {
+    pub read_bps: Option<u64>,
+    pub write_bps: Option<u64>,
+    pub read_iops: Option<u64>,
+    pub write_iops: Option<u64>,
+}
+
 impl Cgroup {
-    pub fn new(core: u64) -> Result<Self> {
+    // The extra cores are shared by the runs of the box, see the 'cpus' option of run
+    pub fn new(core: u64, extra_cores: &[u64]) -> Result<Self> {
         // Enabling controllers globally is necessary on some systems, e.g. WSL
         std::fs::write(
             "/sys/fs/cgroup/cgroup.subtree_control",
             "+cpu +cpuset +memory +pids",
         )
         .context("Failed to enable cgroup controllers")?;
+        // The io controller is optional, as it's only necessary for I/O limits
+        let has_io = std::fs::write("/sys/fs/cgroup/cgroup.subtree_control", "+io").is_ok();
 
         // Create a cgroup for the core; it must be an immediate child of the root cgroup
         let dir = format!("/sys/fs/cgroup/sunwalker-box-core-{core}");
         std::fs::create_dir_all(&dir).with_context(|| format!("Failed to mkdir {dir}"))?;
         std::fs::write(
             format!("{dir}/cgroup.subtree_control"),
-            "+cpu +memory +pids",
+            "+cpu +cpuset +memory +pids",
         )
         .context("Failed to enable cgroup controllers")?;
+        if has_io {
+            std::fs::write(format!("{dir}/cgroup.subtree_control"), "+io")
+                .context("Failed to enable io cgroup controller")?;
+        }
 
         // sysfs is unavailable inside the box, so we have to acquire a file descriptor
         let core_cgroup_fd = openat::Dir::open(&dir).context("Failed to open cgroup directory")?;
@@ -52,8 +72,12 @@ impl Cgroup {
         )
         .with_context(|| format!("Failed to chown {dir}"))?;
 
-        // Set core
-        std::fs::write(format!("{dir}/cpuset.cpus"), format!("{core}\n"))
+        // Set cores
+        let mut cpus = core.to_string();
+        for extra_core in extra_cores {
+            cpus += &format!(",{extra_core}");
+        }
+        std::fs::write(format!("{dir}/cpuset.cpus"), format!("{cpus}\n"))
             .with_context(|| format!("Failed to write to {dir}/cpuset.cpus"))?;
         std::fs::write(format!("{dir}/cpuset.cpus.partition"), "root\n")
             .context("Failed to switch partition type to 'root'")?;
@@ -69,7 +93,10 @@ impl Cgroup {
             Some(ids::EXTERNAL_ROOT_GID),
         )?;
 
-        Ok(Self { core_cgroup_fd })
+        Ok(Self {
+            core_cgroup_fd,
+            disks: list_disks().context("Failed to list block devices")?,
+        })
     }
 
     pub fn add_self_as_manager(&self) -> Result<()> {
@@ -96,8 +123,13 @@ impl Cgroup {
             .with_context(|| {
                 format!("Failed to open proc-{id}/cgroup.subtree_control for writing")
             })?
-            .write(b"+cpu +memory +pids\n")
+            .write(b"+cpu +cpuset +memory +pids\n")
             .context("Failed to enable cgroup controllers")?;
+        // Fails if the io controller is unavailable, which only matters if I/O limits are used
+        let _ = self
+            .core_cgroup_fd
+            .write_file(format!("proc-{id}/cgroup.subtree_control"), 0o700)
+            .and_then(|mut file| file.write(b"+io\n"));
 
         nix::unistd::fchownat::<str>(
             Some(self.core_cgroup_fd.as_raw_fd()),
@@ -111,6 +143,7 @@ impl Cgroup {
         Ok(ProcCgroup {
             core_cgroup_fd: try_clone_dirat(&self.core_cgroup_fd)?,
             id,
+            disks: self.disks.clone(),
         })
     }
 }
@@ -134,10 +167,15 @@ impl ProcCgroup {
                 .sub_dir(format!("proc-{}", self.id))
                 .with_context(|| format!("Failed to open proc-{}", self.id))?,
             box_id,
+            disks: self.disks.clone(),
             dropped: false,
         })
     }
 
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     pub fn destroy(self) -> Result<()> {
         remove_cgroup(&self.core_cgroup_fd, format!("proc-{}", self.id).as_ref())
     }
@@ -146,11 +184,17 @@ impl ProcCgroup {
         Ok(ProcCgroup {
             core_cgroup_fd: try_clone_dirat(&self.core_cgroup_fd)?,
             id: self.id.clone(),
+            disks: self.disks.clone(),
         })
     }
 }
 
 impl BoxCgroup {
+    // Relative to the cgroup of the core
+    pub fn path(&self, proc_cgroup: &ProcCgroup) -> String {
+        format!("proc-{}/box-{}", proc_cgroup.id, self.box_id)
+    }
+
     pub fn add_process(&self, pid: pid_t) -> Result<()> {
         self.proc_cgroup_fd
             .write_file(format!("box-{}/cgroup.procs", self.box_id), 0o700)
@@ -166,11 +210,6 @@ impl BoxCgroup {
             .context("Failed to open memory.max for writing")?
             .write(format!("{limit}\n").as_ref())
             .context("Failed to set memory limit")?;
-        self.proc_cgroup_fd
-            .write_file(format!("box-{}/memory.swap.max", self.box_id), 0o700)
-            .context("Failed to open memory.swap.max for writing")?
-            .write(b"0\n")
-            .context("Failed to disable swap")?;
         self.proc_cgroup_fd
             .write_file(format!("box-{}/memory.oom.group", self.box_id), 0o700)
             .context("Failed to open memory.oom.group for writing")?
@@ -179,6 +218,53 @@ impl BoxCgroup {
         Ok(())
     }
 
+    pub fn set_swap_limit(&self, limit: usize) -> Result<()> {
+        match self
+            .proc_cgroup_fd
+            .open_file(format!("box-{}/memory.swap.max", self.box_id))
+        {
+            Ok(_) => {}
+            // Without swap accounting, there is nothing to disable
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && limit == 0 => return Ok(()),
+            Err(e) => return Err(e).context("Failed to open memory.swap.max"),
+        }
+        self.proc_cgroup_fd
+            .write_file(format!("box-{}/memory.swap.max", self.box_id), 0o700)
+            .context("Failed to open memory.swap.max for writing")?
+            .write(format!("{limit}\n").as_ref())
+            .context("Failed to set swap limit")?;
+        Ok(())
+    }
+
+    pub fn set_io_limit(&self, limit: &IoLimit) -> Result<()> {
+        let value = |value: Option<u64>| value.map_or("max".to_string(), |value| value.to_string());
+        let limits = format!(
+            "rbps={} wbps={} riops={} wiops={}",
+            value(limit.read_bps),
+            value(limit.write_bps),
+            value(limit.read_iops),
+            value(limit.write_iops),
+        );
+        for disk in &self.disks {
+            // Each device has to be written separately
+            self.proc_cgroup_fd
+                .write_file(format!("box-{}/io.max", self.box_id), 0o700)
+                .context("Failed to open io.max for writing, is the io controller available?")?
+                .write(format!("{disk} {limits}\n").as_ref())
+                .with_context(|| format!("Failed to set I/O limit of device {disk}"))?;
+        }
+        Ok(())
+    }
+
+    pub fn set_frozen(&self, frozen: bool) -> Result<()> {
+        self.proc_cgroup_fd
+            .write_file(format!("box-{}/cgroup.freeze", self.box_id), 0o700)
+            .context("Failed to open cgroup.freeze for writing")?
+            .write(if frozen { b"1\n" } else { b"0\n" })
+            .context("Failed to write to cgroup.freeze")?;
+        Ok(())
+    }
+
     pub fn set_processes_limit(&self, limit: usize) -> Result<()> {
         self.proc_cgroup_fd
             .write_file(format!("box-{}/pids.max", self.box_id), 0o700)
@@ -188,6 +274,54 @@ impl BoxCgroup {
         Ok(())
     }
 
+    // cpu.max is a quota per period, so the program runs freely for a part of each period and is
+    // stopped for the rest of it. A short period makes the slowdown more uniform.
+    pub fn set_cpu_quota(&self, fraction: f64) -> Result<()> {
+        const PERIOD: u64 = 10000;
+        // The kernel requires the quota to be at least 1 ms
+        let quota = ((fraction * PERIOD as f64) as u64).max(1000);
+        self.proc_cgroup_fd
+            .write_file(format!("box-{}/cpu.max", self.box_id), 0o700)
+            .context("Failed to open cpu.max for writing")?
+            .write(format!("{quota} {PERIOD}\n").as_ref())
+            .context("Failed to set CPU quota")?;
+        Ok(())
+    }
+
+    pub fn set_cpus(&self, cpus: &[u64]) -> Result<()> {
+        let cpus: Vec<String> = cpus.iter().map(|cpu| cpu.to_string()).collect();
+        self.proc_cgroup_fd
+            .write_file(format!("box-{}/cpuset.cpus", self.box_id), 0o700)
+            .context("Failed to open cpuset.cpus for writing")?
+            .write(format!("{}\n", cpus.join(",")).as_ref())
+            .context("Failed to set CPUs")?;
+        Ok(())
+    }
+
+    // How many cores the processes may run on
+    pub fn get_cpu_count(&self) -> Result<usize> {
+        let mut buf = String::new();
+        self.proc_cgroup_fd
+            .open_file(format!("box-{}/cpuset.cpus.effective", self.box_id))
+            .context("Failed to open cpuset.cpus.effective for reading")?
+            .read_to_string(&mut buf)
+            .context("Failed to read cpuset.cpus.effective")?;
+
+        // A comma-separated list of cores and ranges, e.g. 1,3-5
+        let mut count = 0;
+        for range in buf.trim().split(',').filter(|range| !range.is_empty()) {
+            let (first, last) = range.split_once('-').unwrap_or((range, range));
+            let first: usize = first
+                .parse()
+                .context("Invalid cpuset.cpus.effective format")?;
+            let last: usize = last
+                .parse()
+                .context("Invalid cpuset.cpus.effective format")?;
+            count += last + 1 - first;
+        }
+        Ok(count.max(1))
+    }
+
     pub fn get_cpu_stats(&self) -> Result<CpuStats> {
         let mut buf = String::new();
         self.proc_cgroup_fd
@@ -250,6 +384,62 @@ impl BoxCgroup {
         }
     }
 
+    // Bytes read and written from block devices, zeroes if the io controller is unavailable. Files
+    // in /space, pipes, and the page cache are not counted.
+    pub fn get_io_stats(&self) -> Result<(u64, u64)> {
+        let mut buf = String::new();
+        match self
+            .proc_cgroup_fd
+            .open_file(format!("box-{}/io.stat", self.box_id))
+        {
+            Ok(mut file) => file
+                .read_to_string(&mut buf)
+                .context("Failed to read io.stat")?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+            Err(e) => return Err(e).context("Failed to open io.stat for reading"),
+        };
+
+        // A line per device, e.g. 8:0 rbytes=1024 wbytes=0 rios=1 wios=0 dbytes=0 dios=0
+        let mut read = 0;
+        let mut written = 0;
+        for field in buf.split_ascii_whitespace() {
+            if let Some((key, value)) = field.split_once('=') {
+                let target = match key {
+                    "rbytes" => &mut read,
+                    "wbytes" => &mut written,
+                    _ => continue,
+                };
+                *target += value.parse::<u64>().context("Invalid io.stat format")?;
+            }
+        }
+        Ok((read, written))
+    }
+
+    // The peak if the kernel tracks it, the current usage otherwise; zero without swap accounting
+    pub fn get_swap_usage(&self) -> Result<usize> {
+        for name in ["memory.swap.peak", "memory.swap.current"] {
+            match self
+                .proc_cgroup_fd
+                .open_file(format!("box-{}/{name}", self.box_id))
+            {
+                Ok(mut file) => {
+                    let mut buf = String::new();
+                    file.read_to_string(&mut buf)
+                        .with_context(|| format!("Failed to read {name}"))?;
+                    return buf
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("Invalid {name} format"));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to open {name} for reading"))
+                }
+            }
+        }
+        Ok(0)
+    }
+
     pub fn get_memory_total(&self) -> Result<usize> {
         let mut buf = String::new();
         self.proc_cgroup_fd
@@ -276,6 +466,24 @@ impl BoxCgroup {
         Ok(total)
     }
 
+    // Whether a fork or a clone has failed because of pids.max
+    pub fn was_processes_limit_hit(&self) -> Result<bool> {
+        let mut buf = String::new();
+        self.proc_cgroup_fd
+            .open_file(format!("box-{}/pids.events", self.box_id))
+            .context("Failed to open pids.events for reading")?
+            .read_to_string(&mut buf)
+            .context("Failed to read pids.events")?;
+
+        let max = buf
+            .lines()
+            .find_map(|line| line.strip_prefix("max "))
+            .context("max is missing from pids.events")?
+            .parse::<usize>()
+            .context("Invalid pids.events format")?;
+        Ok(max > 0)
+    }
+
     pub fn was_oom_killed(&self) -> Result<bool> {
         let mut buf = String::new();
         self.proc_cgroup_fd
@@ -330,12 +538,24 @@ impl Drop for BoxCgroup {
     fn drop(&mut self) {
         if !self.dropped {
             if let Err(e) = self._destroy() {
-                eprintln!("Error in Drop: {e:?}");
+                ::tracing::error!(error = %format!("{e:#}"), "Failed to destroy box cgroup in Drop");
             }
         }
     }
 }
 
+// Used to clean up after a box whose processes were killed before they could do that themselves
+pub fn remove_proc_cgroup(core: u64, id: &str) -> Result<()> {
+    let dir = format!("/sys/fs/cgroup/sunwalker-box-core-{core}");
+    let core_cgroup_fd = match openat::Dir::open(&dir) {
+        Ok(fd) => fd,
+        // The core has been freed, and the cgroup with it
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {dir}")),
+    };
+    remove_cgroup(&core_cgroup_fd, format!("proc-{id}").as_ref())
+}
+
 pub fn revert_core_isolation(core: u64) -> Result<()> {
     remove_cgroup(
         &openat::Dir::open("/sys/fs/cgroup").context("Failed to open /sys/fs/cgroup")?,
@@ -351,13 +571,17 @@ fn chown_cgroup(dir: &openat::Dir, uid: Option<u32>, gid: Option<u32>) -> Result
         "cgroup.freeze",
         "cgroup.kill",
         "cgroup.procs",
+        "cpu.max",
         "cpu.stat",
+        "io.max",
         "memory.events",
         "memory.max",
         "memory.oom.group",
         "memory.peak",
         "memory.stat",
+        "memory.swap.current",
         "memory.swap.max",
+        "memory.swap.peak",
     ] {
         if let Err(e) = nix::unistd::fchownat(
             Some(dir.as_raw_fd()),
@@ -375,6 +599,17 @@ fn chown_cgroup(dir: &openat::Dir, uid: Option<u32>, gid: Option<u32>) -> Result
     Ok(())
 }
 
+fn list_disks() -> Result<Vec<String>> {
+    let mut disks = Vec::new();
+    for entry in std::fs::read_dir("/sys/block").context("Failed to read /sys/block")? {
+        let entry = entry.context("Failed to read /sys/block")?;
+        let dev = std::fs::read_to_string(entry.path().join("dev"))
+            .with_context(|| format!("Failed to read {:?}", entry.path().join("dev")))?;
+        disks.push(dev.trim().to_string());
+    }
+    Ok(disks)
+}
+
 // This function is subject to race conditions because one cgroup can be removed by several
 // processes simultaneously. Therefore, ENOENT is not considered an error.
 fn remove_cgroup(parent: &openat::Dir, dir_name: &str) -> Result<()> {
@@ -1,16 +1,27 @@
 use crate::{
     entry,
-    linux::{cgroups, manager, mountns, procs, reaper, rootfs, sandbox, system},
+    linux::{
+        cache, cgroups, dns, exporter, ids, logging, manager, mountns, network, procs, reaper,
+        rootfs, running, sandbox, system, timens, userns, watcher,
+    },
 };
 use anyhow::{anyhow, bail, Context, Result};
 use nix::{libc, libc::SYS_pidfd_open, sys::{signal, resource}, unistd::Pid};
-use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use rand::Rng;
+use std::io::Seek;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, OpenOptionsExt, PermissionsExt};
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
 pub struct Controller {
     quotas: rootfs::DiskQuotas,
+    tmpfs_mounts: Vec<rootfs::TmpfsMount>,
     cgroup: Option<cgroups::Cgroup>,
+    // The main core and the extra cores
+    cores: Vec<u64>,
+    proc_cgroup_id: Option<String>,
     reaper_pid: Option<Pid>,
     reaper_channel: Option<
         multiprocessing::Duplex<reaper::Command, std::result::Result<Option<String>, String>>,
@@ -18,31 +29,109 @@ pub struct Controller {
     manager_channel: Option<
         multiprocessing::Duplex<manager::Command, std::result::Result<Option<String>, String>>,
     >,
+    exporter_channel: Option<
+        multiprocessing::Duplex<exporter::Command, std::result::Result<Option<String>, String>>,
+    >,
+    // Signals to deliver to the running program go one way, events of the run go the other way
+    run_channel: Option<multiprocessing::Duplex<running::RunControl, String>>,
+    pending_run: Option<PendingRun>,
+    next_run_id: u64,
     rootfs_state: Option<rootfs::RootfsState>,
+    quota_dirs: Vec<String>,
+    persistent_links: Vec<PersistentLink>,
+    volumes: Vec<Volume>,
+    watcher: Option<watcher::Watcher>,
+    allow_fuse: bool,
+    cache: Option<cache::Cache>,
+    // Options of run that are used unless passed explicitly, from the config file
+    run_defaults: json::JsonValue,
+    // Outbound networking, if enabled
+    _slirp: Option<network::Slirp>,
+    resolver: Option<dns::Resolver>,
+}
+
+#[derive(Clone)]
+struct Volume {
+    external: String,
+    internal: String,
+    options: BindOptions,
+}
+
+struct PendingRun {
+    id: u64,
+    n_programs: usize,
+    // Whether the judge expects a list of results rather than a single one
+    is_group: bool,
+    // The judge shares the file offsets with us, so they're rewound once the run finishes
+    scratch_files: Vec<std::fs::File>,
+}
+
+struct PersistentLink {
+    external: String,
+    internal: String,
+    allow_hardlink: bool,
+}
+
+#[derive(Clone)]
+pub struct BindOptions {
+    pub ro: bool,
+    pub idmap: bool,
 }
 
 impl Controller {
-    pub fn try_new(quotas: rootfs::DiskQuotas) -> Result<Self> {
+    pub fn try_new(
+        quotas: rootfs::DiskQuotas,
+        tmpfs_mounts: Vec<rootfs::TmpfsMount>,
+        loopback: bool,
+        outbound: Option<network::OutboundOptions>,
+        veth: Option<network::VethOptions>,
+    ) -> Result<Self> {
         // Isolate various non-important namespaces
-        sandbox::unshare_persistent_namespaces()
-            .context("Failed to unshare persistent namespaces")?;
+        let slirp =
+            sandbox::unshare_persistent_namespaces(loopback, outbound.as_ref(), veth.as_ref())
+                .context("Failed to unshare persistent namespaces")?;
+        let resolver = outbound
+            .as_ref()
+            .map(|outbound| dns::Resolver::start(outbound.names()))
+            .transpose()
+            .context("Failed to start DNS resolver")?;
 
         // Core dumps are dangerous if the box dumps in a user-controlled directory. They are incur
-        // a penalty on runtime errors.
-        resource::setrlimit(resource::Resource::RLIMIT_CORE, 0, 0).context("Failed to disable core dumps")?;
+        // a penalty on runtime errors. The hard limit is kept so that runs can opt into them with
+        // `rlimits.core`.
+        let (_, core_hard_limit) = resource::getrlimit(resource::Resource::RLIMIT_CORE)
+            .context("Failed to get core dump limit")?;
+        resource::setrlimit(resource::Resource::RLIMIT_CORE, 0, core_hard_limit)
+            .context("Failed to disable core dumps")?;
 
         Ok(Self {
             quotas,
+            tmpfs_mounts,
             cgroup: None,
+            cores: Vec::new(),
+            proc_cgroup_id: None,
             reaper_pid: None,
             reaper_channel: None,
             manager_channel: None,
+            exporter_channel: None,
+            run_channel: None,
+            pending_run: None,
+            next_run_id: 0,
             rootfs_state: None,
+            quota_dirs: Vec::new(),
+            persistent_links: Vec::new(),
+            volumes: Vec::new(),
+            watcher: None,
+            run_defaults: json::JsonValue::new_object(),
+            _slirp: slirp,
+            resolver,
+            allow_fuse: false,
+            cache: None,
         })
     }
 
-    pub fn join_core(&mut self, core: u64) -> Result<()> {
-        let cgroup = cgroups::Cgroup::new(core).context("Failed to create cgroup")?;
+    pub fn join_core(&mut self, core: u64, extra_cores: &[u64]) -> Result<()> {
+        let cgroup = cgroups::Cgroup::new(core, extra_cores).context("Failed to create cgroup")?;
 
         // Move self to the right core so that spawning processes on the right core is fast. This also
         // has to be done before unsharing userns, as we'd then lose our root privileges, and moving
@@ -53,12 +142,32 @@ impl Controller {
             .context("Failed to add self to manager cgroup")?;
 
         self.cgroup = Some(cgroup);
+        self.cores = std::iter::once(core)
+            .chain(extra_cores.iter().copied())
+            .collect();
 
         Ok(())
     }
 
-    pub fn enter_root(&mut self, root: &Path) -> Result<()> {
-        let root = std::fs::canonicalize(root).context("Failed to resolve path to root")?;
+    pub fn enter_root(&mut self, roots: &[String], options: rootfs::RootfsOptions) -> Result<()> {
+        let mut layers = Vec::with_capacity(roots.len());
+        for root in roots {
+            layers.push(
+                std::fs::canonicalize(root)
+                    .with_context(|| format!("Failed to resolve path to root {root}"))?,
+            );
+        }
+
+        // The exporter has to stay in the host's mount namespace
+        let (exporter_ours, exporter_theirs) = multiprocessing::duplex::<
+            exporter::Command,
+            std::result::Result<Option<String>, String>,
+        >()
+        .context("Failed to create channel")?;
+        exporter::exporter
+            .spawn(logging::options(), exporter_theirs)
+            .context("Failed to start exporter")?;
+        self.exporter_channel = Some(exporter_ours);
 
         // Do whatever cannot be done inside the userns. This mostly amounts to mounting stuff.
         // Create an isolated mountns for a dedicated /tmp/sunwalker_box directory
@@ -69,18 +178,37 @@ impl Controller {
         // Create the dedicated /tmp/sunwalker_box
         sandbox::enter_working_area().context("Failed to enter working area")?;
         // Create a copy of /dev
-        sandbox::create_dev_copy().context("Failed to create /dev copy")?;
+        sandbox::create_dev_copy(options.allow_fuse).context("Failed to create /dev copy")?;
+        self.allow_fuse = options.allow_fuse;
 
         // Setup rootfs
-        let mut root_cur = PathBuf::from("/oldroot");
-        root_cur.extend(root.strip_prefix("/"));
+        let mut layers_cur = Vec::with_capacity(layers.len());
+        for (i, layer) in layers.iter().enumerate() {
+            let mut layer_cur = PathBuf::from("/oldroot");
+            layer_cur.extend(layer.strip_prefix("/"));
+            if std::fs::metadata(&layer_cur)
+                .with_context(|| format!("Failed to stat {layer_cur:?}"))?
+                .is_file()
+            {
+                let mount_point = PathBuf::from(format!("/images/{i}"));
+                rootfs::mount_image(&layer_cur, &mount_point)
+                    .with_context(|| format!("Failed to mount root image {layer:?}"))?;
+                layer_cur = mount_point;
+            }
+            layers_cur.push(layer_cur);
+        }
         self.rootfs_state =
-            Some(rootfs::create_rootfs(&root_cur).context("Failed to create rootfs")?);
+            Some(rootfs::create_rootfs(&layers_cur, options).context("Failed to create rootfs")?);
+        rootfs::check_tmpfs_mounts(&self.tmpfs_mounts).context("Invalid --tmpfs option")?;
 
         Ok(())
     }
 
-    pub fn start(&mut self, cli_command: entry::CLIStartCommand) -> Result<()> {
+    pub fn start(
+        &mut self,
+        cli_command: entry::CLIStartCommand,
+        time_offsets: timens::Offsets,
+    ) -> Result<()> {
         // We need a separate worker to monitor the child (and no, using tokio won't work because then
         // using stdio would require a dedicated thread), but threads can't be created after unsharing
         // pidns, so we create the thread beforehand.
@@ -103,6 +231,9 @@ impl Controller {
         >()
         .context("Failed to create channel")?;
 
+        let (run_ours, run_theirs) = multiprocessing::duplex::<running::RunControl, String>()
+            .context("Failed to create channel")?;
+
         // Run a child in a new PID namespace
         procs::unshare_pidns().context("Failed to unshare pid namespace")?;
 
@@ -119,10 +250,33 @@ impl Controller {
             .cgroup
             .take()
             .context("The controller has not joined a core cgroup yet")?;
+        // The cgroup is created here rather than by the reaper, so that its name can be saved in the
+        // state file
+        let proc_cgroup = cgroup
+            .create_proc_cgroup()
+            .context("Failed to create box cgroup")?;
+        self.proc_cgroup_id = Some(proc_cgroup.id().to_string());
 
         let child = reaper::reaper
-            .spawn(pidfd, cli_command, cgroup, reaper_theirs, manager_theirs)
-            .context("Failed to start child")?;
+            .spawn(
+                pidfd,
+                cli_command,
+                proc_cgroup.try_clone()?,
+                reaper::ReaperArgs {
+                    reaper_channel: reaper_theirs,
+                    manager_channel: manager_theirs,
+                    run_channel: run_theirs,
+                    time_offsets,
+                },
+            )
+            .context("Failed to start child");
+        let child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = proc_cgroup.destroy();
+                return Err(e);
+            }
+        };
         self.reaper_pid = Some(Pid::from_raw(child.id()));
         thread_tx
             .send(child)
@@ -136,6 +290,7 @@ impl Controller {
 
         self.reaper_channel = Some(reaper_ours);
         self.manager_channel = Some(manager_ours);
+        self.run_channel = Some(run_ours);
 
         self.run_reaper_command(reaper::Command::Init)?;
 
@@ -146,6 +301,10 @@ impl Controller {
         Ok(())
     }
 
+    pub fn proc_cgroup_id(&self) -> Option<&str> {
+        self.proc_cgroup_id.as_deref()
+    }
+
     pub fn ensure_allowed_to_modify(&self, path: &Path) -> Result<()> {
         if path.components().count() == 3 {
             // /newroot/*
@@ -155,22 +314,179 @@ impl Controller {
     }
 
     pub fn reset(&mut self) -> Result<()> {
+        // Watched files are about to be removed anyway
+        self.watcher = None;
+
+        // The program is about to be killed anyway, but the manager has to be done with it before
+        // it can handle other commands
+        if let Some(id) = self.pending_run.as_ref().map(|run| run.id) {
+            self.kill_run(id, None, signal::Signal::SIGKILL as i32)?;
+            // We don't care whether the run has failed, nor about its events
+            let _ = self.wait_run(id, None, &mut |_| Ok(()));
+        }
+
         sandbox::reset_persistent_namespaces().context("Failed to persistent namespaces")?;
-        rootfs::reset(
-            self.rootfs_state.as_mut().context("Did not join a core")?,
-            &self.quotas,
-        )
-        .context("Failed to reset rootfs")?;
+        // FUSE filesystems are mounted in the manager's mount namespace, and they would prevent
+        // the mounts they reside on from being unmounted below
+        if self.allow_fuse {
+            self.run_manager_command(manager::Command::UnmountFuse)?;
+        }
+
+        // The namespaces of the reaper and the mounts of the exporter don't depend on the rootfs,
+        // so they are reset concurrently with it. The replies have to be received even if the
+        // rootfs fails to reset, so that they aren't mistaken for replies to later commands.
+        self.send_reaper_command(reaper::Command::Reset)?;
+        if let Err(e) = self.send_exporter_command(exporter::Command::Reset) {
+            let _ = self.recv_reaper_reply();
+            return Err(e);
+        }
 
-        self.run_reaper_command(reaper::Command::Reset)?;
+        let rootfs_result: Result<()> = try {
+            rootfs::reset(
+                self.rootfs_state.as_mut().context("Did not join a core")?,
+                &self.quotas,
+                &self.tmpfs_mounts,
+            )
+            .context("Failed to reset rootfs")?;
+
+            // The overlays are unmounted by now, so their layers are unused
+            while let Some(path) = self.quota_dirs.pop() {
+                system::umount_opt(&path, system::MNT_DETACH)
+                    .with_context(|| format!("Failed to unmount {path}"))?;
+                std::fs::remove_dir(&path).with_context(|| format!("Failed to rmdir {path}"))?;
+            }
+        };
+
+        let reaper_result = self.recv_reaper_reply();
+        let exporter_result = self.recv_exporter_reply();
+        rootfs_result?;
+        reaper_result?;
+        exporter_result?;
+
+        // Mount points are created by bind if necessary
+        for volume in self.volumes.clone() {
+            self.bind(&volume.external, &volume.internal, volume.options)
+                .with_context(|| format!("Failed to mount volume at {}", volume.internal))?;
+        }
+
+        for link in &self.persistent_links {
+            let internal_abs = rootfs::resolve_abs_box_root(&link.internal)?;
+            if let Some(parent) = internal_abs.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to mkdir -p {parent:?}"))?;
+            }
+            link_file(
+                &rootfs::resolve_abs_old_root(&link.external)?,
+                &internal_abs,
+                link.allow_hardlink,
+            )?;
+        }
         Ok(())
     }
 
-    pub fn bind(&mut self, external: &str, internal: &str, ro: bool) -> Result<()> {
+    pub fn set_cache(&mut self, cache: cache::Cache) {
+        self.cache = Some(cache);
+    }
+
+    pub fn cache(&self) -> Result<&cache::Cache> {
+        self.cache
+            .as_ref()
+            .context("The box was started without --cache-dir")
+    }
+
+    pub fn setuid_files(&self) -> Result<&[rootfs::SetuidFile]> {
+        self.rootfs_state
+            .as_ref()
+            .context("Did not join a core")?
+            .setuid_files()
+            .context("The box was started with --setuid-policy allow")
+    }
+
+    pub fn set_run_defaults(&mut self, defaults: json::JsonValue) {
+        self.run_defaults = defaults;
+    }
+
+    pub fn apply_run_defaults(&self, options: &mut json::JsonValue) {
+        for (key, value) in self.run_defaults.entries() {
+            if options[key].is_null() {
+                options[key] = value.clone();
+            }
+        }
+    }
+
+    pub fn add_volume(
+        &mut self,
+        external: &str,
+        internal: &str,
+        options: BindOptions,
+    ) -> Result<()> {
+        self.bind(external, internal, options.clone())?;
+        self.volumes.push(Volume {
+            external: external.to_string(),
+            internal: internal.to_string(),
+            options,
+        });
+        Ok(())
+    }
+
+    pub fn watch(&mut self, path: &str) -> Result<i32> {
+        let path_abs = rootfs::resolve_abs_box_root(path)?;
+        let box_path = Path::new("/").join(path_abs.strip_prefix("/newroot")?);
+        let box_path = box_path.to_str().context("Path is not UTF-8")?.to_string();
+        if self.watcher.is_none() {
+            self.watcher = Some(watcher::Watcher::new()?);
+        }
+        self.watcher.as_mut().unwrap().add(&path_abs, box_path)
+    }
+
+    pub fn poll_watch_events(&mut self, timeout: Duration) -> Result<json::JsonValue> {
+        match self.watcher.as_mut() {
+            Some(watcher) => watcher.poll_events(timeout),
+            None => Ok(json::array![]),
+        }
+    }
+
+    pub fn snapshot(&mut self) -> Result<()> {
+        rootfs::snapshot_space(self.rootfs_state.as_mut().context("Did not join a core")?)
+            .context("Failed to snapshot /space")?;
+        self.reset()
+    }
+
+    pub fn drop_snapshot(&mut self) -> Result<()> {
+        rootfs::drop_snapshot(self.rootfs_state.as_mut().context("Did not join a core")?)
+            .context("Failed to drop snapshot")?;
+        self.reset()
+    }
+
+    pub fn bind(&mut self, external: &str, internal: &str, options: BindOptions) -> Result<()> {
+        let external_abs = rootfs::resolve_abs_old_root(external)?;
         let internal_abs = rootfs::resolve_abs_box_root(internal)?;
-        system::bind_mount(rootfs::resolve_abs_old_root(external)?, &internal_abs)?;
-        system::change_propagation(internal_abs, system::MS_PRIVATE)?; // linux@d29216842a85
-        if ro {
+        let file_type = std::fs::metadata(&external_abs)
+            .with_context(|| format!("Failed to stat {external_abs:?}"))?
+            .file_type();
+        if file_type.is_fifo() || file_type.is_socket() {
+            // Unlike regular files, these can't be created with mkfile
+            self.ensure_allowed_to_modify(&internal_abs)?;
+        }
+        self.create_mount_point(&internal_abs, file_type.is_dir())?;
+
+        if options.idmap {
+            let metadata = std::fs::metadata(&external_abs)
+                .with_context(|| format!("Failed to stat {external_abs:?}"))?;
+            let userns = userns::create_idmap(metadata.uid(), metadata.gid())
+                .context("Failed to create idmap")?;
+            let mount_fd = system::open_tree(&external_abs, system::OPEN_TREE_CLONE)
+                .with_context(|| format!("Failed to clone mount at {external_abs:?}"))?;
+            system::mount_setattr(&mount_fd, 0, system::MOUNT_ATTR_IDMAP, 0, Some(&userns))
+                .context("Failed to idmap mount")?;
+            system::move_mount(&mount_fd, &internal_abs)
+                .with_context(|| format!("Failed to attach mount to {internal_abs:?}"))?;
+        } else {
+            system::bind_mount(&external_abs, &internal_abs)?;
+        }
+
+        system::change_propagation(&internal_abs, system::MS_PRIVATE)?; // linux@d29216842a85
+        if options.ro {
             self.run_manager_command(manager::Command::RemountReadonly {
                 path: internal.to_string(),
             })?;
@@ -178,12 +494,221 @@ impl Controller {
         Ok(())
     }
 
+    fn create_mount_point(&mut self, path: &Path, is_dir: bool) -> Result<()> {
+        if std::fs::symlink_metadata(path).is_ok() {
+            return Ok(());
+        }
+
+        let mut ancestor = path.parent().context("Cannot mount over the root")?;
+        while std::fs::symlink_metadata(ancestor).is_err() {
+            ancestor = ancestor.parent().context("Cannot mount over the root")?;
+        }
+
+        match create_mount_point_in_place(path, is_dir) {
+            Err(e) if e.raw_os_error() == Some(libc::EROFS) => {
+                // The ancestor is a part of the read-only image, so cover it with a shim where the
+                // missing directories can be created, and then make it read-only again so that
+                // the box can't use it to modify the image. The root always contains mount points,
+                // e.g. /space and /proc, which a shim would hide
+                if ancestor == Path::new("/newroot") {
+                    bail!(
+                        "Cannot create mount point {path:?}: missing top-level directories cannot \
+                         be created in a read-only root"
+                    );
+                }
+                self.mount_shim(ancestor)?;
+                let result = create_mount_point_in_place(path, is_dir);
+                system::bind_mount_opt("none", ancestor, system::MS_REMOUNT | system::MS_RDONLY)
+                    .with_context(|| format!("Failed to remount {ancestor:?} read-only"))?;
+                result.with_context(|| format!("Failed to create mount point {path:?}"))
+            }
+            result => result.with_context(|| format!("Failed to create mount point {path:?}")),
+        }
+    }
+
+    fn mount_shim(&mut self, path: &Path) -> Result<()> {
+        // A writable overlay on top of the directory. Its contents stay visible, except for child
+        // mounts, which overlayfs does not duplicate, so refuse to hide them.
+        let path_str = path.to_str().context("Path is not UTF-8")?;
+        if path_str.contains([':', ',', '\\']) {
+            bail!("Cannot create a shim over {path:?}: the path contains special characters");
+        }
+        if !rootfs::list_child_mounts(&format!("{path_str}/"))?.is_empty() {
+            bail!("Cannot create a shim over {path:?}: it contains mount points");
+        }
+
+        // Only directories and empty files are ever created in the shim
+        let dir = self.mount_limited_tmpfs(1024 * 1024)?;
+        let upper = format!("{dir}/upper");
+        let work = format!("{dir}/work");
+        for path in [&upper, &work] {
+            std::fs::create_dir(path).with_context(|| format!("Failed to mkdir {path}"))?;
+        }
+        rootfs::prepare_overlay_upper(&upper, path)?;
+
+        system::mount(
+            "overlay",
+            path,
+            "overlay",
+            0,
+            Some(&format!(
+                "lowerdir={path_str},upperdir={upper},workdir={work}"
+            )),
+        )
+        .with_context(|| format!("Failed to mount overlay on {path:?}"))?;
+        system::change_propagation(path, system::MS_PRIVATE)?;
+        Ok(())
+    }
+
+    fn mount_limited_tmpfs(&mut self, size: u64) -> Result<String> {
+        let mut rng = rand::thread_rng();
+        let id: String = (0..10)
+            .map(|_| rng.sample(rand::distributions::Alphanumeric) as char)
+            .collect();
+        let path = format!("/quotas/{id}");
+        std::fs::create_dir_all(&path).with_context(|| format!("Failed to mkdir {path}"))?;
+        self.quota_dirs.push(path.clone());
+        system::mount(
+            "none",
+            &path,
+            "tmpfs",
+            system::MS_NOSUID | system::MS_NODEV,
+            Some(&format!("size={size}")),
+        )
+        .with_context(|| format!("Failed to mount tmpfs on {path}"))?;
+        Ok(path)
+    }
+
+    pub fn share_memory(&mut self, internal: &str, size: u64) -> Result<OwnedFd> {
+        let internal_abs = rootfs::resolve_abs_box_root_nofollow(internal)?;
+        self.ensure_allowed_to_modify(&internal_abs)?;
+
+        // Memfds reside on an internal kernel mount and thus cannot be bind-mounted, so allocate
+        // the region on a dedicated tmpfs instead. It is backed by shared memory all the same, and
+        // does not count towards the quota of the box.
+        let dir = self.mount_limited_tmpfs(size)?;
+        let region_path = format!("{dir}/region");
+        let region = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&region_path)
+            .with_context(|| format!("Failed to create {region_path}"))?;
+        std::os::unix::fs::fchown(
+            &region,
+            Some(ids::EXTERNAL_USER_UID),
+            Some(ids::EXTERNAL_USER_GID),
+        )
+        .with_context(|| format!("Failed to chown {region_path}"))?;
+        region
+            .set_len(size)
+            .with_context(|| format!("Failed to resize {region_path}"))?;
+
+        // The mount point
+        std::fs::File::options()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&internal_abs)
+            .with_context(|| format!("Failed to create {internal_abs:?}"))?;
+        system::bind_mount(&region_path, &internal_abs)
+            .with_context(|| format!("Failed to mount shared region on {internal_abs:?}"))?;
+        system::change_propagation(&internal_abs, system::MS_PRIVATE)?;
+
+        Ok(region.into())
+    }
+
+    pub fn bind_out(&mut self, internal: &str, external: &str) -> Result<()> {
+        let internal_abs = rootfs::resolve_abs_box_root(internal)?;
+        if !internal_abs.starts_with("/newroot/space") {
+            bail!("Only files under /space can be exposed");
+        }
+
+        // Clone the mount tree into a detached mount and make sure the clone can't affect the box
+        // and vice versa before attaching it to the host
+        let mount_fd = system::open_tree(
+            &internal_abs,
+            system::OPEN_TREE_CLONE | system::AT_RECURSIVE,
+        )
+        .with_context(|| format!("Failed to clone mount tree at {internal_abs:?}"))?;
+        system::mount_setattr(
+            &mount_fd,
+            system::AT_RECURSIVE,
+            system::MOUNT_ATTR_RDONLY
+                | system::MOUNT_ATTR_NOSUID
+                | system::MOUNT_ATTR_NODEV
+                | system::MOUNT_ATTR_NOEXEC,
+            system::MS_PRIVATE,
+            None,
+        )
+        .context("Failed to make the cloned mount tree read-only")?;
+
+        self.run_exporter_command(exporter::Command::BindOut {
+            mount_fd,
+            path: external.to_string(),
+        })?;
+        Ok(())
+    }
+
+    pub fn link(
+        &mut self,
+        external: &str,
+        internal: &str,
+        allow_hardlink: bool,
+        persistent: bool,
+    ) -> Result<&'static str> {
+        let external_abs = rootfs::resolve_abs_old_root(external)?;
+        let internal_abs = rootfs::resolve_abs_box_root(internal)?;
+        self.ensure_allowed_to_modify(&internal_abs)?;
+        let method = link_file(&external_abs, &internal_abs, allow_hardlink)?;
+        if persistent {
+            self.persistent_links.push(PersistentLink {
+                external: external.to_string(),
+                internal: internal.to_string(),
+                allow_hardlink,
+            });
+        }
+        Ok(method)
+    }
+
     pub fn run_reaper_command(&mut self, command: reaper::Command) -> Result<Option<String>> {
+        self.send_reaper_command(command)?;
+        self.recv_reaper_reply()
+    }
+
+    fn send_reaper_command(&mut self, command: reaper::Command) -> Result<()> {
         let channel = self.reaper_channel.as_mut().context("Not started")?;
 
         channel.send(&command).context("Failed to send command")?;
 
         signal::kill(self.reaper_pid.expect("Not started"), signal::Signal::SIGIO)?;
+        Ok(())
+    }
+
+    fn recv_reaper_reply(&mut self) -> Result<Option<String>> {
+        let channel = self.reaper_channel.as_mut().context("Not started")?;
+
+        match channel.recv().context("Failed to recv reply")? {
+            None => bail!("No reply from child"),
+            Some(Ok(value)) => Ok(value),
+            Some(Err(e)) => bail!("{e}"),
+        }
+    }
+
+    pub fn run_exporter_command(&mut self, command: exporter::Command) -> Result<Option<String>> {
+        self.send_exporter_command(command)?;
+        self.recv_exporter_reply()
+    }
+
+    fn send_exporter_command(&mut self, command: exporter::Command) -> Result<()> {
+        let channel = self.exporter_channel.as_mut().context("Not started")?;
+
+        channel.send(&command).context("Failed to send command")
+    }
+
+    fn recv_exporter_reply(&mut self) -> Result<Option<String>> {
+        let channel = self.exporter_channel.as_mut().context("Not started")?;
 
         match channel.recv().context("Failed to recv reply")? {
             None => bail!("No reply from child"),
@@ -192,7 +717,165 @@ impl Controller {
         }
     }
 
+    // A group is several programs run concurrently. The result of a group is a list.
+    pub fn start_run(
+        &mut self,
+        programs: Vec<running::Options>,
+        is_group: bool,
+        events: bool,
+        scratch_files: Vec<std::fs::File>,
+    ) -> Result<u64> {
+        if self.pending_run.is_some() {
+            bail!("Another run is in progress");
+        }
+        // The kernel only reports EINVAL for cores outside of the partition, which is hardly helpful
+        for cpu in programs
+            .iter()
+            .flat_map(|program| program.cpus.iter().flatten())
+        {
+            if !self.cores.contains(cpu) {
+                bail!(
+                    "CPU {cpu} is not a core of the box, which are {:?}; pass it with --core or \
+                     --extra-core",
+                    self.cores
+                );
+            }
+        }
+        // Only the queries of this run are reported
+        if let Some(resolver) = &mut self.resolver {
+            resolver.take_queries()?;
+        }
+        let n_programs = programs.len();
+        let channel = self.manager_channel.as_mut().context("Not started")?;
+        channel
+            .send(&manager::Command::Run { programs, events })
+            .context("Failed to send command")?;
+        let id = self.next_run_id;
+        self.next_run_id += 1;
+        self.pending_run = Some(PendingRun {
+            id,
+            n_programs,
+            is_group,
+            scratch_files,
+        });
+        Ok(id)
+    }
+
+    // Returns None if the run hasn't finished within the timeout. The events emitted by the run in
+    // the meantime are passed to on_event.
+    pub fn wait_run(
+        &mut self,
+        id: u64,
+        timeout: Option<Duration>,
+        on_event: &mut dyn FnMut(&str) -> Result<()>,
+    ) -> Result<Option<String>> {
+        if !self.pending_run.as_ref().is_some_and(|run| run.id == id) {
+            bail!("No run {id}");
+        }
+        let channel = self.manager_channel.as_mut().context("Not started")?;
+        let run_channel = self.run_channel.as_mut().context("Not started")?;
+
+        // The events are sent before the result, so they are always handled first
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let timeout_ms = match deadline {
+                Some(deadline) => deadline
+                    .saturating_duration_since(Instant::now())
+                    .as_millis()
+                    .try_into()
+                    .unwrap_or(i32::MAX),
+                None => -1,
+            };
+            let mut fds = [
+                nix::poll::PollFd::new(run_channel.as_raw_fd(), nix::poll::PollFlags::POLLIN),
+                nix::poll::PollFd::new(channel.as_raw_fd(), nix::poll::PollFlags::POLLIN),
+            ];
+            let n_ready =
+                nix::poll::poll(&mut fds, timeout_ms).context("Failed to poll manager")?;
+            if n_ready == 0 {
+                return Ok(None);
+            }
+            let is_ready = |fd: &nix::poll::PollFd| {
+                fd.revents()
+                    .is_some_and(|revents| revents.contains(nix::poll::PollFlags::POLLIN))
+            };
+            if is_ready(&fds[0]) {
+                let event = run_channel
+                    .recv()
+                    .context("Failed to receive event")?
+                    .context("Manager terminated")?;
+                on_event(&event)?;
+            } else if is_ready(&fds[1]) {
+                break;
+            } else {
+                bail!("Manager terminated");
+            }
+        }
+
+        let run = self.pending_run.take().unwrap();
+        let mut result = match channel.recv().context("Failed to recv reply")? {
+            None => bail!("No reply from child"),
+            Some(Ok(value)) => value.context("No result from child")?,
+            Some(Err(e)) => bail!("{e}"),
+        };
+        for mut scratch in run.scratch_files {
+            scratch.rewind().context("Failed to rewind scratch file")?;
+        }
+        if let Some(resolver) = &mut self.resolver {
+            // The queries can't be attributed to the programs of a group, so each one gets all
+            let queries = dns::queries_to_json(&resolver.take_queries()?);
+            let mut results = json::parse(&result).context("Invalid result from child")?;
+            for result in results.members_mut() {
+                result["dns_queries"] = queries.clone();
+            }
+            result = results.dump();
+        }
+        if !run.is_group {
+            result = json::parse(&result).context("Invalid result from child")?[0].dump();
+        }
+        Ok(Some(result))
+    }
+
+    // Signals the main process of the given program of the run, or of all its programs
+    pub fn kill_run(&mut self, id: u64, program: Option<usize>, signal: i32) -> Result<()> {
+        let Some(run) = self.pending_run.as_ref().filter(|run| run.id == id) else {
+            bail!("No run {id}");
+        };
+        if program.is_some_and(|program| program >= run.n_programs) {
+            bail!("Run {id} has no program {}", program.unwrap());
+        }
+        signal::Signal::try_from(signal).context("Invalid signal")?;
+        // If the run has finished in the meantime, the signal is discarded when the next one starts
+        self.run_channel
+            .as_mut()
+            .context("Not started")?
+            .send(&running::RunControl::Signal(program, signal))
+            .context("Failed to send signal")
+    }
+
+    // Freezes or thaws the given program of the run, or all its programs
+    pub fn pause_run(&mut self, id: u64, program: Option<usize>, paused: bool) -> Result<()> {
+        let Some(run) = self.pending_run.as_ref().filter(|run| run.id == id) else {
+            bail!("No run {id}");
+        };
+        if program.is_some_and(|program| program >= run.n_programs) {
+            bail!("Run {id} has no program {}", program.unwrap());
+        }
+        self.run_channel
+            .as_mut()
+            .context("Not started")?
+            .send(&running::RunControl::Freeze(program, paused))
+            .context("Failed to send request")
+    }
+
+    pub fn has_pending_run(&self) -> bool {
+        self.pending_run.is_some()
+    }
+
     pub fn run_manager_command(&mut self, command: manager::Command) -> Result<Option<String>> {
+        if self.pending_run.is_some() {
+            bail!("A run is in progress, wait for it or kill it first");
+        }
         let channel = self.manager_channel.as_mut().context("Not started")?;
 
         channel.send(&command).context("Failed to send command")?;
@@ -204,3 +887,74 @@ impl Controller {
         }
     }
 }
+
+fn create_mount_point_in_place(path: &Path, is_dir: bool) -> std::io::Result<()> {
+    // The directories are not chowned, so that they stay owned by the real root and thus can't be
+    // modified from inside the box
+    let dir = if is_dir { path } else { path.parent().unwrap() };
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o755)
+        .create(dir)?;
+    if !is_dir {
+        // Any non-directory can be mounted over a regular file
+        std::fs::File::options()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+    }
+    Ok(())
+}
+
+fn link_file(external: &Path, internal: &Path, allow_hardlink: bool) -> Result<&'static str> {
+    let source =
+        std::fs::File::open(external).with_context(|| format!("Failed to open {external:?}"))?;
+    let metadata = source
+        .metadata()
+        .with_context(|| format!("Failed to stat {external:?}"))?;
+    if !metadata.is_file() {
+        bail!("The passed path does not refer to a regular file");
+    }
+
+    // The target may be a hardlink to a file on the host, e.g. one left by a previous link, so it's
+    // replaced rather than truncated
+    match std::fs::remove_file(internal) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to rm {internal:?}")),
+    }
+
+    if allow_hardlink {
+        // This only works if the target is in a writable bind mount from the same filesystem
+        match std::fs::hard_link(external, internal) {
+            Ok(()) => return Ok("hardlink"),
+            Err(e)
+                if e.raw_os_error() == Some(libc::EXDEV)
+                    || e.kind() == std::io::ErrorKind::PermissionDenied => {}
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to link {external:?} to {internal:?}"))
+            }
+        }
+    }
+
+    let mut target = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(internal)
+        .with_context(|| format!("Failed to create {internal:?}"))?;
+    target
+        .set_permissions(std::fs::Permissions::from_mode(metadata.mode() & 0o777))
+        .with_context(|| format!("Failed to chmod {internal:?}"))?;
+
+    // A reflink is as fast as a hardlink but safe to modify. /space is a tmpfs, which doesn't
+    // support reflinks, so this is mostly useful for writable bind mounts
+    if system::reflink(&source, &target).is_ok() {
+        return Ok("reflink");
+    }
+
+    std::io::copy(&mut &source, &mut target)
+        .with_context(|| format!("Failed to copy {external:?} to {internal:?}"))?;
+    Ok("copy")
+}
@@ -0,0 +1,667 @@
+use crate::linux::{
+    entry::{input_payload_len, output_payload_len, Output},
+    logging, network,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use nix::sys::{memfd, socket};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::CStr;
+use std::io::{BufRead, IoSliceMut, Read, Seek, Write};
+use std::os::unix::{
+    io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    net::UnixStream,
+};
+use std::process::{Child, Stdio};
+use std::sync::{mpsc, Arc, Mutex};
+
+// The daemon drives several boxes over a single stream. Each box is still a separate `start`
+// process, because the controller moves itself into the namespaces of the box, but the judge only
+// has to maintain one connection. Every box is served by a thread of its own, so a long run in one
+// box doesn't delay commands to the others; the replies are tagged with the box ID and may thus
+// come out of order.
+//
+// A pool is a set of boxes started in advance, so that the judge doesn't wait for a box to start
+// when a submission arrives. The judge acquires a box from the pool, uses it like any other box,
+// and releases it, after which the box is reset in the background and handed out again.
+//
+// A network connects the boxes created with `--network ID` to each other, but not to the host, for
+// multi-node problems. It's a bridge in a network namespace owned by the daemon; for the boxes, the
+// option is translated to --veth-bridge and --veth-netns.
+
+enum Request {
+    Command {
+        line: String,
+        command: String,
+        payload: Option<std::fs::File>,
+    },
+    // Resets a released box of a pool and returns it to the pool
+    Recycle,
+    // Boxes of a pool are destroyed silently, along with the pool
+    Destroy {
+        quiet: bool,
+    },
+}
+
+struct Pool {
+    members: Vec<String>,
+    // In the order they became ready
+    idle: VecDeque<String>,
+    acquired: HashSet<String>,
+    // The number of acquire requests waiting for a box
+    n_waiting: usize,
+}
+
+impl Pool {
+    fn put(&mut self, pool_id: &str, box_id: String) {
+        if self.n_waiting > 0 {
+            self.n_waiting -= 1;
+            send_acquired(pool_id, &box_id);
+            self.acquired.insert(box_id);
+        } else {
+            self.idle.push_back(box_id);
+        }
+    }
+}
+
+fn send_acquired(pool_id: &str, box_id: &str) {
+    Output::new()
+        .send_line(&format!("{pool_id} ok {}", json::stringify(box_id)))
+        .expect("Failed to write to stdout");
+}
+
+struct Network {
+    hub: network::Hub,
+    // The boxes attached to the network, some of which may have been destroyed since
+    members: Vec<String>,
+}
+
+impl Network {
+    // Replaces --network ID in the arguments of a box
+    fn resolve(
+        networks: &mut HashMap<String, Network>,
+        box_id: &str,
+        args: Vec<String>,
+    ) -> Result<Vec<String>> {
+        let Some(position) = args.iter().position(|arg| arg == "--network") else {
+            return Ok(args);
+        };
+        let network_id = args
+            .get(position + 1)
+            .context("--network requires a network ID")?;
+        let network = networks
+            .get_mut(network_id)
+            .with_context(|| format!("No network {network_id}"))?;
+        network.members.push(box_id.to_string());
+        let mut resolved = args[..position].to_vec();
+        resolved.extend([
+            "--veth-bridge".to_string(),
+            network::Hub::BRIDGE.to_string(),
+            "--veth-netns".to_string(),
+            network.hub.path(),
+        ]);
+        resolved.extend_from_slice(&args[position + 2..]);
+        Ok(resolved)
+    }
+}
+
+struct PoolMembership {
+    pool_id: String,
+    pool: Arc<Mutex<Pool>>,
+    // Whether the box has started, for the reply to create_pool
+    started: mpsc::Sender<std::result::Result<(), String>>,
+}
+
+pub fn serve() -> Result<()> {
+    let mut boxes: HashMap<String, mpsc::Sender<Request>> = HashMap::new();
+    let mut pools: HashMap<String, Arc<Mutex<Pool>>> = HashMap::new();
+    let mut networks: HashMap<String, Network> = HashMap::new();
+    let mut workers = Vec::new();
+
+    let mut stdin = std::io::BufReader::new(std::io::stdin());
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if stdin
+            .read_line(&mut line)
+            .context("Failed to read from stdin")?
+            == 0
+        {
+            break;
+        }
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let (id, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let (command, arg) = rest.split_once(' ').unwrap_or((rest, ""));
+        let command = command.to_lowercase();
+
+        // The payload has to be consumed even if the command is invalid, otherwise we'd interpret
+        // it as commands
+        let mut payload = None;
+        if let Ok(arg) = json::parse(arg) {
+            if let Some(len) = input_payload_len(&command, &arg) {
+                payload = Some(spool_payload(&mut stdin, len)?);
+            }
+        }
+
+        let result: Result<()> = try {
+            if !is_valid_id(id) {
+                Err(anyhow!(
+                    "Box ID must be a non-empty string of letters, digits, '-', and '_'"
+                ))?;
+            }
+            match command.as_str() {
+                "create" => {
+                    if boxes.contains_key(id) || pools.contains_key(id) || networks.contains_key(id)
+                    {
+                        Err(anyhow!("Box {id} already exists"))?;
+                    }
+                    let args = Network::resolve(&mut networks, id, parse_args(arg)?)?;
+                    let (tx, rx) = mpsc::channel();
+                    let id = id.to_string();
+                    boxes.insert(id.clone(), tx);
+                    workers.push(std::thread::spawn(move || serve_box(id, args, rx, None)));
+                }
+                "create_pool" => {
+                    if boxes.contains_key(id) || pools.contains_key(id) || networks.contains_key(id)
+                    {
+                        Err(anyhow!("Pool {id} already exists"))?;
+                    }
+                    let json::JsonValue::Array(array) = json::parse(arg).context("Invalid JSON")?
+                    else {
+                        Err(anyhow!("Expected an array of arguments to start per box"))?
+                    };
+                    if array.is_empty() {
+                        Err(anyhow!("A pool must contain at least one box"))?;
+                    }
+                    let members: Vec<String> =
+                        (0..array.len()).map(|i| format!("{id}-{i}")).collect();
+                    if let Some(member) = members.iter().find(|member| boxes.contains_key(*member))
+                    {
+                        Err(anyhow!("Box {member} already exists"))?;
+                    }
+                    let all_args = array
+                        .iter()
+                        .zip(&members)
+                        .map(|(args, member)| {
+                            Network::resolve(&mut networks, member, parse_args(&args.dump())?)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+
+                    let pool = Arc::new(Mutex::new(Pool {
+                        members: members.clone(),
+                        idle: VecDeque::new(),
+                        acquired: HashSet::new(),
+                        n_waiting: 0,
+                    }));
+                    pools.insert(id.to_string(), pool.clone());
+                    // The boxes start concurrently
+                    let (started_tx, started_rx) = mpsc::channel();
+                    for (member, args) in members.iter().zip(all_args) {
+                        let (tx, rx) = mpsc::channel();
+                        boxes.insert(member.clone(), tx);
+                        let member = member.clone();
+                        let membership = PoolMembership {
+                            pool_id: id.to_string(),
+                            pool: pool.clone(),
+                            started: started_tx.clone(),
+                        };
+                        workers.push(std::thread::spawn(move || {
+                            serve_box(member, args, rx, Some(membership))
+                        }));
+                    }
+                    drop(started_tx);
+                    // As with create, the reply is sent once the boxes have started, which doesn't
+                    // hold up the commands to the other boxes
+                    let id = id.to_string();
+                    workers.push(std::thread::spawn(move || {
+                        // The members keep their senders until they terminate, so only one message
+                        // per box is awaited
+                        let errors: Vec<String> = started_rx
+                            .iter()
+                            .take(members.len())
+                            .filter_map(|started| started.err())
+                            .collect();
+                        // The boxes that have started stay in the pool
+                        let line = if errors.is_empty() {
+                            format!("{id} ok")
+                        } else {
+                            let message = format!(
+                                "{} of {} boxes failed to start: {}",
+                                errors.len(),
+                                members.len(),
+                                errors.join("; ")
+                            );
+                            format!("{id} error {}", json::stringify(message))
+                        };
+                        Output::new()
+                            .send_line(&line)
+                            .expect("Failed to write to stdout");
+                    }));
+                }
+                "create_network" => {
+                    if boxes.contains_key(id) || pools.contains_key(id) || networks.contains_key(id)
+                    {
+                        Err(anyhow!("Network {id} already exists"))?;
+                    }
+                    let hub = network::Hub::create().context("Failed to create network")?;
+                    networks.insert(
+                        id.to_string(),
+                        Network {
+                            hub,
+                            members: Vec::new(),
+                        },
+                    );
+                    Output::new().send_line(&format!("{id} ok"))?;
+                }
+                "acquire" => {
+                    let mut pool = pools
+                        .get(id)
+                        .with_context(|| format!("No pool {id}"))?
+                        .lock()
+                        .unwrap();
+                    // Replied to once a box is released if all of them are in use
+                    match pool.idle.pop_front() {
+                        Some(box_id) => {
+                            send_acquired(id, &box_id);
+                            pool.acquired.insert(box_id);
+                        }
+                        None => pool.n_waiting += 1,
+                    }
+                }
+                "release" => {
+                    let pool = pools.get(id).with_context(|| format!("No pool {id}"))?;
+                    let box_id = json::parse(arg).context("Invalid JSON")?;
+                    let box_id = box_id.as_str().context("Expected a box ID")?;
+                    if !pool.lock().unwrap().acquired.remove(box_id) {
+                        Err(anyhow!("Box {box_id} is not acquired from pool {id}"))?;
+                    }
+                    if let Some(tx) = boxes.get(box_id) {
+                        let _ = tx.send(Request::Recycle);
+                    }
+                    Output::new().send_line(&format!("{id} ok"))?;
+                }
+                "destroy" => {
+                    if let Some(network) = networks.get(id) {
+                        // Destroying the namespace would pull the interfaces from under the boxes
+                        if let Some(member) = network
+                            .members
+                            .iter()
+                            .find(|member| boxes.contains_key(*member))
+                        {
+                            Err(anyhow!(
+                                "Box {member} is attached to network {id}, destroy it first"
+                            ))?;
+                        }
+                        networks.remove(id);
+                        Output::new().send_line(&format!("{id} ok"))?;
+                    } else if let Some(pool) = pools.remove(id) {
+                        let mut pool = pool.lock().unwrap();
+                        for member in &pool.members {
+                            if let Some(tx) = boxes.remove(member) {
+                                let _ = tx.send(Request::Destroy { quiet: true });
+                            }
+                        }
+                        for _ in 0..std::mem::take(&mut pool.n_waiting) {
+                            Output::new().send_line(&format!(
+                                "{id} error {}",
+                                json::stringify("The pool has been destroyed")
+                            ))?;
+                        }
+                        Output::new().send_line(&format!("{id} ok"))?;
+                    } else {
+                        if pools
+                            .values()
+                            .any(|pool| pool.lock().unwrap().members.iter().any(|m| m == id))
+                        {
+                            Err(anyhow!(
+                                "Box {id} belongs to a pool, destroy the pool instead"
+                            ))?;
+                        }
+                        let tx = boxes.remove(id).with_context(|| format!("No box {id}"))?;
+                        // The worker might have terminated already if the box crashed
+                        let _ = tx.send(Request::Destroy { quiet: false });
+                    }
+                }
+                _ => {
+                    let tx = boxes.get(id).with_context(|| format!("No box {id}"))?;
+                    let _ = tx.send(Request::Command {
+                        line: rest.to_string(),
+                        command,
+                        payload,
+                    });
+                }
+            }
+        };
+        if let Err(e) = result {
+            Output::new()
+                .send_line(&format!("{id} error {}", json::stringify(format!("{e:?}"))))?;
+        }
+    }
+
+    // Terminate all boxes gracefully
+    drop(boxes);
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+// The box might still be busy with the previous commands, so the payload can't be streamed to it
+// right away, and waiting for the box would delay the commands to the other boxes. It's spooled to
+// a memfd instead, which, unlike a buffer in the heap of the daemon, can be swapped out.
+fn spool_payload(stdin: &mut impl Read, len: u64) -> Result<std::fs::File> {
+    let fd = memfd::memfd_create(
+        CStr::from_bytes_with_nul(b"payload\0").unwrap(),
+        memfd::MemFdCreateFlag::MFD_CLOEXEC,
+    )
+    .context("Failed to create memfd for payload")?;
+    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+    let n_read =
+        std::io::copy(&mut stdin.take(len), &mut file).context("Failed to read payload")?;
+    if n_read != len {
+        bail!("Unexpected EOF in payload");
+    }
+    file.rewind().context("Failed to rewind payload")?;
+    Ok(file)
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn parse_args(arg: &str) -> Result<Vec<String>> {
+    let json::JsonValue::Array(array) = json::parse(arg).context("Invalid JSON")? else {
+        bail!("Expected an array of arguments to start");
+    };
+    array
+        .iter()
+        .map(|arg| {
+            arg.as_str()
+                .map(|arg| arg.to_string())
+                .context("Expected an array of arguments to start")
+        })
+        .collect()
+}
+
+fn serve_box(
+    id: String,
+    args: Vec<String>,
+    requests: mpsc::Receiver<Request>,
+    pool: Option<PoolMembership>,
+) {
+    let reply = |output: &mut Output, reply: Result<String>| {
+        let line = match reply {
+            Ok(reply) => format!("{id} {reply}"),
+            Err(e) => {
+                output.clear();
+                format!("{id} error {}", json::stringify(format!("{e:?}")))
+            }
+        };
+        output.send_line(&line).expect("Failed to write to stdout");
+    };
+
+    let mut connection = match (BoxConnection::spawn(&args), &pool) {
+        (Ok(connection), None) => {
+            reply(&mut Output::new(), Ok("ok".to_string()));
+            Some(connection)
+        }
+        (Err(e), None) => {
+            reply(&mut Output::new(), Err(e));
+            None
+        }
+        (Ok(connection), Some(membership)) => {
+            let _ = membership.started.send(Ok(()));
+            membership
+                .pool
+                .lock()
+                .unwrap()
+                .put(&membership.pool_id, id.clone());
+            Some(connection)
+        }
+        (Err(e), Some(membership)) => {
+            let _ = membership.started.send(Err(format!("{id}: {e:#}")));
+            None
+        }
+    };
+
+    while let Ok(request) = requests.recv() {
+        match request {
+            Request::Command {
+                line,
+                command,
+                payload,
+            } => {
+                let mut output = Output::new();
+                let result = match connection.as_mut() {
+                    Some(connection) => {
+                        connection.forward(&id, &line, &command, payload, &mut output)
+                    }
+                    None => Err(anyhow!("Box {id} has failed to start")),
+                };
+                reply(&mut output, result);
+            }
+            Request::Recycle => {
+                let Some(membership) = &pool else {
+                    continue;
+                };
+                let result = match connection.as_mut() {
+                    Some(connection) => connection
+                        .forward(&id, "reset", "reset", None, &mut Output::new())
+                        .and_then(|reply| match reply.strip_prefix("error ") {
+                            Some(e) => bail!("{e}"),
+                            None => Ok(()),
+                        }),
+                    None => Err(anyhow!("Box {id} has failed to start")),
+                };
+                // A box that can't be reset is replaced with a new one, so that the pool doesn't
+                // shrink
+                if let Err(e) = result {
+                    ::tracing::warn!(
+                        box_id = id,
+                        error = %format!("{e:#}"),
+                        "Failed to reset box, restarting it"
+                    );
+                    if let Some(connection) = connection.take() {
+                        let _ = connection.destroy();
+                    }
+                    match BoxConnection::spawn(&args) {
+                        Ok(new_connection) => connection = Some(new_connection),
+                        Err(e) => {
+                            ::tracing::error!(
+                                box_id = id,
+                                error = %format!("{e:#}"),
+                                "Failed to restart box"
+                            );
+                            continue;
+                        }
+                    }
+                }
+                membership
+                    .pool
+                    .lock()
+                    .unwrap()
+                    .put(&membership.pool_id, id.clone());
+            }
+            Request::Destroy { quiet } => {
+                let result = match connection.take() {
+                    Some(connection) => connection.destroy(),
+                    None => Ok(()),
+                };
+                if quiet {
+                    if let Err(e) = result {
+                        ::tracing::error!(
+                            box_id = id,
+                            error = %format!("{e:#}"),
+                            "Failed to destroy box"
+                        );
+                    }
+                } else {
+                    reply(&mut Output::new(), result.map(|()| "ok".to_string()));
+                }
+                return;
+            }
+        }
+    }
+
+    // The daemon is shutting down
+    if let Some(connection) = connection {
+        if let Err(e) = connection.destroy() {
+            ::tracing::error!(box_id = id, error = %format!("{e:#}"), "Failed to destroy box");
+        }
+    }
+}
+
+struct BoxConnection {
+    child: Child,
+    socket: UnixStream,
+    // Bytes received after the last reply, i.e. the beginning of its payload
+    buffer: Vec<u8>,
+}
+
+impl BoxConnection {
+    fn spawn(args: &[String]) -> Result<Self> {
+        let (ours, theirs) = UnixStream::pair().context("Failed to create socket pair")?;
+        let theirs = OwnedFd::from(theirs);
+        let mut command = std::process::Command::new(
+            std::env::current_exe().context("Failed to locate the executable")?,
+        );
+        command.arg("start");
+        // The boxes log like the daemon unless told otherwise
+        let log_options = logging::options();
+        for (name, value) in [
+            ("--log-level", log_options.level),
+            ("--log-format", log_options.format),
+        ] {
+            if !args.iter().any(|arg| arg == name) {
+                command.arg(name).arg(value);
+            }
+        }
+        let child = command
+            .args(args)
+            .stdin(Stdio::from(
+                theirs.try_clone().context("Failed to duplicate socket")?,
+            ))
+            .stdout(Stdio::from(theirs))
+            .spawn()
+            .context("Failed to start box")?;
+
+        let mut connection = Self {
+            child,
+            socket: ours,
+            buffer: Vec::new(),
+        };
+        // There is no readiness notification, so wait for a reply to a no-op command. If the box
+        // failed to start, it terminates without replying.
+        connection
+            .socket
+            .write_all(b"stat \"/\"\n")
+            .context("Box terminated during startup")?;
+        let (reply, _) = connection
+            .recv_line()
+            .context("Box terminated during startup")?;
+        if let Some(e) = reply.strip_prefix("error ") {
+            bail!("Box failed to start: {e}");
+        }
+        Ok(connection)
+    }
+
+    fn forward(
+        &mut self,
+        id: &str,
+        line: &str,
+        command: &str,
+        payload: Option<std::fs::File>,
+        output: &mut Output,
+    ) -> Result<String> {
+        self.socket
+            .write_all(format!("{line}\n").as_bytes())
+            .and_then(|()| match payload {
+                Some(mut payload) => std::io::copy(&mut payload, &mut self.socket).map(|_| ()),
+                None => Ok(()),
+            })
+            .context("Box terminated")?;
+
+        // Events of a run precede the reply to it. The descriptors sent with the reply might have
+        // arrived together with an event.
+        let mut fds = Vec::new();
+        let reply = loop {
+            let (line, received) = self.recv_line().context("Box terminated")?;
+            fds.extend(received);
+            match line.strip_prefix("event ") {
+                Some(event) => output.send_line(&format!("{id} event {event}"))?,
+                None => break line,
+            }
+        };
+        for fd in fds {
+            output.attach_fd(fd)?;
+        }
+
+        let (_, arg) = line.split_once(' ').unwrap_or((line, ""));
+        if let (Some(result), Ok(arg)) = (reply.strip_prefix("ok "), json::parse(arg)) {
+            let len = json::parse(result)
+                .ok()
+                .and_then(|result| output_payload_len(command, &arg, &result));
+            if let Some(len) = len {
+                // The payload is streamed by the worker thread while stdout is locked, so the
+                // replies of other boxes don't interleave with it
+                let buffered = std::mem::take(&mut self.buffer);
+                let socket = self
+                    .socket
+                    .try_clone()
+                    .context("Failed to duplicate socket")?;
+                output.attach_payload(Box::new(std::io::Cursor::new(buffered).chain(socket)), len);
+            }
+        }
+
+        Ok(reply)
+    }
+
+    fn recv_line(&mut self) -> Result<(String, Vec<OwnedFd>)> {
+        let mut fds = Vec::new();
+        loop {
+            if let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+                let rest = self.buffer.split_off(pos + 1);
+                let mut line = std::mem::replace(&mut self.buffer, rest);
+                line.pop();
+                let line = String::from_utf8(line).context("Reply is not UTF-8")?;
+                return Ok((line, fds));
+            }
+
+            let mut buf = [0u8; 4096];
+            let mut cmsg_buffer = nix::cmsg_space!([RawFd; 16]);
+            let message = socket::recvmsg::<()>(
+                self.socket.as_raw_fd(),
+                &mut [IoSliceMut::new(&mut buf)],
+                Some(&mut cmsg_buffer),
+                socket::MsgFlags::MSG_CMSG_CLOEXEC,
+            )
+            .context("Failed to receive reply")?;
+            for cmsg in message.cmsgs() {
+                if let socket::ControlMessageOwned::ScmRights(received) = cmsg {
+                    fds.extend(
+                        received
+                            .into_iter()
+                            .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }),
+                    );
+                }
+            }
+            let n_received = message.bytes;
+            if n_received == 0 {
+                bail!("Unexpected EOF");
+            }
+            self.buffer.extend_from_slice(&buf[..n_received]);
+        }
+    }
+
+    fn destroy(mut self) -> Result<()> {
+        // The box terminates when its stdin is closed
+        drop(self.socket);
+        let status = self.child.wait().context("Failed to wait for box")?;
+        if !status.success() {
+            bail!("Box terminated with {status}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use crate::linux::logging;
+use anyhow::{Context, Result};
+use multiprocessing::Object;
+use nix::libc;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::os::unix::io::{AsRawFd, OwnedFd};
+
+// With outbound networking, names are resolved by a stub resolver of the box rather than by the
+// resolver of the host, so that only the allowed host names resolve, and the judge can see what
+// the programs tried to look up. The stub doesn't forward anything: it answers with the addresses
+// the names were resolved to when the box started, which are exactly the addresses that are
+// routed. It listens on 127.0.0.53 in the network namespace of the box, and /etc/resolv.conf of
+// the box points there.
+
+pub const ADDRESS: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 53);
+
+#[derive(Object)]
+pub struct Query {
+    pub name: String,
+    pub query_type: u16,
+    pub allowed: bool,
+}
+
+pub struct Resolver {
+    log: multiprocessing::Receiver<Query>,
+}
+
+impl Resolver {
+    // Must be called in the network namespace of the box, with lo up
+    pub fn start(names: Vec<(String, Ipv4Addr)>) -> Result<Self> {
+        let socket = UdpSocket::bind((ADDRESS, 53))
+            .with_context(|| format!("Failed to bind to {ADDRESS}"))?;
+        let (log_tx, log_rx) = multiprocessing::channel().context("Failed to create channel")?;
+        let names = names
+            .into_iter()
+            .map(|(name, address)| (name, u32::from(address)))
+            .collect();
+        resolver
+            .spawn(logging::options(), OwnedFd::from(socket), names, log_tx)
+            .context("Failed to start resolver")?;
+        Ok(Self { log: log_rx })
+    }
+
+    // Returns the queries answered since the last call. The resolver logs a query before
+    // answering it, so all the queries of a finished run are there.
+    pub fn take_queries(&mut self) -> Result<Vec<Query>> {
+        let mut queries = Vec::new();
+        loop {
+            let mut fds = [nix::poll::PollFd::new(
+                self.log.as_raw_fd(),
+                nix::poll::PollFlags::POLLIN,
+            )];
+            if nix::poll::poll(&mut fds, 0).context("Failed to poll resolver")? == 0 {
+                return Ok(queries);
+            }
+            queries.push(
+                self.log
+                    .recv()
+                    .context("Failed to receive query")?
+                    .context("Resolver terminated")?,
+            );
+        }
+    }
+}
+
+pub fn queries_to_json(queries: &[Query]) -> json::JsonValue {
+    queries
+        .iter()
+        .map(|query| {
+            json::object! {
+                name: query.name.clone(),
+                type: type_name(query.query_type),
+                allowed: query.allowed,
+            }
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+fn type_name(query_type: u16) -> String {
+    match query_type {
+        1 => "A".to_string(),
+        2 => "NS".to_string(),
+        5 => "CNAME".to_string(),
+        6 => "SOA".to_string(),
+        12 => "PTR".to_string(),
+        15 => "MX".to_string(),
+        16 => "TXT".to_string(),
+        28 => "AAAA".to_string(),
+        33 => "SRV".to_string(),
+        65 => "HTTPS".to_string(),
+        255 => "ANY".to_string(),
+        _ => format!("TYPE{query_type}"),
+    }
+}
+
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+const RCODE_FORMERR: u8 = 1;
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_NOTIMP: u8 = 4;
+// Short, as the addresses are only valid for the lifetime of the box
+const TTL: u32 = 60;
+
+#[multiprocessing::entrypoint]
+fn resolver(
+    log_options: logging::Options,
+    socket: OwnedFd,
+    names: Vec<(String, u32)>,
+    mut log: multiprocessing::Sender<Query>,
+) {
+    logging::init(log_options).expect("Failed to configure logging");
+    let _span = ::tracing::info_span!("resolver").entered();
+
+    // The controller doesn't wait for the resolver, so it must not outlive it
+    if unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) } == -1 {
+        panic!(
+            "Failed to prctl(PR_SET_PDEATHSIG): {}",
+            std::io::Error::last_os_error()
+        );
+    }
+
+    let socket = UdpSocket::from(socket);
+    let mut request = [0; 512];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut request) {
+            Ok(received) => received,
+            Err(e) => {
+                ::tracing::warn!("Failed to receive DNS request: {e}");
+                continue;
+            }
+        };
+        let Some((response, query)) = answer(&request[..len], &names) else {
+            continue;
+        };
+        if let Some(query) = query {
+            if log.send(&query).is_err() {
+                // The controller is dead
+                return;
+            }
+        }
+        if let Err(e) = socket.send_to(&response, peer) {
+            ::tracing::warn!("Failed to send DNS response: {e}");
+        }
+    }
+}
+
+// Returns None if the request is too garbled to reply to
+fn answer(request: &[u8], names: &[(String, u32)]) -> Option<(Vec<u8>, Option<Query>)> {
+    if request.len() < 12 {
+        return None;
+    }
+    // QR is set in responses
+    if request[2] & 0x80 != 0 {
+        return None;
+    }
+
+    let reply = |rcode: u8, question: &[u8], answer: Option<u32>| -> Vec<u8> {
+        let mut response = Vec::with_capacity(12 + question.len() + 16);
+        response.extend_from_slice(&request[..2]);
+        // QR, the opcode and RD of the request, RA
+        response.push(0x80 | (request[2] & 0x79));
+        response.push(0x80 | rcode);
+        let qdcount: u16 = if question.is_empty() { 0 } else { 1 };
+        response.extend_from_slice(&qdcount.to_be_bytes());
+        response.extend_from_slice(&(answer.is_some() as u16).to_be_bytes());
+        response.extend_from_slice(&[0, 0, 0, 0]);
+        response.extend_from_slice(question);
+        if let Some(address) = answer {
+            // A pointer to the name in the question
+            response.extend_from_slice(&[0xc0, 12]);
+            response.extend_from_slice(&TYPE_A.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&TTL.to_be_bytes());
+            response.extend_from_slice(&4u16.to_be_bytes());
+            response.extend_from_slice(&address.to_be_bytes());
+        }
+        response
+    };
+
+    let opcode = (request[2] >> 3) & 0xf;
+    if opcode != 0 {
+        return Some((reply(RCODE_NOTIMP, &[], None), None));
+    }
+    let qdcount = u16::from_be_bytes([request[4], request[5]]);
+    if qdcount != 1 {
+        return Some((reply(RCODE_FORMERR, &[], None), None));
+    }
+
+    // Names in questions are never compressed
+    let mut labels = Vec::new();
+    let mut offset = 12;
+    loop {
+        let Some(&len) = request.get(offset) else {
+            return Some((reply(RCODE_FORMERR, &[], None), None));
+        };
+        offset += 1;
+        if len == 0 {
+            break;
+        }
+        if len & 0xc0 != 0 || offset + len as usize > request.len() {
+            return Some((reply(RCODE_FORMERR, &[], None), None));
+        }
+        labels.push(String::from_utf8_lossy(&request[offset..offset + len as usize]).into_owned());
+        offset += len as usize;
+    }
+    if offset + 4 > request.len() {
+        return Some((reply(RCODE_FORMERR, &[], None), None));
+    }
+    let query_type = u16::from_be_bytes([request[offset], request[offset + 1]]);
+    let query_class = u16::from_be_bytes([request[offset + 2], request[offset + 3]]);
+    let question = &request[12..offset + 4];
+
+    let name = labels.join(".");
+    let address = names
+        .iter()
+        .find(|(allowed, _)| allowed.trim_end_matches('.').eq_ignore_ascii_case(&name))
+        .map(|(_, address)| *address);
+    let query = Query {
+        name,
+        query_type,
+        allowed: address.is_some(),
+    };
+    let response = match address {
+        // Other types of records of an allowed name exist, but are empty
+        Some(address) if query_class == CLASS_IN => reply(
+            0,
+            question,
+            (query_type == TYPE_A || query_type == 255).then_some(address),
+        ),
+        Some(_) => reply(0, question, None),
+        None => reply(RCODE_NXDOMAIN, question, None),
+    };
+    Some((response, Some(query)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: &[(&str, Ipv4Addr)] = &[
+        ("pypi.org", Ipv4Addr::new(10, 0, 2, 100)),
+        ("files.pythonhosted.org.", Ipv4Addr::new(10, 0, 2, 101)),
+    ];
+
+    fn names() -> Vec<(String, u32)> {
+        NAMES
+            .iter()
+            .map(|&(name, address)| (name.to_string(), u32::from(address)))
+            .collect()
+    }
+
+    fn request(name: &str, query_type: u16) -> Vec<u8> {
+        // ID 0x1234, RD, one question
+        let mut request = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            request.push(label.len() as u8);
+            request.extend_from_slice(label.as_bytes());
+        }
+        request.push(0);
+        request.extend_from_slice(&query_type.to_be_bytes());
+        request.extend_from_slice(&CLASS_IN.to_be_bytes());
+        request
+    }
+
+    fn rcode(response: &[u8]) -> u8 {
+        response[3] & 0xf
+    }
+
+    fn ancount(response: &[u8]) -> u16 {
+        u16::from_be_bytes([response[6], response[7]])
+    }
+
+    #[test]
+    fn allowed_name() {
+        let request = request("pypi.org", TYPE_A);
+        let (response, query) = answer(&request, &names()).unwrap();
+        let query = query.unwrap();
+        assert_eq!(query.name, "pypi.org");
+        assert_eq!(query.query_type, TYPE_A);
+        assert!(query.allowed);
+
+        assert_eq!(response[..2], [0x12, 0x34]);
+        // QR, RD, RA
+        assert_eq!(response[2], 0x81);
+        assert_eq!(rcode(&response), 0);
+        assert_eq!(ancount(&response), 1);
+        // The question is repeated, and the answer points to its name
+        assert_eq!(response[12..request.len()], request[12..]);
+        assert_eq!(response[request.len()..request.len() + 2], [0xc0, 12]);
+        assert_eq!(response[response.len() - 4..], [10, 0, 2, 100]);
+    }
+
+    #[test]
+    fn allowed_name_is_case_insensitive() {
+        let (response, query) =
+            answer(&request("Files.PythonHosted.org", TYPE_A), &names()).unwrap();
+        assert!(query.unwrap().allowed);
+        assert_eq!(rcode(&response), 0);
+        assert_eq!(response[response.len() - 4..], [10, 0, 2, 101]);
+    }
+
+    #[test]
+    fn allowed_name_without_records_of_type() {
+        let (response, query) = answer(&request("pypi.org", 28), &names()).unwrap();
+        assert!(query.unwrap().allowed);
+        assert_eq!(rcode(&response), 0);
+        assert_eq!(ancount(&response), 0);
+    }
+
+    #[test]
+    fn denied_name() {
+        let (response, query) = answer(&request("example.com", TYPE_A), &names()).unwrap();
+        let query = query.unwrap();
+        assert_eq!(query.name, "example.com");
+        assert!(!query.allowed);
+        assert_eq!(rcode(&response), RCODE_NXDOMAIN);
+        assert_eq!(ancount(&response), 0);
+
+        // A suffix of an allowed name is a different name
+        let (response, query) = answer(&request("evil.pypi.org", TYPE_A), &names()).unwrap();
+        assert!(!query.unwrap().allowed);
+        assert_eq!(rcode(&response), RCODE_NXDOMAIN);
+    }
+
+    #[test]
+    fn ignored_packets() {
+        // Too short for a header
+        assert!(answer(&[0x12, 0x34, 0x01], &names()).is_none());
+        // A response rather than a request
+        let mut response = request("pypi.org", TYPE_A);
+        response[2] |= 0x80;
+        assert!(answer(&response, &names()).is_none());
+    }
+
+    #[test]
+    fn malformed_packets() {
+        let full = request("pypi.org", TYPE_A);
+        // Truncated in the middle of the name and in the middle of the type and class
+        for len in [14, full.len() - 2] {
+            let (response, query) = answer(&full[..len], &names()).unwrap();
+            assert_eq!(rcode(&response), RCODE_FORMERR, "{len}");
+            assert!(query.is_none());
+        }
+
+        let mut two_questions = full.clone();
+        two_questions[5] = 2;
+        let (response, query) = answer(&two_questions, &names()).unwrap();
+        assert_eq!(rcode(&response), RCODE_FORMERR);
+        assert!(query.is_none());
+
+        // An inverse query
+        let mut iquery = full;
+        iquery[2] |= 1 << 3;
+        let (response, query) = answer(&iquery, &names()).unwrap();
+        assert_eq!(rcode(&response), RCODE_NOTIMP);
+        assert!(query.is_none());
+    }
+
+    #[test]
+    fn compression_pointers() {
+        let header = &request("pypi.org", TYPE_A)[..12];
+        // Questions are never compressed, so a pointer is an error rather than something to follow,
+        // be it one to the start of the name, i.e. to itself, or one after a label
+        for name in [&[0xc0, 12][..], &[4, b'p', b'y', b'p', b'i', 0xc0, 12]] {
+            let request = [header, name, &TYPE_A.to_be_bytes(), &CLASS_IN.to_be_bytes()].concat();
+            let (response, query) = answer(&request, &names()).unwrap();
+            assert_eq!(rcode(&response), RCODE_FORMERR, "{name:?}");
+            assert!(query.is_none());
+        }
+    }
+}
@@ -1,65 +1,836 @@
 use crate::{
     entry,
-    linux::{cgroups, controller, manager, rootfs, running, sandbox},
+    linux::{
+        archive, cache, cgroups, controller, daemon, ids, image, landlock, logging, network,
+        rootfs, running, sandbox, seccomp, selftest, sha256, sparse, state, timens, userns,
+        watcher,
+    },
 };
-use anyhow::{bail, Context, Result};
-use std::collections::HashMap;
-use std::io::{BufRead, Read, Seek, SeekFrom, Write};
-use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use anyhow::{anyhow, bail, Context, Result};
+use nix::{
+    libc,
+    poll::{poll, PollFd, PollFlags},
+    sys::{memfd, socket},
+};
+use std::collections::{HashMap, HashSet};
+use std::ffi::CStr;
+use std::io::{BufRead, IoSlice, Read, Seek, SeekFrom, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::{
+    fs::{FileTypeExt, MetadataExt, PermissionsExt},
+    io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
+use std::path::Path;
 use std::time::Duration;
 
 pub fn main(cli_args: entry::CLIArgs) {
+    // Converting images doesn't involve boxes at all, and the daemon and the gRPC server leave the
+    // checks to the boxes they start
+    match cli_args.command {
+        entry::CLICommand::ImportImage(command) => {
+            image::import(command.image.as_ref(), command.output.as_ref())
+                .expect("Failed to import image");
+            return;
+        }
+        entry::CLICommand::Daemon(command) => {
+            logging::init(logging::Options {
+                level: command.log_level,
+                format: command.log_format,
+            })
+            .expect("Failed to configure logging");
+            daemon::serve().expect("Daemon failed");
+            return;
+        }
+        entry::CLICommand::Grpc(command) => {
+            logging::init(logging::Options {
+                level: command.log_level,
+                format: command.log_format,
+            })
+            .expect("Failed to configure logging");
+            #[cfg(feature = "grpc")]
+            {
+                crate::linux::grpc::serve(&command.listen).expect("gRPC server failed");
+                return;
+            }
+            #[cfg(not(feature = "grpc"))]
+            panic!("Built without the grpc feature, see `make grpc`");
+        }
+        entry::CLICommand::Resume(command) => {
+            state::resume(&command.state_dir).expect("Failed to resume box");
+            return;
+        }
+        // The sanity checks are a part of the report
+        entry::CLICommand::Selftest(command) => {
+            let all_ok = selftest::run(command.core).expect("Self-test failed");
+            std::process::exit(if all_ok { 0 } else { 1 });
+        }
+        _ => {}
+    }
+
     sandbox::sanity_checks().expect("Sanity checks failed");
 
     match cli_args.command {
         entry::CLICommand::Isolate(command) => {
-            cgroups::Cgroup::new(command.core).expect("Failed to create cgroup for core");
+            cgroups::Cgroup::new(command.core, &[]).expect("Failed to create cgroup for core");
         }
         entry::CLICommand::Free(command) => {
             cgroups::revert_core_isolation(command.core).expect("Failed to core revert isolation");
         }
         entry::CLICommand::Start(command) => {
+            logging::init(logging::Options {
+                level: command.log_level.clone(),
+                format: command.log_format.clone(),
+            })
+            .expect("Failed to configure logging");
+            let _span = ::tracing::info_span!("box", core = command.core).entered();
             start(command).expect("Failed to start box");
         }
+        entry::CLICommand::Cleanup(command) => {
+            state::cleanup(&command.state_dir).expect("Failed to clean up box");
+        }
+        entry::CLICommand::ImportImage(_)
+        | entry::CLICommand::Daemon(_)
+        | entry::CLICommand::Grpc(_)
+        | entry::CLICommand::Resume(_)
+        | entry::CLICommand::Selftest(_) => unreachable!(),
     }
 }
 
 fn start(cli_command: entry::CLIStartCommand) -> Result<()> {
+    let state_dir = cli_command
+        .state_dir
+        .as_deref()
+        .map(absolute_host_path)
+        .transpose()?;
+    if let Some(state_dir) = &state_dir {
+        match state::State::load(state_dir)? {
+            Some(state) if state.is_running()? => {
+                bail!("Another box with state directory {state_dir} is running");
+            }
+            // The previous box has been killed abruptly
+            Some(_) => state::cleanup(state_dir)?,
+            None => {}
+        }
+    }
+
+    // The handlers are installed before any threads and processes are created, so that the signal
+    // doesn't kill them instead
+    let shutdown_signal = watch_shutdown_signals()?;
+
     let quotas = rootfs::DiskQuotas {
         space: cli_command.quota_space,
         max_inodes: cli_command.quota_inodes,
     };
 
-    let mut controller = controller::Controller::try_new(quotas)?;
-    controller.join_core(cli_command.core)?;
-    controller.enter_root(cli_command.root.as_ref())?;
-    controller.start(cli_command)?;
+    let tmpfs_mounts = cli_command
+        .tmpfs
+        .iter()
+        .map(|tmpfs| tmpfs.parse())
+        .collect::<Result<Vec<rootfs::TmpfsMount>>>()
+        .context("Invalid --tmpfs option")?;
+    rootfs::check_tmpfs_overlaps(&tmpfs_mounts).context("Invalid --tmpfs option")?;
+
+    let mount_flags = cli_command
+        .mount_flags
+        .iter()
+        .map(|mount_flags| mount_flags.parse())
+        .collect::<Result<Vec<rootfs::MountFlags>>>()
+        .context("Invalid --mount-flags option")?;
+
+    let outbound = match &cli_command.slirp4netns {
+        Some(slirp4netns) => Some(network::OutboundOptions {
+            slirp4netns: slirp4netns.clone(),
+            allow: cli_command
+                .allow_host
+                .iter()
+                .map(|host| host.parse())
+                .collect::<Result<Vec<network::AllowedNetwork>>>()
+                .context("Invalid --allow-host option")?,
+        }),
+        None => {
+            if !cli_command.allow_host.is_empty() {
+                bail!("--allow-host requires --slirp4netns");
+            }
+            None
+        }
+    };
+
+    let veth = match &cli_command.veth_bridge {
+        Some(bridge) => {
+            if outbound.is_some() {
+                bail!("--veth-bridge and --slirp4netns are mutually exclusive");
+            }
+            let (address, prefix_len) = network::parse_address(
+                cli_command
+                    .veth_address
+                    .as_deref()
+                    .context("--veth-bridge requires --veth-address")?,
+            )
+            .context("Invalid --veth-address option")?;
+            let gateway = cli_command
+                .veth_gateway
+                .as_deref()
+                .map(|gateway| gateway.parse())
+                .transpose()
+                .context("Invalid --veth-gateway option")?;
+            if cli_command
+                .veth_loss
+                .is_some_and(|loss| !(0.0..=100.0).contains(&loss))
+            {
+                bail!("--veth-loss must be between 0 and 100");
+            }
+            Some(network::VethOptions {
+                bridge: bridge.clone(),
+                netns: cli_command.veth_netns.clone(),
+                address,
+                prefix_len,
+                gateway,
+                shaping: network::Shaping {
+                    rate: cli_command.veth_rate,
+                    delay: cli_command.veth_delay.map(Duration::from_secs_f64),
+                    jitter: cli_command.veth_jitter.map(Duration::from_secs_f64),
+                    loss: cli_command.veth_loss.map(|loss| loss / 100.0),
+                },
+            })
+        }
+        None => {
+            if cli_command.veth_netns.is_some()
+                || cli_command.veth_address.is_some()
+                || cli_command.veth_gateway.is_some()
+                || cli_command.veth_rate.is_some()
+                || cli_command.veth_delay.is_some()
+                || cli_command.veth_jitter.is_some()
+                || cli_command.veth_loss.is_some()
+            {
+                bail!("--veth-* options require --veth-bridge");
+            }
+            None
+        }
+    };
+
+    // The file is on the host, so it's read before the box enters its root
+    let config = match &cli_command.config {
+        Some(path) => Some((
+            path.clone(),
+            read_config_file(path).with_context(|| format!("Failed to read config file {path}"))?,
+        )),
+        None => None,
+    };
+
+    let setuid_policy = cli_command
+        .setuid_policy
+        .parse()
+        .context("Invalid --setuid-policy option")?;
+
+    let time_offsets =
+        timens::Offsets::new(cli_command.monotonic_offset, cli_command.boottime_offset)
+            .context("Invalid --monotonic-offset or --boottime-offset option")?;
+
+    let mut controller = controller::Controller::try_new(
+        quotas,
+        tmpfs_mounts,
+        cli_command.loopback,
+        outbound,
+        veth,
+    )?;
+    controller.join_core(cli_command.core, &cli_command.extra_core)?;
+    let mut roots = vec![cli_command.root.clone()];
+    roots.extend(cli_command.layer.iter().cloned());
+    controller.enter_root(
+        &roots,
+        rootfs::RootfsOptions {
+            prefer_overlay: cli_command.overlay_root,
+            writable_root: cli_command.writable_root,
+            masked_paths: cli_command.mask.clone(),
+            synthesize_etc: cli_command.synthesize_etc,
+            synthesize_resolv_conf: cli_command.slirp4netns.is_some(),
+            max_ptys: cli_command.max_ptys,
+            allow_fuse: cli_command.allow_fuse,
+            mount_flags,
+            setuid_policy,
+        },
+    )?;
+    if let Some(dir) = &cli_command.cache_dir {
+        controller.set_cache(
+            cache::Cache::open(dir).with_context(|| format!("Failed to open cache {dir}"))?,
+        );
+    }
+    let listen = cli_command
+        .listen
+        .as_deref()
+        .map(absolute_host_path)
+        .transpose()?;
+    let core = cli_command.core;
+    controller.start(cli_command, time_offsets)?;
+    ::tracing::info!("Box started");
+
+    if let Some(state_dir) = &state_dir {
+        state::State::of_self(
+            core,
+            controller
+                .proc_cgroup_id()
+                .context("No box cgroup")?
+                .to_string(),
+            listen.clone(),
+        )?
+        .save(rootfs::resolve_abs_old_root(state_dir)?)?;
+    }
+
+    // The options of start have been taken from the config file before parsing the command line
+    if let Some((path, config)) = config {
+        apply_config_file(&mut controller, config)
+            .with_context(|| format!("Failed to apply config file {path}"))?;
+    }
+
+    // Listen only after all the helper processes have been forked, so that they don't inherit the
+    // socket. The box has entered its root by now, so the socket is created via the old root.
+    let listener = match &listen {
+        Some(path) => Some(
+            UnixListener::bind(rootfs::resolve_abs_old_root(path)?)
+                .with_context(|| format!("Failed to listen on {path}"))?,
+        ),
+        None => None,
+    };
+
+    // Some commands are followed by binary payloads, so we can't just iterate over lines
+    let mut clients = vec![Client {
+        fd: libc::STDIN_FILENO,
+        input: std::io::BufReader::new(Input::stdin()?),
+        output: Output::new(),
+        protocol: Protocol::Text,
+    }];
+    let mut line = String::new();
+    loop {
+        // Commands are handled one at a time, whichever client they come from
+        let i = match clients
+            .iter()
+            .position(|client| !client.input.buffer().is_empty())
+        {
+            Some(i) => i,
+            None => {
+                let mut fds: Vec<PollFd> = clients
+                    .iter()
+                    .map(|client| PollFd::new(client.fd, PollFlags::POLLIN))
+                    .collect();
+                fds.push(PollFd::new(shutdown_signal.as_raw_fd(), PollFlags::POLLIN));
+                if let Some(listener) = &listener {
+                    fds.push(PollFd::new(listener.as_raw_fd(), PollFlags::POLLIN));
+                }
+                match poll(&mut fds, -1) {
+                    // The pipe is readable by now if a shutdown signal has arrived
+                    Ok(_) | Err(nix::errno::Errno::EINTR) => {}
+                    Err(e) => Err(e).context("Failed to poll clients")?,
+                }
+                let is_ready =
+                    |fd: &PollFd| fd.revents().is_some_and(|revents| !revents.is_empty());
+                if is_ready(&fds[clients.len()]) {
+                    ::tracing::info!("Shutting down on signal");
+                    break;
+                }
+                if let Some(listener) = &listener {
+                    if is_ready(fds.last().unwrap()) {
+                        let (stream, _) = listener.accept().context("Failed to accept client")?;
+                        ::tracing::debug!("Client connected");
+                        clients.push(Client {
+                            fd: stream.as_raw_fd(),
+                            input: std::io::BufReader::new(Input::from_socket(
+                                stream.try_clone().context("Failed to duplicate socket")?,
+                            )),
+                            output: Output::to_socket(stream),
+                            protocol: Protocol::Text,
+                        });
+                        continue;
+                    }
+                }
+                match fds.iter().position(is_ready) {
+                    Some(i) => i,
+                    None => continue,
+                }
+            }
+        };
+        let client = &mut clients[i];
 
-    for line in std::io::BufReader::new(std::io::stdin()).lines() {
-        let line = line.context("Failed to read from stdin")?;
-        let (command, arg) = line.split_once(' ').unwrap_or((&line, ""));
-        let command = command.to_lowercase();
-        match handle_command(&mut controller, &command, arg) {
-            Ok(None) => {
-                println!("ok");
+        line.clear();
+        let mut eof = false;
+        let result: Result<()> = try {
+            if client
+                .input
+                .read_line(&mut line)
+                .context("Failed to read command")?
+                == 0
+            {
+                eof = true;
+                Err(anyhow!("Unexpected EOF"))?;
             }
-            Ok(Some(s)) => {
-                println!("ok {s}");
+            let line = line.strip_suffix('\n').unwrap_or(&line);
+            let reply = handle_line(&mut controller, client, line);
+            client.output.send_line(&reply)?;
+            // Descriptors not claimed by the command must not be mistaken for those of the next
+            // one. If the next command has been read already, its descriptors are indistinguishable
+            // though.
+            if client.input.buffer().is_empty() {
+                client.input.get_mut().received_fds.clear();
             }
-            Err(e) => {
-                println!("error {}", json::stringify(format!("{e:?}")));
+        };
+        if let Err(e) = result {
+            if i == 0 {
+                // The box terminates when stdin is closed
+                if eof {
+                    break;
+                }
+                return Err(e);
             }
+            // Socket clients just come and go
+            ::tracing::debug!(error = %format!("{e:#}"), "Client disconnected");
+            clients.remove(i);
         }
     }
 
+    if let Some(path) = listen {
+        std::fs::remove_file(rootfs::resolve_abs_old_root(&path)?)
+            .with_context(|| format!("Failed to remove {path}"))?;
+    }
+    if let Some(state_dir) = &state_dir {
+        state::remove(rootfs::resolve_abs_old_root(state_dir)?)?;
+    }
     Ok(())
 }
 
+// Paths on the host are only reachable via the old root once the box has entered its own root, and
+// the working directory is lost by then, so relative paths are made absolute beforehand
+fn absolute_host_path(path: &str) -> Result<String> {
+    let path = std::env::current_dir()
+        .context("Failed to get current directory")?
+        .join(path);
+    path.into_os_string()
+        .into_string()
+        .map_err(|path| anyhow!("Path {path:?} is not UTF-8"))
+}
+
+// The write end of the pipe that SIGTERM and SIGINT are reported to
+static SHUTDOWN_PIPE: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+
+extern "C" fn on_shutdown_signal(_: libc::c_int) {
+    let fd = SHUTDOWN_PIPE.load(std::sync::atomic::Ordering::Relaxed);
+    unsafe {
+        libc::write(fd, b"\0".as_ptr() as *const libc::c_void, 1);
+    }
+}
+
+// SIGTERM and SIGINT make the box terminate gracefully once the current command is handled, as if
+// stdin was closed. Returns the read end of the pipe that becomes readable after such a signal.
+fn watch_shutdown_signals() -> Result<std::fs::File> {
+    let (read_end, write_end) =
+        nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC).context("Failed to create pipe")?;
+    SHUTDOWN_PIPE.store(write_end, std::sync::atomic::Ordering::Relaxed);
+    for signal in [
+        nix::sys::signal::Signal::SIGTERM,
+        nix::sys::signal::Signal::SIGINT,
+    ] {
+        unsafe {
+            nix::sys::signal::sigaction(
+                signal,
+                &nix::sys::signal::SigAction::new(
+                    nix::sys::signal::SigHandler::Handler(on_shutdown_signal),
+                    nix::sys::signal::SaFlags::empty(),
+                    nix::sys::signal::SigSet::empty(),
+                ),
+            )
+        }
+        .context("Failed to configure sigaction")?;
+    }
+    Ok(unsafe { std::fs::File::from_raw_fd(read_end) })
+}
+
+struct Client {
+    fd: RawFd,
+    input: std::io::BufReader<Input>,
+    output: Output,
+    protocol: Protocol,
+}
+
+// The version of the JSON protocol. Requests of newer versions are rejected, so that a judge relying
+// on a feature this box doesn't know about fails loudly instead of having the feature ignored.
+const PROTOCOL_VERSION: u64 = 1;
+
+#[derive(Clone, Copy)]
+enum Protocol {
+    // {COMMAND} {ARGUMENT}, replied to with ok/error lines
+    Text,
+    // {"version": ..., "id": ..., "command": ..., "argument": ...}, replied to with
+    // {"version": ..., "id": ..., "status": "ok", "result": ...} or
+    // {"version": ..., "id": ..., "status": "error", "error": ...}
+    Json,
+}
+
+fn handle_line(controller: &mut controller::Controller, client: &mut Client, line: &str) -> String {
+    // The reply to the protocol command itself is formatted according to the old protocol
+    match client.protocol {
+        Protocol::Text => {
+            let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+            let command = command.to_lowercase();
+            client.output.request_id = None;
+            match handle_client_command(controller, client, &command, arg) {
+                Ok(None) => "ok".to_string(),
+                Ok(Some(s)) => format!("ok {s}"),
+                Err(e) => {
+                    client.output.clear();
+                    format!("error {}", json::stringify(format!("{e:?}")))
+                }
+            }
+        }
+        Protocol::Json => {
+            let mut id = json::JsonValue::Null;
+            let result: Result<Option<String>> = try {
+                let mut request = json::parse(line).context("Invalid JSON")?;
+                id = request["id"].take();
+                client.output.request_id = Some(id.clone());
+                let version = request["version"]
+                    .as_u64()
+                    .context("Invalid 'version' field")?;
+                if version > PROTOCOL_VERSION {
+                    Err(anyhow!(
+                        "Unsupported protocol version {version}, the latest one is \
+                         {PROTOCOL_VERSION}"
+                    ))?;
+                }
+                let command = request["command"]
+                    .as_str()
+                    .context("Invalid 'command' field")?
+                    .to_lowercase();
+                // A missing argument is the same as an empty one in the text protocol
+                let arg = if request["argument"].is_null() {
+                    String::new()
+                } else {
+                    request["argument"].dump()
+                };
+                handle_client_command(controller, client, &command, &arg)?
+            };
+            match result {
+                Ok(value) => {
+                    let result = match value {
+                        None => json::JsonValue::Null,
+                        Some(s) => json::parse(&s).unwrap_or_else(|_| s.into()),
+                    };
+                    json::object! {
+                        version: PROTOCOL_VERSION,
+                        id: id,
+                        status: "ok",
+                        result: result,
+                    }
+                }
+                Err(e) => {
+                    client.output.clear();
+                    json::object! {
+                        version: PROTOCOL_VERSION,
+                        id: id,
+                        status: "error",
+                        error: format!("{e:?}"),
+                    }
+                }
+            }
+            .dump()
+        }
+    }
+}
+
+fn handle_client_command(
+    controller: &mut controller::Controller,
+    client: &mut Client,
+    command: &str,
+    arg: &str,
+) -> Result<Option<String>> {
+    let _span = ::tracing::info_span!("command", name = command).entered();
+    let start = std::time::Instant::now();
+    let result = dispatch_client_command(controller, client, command, arg);
+    match &result {
+        Ok(_) => ::tracing::debug!(elapsed = ?start.elapsed(), "Command succeeded"),
+        // Errors are reported to the client, so this is not a problem of the box per se
+        Err(e) => {
+            ::tracing::info!(elapsed = ?start.elapsed(), error = %format!("{e:#}"), "Command failed")
+        }
+    }
+    result
+}
+
+fn dispatch_client_command(
+    controller: &mut controller::Controller,
+    client: &mut Client,
+    command: &str,
+    arg: &str,
+) -> Result<Option<String>> {
+    if command != "protocol" {
+        return handle_command(
+            controller,
+            command,
+            arg,
+            &mut client.input,
+            &mut client.output,
+        );
+    }
+
+    let arg = json::parse(arg).context("Invalid JSON")?;
+    client.protocol = match arg["format"].as_str() {
+        Some("text") => Protocol::Text,
+        Some("json") => {
+            if !arg["version"].is_null() {
+                let version = arg["version"]
+                    .as_u64()
+                    .context("Invalid 'version' argument")?;
+                if version > PROTOCOL_VERSION {
+                    bail!(
+                        "Unsupported protocol version {version}, the latest one is \
+                         {PROTOCOL_VERSION}"
+                    );
+                }
+            }
+            Protocol::Json
+        }
+        _ => bail!("Invalid 'format' argument"),
+    };
+    Ok(Some(json::object! { version: PROTOCOL_VERSION }.dump()))
+}
+
+// Reads commands and payloads, keeping the descriptors the judge sends alongside them if the input
+// is a Unix socket
+struct Input {
+    fd: RawFd,
+    is_socket: bool,
+    // None for stdin, which must not be closed
+    _socket: Option<UnixStream>,
+    received_fds: std::collections::VecDeque<OwnedFd>,
+}
+
+impl Input {
+    fn stdin() -> Result<Self> {
+        let stat = nix::sys::stat::fstat(libc::STDIN_FILENO).context("Failed to stat stdin")?;
+        Ok(Self {
+            fd: libc::STDIN_FILENO,
+            is_socket: stat.st_mode & libc::S_IFMT == libc::S_IFSOCK,
+            _socket: None,
+            received_fds: std::collections::VecDeque::new(),
+        })
+    }
+
+    fn from_socket(socket: UnixStream) -> Self {
+        Self {
+            fd: socket.as_raw_fd(),
+            is_socket: true,
+            _socket: Some(socket),
+            received_fds: std::collections::VecDeque::new(),
+        }
+    }
+
+    // The descriptors are taken in the order they were sent
+    fn take_fds(&mut self, n: usize) -> Result<Vec<OwnedFd>> {
+        if self.received_fds.len() < n {
+            bail!(
+                "Expected {n} file descriptors alongside the command, got {}",
+                self.received_fds.len()
+            );
+        }
+        Ok(self.received_fds.drain(..n).collect())
+    }
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if !self.is_socket {
+            return Ok(nix::unistd::read(self.fd, buf)?);
+        }
+        let mut cmsg_buffer = nix::cmsg_space!([RawFd; 16]);
+        let message = socket::recvmsg::<()>(
+            self.fd,
+            &mut [std::io::IoSliceMut::new(buf)],
+            Some(&mut cmsg_buffer),
+            socket::MsgFlags::MSG_CMSG_CLOEXEC,
+        )?;
+        for cmsg in message.cmsgs() {
+            if let socket::ControlMessageOwned::ScmRights(fds) = cmsg {
+                self.received_fds.extend(
+                    fds.into_iter()
+                        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }),
+                );
+            }
+        }
+        Ok(message.bytes)
+    }
+}
+
+pub struct Output {
+    // Sent alongside the next reply
+    attached_fds: Vec<OwnedFd>,
+    // Sent right after the next reply, as raw bytes, one after another
+    attached_payloads: Vec<(Box<dyn Read>, u64)>,
+    // Where the replies go, stdout if None
+    socket: Option<UnixStream>,
+    // The ID of the JSON request being handled, which the events are tagged with. None in the text
+    // protocol.
+    request_id: Option<json::JsonValue>,
+}
+
+impl Output {
+    pub fn new() -> Self {
+        Self {
+            attached_fds: Vec::new(),
+            attached_payloads: Vec::new(),
+            socket: None,
+            request_id: None,
+        }
+    }
+
+    pub fn to_socket(socket: UnixStream) -> Self {
+        Self {
+            attached_fds: Vec::new(),
+            attached_payloads: Vec::new(),
+            socket: Some(socket),
+            request_id: None,
+        }
+    }
+
+    pub fn attach_fd(&mut self, fd: OwnedFd) -> Result<()> {
+        if self.socket.is_some() {
+            self.attached_fds.push(fd);
+            return Ok(());
+        }
+        let stat = nix::sys::stat::fstat(libc::STDOUT_FILENO).context("Failed to stat stdout")?;
+        if stat.st_mode & libc::S_IFMT != libc::S_IFSOCK {
+            bail!("File descriptors can only be passed if stdout is a Unix socket");
+        }
+        self.attached_fds.push(fd);
+        Ok(())
+    }
+
+    pub fn attach_payload(&mut self, payload: Box<dyn Read>, len: u64) {
+        self.attached_payloads.push((payload, len));
+    }
+
+    // Drops the attachments, e.g. when the command has failed after all
+    pub fn clear(&mut self) {
+        self.attached_fds.clear();
+        self.attached_payloads.clear();
+    }
+
+    // Used to drop the attachments of a single failed command in a batch
+    fn attachments_len(&self) -> (usize, usize) {
+        (self.attached_fds.len(), self.attached_payloads.len())
+    }
+
+    fn truncate_attachments(&mut self, (n_fds, n_payloads): (usize, usize)) {
+        self.attached_fds.truncate(n_fds);
+        self.attached_payloads.truncate(n_payloads);
+    }
+
+    pub fn send_line(&mut self, line: &str) -> Result<()> {
+        match self.socket.take() {
+            Some(mut socket) => {
+                let result = self.send_line_to(&mut socket, line);
+                self.socket = Some(socket);
+                result
+            }
+            None => self.send_line_to(&mut std::io::stdout().lock(), line),
+        }
+    }
+
+    // Events are sent before the reply to the command, so they must not carry its attachments
+    pub fn send_event(&mut self, event: &str) -> Result<()> {
+        let line = match &self.request_id {
+            None => format!("event {event}"),
+            Some(id) => json::object! {
+                version: PROTOCOL_VERSION,
+                id: id.clone(),
+                event: json::parse(event).context("Invalid event")?,
+            }
+            .dump(),
+        };
+        let attached_fds = std::mem::take(&mut self.attached_fds);
+        let attached_payloads = std::mem::take(&mut self.attached_payloads);
+        let result = self.send_line(&line);
+        self.attached_fds = attached_fds;
+        self.attached_payloads = attached_payloads;
+        result
+    }
+
+    fn send_line_to(&mut self, writer: &mut (impl Write + AsRawFd), line: &str) -> Result<()> {
+        let data = format!("{line}\n");
+        let mut data = data.as_bytes();
+
+        if !self.attached_fds.is_empty() {
+            // The descriptors are attached to the first byte of the reply, so the buffered part has
+            // to be written out first
+            writer.flush().context("Failed to flush stdout")?;
+            let fds: Vec<RawFd> = self.attached_fds.iter().map(|fd| fd.as_raw_fd()).collect();
+            let n_sent = socket::sendmsg::<()>(
+                writer.as_raw_fd(),
+                &[IoSlice::new(data)],
+                &[socket::ControlMessage::ScmRights(&fds)],
+                socket::MsgFlags::empty(),
+                None,
+            )
+            .context("Failed to send file descriptors")?;
+            data = &data[n_sent..];
+            // Our copies are not needed anymore
+            self.attached_fds.clear();
+        }
+
+        writer
+            .write_all(data)
+            .context("Failed to write to stdout")?;
+
+        for (mut payload, len) in self.attached_payloads.drain(..) {
+            // The length has already been announced, so there is no way to report an error to the
+            // judge if we fail to read exactly that many bytes, e.g. because the file has shrunk
+            // in the meantime. The rest is padded with zeros then, so that the stream stays framed.
+            let mut buf = vec![0; 64 * 1024];
+            let mut remaining = len;
+            while remaining > 0 {
+                let chunk = remaining.min(buf.len() as u64) as usize;
+                let n_read = match payload.read(&mut buf[..chunk]) {
+                    Ok(0) => break,
+                    Ok(n_read) => n_read,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        ::tracing::warn!("Failed to read payload: {e}");
+                        break;
+                    }
+                };
+                writer
+                    .write_all(&buf[..n_read])
+                    .context("Failed to stream payload")?;
+                remaining -= n_read as u64;
+            }
+            if remaining > 0 {
+                ::tracing::warn!(
+                    remaining,
+                    "Payload is shorter than announced, padding with zeros"
+                );
+                std::io::copy(&mut std::io::repeat(0).take(remaining), writer)
+                    .context("Failed to stream payload")?;
+            }
+        }
+
+        writer.flush().context("Failed to flush stdout")?;
+        Ok(())
+    }
+}
+
 fn handle_command(
     controller: &mut controller::Controller,
     command: &str,
     arg: &str,
+    input: &mut std::io::BufReader<Input>,
+    output: &mut Output,
 ) -> Result<Option<String>> {
+    // The paths are resolved in userspace and then opened by the controller, which has the host
+    // root at /oldroot, so a running program could replace a component with a symlink in between
+    if FILE_COMMANDS.contains(&command) && controller.has_pending_run() {
+        let arg = json::parse(arg).unwrap_or(json::JsonValue::Null);
+        if let Some(len) = input_payload_len(command, &arg) {
+            skip_payload(input, len)?;
+        }
+        bail!("A run is in progress, wait for it or kill it first");
+    }
+
     match command {
         "mkdir" => {
             let path = json::parse(arg)
@@ -72,57 +843,143 @@ fn handle_command(
             Ok(None)
         }
         "ls" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let (path, recursive);
+            if arg.is_string() {
+                path = arg.take_string().unwrap();
+                recursive = false;
+            } else {
+                path = arg["path"]
+                    .take_string()
+                    .context("Invalid 'path' argument")?;
+                recursive = !arg["recursive"].is_null()
+                    && arg["recursive"]
+                        .as_bool()
+                        .context("Invalid 'recursive' argument")?;
+            }
+            let mut entries = json::object! {};
+            list_directory(
+                &rootfs::resolve_abs_box_root(path)?,
+                "",
+                recursive,
+                &mut entries,
+            )?;
+            Ok(Some(entries.dump()))
+        }
+        "stat" => {
             let path = json::parse(arg)
                 .context("Invalid JSON")?
                 .take_string()
                 .context("Invalid command argument")?;
-            let mut entries = json::object! {};
-            for entry in std::fs::read_dir(rootfs::resolve_abs_box_root(path)?)? {
-                let entry = entry?;
-                let file_name = entry
-                    .file_name()
-                    .into_string()
-                    .or_else(|name| bail!("Invalid file name: {name:?}"))?;
-                let metadata = entry.metadata()?;
-                let file_type = metadata.file_type();
-                let permissions = metadata.permissions();
-                entries[file_name] = json::object! {
-                    file_type: if file_type.is_dir() {
-                        "dir"
-                    } else if file_type.is_file() {
-                        "file"
-                    } else if file_type.is_symlink() {
-                        "symlink"
-                    } else if file_type.is_block_device() {
-                        "block"
-                    } else if file_type.is_char_device() {
-                        "char"
-                    } else if file_type.is_fifo() {
-                        "fifo"
-                    } else if file_type.is_socket() {
-                        "socket"
-                    } else {
-                        "unknown"
-                    },
-                    len: metadata.len(),
-                    mode: permissions.mode(),
+            let metadata = std::fs::symlink_metadata(rootfs::resolve_abs_box_root(path)?)?;
+            Ok(Some(metadata_to_json(&metadata).dump()))
+        }
+        "setuid_files" => {
+            let files: Vec<json::JsonValue> = controller
+                .setuid_files()?
+                .iter()
+                .map(|file| {
+                    json::object! {
+                        path: file.path.clone(),
+                        mode: file.mode,
+                        uid: file.uid,
+                        gid: file.gid,
+                    }
+                })
+                .collect();
+            Ok(Some(json::JsonValue::Array(files).dump()))
+        }
+        "warmup" => {
+            let arg = json::parse(arg).context("Invalid JSON")?;
+            let json::JsonValue::Array(paths) = &arg["paths"] else {
+                bail!("Invalid 'paths' argument");
+            };
+            let max_bytes = if arg["max_bytes"].is_null() {
+                None
+            } else {
+                Some(
+                    arg["max_bytes"]
+                        .as_u64()
+                        .context("Invalid 'max_bytes' argument")?,
+                )
+            };
+            let mut warmup = Warmup {
+                files: 0,
+                bytes: 0,
+                max_bytes,
+                seen: HashSet::new(),
+            };
+            for path in paths {
+                let path = path.as_str().context("Invalid 'paths' argument")?;
+                let path = rootfs::resolve_abs_box_root(path)?;
+                // Paths from a trace recorded in another environment may be missing
+                let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+                    continue;
                 };
+                warmup.add(&path, &metadata)?;
             }
-            Ok(Some(entries.dump()))
+            Ok(Some(
+                json::object! { files: warmup.files, bytes: warmup.bytes }.dump(),
+            ))
+        }
+        "du" => {
+            let path = json::parse(arg)
+                .context("Invalid JSON")?
+                .take_string()
+                .context("Invalid command argument")?;
+            let path = rootfs::resolve_abs_box_root(path)?;
+            let metadata = std::fs::symlink_metadata(&path)?;
+            let mut usage = DiskUsage {
+                dev: metadata.dev(),
+                bytes: 0,
+                inodes: 0,
+                seen: HashSet::new(),
+            };
+            usage.add(&path, &metadata)?;
+            Ok(Some(
+                json::object! { bytes: usage.bytes, inodes: usage.inodes }.dump(),
+            ))
         }
         "cat" => {
             let mut arg = json::parse(arg).context("Invalid JSON")?;
-            let (path, at, len);
+            let (path, at, len, raw, sparse);
             if arg.is_string() {
                 path = arg.take_string().unwrap();
                 at = 0;
                 len = 0;
+                raw = false;
+                sparse = false;
             } else {
                 path = arg["path"]
                     .take_string()
                     .context("Invalid 'path' argument")?;
+
+                if !arg["fd"].is_null() && arg["fd"].as_bool().context("Invalid 'fd' argument")? {
+                    // Let the judge read the file directly instead of copying it via JSON
+                    let file = std::fs::File::open(rootfs::resolve_abs_box_root(path)?)
+                        .context("Failed to open file")?;
+                    if !file
+                        .metadata()
+                        .context("Failed to read metadata")?
+                        .is_file()
+                    {
+                        bail!("The passed path does not refer to a regular file");
+                    }
+                    output.attach_fd(file.into())?;
+                    return Ok(None);
+                }
+
                 at = arg["at"].as_usize().context("Invalid 'at' argument")?;
                 len = arg["len"].as_usize().context("Invalid 'len' argument")?;
+                raw = !arg["raw"].is_null()
+                    && arg["raw"].as_bool().context("Invalid 'raw' argument")?;
+                sparse = !arg["sparse"].is_null()
+                    && arg["sparse"]
+                        .as_bool()
+                        .context("Invalid 'sparse' argument")?;
+                if sparse && !raw {
+                    bail!("Sparse mode is only supported together with raw mode");
+                }
             }
 
             let mut file = std::fs::File::open(rootfs::resolve_abs_box_root(path)?)
@@ -136,6 +993,21 @@ fn handle_command(
                 // Might be a special file
                 let mut buf = vec![];
                 file.read_to_end(&mut buf)?;
+                if raw {
+                    let buf_len = buf.len() as u64;
+                    output.attach_payload(Box::new(std::io::Cursor::new(buf)), buf_len);
+                    let mut reply = json::object! { len: buf_len };
+                    if sparse {
+                        // Special files have no holes
+                        let extents = if buf_len == 0 {
+                            vec![]
+                        } else {
+                            vec![(0, buf_len)]
+                        };
+                        reply["extents"] = sparse::extents_to_json(&extents);
+                    }
+                    return Ok(Some(reply.dump()));
+                }
                 return Ok(Some(json::stringify(buf)));
             }
             if at > file_len {
@@ -147,6 +1019,25 @@ fn handle_command(
             }
             file.seek(SeekFrom::Start(at as u64))
                 .context("Failed to seek")?;
+            if sparse {
+                // Only send the data, the judge can recreate the holes from the extents
+                let extents = sparse::data_extents(&file, at as u64, (at + read_len) as u64)?;
+                let payload_len: u64 = extents.iter().map(|(_, len)| len).sum();
+                let reply = json::object! {
+                    len: payload_len,
+                    extents: sparse::extents_to_json(&extents),
+                };
+                output.attach_payload(
+                    Box::new(sparse::ExtentReader::new(file, extents)),
+                    payload_len,
+                );
+                return Ok(Some(reply.dump()));
+            }
+            if raw {
+                // Stream the file instead of reading it into memory, as it may be huge
+                output.attach_payload(Box::new(file), read_len as u64);
+                return Ok(Some(json::object! { len: read_len }.dump()));
+            }
             let mut buf = vec![0u8; read_len];
             let mut ptr: usize = 0;
             while ptr < read_len {
@@ -159,6 +1050,117 @@ fn handle_command(
             buf.truncate(ptr);
             Ok(Some(json::stringify(buf)))
         }
+        "hash" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let path;
+            if arg.is_string() {
+                path = arg.take_string().unwrap();
+            } else {
+                path = arg["path"]
+                    .take_string()
+                    .context("Invalid 'path' argument")?;
+                if !arg["algo"].is_null() && arg["algo"] != "sha256" {
+                    bail!("Unsupported hash algorithm");
+                }
+            }
+
+            let mut file = std::fs::File::open(rootfs::resolve_abs_box_root(path)?)
+                .context("Failed to open file")?;
+            if !file
+                .metadata()
+                .context("Failed to read metadata")?
+                .is_file()
+            {
+                bail!("The passed path does not refer to a regular file");
+            }
+            let mut hasher = sha256::Sha256::new();
+            std::io::copy(&mut file, &mut hasher).context("Failed to read file")?;
+            let digest: String = hasher
+                .finish()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            Ok(Some(json::stringify(digest)))
+        }
+        "tar" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let (path, fd);
+            if arg.is_string() {
+                path = arg.take_string().unwrap();
+                fd = false;
+            } else {
+                path = arg["path"]
+                    .take_string()
+                    .context("Invalid 'path' argument")?;
+                fd =
+                    !arg["fd"].is_null() && arg["fd"].as_bool().context("Invalid 'fd' argument")?;
+            }
+
+            // The size of the archive is unknown in advance, so build it in memory first
+            let mut archive = std::fs::File::from(unsafe {
+                OwnedFd::from_raw_fd(
+                    memfd::memfd_create(
+                        CStr::from_bytes_with_nul(b"sunwalker_box_archive\0").unwrap(),
+                        memfd::MemFdCreateFlag::MFD_CLOEXEC,
+                    )
+                    .context("Failed to create memfd")?,
+                )
+            });
+            let mut writer = std::io::BufWriter::new(&mut archive);
+            archive::write_tar(&rootfs::resolve_abs_box_root(path)?, &mut writer)?;
+            writer.flush().context("Failed to write archive")?;
+            drop(writer);
+            let archive_len = archive
+                .stream_position()
+                .context("Failed to tell position")?;
+            archive.rewind().context("Failed to rewind archive")?;
+
+            if fd {
+                output.attach_fd(archive.into())?;
+                Ok(None)
+            } else {
+                output.attach_payload(Box::new(archive), archive_len);
+                Ok(Some(json::object! { len: archive_len }.dump()))
+            }
+        }
+        "watch" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let (path, fd);
+            if arg.is_string() {
+                path = arg.take_string().unwrap();
+                fd = false;
+            } else {
+                path = arg["path"]
+                    .take_string()
+                    .context("Invalid 'path' argument")?;
+                fd =
+                    !arg["fd"].is_null() && arg["fd"].as_bool().context("Invalid 'fd' argument")?;
+            }
+
+            let wd;
+            if fd {
+                // Let the judge wait for events on their own, e.g. while a program is running
+                let inotify = watcher::create_inotify()?;
+                wd = watcher::add_watch(&inotify, &rootfs::resolve_abs_box_root(path)?)?;
+                output.attach_fd(inotify)?;
+            } else {
+                wd = controller.watch(&path)?;
+            }
+            Ok(Some(json::object! { wd: wd }.dump()))
+        }
+        "watch_events" => {
+            let arg = json::parse(arg).context("Invalid JSON")?;
+            let timeout = if arg.is_null() {
+                0.0
+            } else {
+                arg["timeout"]
+                    .as_f64()
+                    .context("Invalid 'timeout' argument")?
+            };
+            let timeout =
+                Duration::try_from_secs_f64(timeout).context("Invalid 'timeout' argument")?;
+            Ok(Some(controller.poll_watch_events(timeout)?.dump()))
+        }
         "mkfile" => {
             let mut arg = json::parse(arg).context("Invalid JSON")?;
             let path = arg["path"]
@@ -174,13 +1176,157 @@ fn handle_command(
                 }
                 _ => bail!("Invalid 'content' argument"),
             };
+            let attributes = FileAttributes::parse(&arg)?;
             let path = rootfs::resolve_abs_box_root(path)?;
             controller.ensure_allowed_to_modify(&path)?;
             let mut file = std::fs::File::create(path)?;
+            attributes.apply(&file)?;
             file.write_all(&content)?;
             Ok(None)
         }
-        "mksymlink" => {
+        "write" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let len = arg["len"].as_u64().context("Invalid 'len' argument")?;
+            let mut payload = input.take(len);
+
+            let result: Result<()> = try {
+                let path = arg["path"]
+                    .take_string()
+                    .context("Invalid 'path' argument")?;
+                let attributes = FileAttributes::parse(&arg)?;
+                let path = rootfs::resolve_abs_box_root(path)?;
+                controller.ensure_allowed_to_modify(&path)?;
+                let mut file = std::fs::File::create(path)?;
+                attributes.apply(&file)?;
+                if arg["extents"].is_null() {
+                    std::io::copy(&mut payload, &mut file).context("Failed to write file")?;
+                } else {
+                    // Sparse mode: the payload only contains the data extents
+                    let size = arg["size"].as_u64().context("Invalid 'size' argument")?;
+                    let extents = sparse::extents_from_json(&arg["extents"], size)?;
+                    if extents.iter().map(|(_, len)| len).sum::<u64>() != len {
+                        Err(anyhow!("The total length of extents does not match 'len'"))?;
+                    }
+                    file.set_len(size).context("Failed to resize file")?;
+                    sparse::write_extents(&mut file, &extents, &mut payload)?;
+                }
+            };
+
+            // The payload has to be consumed even if writing failed, otherwise we'd interpret the
+            // rest of it as commands
+            std::io::copy(&mut payload, &mut std::io::sink()).context("Failed to read payload")?;
+            if payload.limit() > 0 {
+                bail!("Unexpected EOF in payload");
+            }
+
+            result?;
+            Ok(None)
+        }
+        "untar" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let len = arg["len"].as_u64().context("Invalid 'len' argument")?;
+            let mut payload = input.take(len);
+
+            let result: Result<()> = try {
+                let path = arg["path"]
+                    .take_string()
+                    .context("Invalid 'path' argument")?;
+                let path = rootfs::resolve_abs_box_root(path)?;
+                controller.ensure_allowed_to_modify(&path)?;
+                archive::extract_tar(&mut payload, &path)?;
+            };
+
+            // Same as with write, and the archive might also be followed by garbage
+            std::io::copy(&mut payload, &mut std::io::sink()).context("Failed to read payload")?;
+            if payload.limit() > 0 {
+                bail!("Unexpected EOF in payload");
+            }
+
+            result?;
+            Ok(None)
+        }
+        "rm" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let (path, recursive);
+            if arg.is_string() {
+                path = arg.take_string().unwrap();
+                recursive = false;
+            } else {
+                path = arg["path"]
+                    .take_string()
+                    .context("Invalid 'path' argument")?;
+                recursive = !arg["recursive"].is_null()
+                    && arg["recursive"]
+                        .as_bool()
+                        .context("Invalid 'recursive' argument")?;
+            }
+            let path = rootfs::resolve_abs_box_root_nofollow(path)?;
+            controller.ensure_allowed_to_modify(&path)?;
+            sandbox::remove_path(&path, recursive)?;
+            Ok(None)
+        }
+        "mv" | "cp" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let from = arg["from"]
+                .take_string()
+                .context("Invalid 'from' argument")?;
+            let to = arg["to"].take_string().context("Invalid 'to' argument")?;
+            let from = rootfs::resolve_abs_box_root_nofollow(from)?;
+            let to = rootfs::resolve_abs_box_root_nofollow(to)?;
+            controller.ensure_allowed_to_modify(&to)?;
+            if to == from {
+                bail!("Cannot {command} {from:?} to itself");
+            }
+            if to.starts_with(&from) {
+                bail!("Cannot {command} a directory into itself");
+            }
+            if command == "mv" {
+                controller.ensure_allowed_to_modify(&from)?;
+                sandbox::move_path(&from, &to)?;
+            } else {
+                let recursive = !arg["recursive"].is_null()
+                    && arg["recursive"]
+                        .as_bool()
+                        .context("Invalid 'recursive' argument")?;
+                sandbox::copy_path(&from, &to, recursive)?;
+            }
+            Ok(None)
+        }
+        "upload" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let len = arg["len"].as_u64().context("Invalid 'len' argument")?;
+            let mut payload = input.take(len);
+
+            let result: Result<()> = try {
+                let path = arg["path"]
+                    .take_string()
+                    .context("Invalid 'path' argument")?;
+                let entries = parse_manifest(&arg["entries"])?;
+                let total_len: u64 = entries
+                    .iter()
+                    .map(|entry| match entry.kind {
+                        archive::ManifestEntryKind::File { len } => len,
+                        _ => 0,
+                    })
+                    .sum();
+                if total_len != len {
+                    Err(anyhow!("The total length of files does not match 'len'"))?;
+                }
+                let path = rootfs::resolve_abs_box_root(path)?;
+                controller.ensure_allowed_to_modify(&path)?;
+                archive::extract_manifest(&entries, &mut payload, &path)?;
+            };
+
+            // Same as with write
+            std::io::copy(&mut payload, &mut std::io::sink()).context("Failed to read payload")?;
+            if payload.limit() > 0 {
+                bail!("Unexpected EOF in payload");
+            }
+
+            result?;
+            Ok(None)
+        }
+        "mksymlink" => {
             let mut arg = json::parse(arg).context("Invalid JSON")?;
             let link = arg["link"]
                 .take_string()
@@ -194,6 +1340,18 @@ fn handle_command(
             Ok(None)
         }
         "bind" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let (external, internal, options) = parse_bind_arg(&mut arg)?;
+            controller.bind(&external, &internal, options)?;
+            Ok(None)
+        }
+        "volume" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let (external, internal, options) = parse_bind_arg(&mut arg)?;
+            controller.add_volume(&external, &internal, options)?;
+            Ok(None)
+        }
+        "link" => {
             let mut arg = json::parse(arg).context("Invalid JSON")?;
             let external = arg["external"]
                 .take_string()
@@ -201,125 +1359,1160 @@ fn handle_command(
             let internal = arg["internal"]
                 .take_string()
                 .context("Invalid 'internal' argument")?;
-            let ro = arg["ro"].as_bool().context("Invalid 'ro' argument")?;
-            controller.bind(&external, &internal, ro)?;
+            let hardlink = !arg["hardlink"].is_null()
+                && arg["hardlink"]
+                    .as_bool()
+                    .context("Invalid 'hardlink' argument")?;
+            let persistent = !arg["persistent"].is_null()
+                && arg["persistent"]
+                    .as_bool()
+                    .context("Invalid 'persistent' argument")?;
+            let method = controller.link(&external, &internal, hardlink, persistent)?;
+            Ok(Some(json::object! { method: method }.dump()))
+        }
+        "share" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let path = arg["path"]
+                .take_string()
+                .context("Invalid 'path' argument")?;
+            let size = arg["size"].as_u64().context("Invalid 'size' argument")?;
+            let region = controller.share_memory(&path, size)?;
+            output.attach_fd(region)?;
+            Ok(None)
+        }
+        "bind_out" => {
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let internal = arg["internal"]
+                .take_string()
+                .context("Invalid 'internal' argument")?;
+            let external = arg["external"]
+                .take_string()
+                .context("Invalid 'external' argument")?;
+            controller.bind_out(&internal, &external)?;
             Ok(None)
         }
         "reset" => {
             controller.reset()?;
             Ok(None)
         }
+        "snapshot" => {
+            controller.snapshot()?;
+            Ok(None)
+        }
+        "drop_snapshot" => {
+            controller.drop_snapshot()?;
+            Ok(None)
+        }
         "run" => {
             let mut arg = json::parse(arg).context("Invalid JSON")?;
-            if !arg["argv"].is_array() {
-                bail!("Invalid 'argv' argument");
-            }
+            let is_async = !arg["async"].is_null()
+                && arg["async"].as_bool().context("Invalid 'async' argument")?;
+            let events = !arg["events"].is_null()
+                && arg["events"]
+                    .as_bool()
+                    .context("Invalid 'events' argument")?;
 
-            let mut argv = Vec::with_capacity(arg["argv"].len());
-            for arg in arg["argv"].members_mut() {
-                argv.push(arg.take_string().context("Invalid 'argv' argument")?);
-            }
-            if argv.is_empty() {
-                bail!("'argv' is empty");
-            }
+            // Several programs can be run together, e.g. a solution and an interactor
+            let is_group = !arg["programs"].is_null();
 
-            let stdin = if arg["stdin"].is_null() {
-                "/dev/null".to_string()
-            } else {
-                arg["stdin"]
-                    .take_string()
-                    .context("Invalid 'stdin' argument")?
-            };
-            let stdout = if arg["stdout"].is_null() {
-                "/dev/null".to_string()
-            } else {
-                arg["stdout"]
-                    .take_string()
-                    .context("Invalid 'stdout' argument")?
-            };
-            let stderr = if arg["stderr"].is_null() {
-                "/dev/null".to_string()
+            // The judge declares what the run produces, and if the same key has been run before,
+            // the outputs are restored instead of running the program again. This is done before
+            // parsing the options, as some of them pass descriptors to the judge
+            let cached_run = if arg["cache"].is_null() {
+                None
             } else {
-                arg["stderr"]
-                    .take_string()
-                    .context("Invalid 'stderr' argument")?
+                if is_group || is_async {
+                    bail!("'cache' is only supported for a single synchronous program");
+                }
+                let cached_run = cache::CachedRun::parse(&arg["cache"])?;
+                if let Some(mut result) = controller.cache()?.lookup(&cached_run)? {
+                    result["cached"] = true.into();
+                    return Ok(Some(result.dump()));
+                }
+                Some(cached_run)
             };
 
-            let real_time_limit = if arg["real_time_limit"].is_null() {
-                None
+            let mut programs = Vec::new();
+            let mut scratch_files = Vec::new();
+            if is_group {
+                if !arg["programs"].is_array() || arg["programs"].is_empty() {
+                    bail!("Invalid 'programs' argument");
+                }
+                for program in arg["programs"].members_mut() {
+                    controller.apply_run_defaults(program);
+                    let (options, scratch) = parse_run_options(program, input.get_mut(), output)?;
+                    programs.push(options);
+                    scratch_files.extend(scratch);
+                }
             } else {
-                Some(Duration::from_secs_f64(
-                    arg["real_time_limit"]
-                        .as_f64()
-                        .context("Invalid 'real_time_limit' argument")?,
-                ))
+                controller.apply_run_defaults(&mut arg);
+                let (options, scratch) = parse_run_options(&mut arg, input.get_mut(), output)?;
+                programs.push(options);
+                scratch_files.extend(scratch);
+            }
+
+            let id = controller.start_run(programs, is_group, events, scratch_files)?;
+            if is_async {
+                return Ok(Some(json::object! { id: id }.dump()));
+            }
+            let result = controller.wait_run(id, None, &mut |event| output.send_event(event))?;
+            let Some(cached_run) = cached_run else {
+                return Ok(result);
             };
-            let cpu_time_limit = if arg["cpu_time_limit"].is_null() {
-                None
+            let mut result =
+                json::parse(result.as_deref().context("No result")?).context("Invalid result")?;
+            // Failed builds are not cached, as they might be caused by the environment, e.g. by a
+            // time limit on a loaded machine
+            if result["verdict"]["kind"] == "OK" {
+                // The result is still valid if the cache is unavailable
+                if let Err(e) = controller.cache()?.store(&cached_run, &result) {
+                    ::tracing::warn!(
+                        key = cached_run.key,
+                        error = %format!("{e:#}"),
+                        "Failed to store run in cache"
+                    );
+                }
+            }
+            result["cached"] = false.into();
+            Ok(Some(result.dump()))
+        }
+        "wait" | "poll" => {
+            let id = json::parse(arg)
+                .context("Invalid JSON")?
+                .as_u64()
+                .context("Invalid command argument")?;
+            let timeout = if command == "poll" {
+                Some(Duration::ZERO)
             } else {
-                Some(Duration::from_secs_f64(
-                    arg["cpu_time_limit"]
-                        .as_f64()
-                        .context("Invalid 'cpu_time_limit' argument")?,
-                ))
-            };
-            let idleness_time_limit = if arg["idleness_time_limit"].is_null() {
                 None
-            } else {
-                Some(Duration::from_secs_f64(
-                    arg["idleness_time_limit"]
-                        .as_f64()
-                        .context("Invalid 'idleness_time_limit' argument")?,
-                ))
             };
-            let memory_limit = if arg["memory_limit"].is_null() {
-                None
+            Ok(Some(
+                controller
+                    .wait_run(id, timeout, &mut |event| output.send_event(event))?
+                    .unwrap_or_else(|| "null".to_string()),
+            ))
+        }
+        "kill" => {
+            let arg = json::parse(arg).context("Invalid JSON")?;
+            let (id, program, signal);
+            if arg.is_number() {
+                id = arg.as_u64().context("Invalid command argument")?;
+                program = None;
+                signal = libc::SIGKILL;
             } else {
-                Some(
-                    arg["memory_limit"]
-                        .as_usize()
-                        .context("Invalid 'memory_limit' argument")?,
-                )
-            };
-            let processes_limit = if arg["processes_limit"].is_null() {
-                None
+                id = arg["id"].as_u64().context("Invalid 'id' argument")?;
+                program = if arg["program"].is_null() {
+                    None
+                } else {
+                    Some(
+                        arg["program"]
+                            .as_usize()
+                            .context("Invalid 'program' argument")?,
+                    )
+                };
+                signal = if arg["signal"].is_null() {
+                    libc::SIGKILL
+                } else {
+                    arg["signal"]
+                        .as_i32()
+                        .context("Invalid 'signal' argument")?
+                };
+            }
+            controller.kill_run(id, program, signal)?;
+            Ok(None)
+        }
+        "pause" | "resume" => {
+            let arg = json::parse(arg).context("Invalid JSON")?;
+            let (id, program);
+            if arg.is_number() {
+                id = arg.as_u64().context("Invalid command argument")?;
+                program = None;
             } else {
-                Some(
-                    arg["processes_limit"]
-                        .as_usize()
-                        .context("Invalid 'processes_limit' argument")?,
-                )
+                id = arg["id"].as_u64().context("Invalid 'id' argument")?;
+                program = if arg["program"].is_null() {
+                    None
+                } else {
+                    Some(
+                        arg["program"]
+                            .as_usize()
+                            .context("Invalid 'program' argument")?,
+                    )
+                };
+            }
+            controller.pause_run(id, program, command == "pause")?;
+            Ok(None)
+        }
+        "run_batch" => {
+            // The same program is run on several tests, typically with different standard streams,
+            // without a round trip per test
+            let mut arg = json::parse(arg).context("Invalid JSON")?;
+            let program = arg["program"].take();
+            if !program.is_object() {
+                bail!("Invalid 'program' argument");
+            }
+            if !arg["tests"].is_array() || arg["tests"].is_empty() {
+                bail!("Invalid 'tests' argument");
+            }
+            let stop_on_failure = !arg["stop_on_failure"].is_null()
+                && arg["stop_on_failure"]
+                    .as_bool()
+                    .context("Invalid 'stop_on_failure' argument")?;
+
+            let mut results = Vec::new();
+            for test in arg["tests"].members() {
+                let result: Result<json::JsonValue> = try {
+                    if !test.is_object() {
+                        Err(anyhow!("Invalid test {test}"))?;
+                    }
+                    let mut options = program.clone();
+                    for (key, value) in test.entries() {
+                        options[key] = value.clone();
+                    }
+                    if !options["async"].is_null() || !options["programs"].is_null() {
+                        Err(anyhow!(
+                            "Tests of a batch run cannot be asynchronous or groups"
+                        ))?;
+                    }
+                    let result = handle_command(controller, "run", &options.dump(), input, output)?
+                        .context("No result")?;
+                    json::parse(&result).context("Invalid result")?
+                };
+                match result {
+                    Ok(result) => {
+                        let failed = result["verdict"]["kind"] != "OK";
+                        results.push(json::object! { status: "ok", result: result });
+                        if stop_on_failure && failed {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        results.push(json::object! { status: "error", error: format!("{e:?}") });
+                        break;
+                    }
+                }
+            }
+            Ok(Some(json::JsonValue::from(results).dump()))
+        }
+        "batch" => {
+            let json::JsonValue::Array(requests) = json::parse(arg).context("Invalid JSON")? else {
+                bail!("Expected an array of commands");
             };
+            let mut requests = requests.into_iter();
+            let mut results = Vec::new();
+            for request in requests.by_ref() {
+                let attachments = output.attachments_len();
+                let result: Result<Option<String>> = try {
+                    let command = request["command"]
+                        .as_str()
+                        .context("Invalid 'command' field")?
+                        .to_lowercase();
+                    if command == "batch" {
+                        Err(anyhow!("Batches cannot be nested"))?;
+                    }
+                    let arg = if request["argument"].is_null() {
+                        String::new()
+                    } else {
+                        request["argument"].dump()
+                    };
+                    handle_command(controller, &command, &arg, input, output)?
+                };
+                match result {
+                    Ok(value) => results.push(json::object! {
+                        status: "ok",
+                        result: match value {
+                            None => json::JsonValue::Null,
+                            Some(s) => json::parse(&s).unwrap_or_else(|_| s.into()),
+                        },
+                    }),
+                    Err(e) => {
+                        output.truncate_attachments(attachments);
+                        results.push(json::object! { status: "error", error: format!("{e:?}") });
+                        break;
+                    }
+                }
+            }
 
-            let mut env = None;
-            if !arg["env"].is_null() {
-                let mut env1 = HashMap::with_capacity(arg["env"].len());
-                for (key, value) in arg["env"].entries_mut() {
-                    env1.insert(
-                        key.to_string(),
-                        value.take_string().context("Invalid 'env' argument")?,
-                    );
+            // The commands after a failed one are skipped, but their payloads still have to be
+            // consumed
+            for request in requests {
+                let command = request["command"].as_str().unwrap_or("").to_lowercase();
+                if let Some(len) = input_payload_len(&command, &request["argument"]) {
+                    skip_payload(input, len)?;
                 }
-                env = Some(env1);
-            }
-
-            controller.run_manager_command(manager::Command::Run {
-                options: running::Options {
-                    argv,
-                    stdin,
-                    stdout,
-                    stderr,
-                    real_time_limit,
-                    cpu_time_limit,
-                    idleness_time_limit,
-                    memory_limit,
-                    processes_limit,
-                    env,
-                },
-            })
+            }
+
+            Ok(Some(json::JsonValue::from(results).dump()))
         }
         _ => {
             bail!("Unknown command {command}");
         }
     }
 }
+
+// The commands that access the filesystem of the box by path, which are refused while a run is in
+// progress
+const FILE_COMMANDS: &[&str] = &[
+    "mkdir",
+    "ls",
+    "stat",
+    "warmup",
+    "du",
+    "cat",
+    "hash",
+    "tar",
+    "watch",
+    "mkfile",
+    "write",
+    "untar",
+    "rm",
+    "mv",
+    "cp",
+    "upload",
+    "mksymlink",
+    "bind",
+    "volume",
+    "link",
+    "share",
+    "bind_out",
+    "snapshot",
+];
+
+fn skip_payload(input: &mut std::io::BufReader<Input>, len: u64) -> Result<()> {
+    let n_read = std::io::copy(&mut input.by_ref().take(len), &mut std::io::sink())
+        .context("Failed to read payload")?;
+    if n_read != len {
+        bail!("Unexpected EOF in payload");
+    }
+    Ok(())
+}
+
+// The length of the payload following the command, if any
+pub fn input_payload_len(command: &str, arg: &json::JsonValue) -> Option<u64> {
+    match command {
+        "write" | "untar" | "upload" => arg["len"].as_u64(),
+        "batch" => Some(
+            arg.members()
+                .filter_map(|request| {
+                    let command = request["command"].as_str()?.to_lowercase();
+                    input_payload_len(&command, &request["argument"])
+                })
+                .sum(),
+        ),
+        _ => None,
+    }
+}
+
+// The length of the payload following the reply to the command, if any
+pub fn output_payload_len(
+    command: &str,
+    arg: &json::JsonValue,
+    result: &json::JsonValue,
+) -> Option<u64> {
+    match command {
+        "cat" | "tar" => result["len"].as_u64(),
+        "batch" => Some(
+            arg.members()
+                .zip(result.members())
+                .filter(|(_, result)| result["status"] == "ok")
+                .filter_map(|(request, result)| {
+                    let command = request["command"].as_str()?.to_lowercase();
+                    output_payload_len(&command, &request["argument"], &result["result"])
+                })
+                .sum(),
+        ),
+        _ => None,
+    }
+}
+
+fn parse_run_options(
+    arg: &mut json::JsonValue,
+    input: &mut Input,
+    output: &mut Output,
+) -> Result<(running::Options, Option<std::fs::File>)> {
+    if !arg["argv"].is_array() {
+        bail!("Invalid 'argv' argument");
+    }
+
+    let mut argv = Vec::with_capacity(arg["argv"].len());
+    for arg in arg["argv"].members_mut() {
+        argv.push(arg.take_string().context("Invalid 'argv' argument")?);
+    }
+    if argv.is_empty() {
+        bail!("'argv' is empty");
+    }
+
+    let argv0 = if arg["argv0"].is_null() {
+        None
+    } else {
+        Some(
+            arg["argv0"]
+                .take_string()
+                .context("Invalid 'argv0' argument")?,
+        )
+    };
+
+    // Checked here to report a sensible error. The program chdirs by itself, within the root of
+    // the box, so this is not a security check.
+    let cwd = if arg["cwd"].is_null() {
+        None
+    } else {
+        let cwd = arg["cwd"].as_str().context("Invalid 'cwd' argument")?;
+        let path = rootfs::resolve_abs_box_root(cwd)
+            .with_context(|| format!("Failed to resolve path {cwd}"))?;
+        if !path.is_dir() {
+            bail!("{cwd} is not a directory");
+        }
+        let path = Path::new("/").join(
+            path.strip_prefix("/newroot")
+                .context("Path resolved outside the box")?,
+        );
+        Some(
+            path.into_os_string()
+                .into_string()
+                .map_err(|_| anyhow!("'cwd' is not UTF-8"))?,
+        )
+    };
+
+    let tty = if arg["tty"].is_null() {
+        false
+    } else {
+        arg["tty"].as_bool().context("Invalid 'tty' argument")?
+    };
+    if tty && !(arg["stdin"].is_null() && arg["stdout"].is_null() && arg["stderr"].is_null()) {
+        bail!("Standard streams cannot be redirected in 'tty' mode");
+    }
+
+    let stdin = parse_stream(&mut arg["stdin"]).context("Invalid 'stdin' argument")?;
+    let stdout = parse_stream(&mut arg["stdout"]).context("Invalid 'stdout' argument")?;
+    let stderr = parse_stream(&mut arg["stderr"]).context("Invalid 'stderr' argument")?;
+
+    let real_time_limit = if arg["real_time_limit"].is_null() {
+        None
+    } else {
+        Some(Duration::from_secs_f64(
+            arg["real_time_limit"]
+                .as_f64()
+                .context("Invalid 'real_time_limit' argument")?,
+        ))
+    };
+    let cpu_time_limit = if arg["cpu_time_limit"].is_null() {
+        None
+    } else {
+        Some(Duration::from_secs_f64(
+            arg["cpu_time_limit"]
+                .as_f64()
+                .context("Invalid 'cpu_time_limit' argument")?,
+        ))
+    };
+    let idleness_time_limit = if arg["idleness_time_limit"].is_null() {
+        None
+    } else {
+        Some(Duration::from_secs_f64(
+            arg["idleness_time_limit"]
+                .as_f64()
+                .context("Invalid 'idleness_time_limit' argument")?,
+        ))
+    };
+    let memory_limit = if arg["memory_limit"].is_null() {
+        None
+    } else {
+        Some(
+            arg["memory_limit"]
+                .as_usize()
+                .context("Invalid 'memory_limit' argument")?,
+        )
+    };
+    let swap_limit = if arg["swap_limit"].is_null() {
+        0
+    } else {
+        arg["swap_limit"]
+            .as_usize()
+            .context("Invalid 'swap_limit' argument")?
+    };
+    let io_limit = if arg["io_limit"].is_null() {
+        None
+    } else {
+        if !arg["io_limit"].is_object() {
+            bail!("Invalid 'io_limit' argument");
+        }
+        let mut io_limit = cgroups::IoLimit::default();
+        for (key, value) in arg["io_limit"].entries() {
+            let target = match key {
+                "read_bps" => &mut io_limit.read_bps,
+                "write_bps" => &mut io_limit.write_bps,
+                "read_iops" => &mut io_limit.read_iops,
+                "write_iops" => &mut io_limit.write_iops,
+                _ => bail!("Unknown key '{key}' in 'io_limit' argument"),
+            };
+            *target = Some(
+                value
+                    .as_u64()
+                    .filter(|value| *value > 0)
+                    .with_context(|| format!("Invalid '{key}' in 'io_limit' argument"))?,
+            );
+        }
+        Some(io_limit)
+    };
+    let processes_limit = if arg["processes_limit"].is_null() {
+        None
+    } else {
+        Some(
+            arg["processes_limit"]
+                .as_usize()
+                .context("Invalid 'processes_limit' argument")?,
+        )
+    };
+
+    let mut output_limit = if arg["output_limit"].is_null() {
+        None
+    } else {
+        Some(
+            arg["output_limit"]
+                .as_usize()
+                .context("Invalid 'output_limit' argument")?,
+        )
+    };
+
+    let network_limit = if arg["network_limit"].is_null() {
+        None
+    } else {
+        Some(
+            arg["network_limit"]
+                .as_u64()
+                .context("Invalid 'network_limit' argument")?,
+        )
+    };
+
+    let stack_limit = if arg["stack_limit"].is_null() {
+        None
+    } else if arg["stack_limit"] == "unlimited" {
+        Some(libc::RLIM_INFINITY)
+    } else {
+        Some(
+            arg["stack_limit"]
+                .as_u64()
+                .context("Invalid 'stack_limit' argument")?,
+        )
+    };
+
+    let mut rlimits = running::Rlimits {
+        // Core dumps are written to the working directory of the program by default, which wastes
+        // both the quota and the time of the run on a runtime error nobody is going to debug
+        core: Some(0),
+        ..Default::default()
+    };
+    if !arg["rlimits"].is_null() {
+        if !arg["rlimits"].is_object() {
+            bail!("Invalid 'rlimits' argument");
+        }
+        for (key, value) in arg["rlimits"].entries() {
+            let limit = if value == "unlimited" {
+                libc::RLIM_INFINITY
+            } else {
+                value
+                    .as_u64()
+                    .with_context(|| format!("Invalid '{key}' in 'rlimits' argument"))?
+            };
+            match key {
+                "nofile" => rlimits.nofile = Some(limit),
+                "nproc" => rlimits.nproc = Some(limit),
+                "core" => rlimits.core = Some(limit),
+                "memlock" => rlimits.memlock = Some(limit),
+                "msgqueue" => rlimits.msgqueue = Some(limit),
+                "fsize" => {
+                    if output_limit.is_some() {
+                        bail!("'rlimits.fsize' and 'output_limit' are mutually exclusive");
+                    }
+                    output_limit = Some(limit.try_into().unwrap_or(usize::MAX));
+                }
+                "stack" => bail!("Use 'stack_limit' instead of 'rlimits.stack'"),
+                _ => bail!("Unknown resource '{key}' in 'rlimits' argument"),
+            }
+        }
+    }
+
+    let core_dump = if arg["core_dump"].is_null() {
+        None
+    } else {
+        let limit = arg["core_dump"]["limit"]
+            .as_u64()
+            .context("Invalid 'core_dump.limit' argument")?;
+        if !arg["rlimits"]["core"].is_null() {
+            bail!("'core_dump' and 'rlimits.core' are mutually exclusive");
+        }
+        let core_dump = running::CoreDumpOptions::new(limit)?;
+        rlimits.core = Some(core_dump.limit);
+        Some(core_dump)
+    };
+
+    let cpus = if arg["cpus"].is_null() {
+        None
+    } else {
+        if !arg["cpus"].is_array() || arg["cpus"].is_empty() {
+            bail!("Invalid 'cpus' argument");
+        }
+        let mut cpus = Vec::with_capacity(arg["cpus"].len());
+        for cpu in arg["cpus"].members() {
+            cpus.push(cpu.as_u64().context("Invalid 'cpus' argument")?);
+        }
+        Some(cpus)
+    };
+
+    let cpu_quota = if arg["cpu_quota"].is_null() {
+        None
+    } else {
+        Some(
+            arg["cpu_quota"]
+                .as_f64()
+                .filter(|quota| *quota > 0.0 && quota.is_finite())
+                .context("Invalid 'cpu_quota' argument")?,
+        )
+    };
+
+    let transparent_hugepages = if arg["transparent_hugepages"].is_null() {
+        None
+    } else {
+        Some(
+            arg["transparent_hugepages"]
+                .as_bool()
+                .context("Invalid 'transparent_hugepages' argument")?,
+        )
+    };
+
+    let mut capabilities = Vec::new();
+    if !arg["capabilities"].is_null() {
+        if !arg["capabilities"].is_array() {
+            bail!("Invalid 'capabilities' argument");
+        }
+        for name in arg["capabilities"].members() {
+            let name = name.as_str().context("Invalid 'capabilities' argument")?;
+            capabilities.push(userns::parse_capability(name)?);
+        }
+    }
+
+    let landlock = if arg["landlock"].is_null() {
+        None
+    } else {
+        landlock::Ruleset::parse(&arg["landlock"]).context("Invalid 'landlock' argument")?
+    };
+
+    let seccomp_profile = if arg["seccomp_profile"].is_null() {
+        None
+    } else {
+        seccomp::Profile::parse(&arg["seccomp_profile"])
+            .context("Invalid 'seccomp_profile' argument")?
+    };
+
+    let syscall_audit = if arg["syscall_audit"].is_null() {
+        None
+    } else {
+        seccomp::parse_syscall_audit(&arg["syscall_audit"])
+            .context("Invalid 'syscall_audit' argument")?
+    };
+
+    let socket_isolation = if arg["socket_isolation"].is_null() {
+        None
+    } else {
+        seccomp::parse_socket_isolation(&arg["socket_isolation"])
+            .context("Invalid 'socket_isolation' argument")?
+    };
+
+    let realtime = if arg["realtime"].is_null() {
+        None
+    } else {
+        Some(timens::RealTime::parse(&arg["realtime"]).context("Invalid 'realtime' argument")?)
+    };
+
+    let mut exec_deny = Vec::new();
+    if !arg["exec_deny"].is_null() {
+        if !arg["exec_deny"].is_array() {
+            bail!("Invalid 'exec_deny' argument");
+        }
+        for rule in arg["exec_deny"].members() {
+            if let Some(path) = rule["path"].as_str() {
+                // The box sees the executable by its canonical path
+                let canonical = match rootfs::resolve_abs_box_root(path) {
+                    Ok(path) => format!(
+                        "/{}",
+                        path.strip_prefix("/newroot")
+                            .unwrap_or(&path)
+                            .to_string_lossy()
+                    ),
+                    Err(_) => path.to_string(),
+                };
+                exec_deny.push(running::ExecRule::Path(canonical));
+            } else if let Some(digest) = rule["sha256"].as_str() {
+                if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+                    bail!("Invalid 'sha256' of 'exec_deny' rule");
+                }
+                exec_deny.push(running::ExecRule::Hash(digest.to_ascii_lowercase()));
+            } else {
+                bail!("Expected {{\"path\": ...}} or {{\"sha256\": ...}} in 'exec_deny'");
+            }
+        }
+    }
+
+    let intercept = if arg["intercept"].is_null() {
+        Vec::new()
+    } else {
+        seccomp::parse_intercept_rules(&arg["intercept"]).context("Invalid 'intercept' argument")?
+    };
+
+    let debug =
+        !arg["debug"].is_null() && arg["debug"].as_bool().context("Invalid 'debug' argument")?;
+    if debug {
+        // These rely on the box tracing the program
+        for key in [
+            "intercept",
+            "syscall_audit",
+            "socket_isolation",
+            "realtime",
+            "exec_deny",
+            "core_dump",
+        ] {
+            if !arg[key].is_null() {
+                bail!("'{key}' can't be combined with 'debug'");
+            }
+        }
+    }
+
+    let mut env = None;
+    if !arg["env"].is_null() {
+        let mut env1 = HashMap::with_capacity(arg["env"].len());
+        for (key, value) in arg["env"].entries_mut() {
+            env1.insert(
+                key.to_string(),
+                value.take_string().context("Invalid 'env' argument")?,
+            );
+        }
+        env = Some(env1);
+    }
+
+    let mut scratch_file = None;
+    if !arg["scratch_fd"].is_null() {
+        let fd = arg["scratch_fd"]
+            .as_i32()
+            .context("Invalid 'scratch_fd' argument")?;
+        if fd < 3 {
+            bail!("'scratch_fd' must not refer to a standard stream");
+        }
+        let file = std::fs::File::from(open_scratch_file()?);
+        // Attach early so that we don't run the program in vain if the descriptor can't be
+        // passed
+        output.attach_fd(
+            file.try_clone()
+                .context("Failed to clone scratch file")?
+                .into(),
+        )?;
+        scratch_file = Some((fd, file));
+    }
+    let mut inherited_fds = Vec::new();
+    if !arg["fds"].is_null() {
+        if !arg["fds"].is_array() {
+            bail!("Invalid 'fds' argument");
+        }
+        let mut targets = Vec::with_capacity(arg["fds"].len());
+        for fd in arg["fds"].members() {
+            let fd = fd.as_i32().context("Invalid 'fds' argument")?;
+            if fd < 3 {
+                bail!("'fds' must not refer to standard streams");
+            }
+            if targets.contains(&fd)
+                || scratch_file
+                    .as_ref()
+                    .is_some_and(|(scratch_fd, _)| fd == *scratch_fd)
+            {
+                bail!("Descriptor {fd} is passed to the program twice");
+            }
+            targets.push(fd);
+        }
+        let fds = input.take_fds(targets.len())?;
+        inherited_fds = targets
+            .into_iter()
+            .zip(fds)
+            .map(|(fd, file)| (fd, std::fs::File::from(file)))
+            .collect();
+    }
+
+    let tty = if tty {
+        let (master, slave) = open_pty()?;
+        // Passed to the judge alongside the reply, after the scratch file if there is one
+        output.attach_fd(master)?;
+        Some(slave)
+    } else {
+        None
+    };
+
+    let scratch = scratch_file
+        .as_ref()
+        .map(|(_, file)| file.try_clone())
+        .transpose()
+        .context("Failed to clone scratch file")?;
+
+    Ok((
+        running::Options {
+            argv,
+            argv0,
+            cwd,
+            stdin,
+            stdout,
+            stderr,
+            real_time_limit,
+            cpu_time_limit,
+            idleness_time_limit,
+            memory_limit,
+            swap_limit,
+            io_limit,
+            processes_limit,
+            output_limit,
+            network_limit,
+            stack_limit,
+            rlimits,
+            cpus,
+            cpu_quota,
+            transparent_hugepages,
+            capabilities,
+            landlock,
+            seccomp_profile,
+            intercept,
+            syscall_audit,
+            socket_isolation,
+            realtime,
+            exec_deny,
+            core_dump,
+            debug,
+            env,
+            scratch_file,
+            inherited_fds,
+            tty,
+        },
+        scratch,
+    ))
+}
+
+fn parse_stream(arg: &mut json::JsonValue) -> Result<running::Stream> {
+    if arg.is_null() {
+        Ok(running::Stream::File("/dev/null".to_string()))
+    } else if arg.is_string() {
+        Ok(running::Stream::File(arg.take_string().unwrap()))
+    } else {
+        Ok(running::Stream::Pipe(
+            arg["pipe"].take_string().context("Invalid pipe name")?,
+        ))
+    }
+}
+
+// A pseudoterminal of the devpts instance of the box, so that the program sees it at /dev/pts.
+// Returns the master and the slave.
+fn open_pty() -> Result<(OwnedFd, std::fs::File)> {
+    let master = nix::fcntl::open(
+        "/newroot/dev/ptmx",
+        nix::fcntl::OFlag::O_RDWR | nix::fcntl::OFlag::O_NOCTTY | nix::fcntl::OFlag::O_CLOEXEC,
+        nix::sys::stat::Mode::empty(),
+    )
+    .context("Failed to open /dev/ptmx")?;
+    let master = unsafe { OwnedFd::from_raw_fd(master) };
+    if unsafe { libc::unlockpt(master.as_raw_fd()) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to unlock pseudoterminal");
+    }
+    // Unlike opening /dev/pts/N by name, this can't be raced
+    let slave = unsafe {
+        libc::ioctl(
+            master.as_raw_fd(),
+            libc::TIOCGPTPEER,
+            libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC,
+        )
+    };
+    if slave == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to open pseudoterminal slave");
+    }
+    Ok((master, unsafe { std::fs::File::from_raw_fd(slave) }))
+}
+
+fn open_scratch_file() -> Result<OwnedFd> {
+    // An unnamed file on the tmpfs of /space, so that it counts towards the disk quota
+    let fd = nix::fcntl::open(
+        "/newroot/space",
+        nix::fcntl::OFlag::O_TMPFILE | nix::fcntl::OFlag::O_RDWR | nix::fcntl::OFlag::O_CLOEXEC,
+        nix::sys::stat::Mode::from_bits_truncate(0o600),
+    )
+    .context("Failed to create scratch file")?;
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+fn list_directory(
+    path: &Path,
+    prefix: &str,
+    recursive: bool,
+    entries: &mut json::JsonValue,
+) -> Result<()> {
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let file_name = entry
+            .file_name()
+            .into_string()
+            .or_else(|name| bail!("Invalid file name: {name:?}"))?;
+        let name = format!("{prefix}{file_name}");
+        // DirEntry::metadata does not follow symlinks, so recursion can't loop
+        let metadata = entry.metadata()?;
+        entries[name.as_str()] = metadata_to_json(&metadata);
+        if recursive && metadata.is_dir() {
+            list_directory(&entry.path(), &format!("{name}/"), true, entries)?;
+        }
+    }
+    Ok(())
+}
+
+struct DiskUsage {
+    // Only files on the same filesystem count towards the same quota
+    dev: u64,
+    bytes: u64,
+    inodes: u64,
+    // Hard links share the disk space
+    seen: HashSet<(u64, u64)>,
+}
+
+impl DiskUsage {
+    fn add(&mut self, path: &Path, metadata: &std::fs::Metadata) -> Result<()> {
+        if metadata.dev() != self.dev || !self.seen.insert((metadata.dev(), metadata.ino())) {
+            return Ok(());
+        }
+        self.bytes += metadata.blocks() * 512;
+        self.inodes += 1;
+        if metadata.is_dir() {
+            for entry in
+                std::fs::read_dir(path).with_context(|| format!("Failed to readdir {path:?}"))?
+            {
+                let entry = entry.with_context(|| format!("Failed to readdir {path:?}"))?;
+                // DirEntry::metadata does not follow symlinks
+                let metadata = entry
+                    .metadata()
+                    .with_context(|| format!("Failed to stat {:?}", entry.path()))?;
+                self.add(&entry.path(), &metadata)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Warmup {
+    files: u64,
+    bytes: u64,
+    max_bytes: Option<u64>,
+    // Hard links share the page cache
+    seen: HashSet<(u64, u64)>,
+}
+
+impl Warmup {
+    fn add(&mut self, path: &Path, metadata: &std::fs::Metadata) -> Result<()> {
+        if self
+            .max_bytes
+            .is_some_and(|max_bytes| self.bytes >= max_bytes)
+            || !self.seen.insert((metadata.dev(), metadata.ino()))
+        {
+            return Ok(());
+        }
+        if metadata.is_dir() {
+            // Reading pseudo-files is pointless at best
+            if path == Path::new("/newroot/proc") || path == Path::new("/newroot/dev") {
+                return Ok(());
+            }
+            for entry in
+                std::fs::read_dir(path).with_context(|| format!("Failed to readdir {path:?}"))?
+            {
+                let entry = entry.with_context(|| format!("Failed to readdir {path:?}"))?;
+                // DirEntry::metadata does not follow symlinks
+                let metadata = entry
+                    .metadata()
+                    .with_context(|| format!("Failed to stat {:?}", entry.path()))?;
+                self.add(&entry.path(), &metadata)?;
+            }
+        } else if metadata.is_file() {
+            // Actually reading the file is the only way to be sure it ends up in the page cache,
+            // as readahead hints may be ignored
+            let file =
+                std::fs::File::open(path).with_context(|| format!("Failed to open {path:?}"))?;
+            let limit = match self.max_bytes {
+                Some(max_bytes) => max_bytes - self.bytes,
+                None => u64::MAX,
+            };
+            self.bytes += std::io::copy(&mut file.take(limit), &mut std::io::sink())
+                .with_context(|| format!("Failed to read {path:?}"))?;
+            self.files += 1;
+        }
+        Ok(())
+    }
+}
+
+fn read_config_file(path: &str) -> Result<toml::value::Table> {
+    let config = std::fs::read_to_string(path).context("Failed to read file")?;
+    toml::from_str(&config).context("Invalid TOML")
+}
+
+fn apply_config_file(
+    controller: &mut controller::Controller,
+    mut config: toml::value::Table,
+) -> Result<()> {
+    if let Some(run) = config.remove("run") {
+        if !run.is_table() {
+            bail!("'run' must be a table");
+        }
+        controller.set_run_defaults(toml_to_json(run));
+    }
+
+    if let Some(volumes) = config.remove("volume") {
+        let toml::Value::Array(volumes) = volumes else {
+            bail!("'volume' must be an array of tables");
+        };
+        for volume in volumes {
+            let mut arg = toml_to_json(volume);
+            let (external, internal, options) = parse_bind_arg(&mut arg)?;
+            controller
+                .add_volume(&external, &internal, options)
+                .with_context(|| format!("Failed to add volume at {internal}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn toml_to_json(value: toml::Value) -> json::JsonValue {
+    match value {
+        toml::Value::String(value) => value.into(),
+        toml::Value::Integer(value) => value.into(),
+        toml::Value::Float(value) => value.into(),
+        toml::Value::Boolean(value) => value.into(),
+        toml::Value::Datetime(value) => value.to_string().into(),
+        toml::Value::Array(array) => {
+            json::JsonValue::Array(array.into_iter().map(toml_to_json).collect())
+        }
+        toml::Value::Table(table) => {
+            let mut object = json::JsonValue::new_object();
+            for (key, value) in table {
+                object[key.as_str()] = toml_to_json(value);
+            }
+            object
+        }
+    }
+}
+
+fn parse_bind_arg(arg: &mut json::JsonValue) -> Result<(String, String, controller::BindOptions)> {
+    let external = arg["external"]
+        .take_string()
+        .context("Invalid 'external' argument")?;
+    let internal = arg["internal"]
+        .take_string()
+        .context("Invalid 'internal' argument")?;
+    let ro = arg["ro"].as_bool().context("Invalid 'ro' argument")?;
+    let idmap =
+        !arg["idmap"].is_null() && arg["idmap"].as_bool().context("Invalid 'idmap' argument")?;
+    // A cap on writes could only be enforced by keeping them off the external directory
+    if !arg["quota"].is_null() {
+        bail!("Mounts cannot have a quota, as writes go directly to the external directory");
+    }
+    Ok((external, internal, controller::BindOptions { ro, idmap }))
+}
+
+fn parse_manifest(value: &json::JsonValue) -> Result<Vec<archive::ManifestEntry>> {
+    let json::JsonValue::Array(array) = value else {
+        bail!("Invalid 'entries' argument");
+    };
+    let mut entries = Vec::with_capacity(array.len());
+    for entry in array {
+        let path = entry["path"]
+            .as_str()
+            .context("Invalid 'path' of manifest entry")?;
+        let kind = match entry["type"].as_str() {
+            Some("dir") => archive::ManifestEntryKind::Dir,
+            Some("file") => archive::ManifestEntryKind::File {
+                len: entry["len"]
+                    .as_u64()
+                    .context("Invalid 'len' of manifest entry")?,
+            },
+            Some("symlink") => archive::ManifestEntryKind::Symlink {
+                target: entry["target"]
+                    .as_str()
+                    .context("Invalid 'target' of manifest entry")?
+                    .as_bytes()
+                    .to_vec(),
+            },
+            _ => bail!("Invalid 'type' of manifest entry"),
+        };
+        let attributes = FileAttributes::parse(entry)?;
+        entries.push(archive::ManifestEntry {
+            path: path.as_bytes().to_vec(),
+            kind,
+            mode: attributes.mode,
+            owner: attributes.owner,
+        });
+    }
+    Ok(entries)
+}
+
+struct FileAttributes {
+    mode: Option<u32>,
+    owner: Option<(u32, u32)>,
+}
+
+impl FileAttributes {
+    fn parse(arg: &json::JsonValue) -> Result<Self> {
+        let mode = if arg["mode"].is_null() {
+            None
+        } else {
+            let mode = arg["mode"].as_u32().context("Invalid 'mode' argument")?;
+            if mode & !0o7777 != 0 {
+                bail!("Invalid 'mode' argument");
+            }
+            Some(mode)
+        };
+        // Files created by us are owned by the real root, so translate the owner to external IDs
+        let owner = match arg["owner"].as_str() {
+            None if arg["owner"].is_null() => None,
+            Some("root") => Some((ids::EXTERNAL_ROOT_UID, ids::EXTERNAL_ROOT_GID)),
+            Some("user") => Some((ids::EXTERNAL_USER_UID, ids::EXTERNAL_USER_GID)),
+            _ => bail!("Invalid 'owner' argument"),
+        };
+        Ok(Self { mode, owner })
+    }
+
+    fn apply(&self, file: &std::fs::File) -> Result<()> {
+        if let Some((uid, gid)) = self.owner {
+            std::os::unix::fs::fchown(file, Some(uid), Some(gid))
+                .context("Failed to chown file")?;
+        }
+        // chown resets setuid and setgid bits, so chmod afterwards
+        if let Some(mode) = self.mode {
+            file.set_permissions(std::fs::Permissions::from_mode(mode))
+                .context("Failed to chmod file")?;
+        }
+        Ok(())
+    }
+}
+
+fn metadata_to_json(metadata: &std::fs::Metadata) -> json::JsonValue {
+    let file_type = metadata.file_type();
+    json::object! {
+        file_type: if file_type.is_dir() {
+            "dir"
+        } else if file_type.is_file() {
+            "file"
+        } else if file_type.is_symlink() {
+            "symlink"
+        } else if file_type.is_block_device() {
+            "block"
+        } else if file_type.is_char_device() {
+            "char"
+        } else if file_type.is_fifo() {
+            "fifo"
+        } else if file_type.is_socket() {
+            "socket"
+        } else {
+            "unknown"
+        },
+        len: metadata.len(),
+        // st_blocks is always in 512-byte units
+        allocated: metadata.blocks() * 512,
+        mode: metadata.permissions().mode(),
+        mtime: metadata.mtime() as f64 + metadata.mtime_nsec() as f64 / 1e9,
+    }
+}
@@ -1,4 +1,4 @@
-; Runs another the executable from argv[1:], copying envp. Designed to use as little physical memory
+; Runs the executable argv[1] with arguments argv[2:], copying envp. Designed to use as little physical memory
 ; possible. On error, returns an exit code equal to errno.
 
 [bits 64]
@@ -6,10 +6,10 @@
 [global _start]
 _start:
 	mov eax, 59  ; execve
-	lea rsi, [rsp+16]  ; argv <- argv + 1
-	mov rdi, [rsi]  ; pathname <- argv[1]
+	mov rdi, [rsp+16]  ; pathname <- argv[1]
+	lea rsi, [rsp+24]  ; argv <- argv + 2
 	mov rdx, [rsp]  ; envp <- argv + argc + 1
-	lea rdx, [rsi+rdx*8]
+	lea rdx, [rsi+rdx*8-8]
 	syscall
 
 	mov rdi, rax  ; status
@@ -0,0 +1,69 @@
+use crate::linux::{logging, system};
+use anyhow::{Context, Result};
+use multiprocessing::Object;
+use std::os::unix::io::OwnedFd;
+
+// The exporter stays in the host's mount namespace and attaches mounts from the box there, so that
+// the judge can read files from the box without copying them. It can't be the controller itself,
+// because the controller has to leave the host's mount namespace, and returning there via setns(2)
+// is impossible for multithreaded processes.
+
+#[derive(Object)]
+pub enum Command {
+    BindOut { mount_fd: OwnedFd, path: String },
+    Reset,
+}
+
+#[multiprocessing::entrypoint]
+pub fn exporter(
+    log_options: logging::Options,
+    mut channel: multiprocessing::Duplex<std::result::Result<Option<String>, String>, Command>,
+) {
+    logging::init(log_options).expect("Failed to configure logging");
+    let _span = ::tracing::info_span!("exporter").entered();
+
+    let mut mount_points = Vec::new();
+
+    while let Some(command) = channel
+        .recv()
+        .expect("Failed to receive message from channel")
+    {
+        channel
+            .send(&match execute_command(command, &mut mount_points) {
+                Ok(value) => Ok(value),
+                Err(e) => Err(format!("{e:?}")),
+            })
+            .expect("Failed to send reply to channel")
+    }
+
+    // The controller is dead, so there's no one to reset the box anymore, and the mounts would
+    // leak otherwise
+    if let Err(e) = unmount_all(&mut mount_points) {
+        ::tracing::error!(error = %format!("{e:#}"), "Failed to unmount exported directories");
+    }
+}
+
+fn execute_command(command: Command, mount_points: &mut Vec<String>) -> Result<Option<String>> {
+    match command {
+        Command::BindOut { mount_fd, path } => {
+            system::move_mount(&mount_fd, &path)
+                .with_context(|| format!("Failed to attach mount to {path}"))?;
+            mount_points.push(path);
+            Ok(None)
+        }
+        Command::Reset => {
+            unmount_all(mount_points)?;
+            Ok(None)
+        }
+    }
+}
+
+fn unmount_all(mount_points: &mut Vec<String>) -> Result<()> {
+    // A path may be exported several times, so unmount in reverse order
+    while let Some(path) = mount_points.pop() {
+        // The judge may still have files open, which is fine
+        system::umount_opt(&path, system::MNT_DETACH)
+            .with_context(|| format!("Failed to unmount {path}"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use crate::linux::{api, logging};
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tonic::{Request, Response, Status};
+
+// A gRPC server for orchestrators that would rather not speak the protocol of the box. Each box is
+// a separate `start` process driven via the library API, so this is just a translation layer; the
+// boxes are independent, so a long run in one box doesn't delay commands to the others. The server
+// listens on a Unix socket rather than on TCP, because anyone who can connect controls the boxes.
+
+mod proto {
+    tonic::include_proto!("sunwalker_box");
+}
+
+use proto::boxes_server::{Boxes, BoxesServer};
+
+pub fn serve(listen: &str) -> Result<()> {
+    let runtime = tokio::runtime::Runtime::new().context("Failed to start tokio runtime")?;
+    runtime.block_on(async {
+        let listener = tokio::net::UnixListener::bind(listen)
+            .with_context(|| format!("Failed to listen on {listen}"))?;
+        ::tracing::info!("Listening on {listen}");
+        tonic::transport::Server::builder()
+            .add_service(BoxesServer::new(Service::default()))
+            .serve_with_incoming(tokio_stream::wrappers::UnixListenerStream::new(listener))
+            .await
+            .context("gRPC server failed")
+    })
+}
+
+#[derive(Default)]
+struct Service {
+    // Commands to a box are handled one at a time, so each box has a lock of its own
+    boxes: Mutex<HashMap<u64, Arc<Mutex<api::Box>>>>,
+    next_id: Mutex<u64>,
+}
+
+impl Service {
+    fn get(&self, id: u64) -> Result<Arc<Mutex<api::Box>>, Status> {
+        self.boxes
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| Status::not_found(format!("No box with ID {id}")))
+    }
+
+    // The library API is blocking, so it's called off the async threads
+    async fn with_box<T: Send + 'static>(
+        &self,
+        id: u64,
+        f: impl FnOnce(&mut api::Box) -> Result<T> + Send + 'static,
+    ) -> Result<Response<T>, Status> {
+        let sandbox = self.get(id)?;
+        blocking(move || f(&mut sandbox.lock().unwrap())).await
+    }
+}
+
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T> + Send + 'static,
+) -> Result<Response<T>, Status> {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(Ok(value)) => Ok(Response::new(value)),
+        Ok(Err(e)) => Err(Status::unknown(format!("{e:?}"))),
+        Err(e) => Err(Status::internal(format!("{e}"))),
+    }
+}
+
+fn duration_from_secs(secs: f64, name: &str) -> Result<Duration, Status> {
+    Duration::try_from_secs_f64(secs)
+        .map_err(|_| Status::invalid_argument(format!("Invalid '{name}'")))
+}
+
+#[tonic::async_trait]
+impl Boxes for Service {
+    async fn create(
+        &self,
+        request: Request<proto::CreateRequest>,
+    ) -> Result<Response<proto::BoxId>, Status> {
+        let request = request.into_inner();
+        let executable = std::env::current_exe()
+            .map_err(|e| Status::internal(format!("Failed to locate the executable: {e}")))?;
+        let mut config = api::Config::new(executable, request.core);
+        if let Some(root) = request.root {
+            config.root = root;
+        }
+        if let Some(quota_space) = request.quota_space {
+            config.quota_space = quota_space;
+        }
+        if let Some(quota_inodes) = request.quota_inodes {
+            config.quota_inodes = quota_inodes;
+        }
+        // The boxes log like the server unless told otherwise
+        let log_options = logging::options();
+        for (name, value) in [
+            ("--log-level", log_options.level),
+            ("--log-format", log_options.format),
+        ] {
+            if !request.extra_args.iter().any(|arg| arg == name) {
+                config.extra_args.push(name.to_string());
+                config.extra_args.push(value);
+            }
+        }
+        config.extra_args.extend(request.extra_args);
+
+        let sandbox = blocking(move || api::Box::new(config)).await?.into_inner();
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+        self.boxes
+            .lock()
+            .unwrap()
+            .insert(id, Arc::new(Mutex::new(sandbox)));
+        Ok(Response::new(proto::BoxId { id }))
+    }
+
+    async fn destroy(
+        &self,
+        request: Request<proto::BoxId>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let id = request.into_inner().id;
+        let sandbox = self
+            .boxes
+            .lock()
+            .unwrap()
+            .remove(&id)
+            .ok_or_else(|| Status::not_found(format!("No box with ID {id}")))?;
+        // Dropping the box waits for it to terminate, which includes finishing a run in progress
+        blocking(move || {
+            drop(sandbox.lock().unwrap());
+            drop(sandbox);
+            Ok(proto::Empty {})
+        })
+        .await
+    }
+
+    async fn reset(
+        &self,
+        request: Request<proto::BoxId>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        self.with_box(request.into_inner().id, |sandbox| {
+            sandbox.reset()?;
+            Ok(proto::Empty {})
+        })
+        .await
+    }
+
+    async fn run(
+        &self,
+        request: Request<proto::RunRequest>,
+    ) -> Result<Response<proto::RunResult>, Status> {
+        let request = request.into_inner();
+        let mut spec = api::RunSpec::new(
+            request.argv,
+            duration_from_secs(request.cpu_time_limit, "cpu_time_limit")?,
+            request.processes_limit,
+        );
+        spec.argv0 = request.argv0;
+        spec.cwd = request.cwd;
+        spec.stdin = request.stdin;
+        spec.stdout = request.stdout;
+        spec.stderr = request.stderr;
+        spec.real_time_limit = request
+            .real_time_limit
+            .map(|limit| duration_from_secs(limit, "real_time_limit"))
+            .transpose()?;
+        spec.idleness_time_limit = request
+            .idleness_time_limit
+            .map(|limit| duration_from_secs(limit, "idleness_time_limit"))
+            .transpose()?;
+        spec.memory_limit = request.memory_limit;
+        spec.swap_limit = request.swap_limit;
+        spec.output_limit = request.output_limit;
+        spec.stack_limit = request.stack_limit;
+        if !request.cpus.is_empty() {
+            spec.cpus = Some(request.cpus);
+        }
+        if request.replace_env {
+            spec.env = Some(request.env);
+        }
+
+        self.with_box(request.box_id, move |sandbox| {
+            let result = sandbox.run(&spec)?;
+            let (verdict, exit_code, signal) = match result.verdict {
+                api::Verdict::ExitCode(code) => (proto::Verdict::ExitCode, code, 0),
+                api::Verdict::Signaled(signal) => (proto::Verdict::Signaled, 0, signal),
+                api::Verdict::SecurityViolation => (proto::Verdict::SecurityViolation, 0, 0),
+                api::Verdict::CPUTimeLimitExceeded => (proto::Verdict::CpuTimeLimitExceeded, 0, 0),
+                api::Verdict::RealTimeLimitExceeded => {
+                    (proto::Verdict::RealTimeLimitExceeded, 0, 0)
+                }
+                api::Verdict::IdlenessTimeLimitExceeded => {
+                    (proto::Verdict::IdlenessTimeLimitExceeded, 0, 0)
+                }
+                api::Verdict::MemoryLimitExceeded => (proto::Verdict::MemoryLimitExceeded, 0, 0),
+                api::Verdict::OutputLimitExceeded => (proto::Verdict::OutputLimitExceeded, 0, 0),
+                api::Verdict::NetworkLimitExceeded => (proto::Verdict::NetworkLimitExceeded, 0, 0),
+            };
+            Ok(proto::RunResult {
+                verdict: verdict.into(),
+                exit_code,
+                signal,
+                real_time: result.real_time.as_secs_f64(),
+                cpu_time: result.cpu_time.as_secs_f64(),
+                user_time: result.user_time.as_secs_f64(),
+                system_time: result.system_time.as_secs_f64(),
+                idleness_time: result.idleness_time.as_secs_f64(),
+                memory: result.memory,
+                memory_source: result.memory_source,
+                swap: result.swap,
+                processes_limit_hit: result.processes_limit_hit,
+            })
+        })
+        .await
+    }
+
+    async fn read_file(
+        &self,
+        request: Request<proto::ReadFileRequest>,
+    ) -> Result<Response<proto::ReadFileReply>, Status> {
+        let request = request.into_inner();
+        self.with_box(request.box_id, move |sandbox| {
+            Ok(proto::ReadFileReply {
+                content: sandbox.read_file(&request.path)?,
+            })
+        })
+        .await
+    }
+
+    async fn write_file(
+        &self,
+        request: Request<proto::WriteFileRequest>,
+    ) -> Result<Response<proto::Empty>, Status> {
+        let request = request.into_inner();
+        self.with_box(request.box_id, move |sandbox| {
+            sandbox.write_file(&request.path, &request.content)?;
+            Ok(proto::Empty {})
+        })
+        .await
+    }
+
+    async fn command(
+        &self,
+        request: Request<proto::CommandRequest>,
+    ) -> Result<Response<proto::CommandReply>, Status> {
+        let request = request.into_inner();
+        let argument = if request.argument.is_empty() {
+            json::JsonValue::Null
+        } else {
+            json::parse(&request.argument)
+                .map_err(|e| Status::invalid_argument(format!("Invalid 'argument': {e}")))?
+        };
+        self.with_box(request.box_id, move |sandbox| {
+            Ok(proto::CommandReply {
+                result: sandbox.command(&request.command, argument)?.dump(),
+            })
+        })
+        .await
+    }
+}
@@ -0,0 +1,362 @@
+use crate::linux::{archive, ids, rootfs};
+use anyhow::{bail, Context, Result};
+use nix::{libc, sys::stat::Mode, unistd::mkfifo};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs::{File, Permissions};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::os::unix::{
+    ffi::OsStrExt,
+    fs::{OpenOptionsExt, PermissionsExt},
+};
+use std::path::{Component, Path, PathBuf};
+
+// Converts container images into directories suitable for --root. Both the OCI image layout (as
+// produced by skopeo or `docker save` since Docker 25) and the legacy `docker save` format are
+// supported, either as a directory or as a tarball. The layers are applied on top of each other,
+// handling whiteouts the way overlayfs-based container runtimes do.
+//
+// Unlike the archives from the judge, images are trusted to be sane, so absolute symlinks are
+// allowed, but paths are still resolved relative to the image root, so that a malformed image
+// can't damage the host. All files are owned by the sandboxed root, and setuid and setgid bits are
+// dropped.
+
+pub fn import(image: &Path, output: &Path) -> Result<()> {
+    archive::unpack_atomically(output, |staging| {
+        chown(staging)?;
+        let metadata =
+            std::fs::metadata(image).with_context(|| format!("Failed to stat {image:?}"))?;
+        if metadata.is_dir() {
+            return apply_layers(image, staging);
+        }
+
+        // The layers are stored in an arbitrary order, so unpack the tarball next to the result
+        // first
+        let parent = output.parent().context("Invalid output path")?;
+        let unpacked = parent.join(format!(
+            ".{}.image",
+            output
+                .file_name()
+                .context("Invalid output path")?
+                .to_string_lossy()
+        ));
+        std::fs::create_dir(&unpacked).with_context(|| format!("Failed to mkdir {unpacked:?}"))?;
+        let result: Result<()> = try {
+            let mut input = BufReader::new(
+                File::open(image).with_context(|| format!("Failed to open {image:?}"))?,
+            );
+            unpack_image_archive(&mut input, &unpacked)?;
+            apply_layers(&unpacked, staging)?;
+        };
+        // The original error is more important than a failure here
+        let _ = std::fs::remove_dir_all(&unpacked);
+        result
+    })
+}
+
+fn unpack_image_archive(input: &mut impl Read, root: &Path) -> Result<()> {
+    while let Some(entry) = archive::read_entry_header(input)? {
+        let Some(path) = resolve_entry(root, &entry.name)? else {
+            archive::skip_data(input, entry.size)?;
+            continue;
+        };
+        match entry.type_flag {
+            b'0' | b'\0' | b'7' => {
+                let mut file = File::options()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .with_context(|| format!("Failed to create {path:?}"))?;
+                let copied = std::io::copy(&mut input.take(entry.size), &mut file)
+                    .with_context(|| format!("Failed to write {path:?}"))?;
+                if copied < entry.size {
+                    bail!("Unexpected end of archive");
+                }
+                archive::skip_padding(input, entry.size)?;
+            }
+            b'2' => {
+                // Legacy layer directories may link to blobs
+                std::os::unix::fs::symlink(OsStr::from_bytes(&entry.link_name), &path)
+                    .with_context(|| format!("Failed to create symlink {path:?}"))?;
+                archive::skip_data(input, entry.size)?;
+            }
+            b'5' => {
+                create_dir_if_missing(&path)?;
+                archive::skip_data(input, entry.size)?;
+            }
+            _ => archive::skip_data(input, entry.size)?,
+        }
+    }
+    Ok(())
+}
+
+fn apply_layers(image: &Path, root: &Path) -> Result<()> {
+    for layer in list_layers(image)? {
+        let path = rootfs::resolve_abs_in(image, &layer)
+            .with_context(|| format!("Failed to resolve layer {layer}"))?;
+        let file = File::open(&path).with_context(|| format!("Failed to open layer {layer}"))?;
+        let mut input = BufReader::new(file);
+        let magic = input
+            .fill_buf()
+            .with_context(|| format!("Failed to read layer {layer}"))?;
+        let result = if magic.starts_with(&[0x1f, 0x8b]) {
+            apply_layer(&mut flate2::bufread::MultiGzDecoder::new(input), root)
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            bail!("Layer {layer} is compressed with zstd, which is not supported");
+        } else {
+            apply_layer(&mut input, root)
+        };
+        result.with_context(|| format!("Failed to apply layer {layer}"))?;
+    }
+    Ok(())
+}
+
+fn list_layers(image: &Path) -> Result<Vec<String>> {
+    // The legacy format is simpler, and `docker save` produces both
+    if let Ok(manifest) = read_json(image, "manifest.json") {
+        let layers = &manifest[0]["Layers"];
+        if !layers.is_array() {
+            bail!("Invalid manifest.json");
+        }
+        return layers
+            .members()
+            .map(|layer| {
+                layer
+                    .as_str()
+                    .map(|layer| layer.to_string())
+                    .context("Invalid manifest.json")
+            })
+            .collect();
+    }
+
+    let mut manifest = read_json(image, "index.json")
+        .context("Neither manifest.json nor index.json could be read")?;
+    // Indexes can be nested, e.g. for multi-platform images
+    while manifest["manifests"].is_array() {
+        let descriptor = choose_manifest(&manifest["manifests"])?;
+        manifest = read_json(image, &blob_path(descriptor)?)?;
+    }
+    let layers = &manifest["layers"];
+    if !layers.is_array() {
+        bail!("Invalid image manifest");
+    }
+    layers.members().map(blob_path).collect()
+}
+
+fn choose_manifest(manifests: &json::JsonValue) -> Result<&json::JsonValue> {
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        arch => arch,
+    };
+    manifests
+        .members()
+        .find(|descriptor| {
+            let platform = &descriptor["platform"];
+            platform.is_null() || (platform["os"] == "linux" && platform["architecture"] == arch)
+        })
+        .with_context(|| format!("No manifest for linux/{arch} in the image"))
+}
+
+fn blob_path(descriptor: &json::JsonValue) -> Result<String> {
+    let digest = descriptor["digest"]
+        .as_str()
+        .context("Invalid digest in image manifest")?;
+    let (algorithm, hash) = digest
+        .split_once(':')
+        .context("Invalid digest in image manifest")?;
+    Ok(format!("blobs/{algorithm}/{hash}"))
+}
+
+fn read_json(image: &Path, name: &str) -> Result<json::JsonValue> {
+    let path = rootfs::resolve_abs_in(image, name)?;
+    let data = std::fs::read_to_string(path).with_context(|| format!("Failed to read {name}"))?;
+    json::parse(&data).with_context(|| format!("Invalid JSON in {name}"))
+}
+
+fn apply_layer(input: &mut impl Read, root: &Path) -> Result<()> {
+    let mut directories = Vec::new();
+    // Opaque directories hide the contents of the lower layers only, and the marker may come after
+    // entries of the same layer, so the paths extracted from this layer are kept track of
+    let mut extracted = HashSet::new();
+    let mut opaque = Vec::new();
+
+    while let Some(entry) = archive::read_entry_header(input)? {
+        let Some(path) = resolve_entry(root, &entry.name)? else {
+            archive::skip_data(input, entry.size)?;
+            continue;
+        };
+        let mode = entry.mode & 0o1777;
+
+        // Whiteouts hide files from the lower layers
+        let file_name = path.file_name().unwrap().as_bytes();
+        if file_name == b".wh..wh..opq" {
+            let parent = path.parent().unwrap().to_path_buf();
+            mark_extracted(&mut extracted, &parent);
+            opaque.push(parent);
+            archive::skip_data(input, entry.size)?;
+            continue;
+        }
+        if let Some(hidden) = file_name.strip_prefix(b".wh.") {
+            remove_existing(&path.with_file_name(OsStr::from_bytes(hidden)))?;
+            archive::skip_data(input, entry.size)?;
+            continue;
+        }
+
+        // Upper layers replace files from the lower layers, except that directories are merged
+        if entry.type_flag != b'5' || !std::fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir()) {
+            remove_existing(&path)?;
+        }
+        mark_extracted(&mut extracted, &path);
+
+        match entry.type_flag {
+            b'0' | b'\0' | b'7' => {
+                let mut file = File::options()
+                    .write(true)
+                    .create_new(true)
+                    .custom_flags(libc::O_NOFOLLOW)
+                    .open(&path)
+                    .with_context(|| format!("Failed to create {path:?}"))?;
+                let copied = std::io::copy(&mut input.take(entry.size), &mut file)
+                    .with_context(|| format!("Failed to write {path:?}"))?;
+                if copied < entry.size {
+                    bail!("Unexpected end of archive");
+                }
+                archive::skip_padding(input, entry.size)?;
+                chown(&path)?;
+                file.set_permissions(Permissions::from_mode(mode))
+                    .with_context(|| format!("Failed to chmod {path:?}"))?;
+                archive::set_mtime(&path, entry.mtime)?;
+            }
+            b'1' => {
+                let source = resolve_entry(root, &entry.link_name)?
+                    .with_context(|| format!("Invalid hard link target of {path:?}"))?;
+                std::fs::hard_link(&source, &path)
+                    .with_context(|| format!("Failed to link {path:?} to {source:?}"))?;
+                archive::skip_data(input, entry.size)?;
+            }
+            b'2' => {
+                std::os::unix::fs::symlink(OsStr::from_bytes(&entry.link_name), &path)
+                    .with_context(|| format!("Failed to create symlink {path:?}"))?;
+                chown(&path)?;
+                archive::set_mtime(&path, entry.mtime)?;
+                archive::skip_data(input, entry.size)?;
+            }
+            b'3' | b'4' => {
+                // The box has its own /dev
+                archive::skip_data(input, entry.size)?;
+            }
+            b'5' => {
+                create_dir_if_missing(&path)?;
+                chown(&path)?;
+                directories.push((path, mode, entry.mtime));
+                archive::skip_data(input, entry.size)?;
+            }
+            b'6' => {
+                mkfifo(&path, Mode::from_bits_truncate(mode))
+                    .with_context(|| format!("Failed to mkfifo {path:?}"))?;
+                chown(&path)?;
+                std::fs::set_permissions(&path, Permissions::from_mode(mode))
+                    .with_context(|| format!("Failed to chmod {path:?}"))?;
+                archive::set_mtime(&path, entry.mtime)?;
+                archive::skip_data(input, entry.size)?;
+            }
+            type_flag => bail!(
+                "Unsupported type {:?} of layer entry {path:?}",
+                type_flag as char
+            ),
+        }
+    }
+
+    for dir in opaque {
+        remove_lower_entries(&dir, &extracted)?;
+    }
+
+    // Same as in archive.rs
+    for (path, mode, mtime) in directories.into_iter().rev() {
+        std::fs::set_permissions(&path, Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to chmod {path:?}"))?;
+        archive::set_mtime(&path, mtime)?;
+    }
+
+    Ok(())
+}
+
+fn resolve_entry(root: &Path, name: &[u8]) -> Result<Option<PathBuf>> {
+    let name = Path::new(OsStr::from_bytes(name));
+    let mut parts = Vec::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            // Some tools store absolute paths
+            Component::RootDir | Component::CurDir => {}
+            _ => bail!("Layer entry {name:?} contains '..'"),
+        }
+    }
+    let Some(file_name) = parts.pop() else {
+        return Ok(None);
+    };
+    // Symlinks from the image are resolved inside the image
+    let parent: PathBuf = parts.into_iter().collect();
+    let parent = rootfs::resolve_abs_in(root, parent)
+        .with_context(|| format!("Failed to resolve parent of {name:?}"))?;
+    Ok(Some(parent.join(file_name)))
+}
+
+fn mark_extracted(extracted: &mut HashSet<PathBuf>, path: &Path) {
+    // The parents are extracted as well, even if the layer doesn't list them
+    for ancestor in path.ancestors() {
+        if !extracted.insert(ancestor.to_path_buf()) {
+            break;
+        }
+    }
+}
+
+fn remove_lower_entries(dir: &Path, extracted: &HashSet<PathBuf>) -> Result<()> {
+    for child in std::fs::read_dir(dir).with_context(|| format!("Failed to readdir {dir:?}"))? {
+        let child = child.with_context(|| format!("Failed to readdir {dir:?}"))?;
+        let path = child.path();
+        if !extracted.contains(&path) {
+            remove_existing(&path)?;
+        } else if std::fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir()) {
+            remove_lower_entries(&path, extracted)?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_existing(path: &Path) -> Result<()> {
+    let result = match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => std::fs::remove_dir_all(path),
+        Ok(_) => std::fs::remove_file(path),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {path:?}")),
+    }
+}
+
+fn create_dir_if_missing(path: &Path) -> Result<()> {
+    match std::fs::create_dir(path) {
+        Ok(()) => Ok(()),
+        Err(e)
+            if e.kind() == ErrorKind::AlreadyExists
+                && std::fs::symlink_metadata(path).is_ok_and(|m| m.is_dir()) =>
+        {
+            Ok(())
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to mkdir {path:?}")),
+    }
+}
+
+fn chown(path: &Path) -> Result<()> {
+    std::os::unix::fs::lchown(
+        path,
+        Some(ids::EXTERNAL_ROOT_UID),
+        Some(ids::EXTERNAL_ROOT_GID),
+    )
+    .with_context(|| format!("Failed to chown {path:?}"))
+}
@@ -0,0 +1,195 @@
+use anyhow::{bail, Context, Result};
+use multiprocessing::Object;
+use nix::libc;
+use std::ffi::CString;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+// Landlock restricts the file accesses of the program by path, independently of mounts and
+// permissions, so that a program that escapes the chroot through a kernel bug still can't write
+// anywhere but its scratch directories. The ruleset is applied to the program and whatever it
+// executes, after the box is set up, and it only concerns paths opened afterwards: the standard
+// streams and the inherited descriptors work regardless.
+
+#[derive(Clone, Object)]
+pub struct Ruleset {
+    // Paths inside the box beneath which files may be read and executed
+    pub read_only: Vec<String>,
+    // Paths inside the box beneath which anything may be done
+    pub read_write: Vec<String>,
+}
+
+const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+
+const LANDLOCK_ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const LANDLOCK_ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const LANDLOCK_ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const LANDLOCK_ACCESS_FS_READ_DIR: u64 = 1 << 3;
+// Everything up to LANDLOCK_ACCESS_FS_MAKE_SYM
+const ACCESS_FS_ABI_1: u64 = (1 << 13) - 1;
+const LANDLOCK_ACCESS_FS_REFER: u64 = 1 << 13;
+const LANDLOCK_ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+const ACCESS_READ_ONLY: u64 =
+    LANDLOCK_ACCESS_FS_EXECUTE | LANDLOCK_ACCESS_FS_READ_FILE | LANDLOCK_ACCESS_FS_READ_DIR;
+// The only rights that make sense for a file rather than a directory
+const ACCESS_FILE: u64 = LANDLOCK_ACCESS_FS_EXECUTE
+    | LANDLOCK_ACCESS_FS_WRITE_FILE
+    | LANDLOCK_ACCESS_FS_READ_FILE
+    | LANDLOCK_ACCESS_FS_TRUNCATE;
+
+#[repr(C)]
+struct landlock_ruleset_attr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct landlock_path_beneath_attr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+impl Ruleset {
+    // The image is read-only, and the directories backed by the quota of /space are read-write.
+    // /dev is read-write so that e.g. /dev/null and the terminal can be opened for writing.
+    pub fn default_for_box() -> Self {
+        Self {
+            read_only: vec!["/".to_string()],
+            read_write: ["/space", "/tmp", "/dev"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    // Either true for the default ruleset or {"read_only": [...], "read_write": [...]}
+    pub fn parse(arg: &json::JsonValue) -> Result<Option<Self>> {
+        if let Some(enabled) = arg.as_bool() {
+            return Ok(enabled.then(Self::default_for_box));
+        }
+        if !arg.is_object() {
+            bail!("Expected a boolean or an object");
+        }
+        let parse_paths = |key: &str| -> Result<Vec<String>> {
+            if arg[key].is_null() {
+                return Ok(Vec::new());
+            }
+            if !arg[key].is_array() {
+                bail!("Invalid '{key}'");
+            }
+            arg[key]
+                .members()
+                .map(|path| {
+                    let path = path.as_str().with_context(|| format!("Invalid '{key}'"))?;
+                    if !path.starts_with('/') {
+                        bail!("Path {path} is not absolute");
+                    }
+                    Ok(path.to_string())
+                })
+                .collect()
+        };
+        Ok(Some(Self {
+            read_only: parse_paths("read_only")?,
+            read_write: parse_paths("read_write")?,
+        }))
+    }
+
+    // Applies to the calling thread and whatever it executes. The executable is allowed in addition
+    // to the paths, as exec_wrapper lives in a memfd, which isn't beneath any path of the box.
+    pub fn apply(&self, executable: RawFd) -> Result<()> {
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<landlock_ruleset_attr>(),
+                0,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        if abi == -1 {
+            return Err(std::io::Error::last_os_error())
+                .context("Landlock is not supported or disabled by the kernel");
+        }
+
+        // Rights the kernel doesn't know about can't be handled, so they stay unrestricted
+        let mut handled = ACCESS_FS_ABI_1;
+        if abi >= 2 {
+            handled |= LANDLOCK_ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            handled |= LANDLOCK_ACCESS_FS_TRUNCATE;
+        }
+
+        let attr = landlock_ruleset_attr {
+            handled_access_fs: handled,
+        };
+        let ruleset = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const landlock_ruleset_attr,
+                std::mem::size_of::<landlock_ruleset_attr>(),
+                0,
+            )
+        };
+        if ruleset == -1 {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to create Landlock ruleset");
+        }
+        let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset as RawFd) };
+
+        for path in &self.read_only {
+            add_path_rule(&ruleset, path, ACCESS_READ_ONLY & handled)?;
+        }
+        for path in &self.read_write {
+            add_path_rule(&ruleset, path, handled)?;
+        }
+        add_rule(
+            &ruleset,
+            executable,
+            LANDLOCK_ACCESS_FS_EXECUTE | LANDLOCK_ACCESS_FS_READ_FILE,
+        )
+        .context("Failed to allow executing exec_wrapper")?;
+
+        if unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) } == -1
+        {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to apply Landlock ruleset");
+        }
+        Ok(())
+    }
+}
+
+fn add_path_rule(ruleset: &OwnedFd, path: &str, mut access: u64) -> Result<()> {
+    let c_path = CString::new(path).with_context(|| format!("Invalid path {path}"))?;
+    let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+    if fd == -1 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to open {path}"));
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let metadata = std::fs::metadata(path).with_context(|| format!("Failed to stat {path}"))?;
+    if !metadata.is_dir() {
+        access &= ACCESS_FILE;
+    }
+    add_rule(ruleset, fd.as_raw_fd(), access)
+        .with_context(|| format!("Failed to add Landlock rule for {path}"))
+}
+
+fn add_rule(ruleset: &OwnedFd, fd: RawFd, access: u64) -> Result<()> {
+    let attr = landlock_path_beneath_attr {
+        allowed_access: access,
+        parent_fd: fd,
+    };
+    if unsafe {
+        libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset.as_raw_fd(),
+            LANDLOCK_RULE_PATH_BENEATH,
+            &attr as *const landlock_path_beneath_attr,
+            0,
+        )
+    } == -1
+    {
+        Err(std::io::Error::last_os_error())?;
+    }
+    Ok(())
+}
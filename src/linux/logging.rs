@@ -0,0 +1,43 @@
+use anyhow::{anyhow, bail, Result};
+use multiprocessing::Object;
+use std::sync::Mutex;
+
+// Diagnostics go to stderr, as stdout is taken by the protocol. The controller, the reaper, the
+// manager, and the exporter are separate processes started via exec, so each of them installs a
+// subscriber of its own with the options of the box.
+
+#[derive(Clone, Object)]
+pub struct Options {
+    // One of "off", "error", "warn", "info", "debug", "trace"
+    pub level: String,
+    // "text" or "json"
+    pub format: String,
+}
+
+static OPTIONS: Mutex<Option<Options>> = Mutex::new(None);
+
+pub fn init(options: Options) -> Result<()> {
+    let level: tracing_subscriber::filter::LevelFilter = options
+        .level
+        .parse()
+        .map_err(|_| anyhow!("Invalid log level {}", options.level))?;
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr);
+    match options.format.as_str() {
+        "text" => builder.try_init(),
+        "json" => builder.json().try_init(),
+        format => bail!("Invalid log format {format}"),
+    }
+    .map_err(|e| anyhow!("Failed to install logger: {e}"))?;
+    *OPTIONS.lock().unwrap() = Some(options);
+    Ok(())
+}
+
+// For passing to subprocesses
+pub fn options() -> Options {
+    OPTIONS.lock().unwrap().clone().unwrap_or_else(|| Options {
+        level: "warn".to_string(),
+        format: "text".to_string(),
+    })
+}
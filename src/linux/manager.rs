@@ -1,20 +1,34 @@
-use crate::linux::{cgroups, running, system};
+use crate::linux::{cgroups, logging, rootfs, running, seccomp, system, timens};
 use anyhow::{Context, Result};
 use multiprocessing::Object;
+use nix::libc;
 use std::io::ErrorKind;
 
 #[derive(Object)]
 pub enum Command {
-    RemountReadonly { path: String },
-    Run { options: running::Options },
+    RemountReadonly {
+        path: String,
+    },
+    UnmountFuse,
+    Run {
+        programs: Vec<running::Options>,
+        events: bool,
+    },
 }
 
 #[multiprocessing::entrypoint]
 pub fn manager(
+    log_options: logging::Options,
     proc_cgroup: cgroups::ProcCgroup,
     mut channel: multiprocessing::Duplex<std::result::Result<Option<String>, String>, Command>,
+    run_channel: multiprocessing::Duplex<String, running::RunControl>,
+    time_offsets: timens::Offsets,
 ) {
-    let mut runner = running::Runner::new(proc_cgroup).expect("Failed to create runner");
+    logging::init(log_options).expect("Failed to configure logging");
+    let _span = ::tracing::info_span!("manager").entered();
+
+    let mut runner = running::Runner::new(proc_cgroup, run_channel, time_offsets)
+        .expect("Failed to create runner");
 
     channel
         .send(&Ok(None))
@@ -70,43 +84,276 @@ fn execute_command(command: Command, runner: &mut running::Runner) -> Result<Opt
             result.with_context(|| format!("Failed to remount {path} read-only"))?;
             Ok(None)
         }
-        Command::Run { options } => {
-            let results = runner.run(options)?;
+        Command::UnmountFuse => {
+            rootfs::unmount_fuse().context("Failed to unmount FUSE filesystems")?;
+            Ok(None)
+        }
+        Command::Run { programs, events } => {
+            let results: Vec<json::JsonValue> = runner
+                .run(programs, events)?
+                .into_iter()
+                .map(results_to_json)
+                .collect();
+            Ok(Some(json::stringify(results)))
+        }
+    }
+}
 
-            let limit_verdict;
-            let mut exit_code = -1;
+// The symbolic name of si_code, e.g. "SEGV_MAPERR" for a dereference of an unmapped address
+fn signal_code_name(signal: i32, code: i32) -> Option<&'static str> {
+    let names: &[&str] = match signal {
+        libc::SIGILL => &[
+            "ILL_ILLOPC",
+            "ILL_ILLOPN",
+            "ILL_ILLADR",
+            "ILL_ILLTRP",
+            "ILL_PRVOPC",
+            "ILL_PRVREG",
+            "ILL_COPROC",
+            "ILL_BADSTK",
+        ],
+        libc::SIGFPE => &[
+            "FPE_INTDIV",
+            "FPE_INTOVF",
+            "FPE_FLTDIV",
+            "FPE_FLTOVF",
+            "FPE_FLTUND",
+            "FPE_FLTRES",
+            "FPE_FLTINV",
+            "FPE_FLTSUB",
+        ],
+        libc::SIGSEGV => &["SEGV_MAPERR", "SEGV_ACCERR", "SEGV_BNDERR", "SEGV_PKUERR"],
+        libc::SIGBUS => &[
+            "BUS_ADRALN",
+            "BUS_ADRERR",
+            "BUS_OBJERR",
+            "BUS_MCEERR_AR",
+            "BUS_MCEERR_AO",
+        ],
+        libc::SIGTRAP => &["TRAP_BRKPT", "TRAP_TRACE"],
+        _ => &[],
+    };
+    match code {
+        0 => Some("SI_USER"),
+        0x80 => Some("SI_KERNEL"),
+        -1 => Some("SI_QUEUE"),
+        -2 => Some("SI_TIMER"),
+        -6 => Some("SI_TKILL"),
+        // Signal-specific codes start at 1
+        code if code > 0 => names.get(code as usize - 1).copied(),
+        _ => None,
+    }
+}
 
-            match results.verdict {
-                running::Verdict::ExitCode(exit_code_) => {
-                    limit_verdict = "OK";
-                    exit_code = exit_code_;
-                }
-                running::Verdict::Signaled(signal_number) => {
-                    limit_verdict = "Signaled";
-                    exit_code = -signal_number;
-                }
-                running::Verdict::CPUTimeLimitExceeded => {
-                    limit_verdict = "CPUTimeLimitExceeded";
-                }
-                running::Verdict::RealTimeLimitExceeded => {
-                    limit_verdict = "RealTimeLimitExceeded";
-                }
-                running::Verdict::IdlenessTimeLimitExceeded => {
-                    limit_verdict = "IdlenessTimeLimitExceeded";
-                }
-                running::Verdict::MemoryLimitExceeded => {
-                    limit_verdict = "MemoryLimitExceeded";
-                }
+fn results_to_json(results: running::RunResults) -> json::JsonValue {
+    let limit_verdict;
+    let mut exit_code = -1;
+
+    // The flat limit_verdict/exit_code pair is kept for compatibility, verdict is the structured
+    // classification
+    let mut verdict = match results.verdict {
+        running::Verdict::ExitCode(exit_code_) => {
+            limit_verdict = "OK";
+            exit_code = exit_code_;
+            if exit_code == 0 {
+                json::object! { kind: "OK" }
+            } else {
+                json::object! { kind: "RuntimeError", exit_code: exit_code }
             }
+        }
+        running::Verdict::Signaled(signal_number) => {
+            limit_verdict = "Signaled";
+            exit_code = -signal_number;
+            json::object! { kind: "RuntimeError", signal: signal_number }
+        }
+        running::Verdict::SecurityViolation => {
+            limit_verdict = "Signaled";
+            exit_code = -(libc::SIGSYS);
+            json::object! { kind: "SecurityViolation" }
+        }
+        running::Verdict::CPUTimeLimitExceeded(limit) => {
+            limit_verdict = "CPUTimeLimitExceeded";
+            json::object! {
+                kind: "TimeLimitExceeded",
+                limit: "cpu_time_limit",
+                limit_value: limit.as_secs_f64(),
+            }
+        }
+        running::Verdict::RealTimeLimitExceeded(limit) => {
+            limit_verdict = "RealTimeLimitExceeded";
+            json::object! {
+                kind: "TimeLimitExceeded",
+                limit: "real_time_limit",
+                limit_value: limit.as_secs_f64(),
+            }
+        }
+        running::Verdict::IdlenessTimeLimitExceeded(limit) => {
+            limit_verdict = "IdlenessTimeLimitExceeded";
+            json::object! {
+                kind: "TimeLimitExceeded",
+                limit: "idleness_time_limit",
+                limit_value: limit.as_secs_f64(),
+            }
+        }
+        running::Verdict::MemoryLimitExceeded(limit) => {
+            limit_verdict = "MemoryLimitExceeded";
+            json::object! {
+                kind: "MemoryLimitExceeded",
+                limit: "memory_limit",
+                limit_value: limit,
+            }
+        }
+        running::Verdict::OutputLimitExceeded(limit) => {
+            limit_verdict = "OutputLimitExceeded";
+            json::object! {
+                kind: "OutputLimitExceeded",
+                limit: "output_limit",
+                limit_value: limit,
+            }
+        }
+        running::Verdict::NetworkLimitExceeded(limit) => {
+            limit_verdict = "NetworkLimitExceeded";
+            json::object! {
+                kind: "NetworkLimitExceeded",
+                limit: "network_limit",
+                limit_value: limit,
+            }
+        }
+    };
 
-            Ok(Some(json::stringify(json::object! {
-                limit_verdict: limit_verdict,
-                exit_code: exit_code,
-                real_time: results.real_time.as_secs_f64(),
-                cpu_time: results.cpu_time.as_secs_f64(),
-                idleness_time: results.idleness_time.as_secs_f64(),
-                memory: results.memory,
-            })))
+    if verdict["kind"] == "RuntimeError" && results.processes_limit_hit {
+        verdict["limit"] = "processes_limit".into();
+    }
+
+    let mut result = json::object! {
+        verdict: verdict,
+        limit_verdict: limit_verdict,
+        exit_code: exit_code,
+        real_time: results.real_time.as_secs_f64(),
+        cpu_time: results.cpu_time.as_secs_f64(),
+        user_time: results.user_time.as_secs_f64(),
+        system_time: results.system_time.as_secs_f64(),
+        threads: results
+            .threads
+            .iter()
+            .map(|thread| {
+                json::object! {
+                    tid: thread.tid,
+                    user_time: thread.user_time.as_secs_f64(),
+                    system_time: thread.system_time.as_secs_f64(),
+                }
+            })
+            .collect::<Vec<_>>(),
+        idleness_time: results.idleness_time.as_secs_f64(),
+        memory: results.memory,
+        memory_source: results.memory_source,
+        swap: results.swap,
+        io_read: results.io_read,
+        io_written: results.io_written,
+        processes_limit_hit: results.processes_limit_hit,
+    };
+    if let Some(attempts) = results.socket_attempts {
+        result["security_report"] = json::object! {
+            sockets: attempts
+                .into_iter()
+                .map(|attempt| {
+                    let mut object = json::object! {
+                        pid: attempt.pid,
+                        syscall: seccomp::syscall_name(attempt.syscall),
+                        denied: attempt.denied,
+                    };
+                    match attempt.target {
+                        running::SocketTarget::AbstractUnix(name) => {
+                            object["kind"] = "abstract_unix".into();
+                            // Written like ss(8) does, with @ for the leading and other NULs
+                            object["name"] = name
+                                .map(|name| {
+                                    format!("@{}", String::from_utf8_lossy(&name).replace('\0', "@"))
+                                })
+                                .into();
+                        }
+                        running::SocketTarget::Netlink(protocol) => {
+                            object["kind"] = "netlink".into();
+                            object["protocol"] = protocol.into();
+                        }
+                    }
+                    object
+                })
+                .collect::<Vec<_>>(),
+        };
+    }
+    if let Some(network) = results.network {
+        result["network"] = json::object! {
+            bytes_sent: network.bytes_sent,
+            bytes_received: network.bytes_received,
+            packets_sent: network.packets_sent,
+            packets_received: network.packets_received,
+        };
+    }
+    if let Some(syscalls) = results.syscalls {
+        let mut syscalls: Vec<(String, u64)> = syscalls
+            .into_iter()
+            .map(|(syscall, count)| {
+                let name = seccomp::syscall_name(syscall)
+                    .map_or_else(|| syscall.to_string(), String::from);
+                (name, count)
+            })
+            .collect();
+        syscalls.sort();
+        let mut object = json::JsonValue::new_object();
+        for (name, count) in syscalls {
+            object[name] = count.into();
         }
+        result["syscalls"] = object;
+    }
+    if let Some(crash) = results.crash {
+        let regs = &crash.registers;
+        let mut registers = json::JsonValue::new_object();
+        for (name, value) in [
+            ("rip", regs.rip),
+            ("rsp", regs.rsp),
+            ("rbp", regs.rbp),
+            ("rax", regs.rax),
+            ("rbx", regs.rbx),
+            ("rcx", regs.rcx),
+            ("rdx", regs.rdx),
+            ("rsi", regs.rsi),
+            ("rdi", regs.rdi),
+            ("r8", regs.r8),
+            ("r9", regs.r9),
+            ("r10", regs.r10),
+            ("r11", regs.r11),
+            ("r12", regs.r12),
+            ("r13", regs.r13),
+            ("r14", regs.r14),
+            ("r15", regs.r15),
+            ("eflags", regs.eflags),
+        ] {
+            // Registers often hold values that don't fit in a double
+            registers[name] = format!("{value:#x}").into();
+        }
+        result["crash"] = json::object! {
+            signal: crash.signal,
+            code: signal_code_name(crash.signal, crash.code),
+            si_code: crash.code,
+            address: crash.address.map(|address| format!("{address:#x}")),
+            tid: crash.tid,
+            registers: registers,
+        };
+    }
+    if let Some(core_dumps) = results.core_dumps {
+        result["core_dumps"] = core_dumps
+            .into_iter()
+            .map(|core_dump| {
+                json::object! {
+                    pid: core_dump.pid,
+                    path: core_dump.path,
+                    size: core_dump.size,
+                    truncated: core_dump.truncated,
+                }
+            })
+            .collect::<Vec<_>>()
+            .into();
     }
+    result
 }
@@ -0,0 +1,690 @@
+use anyhow::{bail, Context, Result};
+use nix::{fcntl, libc, unistd};
+use rand::Rng;
+use std::ffi::CString;
+use std::io::Read;
+use std::net::{Ipv4Addr, ToSocketAddrs};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::time::Duration;
+
+// Outbound networking is provided by slirp4netns, a user-mode TCP/IP stack that runs on the host
+// and connects a tap device in the network namespace of the box to the sockets of the host, so the
+// box gets neither a real interface nor any capabilities. slirp4netns has no filtering of its own,
+// so instead of the default route, the box only gets routes to the allowed networks; everything
+// else is unreachable. The box is 10.0.2.100. The DNS forwarder of slirp4netns is disabled in favor
+// of the stub resolver of the box, see dns.rs.
+
+const TAP_NAME: &str = "tap0";
+const GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+
+pub struct OutboundOptions {
+    // A path on the host
+    pub slirp4netns: String,
+    pub allow: Vec<AllowedNetwork>,
+}
+
+#[derive(Debug)]
+pub struct AllowedNetwork {
+    address: Ipv4Addr,
+    prefix_len: u8,
+    // The host name the address was resolved from
+    name: Option<String>,
+}
+
+impl OutboundOptions {
+    // The names that the stub resolver resolves
+    pub fn names(&self) -> Vec<(String, Ipv4Addr)> {
+        self.allow
+            .iter()
+            .filter_map(|network| Some((network.name.clone()?, network.address)))
+            .collect()
+    }
+}
+
+impl std::str::FromStr for AllowedNetwork {
+    type Err = anyhow::Error;
+
+    // An address, a CIDR range, or a host name, which is resolved right away
+    fn from_str(s: &str) -> Result<Self> {
+        let (host, prefix_len) = match s.split_once('/') {
+            Some((host, prefix_len)) => {
+                let prefix_len: u8 = prefix_len
+                    .parse()
+                    .with_context(|| format!("Invalid prefix length in {s}"))?;
+                if prefix_len > 32 {
+                    bail!("Invalid prefix length in {s}");
+                }
+                (host, prefix_len)
+            }
+            None => (s, 32),
+        };
+        if let Ok(address) = host.parse() {
+            return Ok(Self {
+                address,
+                prefix_len,
+                name: None,
+            });
+        }
+        if prefix_len != 32 {
+            bail!("{s} is neither an address nor a network");
+        }
+        let address = (host, 0)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {host}"))?
+            .find_map(|address| match address.ip() {
+                std::net::IpAddr::V4(address) => Some(address),
+                std::net::IpAddr::V6(_) => None,
+            })
+            .with_context(|| format!("{host} has no IPv4 address"))?;
+        Ok(Self {
+            address,
+            prefix_len: 32,
+            name: Some(host.to_string()),
+        })
+    }
+}
+
+// slirp4netns exits when the exit pipe is closed, i.e. when this is dropped or the controller dies
+pub struct Slirp {
+    pid: unistd::Pid,
+    exit_pipe: Option<OwnedFd>,
+}
+
+impl Drop for Slirp {
+    fn drop(&mut self) {
+        self.exit_pipe = None;
+        let _ = nix::sys::wait::waitpid(self.pid, None);
+    }
+}
+
+// Must be called by a process of the host, right after the network namespace is unshared
+pub fn start_outbound(options: &OutboundOptions) -> Result<Slirp> {
+    let (ready_read, ready_write) = pipe()?;
+    let (exit_read, exit_write) = pipe()?;
+
+    let netns = format!("/proc/{}/ns/net", unistd::getpid());
+    let child_fds = [ready_write.as_raw_fd(), exit_read.as_raw_fd()];
+    let mut command = std::process::Command::new(&options.slirp4netns);
+    command
+        .arg("--configure")
+        .arg("--mtu=65520")
+        // Otherwise 10.0.2.2 is the loopback of the host
+        .arg("--disable-host-loopback")
+        .arg("--disable-dns")
+        .arg(format!("--ready-fd={}", ready_write.as_raw_fd()))
+        .arg(format!("--exit-fd={}", exit_read.as_raw_fd()))
+        .arg("--netns-type=path")
+        .arg(&netns)
+        .arg(TAP_NAME)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null());
+    unsafe {
+        command.pre_exec(move || {
+            for fd in child_fds {
+                fcntl::fcntl(fd, fcntl::FcntlArg::F_SETFD(fcntl::FdFlag::empty()))?;
+            }
+            Ok(())
+        });
+    }
+    let child = command
+        .spawn()
+        .with_context(|| format!("Failed to start {}", options.slirp4netns))?;
+    let slirp = Slirp {
+        pid: unistd::Pid::from_raw(child.id() as i32),
+        exit_pipe: Some(exit_write),
+    };
+    drop(ready_write);
+    drop(exit_read);
+
+    // slirp4netns writes "1" once the tap device is configured, and exits on failure, which closes
+    // the pipe
+    let mut ready = [0];
+    let n_read = std::fs::File::from(ready_read)
+        .read(&mut ready)
+        .context("Failed to wait for slirp4netns")?;
+    if n_read == 0 {
+        bail!("slirp4netns failed to configure the network, see its stderr");
+    }
+
+    // --configure adds a default route, which is replaced with the allowed networks
+    let tap_index = interface_index(TAP_NAME)?;
+    let netlink = Netlink::open()?;
+    netlink
+        .change_route(
+            libc::RTM_DELROUTE,
+            Ipv4Addr::UNSPECIFIED,
+            0,
+            GATEWAY,
+            tap_index,
+        )
+        .context("Failed to remove default route")?;
+    for network in &options.allow {
+        netlink
+            .change_route(
+                libc::RTM_NEWROUTE,
+                network.address,
+                network.prefix_len,
+                GATEWAY,
+                tap_index,
+            )
+            .with_context(|| {
+                format!(
+                    "Failed to add route to {}/{}",
+                    network.address, network.prefix_len
+                )
+            })?;
+    }
+
+    Ok(slirp)
+}
+
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let (read, write) = unistd::pipe2(fcntl::OFlag::O_CLOEXEC).context("Failed to create pipe")?;
+    Ok(unsafe { (OwnedFd::from_raw_fd(read), OwnedFd::from_raw_fd(write)) })
+}
+
+fn interface_index(name: &str) -> Result<u32> {
+    let c_name = CString::new(name).unwrap();
+    let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+    if index == 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to get index of {name}"));
+    }
+    Ok(index)
+}
+
+// For distributed systems assignments, the box can instead get an interface of its own: one end
+// of a veth pair is attached to a bridge of the host, and the other one is moved into the network
+// namespace of the box as eth0. The judge sets up the bridge, its addresses and its firewall. The
+// pair is destroyed by the kernel together with the namespace, i.e. when the box terminates.
+
+pub struct VethOptions {
+    pub bridge: String,
+    // The network namespace of the bridge if not that of the host
+    pub netns: Option<String>,
+    pub address: Ipv4Addr,
+    pub prefix_len: u8,
+    pub gateway: Option<Ipv4Addr>,
+    pub shaping: Shaping,
+}
+
+// Applied by netem to both directions
+#[derive(Default)]
+pub struct Shaping {
+    // In bytes per second
+    pub rate: Option<u32>,
+    pub delay: Option<Duration>,
+    pub jitter: Option<Duration>,
+    // A fraction of packets from 0 to 1
+    pub loss: Option<f64>,
+}
+
+impl Shaping {
+    fn is_empty(&self) -> bool {
+        self.rate.is_none() && self.delay.is_none() && self.jitter.is_none() && self.loss.is_none()
+    }
+}
+
+// Created in the network namespace of the host, before the box unshares its own
+pub struct Veth {
+    // Bound to the network namespace of the host
+    host_netlink: Netlink,
+    box_name: String,
+    box_index: u32,
+}
+
+// Parses ADDRESS/PREFIX_LEN
+pub fn parse_address(s: &str) -> Result<(Ipv4Addr, u8)> {
+    let (address, prefix_len) = s
+        .split_once('/')
+        .with_context(|| format!("{s} is not of form ADDRESS/PREFIX_LEN"))?;
+    let address = address
+        .parse()
+        .with_context(|| format!("Invalid address {address}"))?;
+    let prefix_len = prefix_len
+        .parse()
+        .ok()
+        .filter(|prefix_len| *prefix_len <= 32)
+        .with_context(|| format!("Invalid prefix length {prefix_len}"))?;
+    Ok((address, prefix_len))
+}
+
+pub fn create_veth(options: &VethOptions) -> Result<Veth> {
+    let host_netlink = Netlink::open()?;
+
+    // Interface names are limited to 15 characters and have to be unique on the host
+    let mut rng = rand::thread_rng();
+    let id: String = (0..8)
+        .map(|_| rng.sample(rand::distributions::Alphanumeric) as char)
+        .collect();
+    let host_name = format!("swh-{id}");
+    let box_name = format!("swb-{id}");
+
+    let mut message = Message::new(
+        libc::RTM_NEWLINK,
+        libc::NLM_F_CREATE | libc::NLM_F_EXCL,
+        &ifinfomsg(0, 0, 0),
+    );
+    message.attribute(libc::IFLA_IFNAME, &c_string(&host_name));
+    let link_info = message.begin_nested(libc::IFLA_LINKINFO);
+    message.attribute(libc::IFLA_INFO_KIND, b"veth");
+    let info_data = message.begin_nested(libc::IFLA_INFO_DATA);
+    let peer = message.begin_nested(VETH_INFO_PEER);
+    message.push(&ifinfomsg(0, 0, 0));
+    message.attribute(libc::IFLA_IFNAME, &c_string(&box_name));
+    message.end_nested(peer);
+    message.end_nested(info_data);
+    message.end_nested(link_info);
+    host_netlink
+        .send(message)
+        .context("Failed to create veth pair")?;
+
+    let veth = Veth {
+        host_netlink,
+        box_index: interface_index(&box_name)?,
+        box_name,
+    };
+    // Until the other end is moved into the box, the pair has to be removed in case of failure
+    let result = match &options.netns {
+        None => attach_to_bridge(&host_name, options),
+        Some(netns) => {
+            try {
+                let netns = std::fs::File::open(netns)
+                    .with_context(|| format!("Failed to open {netns}"))?;
+                veth.host_netlink
+                    .move_link(interface_index(&host_name)?, &netns)
+                    .context("Failed to move veth into the network namespace of the bridge")?;
+                in_netns(&netns, || attach_to_bridge(&host_name, options))?;
+            }
+        }
+    };
+    if let Err(e) = result {
+        // If the host end has been moved away, attach_to_bridge has removed it already
+        delete_link(&veth.host_netlink, veth.box_index);
+        return Err(e);
+    }
+    Ok(veth)
+}
+
+// Called in the network namespace of the bridge
+fn attach_to_bridge(name: &str, options: &VethOptions) -> Result<()> {
+    let netlink = Netlink::open()?;
+    let index = interface_index(name)?;
+    let result: Result<()> = try {
+        let bridge_index = interface_index(&options.bridge)?;
+        let mut message = Message::new(libc::RTM_NEWLINK, 0, &ifinfomsg(index, 0, 0));
+        message.attribute(libc::IFLA_MASTER, &bridge_index.to_ne_bytes());
+        netlink
+            .send(message)
+            .with_context(|| format!("Failed to attach {name} to {}", options.bridge))?;
+        netlink.set_up(index)?;
+        netlink.shape(index, &options.shaping)?;
+    };
+    if result.is_err() && options.netns.is_some() {
+        // Not visible from the network namespace of the host anymore
+        delete_link(&netlink, index);
+    }
+    result
+}
+
+// Removing either end of a veth pair removes both
+fn delete_link(netlink: &Netlink, index: u32) {
+    let message = Message::new(libc::RTM_DELLINK, 0, &ifinfomsg(index, 0, 0));
+    if let Err(e) = netlink.send(message) {
+        ::tracing::warn!(index, "Failed to remove veth: {e:?}");
+    }
+}
+
+// Runs the closure in a thread that has joined the network namespace, so that the namespace of the
+// caller is left intact
+fn in_netns<T: Send>(netns: &std::fs::File, f: impl FnOnce() -> Result<T> + Send) -> Result<T> {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                if unsafe { libc::setns(netns.as_raw_fd(), libc::CLONE_NEWNET) } == -1 {
+                    return Err(std::io::Error::last_os_error())
+                        .context("Failed to join network namespace");
+                }
+                f()
+            })
+            .join()
+            .expect("Thread panicked")
+    })
+}
+
+// A private network of the daemon: a bridge in a network namespace of its own, to which the boxes
+// started with --network are attached, so that they can talk to each other but not to the host.
+// The namespace is pinned by a descriptor of the daemon, which the boxes open via /proc.
+pub struct Hub {
+    netns: std::fs::File,
+}
+
+impl Hub {
+    pub const BRIDGE: &str = "br0";
+
+    pub fn create() -> Result<Self> {
+        let netns = std::thread::scope(|scope| {
+            scope
+                .spawn(|| -> Result<std::fs::File> {
+                    if unsafe { libc::unshare(libc::CLONE_NEWNET) } == -1 {
+                        return Err(std::io::Error::last_os_error())
+                            .context("Failed to unshare network namespace");
+                    }
+                    let netns = std::fs::File::open("/proc/thread-self/ns/net")
+                        .context("Failed to open /proc/thread-self/ns/net")?;
+                    let netlink = Netlink::open()?;
+                    let mut message = Message::new(
+                        libc::RTM_NEWLINK,
+                        libc::NLM_F_CREATE | libc::NLM_F_EXCL,
+                        &ifinfomsg(0, 0, 0),
+                    );
+                    message.attribute(libc::IFLA_IFNAME, &c_string(Self::BRIDGE));
+                    let link_info = message.begin_nested(libc::IFLA_LINKINFO);
+                    message.attribute(libc::IFLA_INFO_KIND, b"bridge");
+                    message.end_nested(link_info);
+                    netlink.send(message).context("Failed to create bridge")?;
+                    netlink.set_up(interface_index(Self::BRIDGE)?)?;
+                    Ok(netns)
+                })
+                .join()
+                .expect("Thread panicked")
+        })?;
+        // Kept open across exec, so that the path stays valid for the children
+        fcntl::fcntl(
+            netns.as_raw_fd(),
+            fcntl::FcntlArg::F_SETFD(fcntl::FdFlag::empty()),
+        )
+        .context("Failed to clear FD_CLOEXEC")?;
+        Ok(Self { netns })
+    }
+
+    // To be passed to --veth-netns
+    pub fn path(&self) -> String {
+        format!("/proc/{}/fd/{}", std::process::id(), self.netns.as_raw_fd())
+    }
+}
+
+impl Veth {
+    // Must be called right after the network namespace of the box is unshared
+    pub fn move_to_box(self, options: &VethOptions) -> Result<()> {
+        let netns =
+            std::fs::File::open("/proc/self/ns/net").context("Failed to open /proc/self/ns/net")?;
+        if let Err(e) = self.host_netlink.move_link(self.box_index, &netns) {
+            delete_link(&self.host_netlink, self.box_index);
+            return Err(e).context("Failed to move veth into the box");
+        }
+
+        // The index may change when the interface is moved
+        let netlink = Netlink::open()?;
+        let index = interface_index(&self.box_name)?;
+        let mut message = Message::new(libc::RTM_NEWLINK, 0, &ifinfomsg(index, 0, 0));
+        message.attribute(libc::IFLA_IFNAME, &c_string("eth0"));
+        netlink
+            .send(message)
+            .context("Failed to rename veth to eth0")?;
+
+        // struct ifaddrmsg
+        let mut message = Message::new(
+            libc::RTM_NEWADDR,
+            libc::NLM_F_CREATE | libc::NLM_F_EXCL,
+            &[
+                &[
+                    libc::AF_INET as u8,
+                    options.prefix_len,
+                    0,
+                    libc::RT_SCOPE_UNIVERSE,
+                ][..],
+                &index.to_ne_bytes(),
+            ]
+            .concat(),
+        );
+        message.attribute(libc::IFA_LOCAL, &options.address.octets());
+        message.attribute(libc::IFA_ADDRESS, &options.address.octets());
+        netlink
+            .send(message)
+            .with_context(|| format!("Failed to assign {} to eth0", options.address))?;
+
+        netlink.set_up(index)?;
+        if let Some(gateway) = options.gateway {
+            netlink
+                .change_route(libc::RTM_NEWROUTE, Ipv4Addr::UNSPECIFIED, 0, gateway, index)
+                .context("Failed to add default route")?;
+        }
+        netlink.shape(index, &options.shaping)
+    }
+}
+
+fn c_string(s: &str) -> Vec<u8> {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.push(0);
+    bytes
+}
+
+const VETH_INFO_PEER: u16 = 1;
+const TCA_NETEM_RATE: u16 = 6;
+const TCA_NETEM_LATENCY64: u16 = 10;
+const TCA_NETEM_JITTER64: u16 = 11;
+const RTM_NEWQDISC: u16 = 36;
+const TC_H_ROOT: u32 = 0xffffffff;
+
+// Just enough rtnetlink to set up the interfaces of the box, as there is no netlink in the
+// dependencies
+struct Netlink {
+    fd: OwnedFd,
+}
+
+struct Message {
+    buffer: Vec<u8>,
+}
+
+impl Message {
+    // The header is followed by the fixed-size part of the message, e.g. struct rtmsg
+    fn new(message_type: u16, flags: i32, body: &[u8]) -> Self {
+        let mut buffer = Vec::new();
+        // struct nlmsghdr, the length is filled in when the message is sent
+        buffer.extend_from_slice(&0u32.to_ne_bytes());
+        buffer.extend_from_slice(&message_type.to_ne_bytes());
+        buffer.extend_from_slice(
+            &((flags | libc::NLM_F_REQUEST | libc::NLM_F_ACK) as u16).to_ne_bytes(),
+        );
+        buffer.extend_from_slice(&1u32.to_ne_bytes());
+        buffer.extend_from_slice(&0u32.to_ne_bytes());
+        let mut message = Self { buffer };
+        message.push(body);
+        message
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+        while self.buffer.len() % 4 != 0 {
+            self.buffer.push(0);
+        }
+    }
+
+    // struct rtattr
+    fn attribute(&mut self, kind: u16, value: &[u8]) {
+        self.push(
+            &[
+                &((4 + value.len()) as u16).to_ne_bytes()[..],
+                &kind.to_ne_bytes(),
+                value,
+            ]
+            .concat(),
+        );
+    }
+
+    // Attributes added until end_nested are nested into this one
+    fn begin_nested(&mut self, kind: u16) -> usize {
+        let start = self.buffer.len();
+        self.attribute(kind, &[]);
+        start
+    }
+
+    fn end_nested(&mut self, start: usize) {
+        let length = (self.buffer.len() - start) as u16;
+        self.buffer[start..start + 2].copy_from_slice(&length.to_ne_bytes());
+    }
+}
+
+// struct ifinfomsg
+fn ifinfomsg(index: u32, flags: i32, change: i32) -> Vec<u8> {
+    [
+        &[libc::AF_UNSPEC as u8, 0, 0, 0][..],
+        &index.to_ne_bytes(),
+        &flags.to_ne_bytes(),
+        &change.to_ne_bytes(),
+    ]
+    .concat()
+}
+
+impl Netlink {
+    fn open() -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd == -1 {
+            return Err(std::io::Error::last_os_error()).context("Failed to create netlink socket");
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd as RawFd) },
+        })
+    }
+
+    fn send(&self, mut message: Message) -> Result<()> {
+        let length = message.buffer.len() as u32;
+        message.buffer[..4].copy_from_slice(&length.to_ne_bytes());
+        unistd::write(self.fd.as_raw_fd(), &message.buffer)
+            .context("Failed to send netlink message")?;
+
+        // The acknowledgement is an error message with error 0
+        let mut reply = [0u8; 1024];
+        let n_read = unistd::read(self.fd.as_raw_fd(), &mut reply)
+            .context("Failed to receive netlink reply")?;
+        if n_read < 20 || u16::from_ne_bytes([reply[4], reply[5]]) != libc::NLMSG_ERROR as u16 {
+            bail!("Unexpected netlink reply");
+        }
+        let error = i32::from_ne_bytes(reply[16..20].try_into().unwrap());
+        if error != 0 {
+            return Err(std::io::Error::from_raw_os_error(-error).into());
+        }
+        Ok(())
+    }
+
+    // A route in the main table, the default one if prefix_len is 0
+    fn change_route(
+        &self,
+        message_type: u16,
+        destination: Ipv4Addr,
+        prefix_len: u8,
+        gateway: Ipv4Addr,
+        interface: u32,
+    ) -> Result<()> {
+        let flags = if message_type == libc::RTM_NEWROUTE {
+            libc::NLM_F_CREATE | libc::NLM_F_EXCL
+        } else {
+            0
+        };
+        // struct rtmsg
+        let mut message = Message::new(
+            message_type,
+            flags,
+            &[
+                libc::AF_INET as u8,
+                prefix_len,
+                0,
+                0,
+                libc::RT_TABLE_MAIN,
+                libc::RTPROT_BOOT,
+                libc::RT_SCOPE_UNIVERSE,
+                libc::RTN_UNICAST,
+                0,
+                0,
+                0,
+                0,
+            ],
+        );
+        if prefix_len > 0 {
+            message.attribute(libc::RTA_DST, &destination.octets());
+        }
+        message.attribute(libc::RTA_GATEWAY, &gateway.octets());
+        message.attribute(libc::RTA_OIF, &interface.to_ne_bytes());
+        self.send(message)
+    }
+
+    fn move_link(&self, index: u32, netns: &std::fs::File) -> Result<()> {
+        let mut message = Message::new(libc::RTM_NEWLINK, 0, &ifinfomsg(index, 0, 0));
+        message.attribute(libc::IFLA_NET_NS_FD, &netns.as_raw_fd().to_ne_bytes());
+        self.send(message)
+    }
+
+    fn set_up(&self, index: u32) -> Result<()> {
+        self.send(Message::new(
+            libc::RTM_NEWLINK,
+            0,
+            &ifinfomsg(index, libc::IFF_UP, libc::IFF_UP),
+        ))
+        .context("Failed to bring interface up")
+    }
+
+    // Replaces the root qdisc of the interface with netem
+    fn shape(&self, index: u32, shaping: &Shaping) -> Result<()> {
+        if shaping.is_empty() {
+            return Ok(());
+        }
+        // struct tcmsg
+        let mut message = Message::new(
+            RTM_NEWQDISC,
+            libc::NLM_F_CREATE | libc::NLM_F_REPLACE,
+            &[
+                &[libc::AF_UNSPEC as u8, 0, 0, 0][..],
+                &index.to_ne_bytes(),
+                // 1:0 under the root
+                &0x10000u32.to_ne_bytes(),
+                &TC_H_ROOT.to_ne_bytes(),
+                &0u32.to_ne_bytes(),
+            ]
+            .concat(),
+        );
+        message.attribute(libc::TCA_KIND, &c_string("netem"));
+        let options = message.begin_nested(libc::TCA_OPTIONS);
+        // struct tc_netem_qopt: latency and jitter in ticks are overridden by the 64-bit
+        // attributes. The limit is the length of the queue in packets, 1000 as with tc.
+        let loss = (shaping.loss.unwrap_or(0.0).clamp(0.0, 1.0) * u32::MAX as f64) as u32;
+        message.push(
+            &[0u32, 1000, loss, 0, 0, 0]
+                .iter()
+                .flat_map(|field| field.to_ne_bytes())
+                .collect::<Vec<u8>>(),
+        );
+        if let Some(delay) = shaping.delay {
+            message.attribute(
+                TCA_NETEM_LATENCY64,
+                &(delay.as_nanos() as i64).to_ne_bytes(),
+            );
+        }
+        if let Some(jitter) = shaping.jitter {
+            message.attribute(
+                TCA_NETEM_JITTER64,
+                &(jitter.as_nanos() as i64).to_ne_bytes(),
+            );
+        }
+        if let Some(rate) = shaping.rate {
+            // struct tc_netem_rate
+            message.attribute(
+                TCA_NETEM_RATE,
+                &[rate, 0, 0, 0]
+                    .iter()
+                    .flat_map(|field| field.to_ne_bytes())
+                    .collect::<Vec<u8>>(),
+            );
+        }
+        message.end_nested(options);
+        self.send(message)
+            .context("Failed to configure traffic shaping")
+    }
+}
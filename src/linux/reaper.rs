@@ -1,6 +1,6 @@
 use crate::{
     entry,
-    linux::{cgroups, ipc, manager, procs},
+    linux::{cgroups, ipc, logging, manager, procs, running, timens},
 };
 use anyhow::{Context, Result};
 use multiprocessing::Object;
@@ -24,31 +24,49 @@ pub enum Command {
     Reset,
 }
 
+// What the reaper talks over and passes on to the manager
+#[derive(Object)]
+pub struct ReaperArgs {
+    pub reaper_channel:
+        multiprocessing::Duplex<std::result::Result<Option<String>, String>, Command>,
+    pub manager_channel:
+        multiprocessing::Duplex<std::result::Result<Option<String>, String>, manager::Command>,
+    pub run_channel: multiprocessing::Duplex<String, running::RunControl>,
+    pub time_offsets: timens::Offsets,
+}
+
 #[multiprocessing::entrypoint]
 pub fn reaper(
     ppidfd: OwnedFd,
     cli_command: entry::CLIStartCommand,
-    cgroup: cgroups::Cgroup,
-    mut reaper_channel: multiprocessing::Duplex<
-        std::result::Result<Option<String>, String>,
-        Command,
-    >,
-    manager_channel: multiprocessing::Duplex<
-        std::result::Result<Option<String>, String>,
-        manager::Command,
-    >,
+    proc_cgroup: cgroups::ProcCgroup,
+    args: ReaperArgs,
 ) -> ! {
+    let ReaperArgs {
+        mut reaper_channel,
+        manager_channel,
+        run_channel,
+        time_offsets,
+    } = args;
+
     if nix::unistd::getpid().as_raw() != 1 {
         panic!("Reaper must have PID 1");
     }
 
+    let log_options = logging::Options {
+        level: cli_command.log_level.clone(),
+        format: cli_command.log_format.clone(),
+    };
+    logging::init(log_options.clone()).expect("Failed to configure logging");
+    let _span = ::tracing::info_span!("reaper", core = cli_command.core).entered();
+
     // We want to receive SIGUSR1 and SIGCHLD, but not handle them immediately
     let mut mask = signal::SigSet::empty();
     mask.add(signal::Signal::SIGUSR1);
     mask.add(signal::Signal::SIGIO);
     mask.add(signal::Signal::SIGCHLD);
     if let Err(e) = mask.thread_block() {
-        eprintln!("Failed to configure signal mask: {e}");
+        ::tracing::error!(error = %e, "Failed to configure signal mask");
         std::process::exit(1);
     }
 
@@ -66,7 +84,7 @@ pub fn reaper(
                 ),
             )
         } {
-            eprintln!("Failed to configure sigaction: {e}");
+            ::tracing::error!(error = %e, "Failed to configure sigaction");
             std::process::exit(1);
         }
     }
@@ -125,15 +143,15 @@ pub fn reaper(
 
     // We have to separate reaping and sandbox management, because we need to spawn processes, and
     // reaping all of them continuously is going to be confusing to stdlib.
-    let proc_cgroup = cgroup
-        .create_proc_cgroup()
-        .expect("Failed to create box cgroup");
     let child = manager::manager
         .spawn(
+            log_options,
             proc_cgroup
                 .try_clone()
                 .expect("Failed to clone box cgroup reference"),
             manager_channel,
+            run_channel,
+            time_offsets,
         )
         .expect("Failed to start child");
     // We purposefully don't join manager here, as it may die unexpectedly
@@ -194,7 +212,7 @@ pub fn reaper(
 
     // If parent is dead by now, we don't have anyone to report the error to
     if let Err(e) = proc_cgroup.destroy() {
-        eprintln!("Failed to destroy box cgroup: {e:?}");
+        ::tracing::error!(error = %format!("{e:#}"), "Failed to destroy box cgroup");
     }
 
     // Don't send the result to the parent
@@ -1,9 +1,12 @@
-use crate::linux::{ids, mountns, procs, system};
-use anyhow::{anyhow, Context, Result};
+use crate::linux::{dns, ids, mountns, procs, sandbox, system};
+use anyhow::{anyhow, bail, Context, Result};
 use std::collections::HashMap;
 use std::ffi::{OsStr, OsString};
 use std::io::{BufRead, ErrorKind};
-use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::{
+    ffi::{OsStrExt, OsStringExt},
+    fs::MetadataExt,
+};
 use std::path::{Component, Path, PathBuf};
 
 pub struct DiskQuotas {
@@ -11,73 +14,338 @@ pub struct DiskQuotas {
     pub max_inodes: u64,
 }
 
+pub struct TmpfsMount {
+    pub path: String,
+    pub size: u64,
+    pub max_inodes: u64,
+    pub mode: u32,
+}
+
+impl std::str::FromStr for TmpfsMount {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split(':');
+        let (Some(path), Some(size), Some(max_inodes)) = (parts.next(), parts.next(), parts.next())
+        else {
+            bail!("Expected PATH:SIZE:INODES[:MODE], got {s:?}");
+        };
+        if !path.starts_with('/') {
+            bail!("Path to tmpfs {path:?} must be absolute");
+        }
+        // Normalized, so that the checks below and in check_tmpfs_mounts can compare paths
+        let mut normalized = PathBuf::from("/");
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => normalized.push(name),
+                Component::ParentDir => bail!("Path to tmpfs {path:?} must not contain '..'"),
+                Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+            }
+        }
+        if normalized == Path::new("/") {
+            bail!("tmpfs cannot be mounted on the root, use --writable-root instead");
+        }
+        if let Some(dir) = reserved_tmpfs_dir(&normalized) {
+            bail!(
+                "tmpfs cannot be mounted on {path:?}, because {dir} is managed by the box itself"
+            );
+        }
+        let mode = match parts.next() {
+            Some(mode) => u32::from_str_radix(mode, 8).context("Invalid mode of tmpfs")?,
+            None => 0o1777,
+        };
+        if parts.next().is_some() {
+            bail!("Expected PATH:SIZE:INODES[:MODE], got {s:?}");
+        }
+        Ok(Self {
+            path: normalized
+                .into_os_string()
+                .into_string()
+                .expect("Path is built from a string"),
+            size: size.parse().context("Invalid size of tmpfs")?,
+            max_inodes: max_inodes.parse().context("Invalid inode limit of tmpfs")?,
+            mode,
+        })
+    }
+}
+
+// /space, /tmp, and /dev/shm share a quota and are replaced or kept as a whole on reset, so a
+// tmpfs inside them would either disappear or survive reset
+fn reserved_tmpfs_dir(path: &Path) -> Option<&'static str> {
+    ["/space", "/tmp", "/dev/shm"]
+        .into_iter()
+        .find(|dir| path.starts_with(dir))
+}
+
+// Each tmpfs is mounted anew on reset, so a tmpfs inside another one would be mounted onto a
+// directory that the other one has just hidden
+pub fn check_tmpfs_overlaps(tmpfs_mounts: &[TmpfsMount]) -> Result<()> {
+    for (i, a) in tmpfs_mounts.iter().enumerate() {
+        for b in &tmpfs_mounts[..i] {
+            if Path::new(&a.path).starts_with(&b.path) || Path::new(&b.path).starts_with(&a.path) {
+                bail!("tmpfs on {:?} overlaps tmpfs on {:?}", a.path, b.path);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Called once the root is created, so that a bad path fails the start rather than the first reset
+pub fn check_tmpfs_mounts(tmpfs_mounts: &[TmpfsMount]) -> Result<()> {
+    for tmpfs in tmpfs_mounts {
+        let path = resolve_abs_box_root(&tmpfs.path)
+            .with_context(|| format!("Failed to resolve {:?}", tmpfs.path))?;
+        // Symlinks can point into the directories rejected when parsing the option
+        if let Some(dir) = path
+            .strip_prefix("/newroot")
+            .ok()
+            .and_then(|path| reserved_tmpfs_dir(&Path::new("/").join(path)))
+        {
+            bail!(
+                "tmpfs cannot be mounted on {:?}, because it is inside {dir}, which is managed by \
+                 the box itself",
+                tmpfs.path
+            );
+        }
+        match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => bail!(
+                "tmpfs cannot be mounted on {:?}, because it is not a directory in the root",
+                tmpfs.path
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => bail!(
+                "tmpfs cannot be mounted on {:?}, because it does not exist in the root",
+                tmpfs.path
+            ),
+            Err(e) => Err(e).with_context(|| format!("Failed to stat {path:?}"))?,
+        }
+    }
+    Ok(())
+}
+
+pub struct MountFlags {
+    // A top-level directory
+    pub path: String,
+    pub flags: nix::libc::c_ulong,
+}
+
+impl std::str::FromStr for MountFlags {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let Some((path, names)) = s.split_once(':') else {
+            bail!("Expected PATH:FLAGS, got {s:?}");
+        };
+        if !path.starts_with('/') || path[1..].is_empty() || path[1..].contains('/') {
+            bail!("Flags can only be set for top-level directories, not {path:?}");
+        }
+        let mut flags = 0;
+        for name in names.split(',') {
+            flags |= match name {
+                "noexec" => system::MS_NOEXEC,
+                "nodev" => system::MS_NODEV,
+                "nosuid" => system::MS_NOSUID,
+                _ => bail!("Unknown mount flag {name:?}, expected noexec, nodev, or nosuid"),
+            };
+        }
+        Ok(Self {
+            path: path.to_string(),
+            flags,
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SetuidPolicy {
+    Allow,
+    // Remount the top-level directories that contain setuid and setgid files with nosuid
+    Nosuid,
+    // Only list the files
+    Report,
+    // Fail to start if there are any
+    Refuse,
+}
+
+impl std::str::FromStr for SetuidPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "allow" => Ok(Self::Allow),
+            "nosuid" => Ok(Self::Nosuid),
+            "report" => Ok(Self::Report),
+            "refuse" => Ok(Self::Refuse),
+            _ => bail!("Unknown setuid policy {s:?}, expected allow, nosuid, report, or refuse"),
+        }
+    }
+}
+
+pub struct SetuidFile {
+    // A path inside the box
+    pub path: String,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+pub struct RootfsOptions {
+    pub prefer_overlay: bool,
+    // The size of the tmpfs storing modifications of the root, if it is writable
+    pub writable_root: Option<u64>,
+    // Paths inside the box that are hidden behind an empty file or directory
+    pub masked_paths: Vec<String>,
+    // Whether to replace /etc/passwd and the like with files describing the box
+    pub synthesize_etc: bool,
+    // Whether to point /etc/resolv.conf at the stub resolver
+    pub synthesize_resolv_conf: bool,
+    // The maximum number of pseudoterminals open at once
+    pub max_ptys: Option<u64>,
+    // Whether /dev/fuse is available to the box
+    pub allow_fuse: bool,
+    pub mount_flags: Vec<MountFlags>,
+    pub setuid_policy: SetuidPolicy,
+}
+
 pub struct RootfsState {
     mount_points: HashMap<String, usize>,
+    // The number of snapshots /space is stacked on, 0 if there is none
+    snapshot_depth: usize,
+    writable_root: Option<u64>,
+    // Top-level directories and the layers they are merged from
+    writable_dirs: Vec<(String, Vec<PathBuf>)>,
+    masked_paths: Vec<String>,
+    synthesize_etc: bool,
+    synthesize_resolv_conf: bool,
+    max_ptys: Option<u64>,
+    mount_flags: Vec<MountFlags>,
+    // Found when the root was created, None if the setuid policy is allow
+    setuid_files: Option<Vec<SetuidFile>>,
+    // The state of /space right after it was last mounted, None if it's mounted over a snapshot
+    space_fingerprint: Option<SpaceFingerprint>,
 }
 
-pub fn create_rootfs(root: &std::path::Path) -> Result<RootfsState> {
+// Most runs never touch /space, /tmp, and /dev/shm, so reset keeps the tmpfs if it's unchanged.
+// Every modification either allocates or frees an inode or a block, or changes the ctime of one
+// of the three directories that exist on a fresh /space.
+#[derive(PartialEq, Eq)]
+struct SpaceFingerprint {
+    quotas: (u64, u64),
+    blocks_free: u64,
+    files_free: u64,
+    ctimes: [(i64, i64); 3],
+}
+
+fn space_fingerprint(quotas: &DiskQuotas) -> Result<SpaceFingerprint> {
+    let stat =
+        nix::sys::statvfs::statvfs("/newroot/space").context("Failed to statvfs /newroot/space")?;
+    let mut ctimes = [(0, 0); 3];
+    for (ctime, path) in ctimes.iter_mut().zip([
+        "/newroot/space",
+        "/newroot/space/.shm",
+        "/newroot/space/.tmp",
+    ]) {
+        // The user may remove /space/.tmp and /space/.shm, which only means that /space has changed
+        *ctime = std::fs::symlink_metadata(path).map_or((-1, -1), |metadata| {
+            (metadata.ctime(), metadata.ctime_nsec())
+        });
+    }
+    Ok(SpaceFingerprint {
+        quotas: (quotas.space, quotas.max_inodes),
+        blocks_free: stat.blocks_free(),
+        files_free: stat.files_free(),
+        ctimes,
+    })
+}
+
+pub fn mount_image(image_path: &Path, mount_point: &Path) -> Result<()> {
+    // Compressed images are mounted via a loop device. The mount is only visible in our mount
+    // namespace, so the device is freed as soon as the box terminates.
+    let image = std::fs::File::open(image_path)
+        .with_context(|| format!("Failed to open {image_path:?}"))?;
+    let (number, _device) =
+        system::attach_loop_device(Path::new("/oldroot/dev/loop-control"), &image)
+            .context("Failed to attach image to a loop device")?;
+    std::fs::create_dir_all(mount_point)
+        .with_context(|| format!("Failed to mkdir {mount_point:?}"))?;
+    system::mount(
+        format!("/oldroot/dev/loop{number}"),
+        mount_point,
+        "squashfs",
+        system::MS_RDONLY,
+        None,
+    )
+    .context("Failed to mount squashfs image")?;
+    Ok(())
+}
+
+pub fn create_rootfs(layers: &[PathBuf], options: RootfsOptions) -> Result<RootfsState> {
     // We need to mount an image, and also add some directories to the hierarchy.
     //
-    // We can't use overlayfs: it doesn't work as expected when a lowerdir contains child mounts
-    // (namely, it doesn't duplicate them), and that's rather common. In fact, mount(2) even fails
-    // with EINVAL in this case if you're not being careful enough.
+    // We can't use overlayfs in general: it doesn't work as expected when a lowerdir contains child
+    // mounts (namely, it doesn't duplicate them), and that's rather common. In fact, mount(2) even
+    // fails with EINVAL in this case if you're not being careful enough.
     //
     // Therefore we create a root in tmpfs from scratch, and bind-mount all top-level directories
     // from the image, and then simply add the required directories.
+    //
+    // Several images can be stacked, the last one being the topmost. Directories that are present
+    // in several layers are created in tmpfs too, and their contents are merged recursively;
+    // everything else is bind-mounted from the topmost layer that contains it.
+    //
+    // If the images don't contain child mounts, which is the case for dedicated image directories
+    // as opposed to the host root, overlayfs can be used if the user asks for it. This takes a
+    // single mount instead of one per top-level directory and merges layers for free.
+    //
+    // A writable root is implemented with a writable overlay on top of each top-level directory,
+    // which is remounted on reset, so that the changes are dropped.
 
     // Create the new root directory
     std::fs::create_dir("/newroot").context("Failed to mkdir /newroot")?;
 
-    // Mount directories from image
-    for entry in std::fs::read_dir(root).context("Failed to read root directory")? {
-        let entry = entry.context("Failed to read root directory")?;
-        let name = entry
-            .file_name()
-            .into_string()
-            .map_err(|name| anyhow!("File name {name:?} is not UTF-8"))?;
-        // Don't clone directories we're going to mount over anyway, and also /sys, because it's too
-        // dangerous
-        if name != "space" && name != "dev" && name != "proc" && name != "tmp" && name != "sys" {
-            let source_path = entry
-                .path()
-                .into_os_string()
-                .into_string()
-                .map_err(|path| anyhow!("Path {path:?} is not UTF-8"))?;
-
-            let target_path = format!("/newroot/{name}");
-
-            let file_type = entry.file_type().context("Failed to acquire file type")?;
-
-            if file_type.is_symlink() {
-                // Bind-mounting a symlink might be a bad idea
-                let link_target =
-                    std::fs::read_link(entry.path()).context("Failed to read link")?;
-                std::os::unix::fs::symlink(&link_target, &target_path).with_context(|| {
-                    format!("Failed to symlink {link_target:?} to {target_path}")
-                })?;
-                continue;
-            } else if file_type.is_dir() {
-                std::fs::create_dir(&target_path)
-                    .with_context(|| format!("Failed to mkdir {target_path}"))?;
-            } else {
-                std::fs::File::create(&target_path)
-                    .with_context(|| format!("Failed to touch {target_path}"))?;
+    let layers_top_first: Vec<&Path> = layers.iter().rev().map(|layer| layer.as_ref()).collect();
+    let mut writable_dirs = Vec::new();
+    if options.writable_root.is_some() {
+        for (name, sources) in merge_directories(&layers_top_first)? {
+            if name != "space"
+                && name != "dev"
+                && name != "proc"
+                && name != "tmp"
+                && name != "sys"
+                && std::fs::symlink_metadata(&sources[0]).is_ok_and(|m| m.is_dir())
+            {
+                if !can_use_overlay(&sources)? {
+                    bail!(
+                        "Cannot make /{name} writable: it contains mount points or its path \
+                         contains special characters"
+                    );
+                }
+                writable_dirs.push((name, sources));
             }
-
-            system::bind_mount(&source_path, &target_path)
-                .with_context(|| format!("Failed to bind-mount {source_path} to {target_path}"))?;
-            system::bind_mount_opt("none", &target_path, system::MS_REMOUNT | system::MS_RDONLY)
-                .with_context(|| format!("Failed to remount {target_path} read-only"))?;
         }
     }
 
-    // Mount ephemeral directories
-    for name in ["space", "dev", "proc", "tmp"] {
-        let path = format!("/newroot/{name}");
-        std::fs::create_dir(&path).with_context(|| format!("Failed to mkdir {path}"))?;
+    if options.prefer_overlay && can_use_overlay(layers)? {
+        mount_overlay_root(layers)?;
+    } else {
+        // Mount directories from image
+        for (name, sources) in merge_directories(&layers_top_first)? {
+            // Don't clone directories we're going to mount over anyway, and also /sys, because it's
+            // too dangerous
+            if name != "space" && name != "dev" && name != "proc" && name != "tmp" && name != "sys"
+            {
+                mount_from_layers(&sources, &format!("/newroot/{name}"))?;
+            }
+        }
+
+        // Mount ephemeral directories
+        for name in ["space", "dev", "proc", "tmp"] {
+            let path = format!("/newroot/{name}");
+            std::fs::create_dir(&path).with_context(|| format!("Failed to mkdir {path}"))?;
+        }
     }
     // Don't mount /space and /tmp immediately, we'll mount them later
-    // Mount /dev
+    // Mount /dev. This is not the host's /dev, but the minimal copy built by create_dev_copy, so
+    // only the devices listed there are exposed
     system::bind_mount_opt("/dev", "/newroot/dev", system::MS_REC)
         .context("Failed to bind-mount /newroot/dev")?;
     system::bind_mount_opt(
@@ -87,9 +355,71 @@ pub fn create_rootfs(root: &std::path::Path) -> Result<RootfsState> {
     )
     .context("Failed to remount /newroot/dev read-only")?;
 
+    if options.synthesize_etc {
+        write_synthesized_etc()?;
+    }
+    if options.synthesize_resolv_conf {
+        write_synthesized_etc_file("resolv.conf", &format!("nameserver {}\n", dns::ADDRESS))?;
+    }
+
+    let mut mount_flags = options.mount_flags;
+    let setuid_files = if options.setuid_policy == SetuidPolicy::Allow {
+        None
+    } else {
+        let setuid_files = find_setuid_files()?;
+        match options.setuid_policy {
+            SetuidPolicy::Allow | SetuidPolicy::Report => {}
+            SetuidPolicy::Nosuid => {
+                // The flags are applied on reset, like those passed by the user
+                for file in &setuid_files {
+                    let Some((top_level, _)) = file.path[1..].split_once('/') else {
+                        bail!("Cannot apply nosuid to {}: not in a directory", file.path);
+                    };
+                    let path = format!("/{top_level}");
+                    if !mount_flags
+                        .iter()
+                        .any(|flags| flags.path == path && flags.flags & system::MS_NOSUID != 0)
+                    {
+                        mount_flags.push(MountFlags {
+                            path,
+                            flags: system::MS_NOSUID,
+                        });
+                    }
+                }
+            }
+            SetuidPolicy::Refuse => {
+                if let Some(file) = setuid_files.first() {
+                    bail!(
+                        "The root environment contains {} setuid or setgid files, e.g. {}",
+                        setuid_files.len(),
+                        file.path
+                    );
+                }
+            }
+        }
+        for file in &setuid_files {
+            ::tracing::info!(
+                path = file.path,
+                mode = file.mode,
+                "Found setuid or setgid file"
+            );
+        }
+        Some(setuid_files)
+    };
+
     // Remember current mounts so that we can restore the state on reset
     let mut state = RootfsState {
         mount_points: HashMap::new(),
+        snapshot_depth: 0,
+        writable_root: options.writable_root,
+        writable_dirs,
+        masked_paths: options.masked_paths,
+        synthesize_etc: options.synthesize_etc,
+        synthesize_resolv_conf: options.synthesize_resolv_conf,
+        max_ptys: options.max_ptys,
+        mount_flags,
+        setuid_files,
+        space_fingerprint: None,
     };
     for path in list_child_mounts("/newroot/")? {
         *state.mount_points.entry(path).or_insert(0) += 1;
@@ -97,6 +427,236 @@ pub fn create_rootfs(root: &std::path::Path) -> Result<RootfsState> {
     Ok(state)
 }
 
+impl RootfsState {
+    pub fn setuid_files(&self) -> Option<&[SetuidFile]> {
+        self.setuid_files.as_deref()
+    }
+}
+
+// Scans the image, i.e. everything but the directories the box mounts itself. Symlinks aren't
+// followed, and files of volumes bound later aren't included.
+fn find_setuid_files() -> Result<Vec<SetuidFile>> {
+    let root_dev = std::fs::metadata("/newroot")
+        .context("Failed to stat /newroot")?
+        .dev();
+    let mut files = Vec::new();
+    visit_setuid_files(Path::new("/newroot"), root_dev, None, &mut files)?;
+    Ok(files)
+}
+
+// Entries that vanish or can't be read are skipped rather than failing the start, as the layers
+// may belong to a live system, e.g. with --root /
+fn is_skippable_error(e: &std::io::Error) -> bool {
+    matches!(
+        e.raw_os_error(),
+        Some(nix::libc::ENOENT | nix::libc::EACCES | nix::libc::EPERM)
+    )
+}
+
+// A directory of /newroot itself is either the skeleton we have created, e.g. a directory merged
+// from several layers, or a mount of the image. Once we have entered the image, i.e. layer_dev is
+// set, mount points with a different device are not the image and are skipped, so that the scan
+// stays on the image's filesystem.
+fn visit_setuid_files(
+    dir: &Path,
+    root_dev: u64,
+    layer_dev: Option<u64>,
+    files: &mut Vec<SetuidFile>,
+) -> Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if is_skippable_error(&e) => {
+            ::tracing::warn!("Skipping {dir:?} while looking for setuid files: {e}");
+            return Ok(());
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {dir:?}")),
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) if is_skippable_error(&e) => {
+                ::tracing::warn!("Skipping {dir:?} while looking for setuid files: {e}");
+                return Ok(());
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {dir:?}")),
+        };
+        let path = entry.path();
+        let box_path = Path::new("/").join(path.strip_prefix("/newroot")?);
+        if [
+            Path::new("/space"),
+            Path::new("/dev"),
+            Path::new("/proc"),
+            Path::new("/tmp"),
+            Path::new("/sys"),
+        ]
+        .contains(&box_path.as_path())
+        {
+            continue;
+        }
+        let metadata = match std::fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if is_skippable_error(&e) => {
+                ::tracing::warn!("Skipping {path:?} while looking for setuid files: {e}");
+                continue;
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to stat {path:?}")),
+        };
+        if metadata.is_dir() {
+            let layer_dev = match layer_dev {
+                Some(layer_dev) if metadata.dev() != layer_dev => {
+                    ::tracing::info!("Not looking for setuid files in mount point {box_path:?}");
+                    continue;
+                }
+                Some(layer_dev) => Some(layer_dev),
+                None if metadata.dev() == root_dev => None,
+                None => Some(metadata.dev()),
+            };
+            visit_setuid_files(&path, root_dev, layer_dev, files)?;
+        } else if metadata.is_file()
+            && metadata.mode() & (nix::libc::S_ISUID | nix::libc::S_ISGID) != 0
+        {
+            files.push(SetuidFile {
+                path: box_path.to_string_lossy().into_owned(),
+                mode: metadata.mode(),
+                uid: metadata.uid(),
+                gid: metadata.gid(),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn can_use_overlay(layers: &[PathBuf]) -> Result<bool> {
+    for layer in layers {
+        let layer = layer.to_str().context("Path to root is not UTF-8")?;
+        // overlayfs uses these characters as separators in options
+        if layer.contains([':', ',', '\\']) {
+            return Ok(false);
+        }
+        if !list_child_mounts(&format!("{layer}/"))?.is_empty() {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn mount_overlay_root(layers: &[PathBuf]) -> Result<()> {
+    // The image may lack the directories we mount over, and the overlay is read-only, so add them
+    // in a topmost layer of our own
+    std::fs::create_dir("/rootfs-skeleton").context("Failed to mkdir /rootfs-skeleton")?;
+    for name in ["space", "dev", "proc", "tmp"] {
+        let path = format!("/rootfs-skeleton/{name}");
+        std::fs::create_dir(&path).with_context(|| format!("Failed to mkdir {path}"))?;
+    }
+
+    let mut lower = "/rootfs-skeleton".to_string();
+    for layer in layers.iter().rev() {
+        lower.push(':');
+        lower.push_str(layer.to_str().context("Path to root is not UTF-8")?);
+    }
+    system::mount(
+        "overlay",
+        "/newroot",
+        "overlay",
+        system::MS_RDONLY,
+        Some(&format!("lowerdir={lower}")),
+    )
+    .context("Failed to mount overlay on /newroot")?;
+    Ok(())
+}
+
+// Lists the entries of directories in several layers, topmost first. For each name, returns the
+// paths in the layers that affect the result, i.e. the topmost entry, followed by the entries below
+// it if they are all directories
+fn merge_directories(layers: &[&Path]) -> Result<Vec<(String, Vec<PathBuf>)>> {
+    let mut entries: Vec<(String, Vec<PathBuf>)> = Vec::new();
+    let mut indices: HashMap<String, usize> = HashMap::new();
+    let mut shadowed: HashMap<String, bool> = HashMap::new();
+    for layer in layers {
+        for entry in std::fs::read_dir(layer)
+            .with_context(|| format!("Failed to read directory {layer:?}"))?
+        {
+            let entry = entry.with_context(|| format!("Failed to read directory {layer:?}"))?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|name| anyhow!("File name {name:?} is not UTF-8"))?;
+            let is_dir = entry
+                .file_type()
+                .context("Failed to acquire file type")?
+                .is_dir();
+            match indices.get(&name) {
+                None => {
+                    indices.insert(name.clone(), entries.len());
+                    shadowed.insert(name.clone(), !is_dir);
+                    entries.push((name, vec![entry.path()]));
+                }
+                Some(&index) => {
+                    // A non-directory hides everything below it
+                    let shadowed = shadowed.get_mut(&name).unwrap();
+                    if !*shadowed {
+                        if is_dir {
+                            entries[index].1.push(entry.path());
+                        } else {
+                            *shadowed = true;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+fn mount_from_layers(sources: &[PathBuf], target_path: &str) -> Result<()> {
+    let source = &sources[0];
+
+    if sources.len() > 1 {
+        // The directory is present in several layers, so merge them
+        let metadata =
+            std::fs::metadata(source).with_context(|| format!("Failed to stat {source:?}"))?;
+        std::fs::create_dir(target_path)
+            .with_context(|| format!("Failed to mkdir {target_path}"))?;
+        let layers: Vec<&Path> = sources.iter().map(|source| source.as_ref()).collect();
+        for (name, sources) in merge_directories(&layers)? {
+            mount_from_layers(&sources, &format!("{target_path}/{name}"))?;
+        }
+        // The directory is not chowned, so that it stays owned by the real root and thus can't be
+        // modified from inside the box, just like /newroot itself
+        std::fs::set_permissions(target_path, metadata.permissions())
+            .with_context(|| format!("Failed to chmod {target_path}"))?;
+        return Ok(());
+    }
+
+    let source_path = source
+        .to_str()
+        .with_context(|| format!("Path {source:?} is not UTF-8"))?;
+
+    let file_type = std::fs::symlink_metadata(source)
+        .context("Failed to acquire file type")?
+        .file_type();
+
+    if file_type.is_symlink() {
+        // Bind-mounting a symlink might be a bad idea
+        let link_target = std::fs::read_link(source).context("Failed to read link")?;
+        std::os::unix::fs::symlink(&link_target, target_path)
+            .with_context(|| format!("Failed to symlink {link_target:?} to {target_path}"))?;
+        return Ok(());
+    } else if file_type.is_dir() {
+        std::fs::create_dir(target_path)
+            .with_context(|| format!("Failed to mkdir {target_path}"))?;
+    } else {
+        std::fs::File::create(target_path)
+            .with_context(|| format!("Failed to touch {target_path}"))?;
+    }
+
+    system::bind_mount(source_path, target_path)
+        .with_context(|| format!("Failed to bind-mount {source_path} to {target_path}"))?;
+    system::bind_mount_opt("none", target_path, system::MS_REMOUNT | system::MS_RDONLY)
+        .with_context(|| format!("Failed to remount {target_path} read-only"))?;
+    Ok(())
+}
+
 pub fn configure_rootfs() -> Result<()> {
     // Mount /proc. This has to happen inside the pidns.
     procs::mount_procfs("/newroot/proc").context("Failed to mount /newroot/proc")?;
@@ -147,12 +707,27 @@ pub fn enter_rootfs() -> Result<()> {
     Ok(())
 }
 
-pub fn reset(state: &RootfsState, quotas: &DiskQuotas) -> Result<()> {
+pub fn reset(
+    state: &mut RootfsState,
+    quotas: &DiskQuotas,
+    tmpfs_mounts: &[TmpfsMount],
+) -> Result<()> {
+    // There is no fingerprint before the first reset, which mounts /space in the first place
+    let keep_space = match &state.space_fingerprint {
+        Some(fingerprint) => *fingerprint == space_fingerprint(quotas)?,
+        None => false,
+    };
+
     // Unmount all non-whitelisted mounts. Except for /proc/*, which is a nightmare, and /dev/mqueue.
     let mut mount_points: HashMap<&str, usize> = HashMap::new();
     for (path, count) in &state.mount_points {
         mount_points.insert(path, *count);
     }
+    if keep_space {
+        for path in ["/newroot/space", "/newroot/tmp", "/newroot/dev/shm"] {
+            *mount_points.entry(path).or_insert(0) += 1;
+        }
+    }
     let mut paths_to_umount: Vec<&str> = Vec::new();
     let current_mounts = list_child_mounts("/newroot/")?;
     for path in &current_mounts {
@@ -172,21 +747,95 @@ pub fn reset(state: &RootfsState, quotas: &DiskQuotas) -> Result<()> {
         system::umount(path).with_context(|| format!("Failed to unmount {path}"))?;
     }
 
-    // (Re)mount /space
+    if let Some(size) = state.writable_root {
+        mount_writable_root(state, quotas, size)?;
+    }
+
+    // Synthesized files and masks are applied on each reset rather than once, because they have to
+    // be on top of the writable root, if any
+    if state.synthesize_etc {
+        for name in SYNTHESIZED_ETC_FILES {
+            mount_synthesized_etc_file(name)?;
+        }
+    }
+    if state.synthesize_resolv_conf {
+        mount_synthesized_etc_file("resolv.conf")?;
+    }
+    for path in &state.masked_paths {
+        mask_path(path)?;
+    }
+
+    if !keep_space {
+        remount_space(state, quotas)?;
+    }
+
+    // Additional tmpfs mounts have been unmounted along with other non-whitelisted mounts above, so
+    // they are simply mounted anew, with their own quotas
+    for tmpfs in tmpfs_mounts {
+        let path = resolve_abs_box_root(&tmpfs.path)?;
+        system::mount(
+            "none",
+            &path,
+            "tmpfs",
+            system::MS_NOSUID | system::MS_NODEV,
+            Some(
+                format!(
+                    "size={},nr_inodes={},mode={:o}",
+                    tmpfs.size, tmpfs.max_inodes, tmpfs.mode
+                )
+                .as_ref(),
+            ),
+        )
+        .with_context(|| format!("Failed to mount tmpfs on {path:?}"))?;
+    }
+
+    // Reset pseudoterminals. Each devpts mount is a separate instance with its own IDs, so mounting
+    // a fresh one over /dev/pts guarantees that no IDs leak between runs. The previous instance has
+    // been unmounted above, along with /dev/ptmx that refers to it.
+    let mut devpts_options = "mode=666,ptmxmode=666".to_string();
+    if let Some(max_ptys) = state.max_ptys {
+        devpts_options += &format!(",max={max_ptys}");
+    }
     system::mount(
-        "none",
-        "/newroot/space",
-        "tmpfs",
-        system::MS_NOSUID,
-        Some(format!("size={},nr_inodes={}", quotas.space, quotas.max_inodes).as_ref()),
-    )
-    .context("Failed to mount tmpfs on /newroot/space")?;
-    std::os::unix::fs::chown(
-        "/newroot/space",
-        Some(ids::INTERNAL_USER_UID),
-        Some(ids::INTERNAL_USER_GID),
+        "devpts",
+        "/newroot/dev/pts",
+        "devpts",
+        system::MS_NOSUID | system::MS_NOEXEC,
+        Some(&devpts_options),
     )
-    .context("Failed to chown /newroot/space")?;
+    .context("Failed to mount devpts at /newroot/dev/pts")?;
+    system::bind_mount("/newroot/dev/pts/ptmx", "/newroot/dev/ptmx")
+        .context("Failed to bind-mount /newroot/dev/pts/ptmx to /newroot/dev/ptmx")?;
+
+    // This has to happen after /tmp and /dev/shm are bind-mounted from /space, because bind mounts
+    // inherit the flags of the source
+    for mount_flags in &state.mount_flags {
+        apply_mount_flags(mount_flags)?;
+    }
+
+    Ok(())
+}
+
+fn remount_space(state: &mut RootfsState, quotas: &DiskQuotas) -> Result<()> {
+    // (Re)mount /space
+    if state.snapshot_depth > 0 {
+        mount_space_over_snapshot(quotas)?;
+    } else {
+        system::mount(
+            "none",
+            "/newroot/space",
+            "tmpfs",
+            system::MS_NOSUID,
+            Some(format!("size={},nr_inodes={}", quotas.space, quotas.max_inodes).as_ref()),
+        )
+        .context("Failed to mount tmpfs on /newroot/space")?;
+        std::os::unix::fs::chown(
+            "/newroot/space",
+            Some(ids::INTERNAL_USER_UID),
+            Some(ids::INTERNAL_USER_GID),
+        )
+        .context("Failed to chown /newroot/space")?;
+    }
 
     // (Re)mount /dev/shm and /tmp
     for (path, orig_path) in [
@@ -216,25 +865,298 @@ pub fn reset(state: &RootfsState, quotas: &DiskQuotas) -> Result<()> {
             .with_context(|| format!("Failed to bind-mount {orig_path} to {path}"))?;
     }
 
-    // Reset pseudoterminals. On linux, devptsfs uses non-cyclic ida_alloc*, which allocates IDs
-    // sequentially, returning the first unused ID each time, so simply deleting everything from
-    // /dev/pts works. See https://www.kernel.org/doc/htmldocs/kernel-api/idr.html for more info.
-    for entry in
-        std::fs::read_dir("/newroot/dev/pts").context("Failed to readdir /newroot/dev/pts")?
-    {
-        let entry = entry.context("Failed to readdir /newroot/dev/pts")?;
-        if let Ok(file_name) = entry.file_name().into_string() {
-            if file_name.parse::<u64>().is_ok() {
-                std::fs::remove_file(entry.path())
-                    .with_context(|| format!("Failed to rm {:?}", entry.path()))?;
-            }
+    state.space_fingerprint = if state.snapshot_depth > 0 {
+        None
+    } else {
+        Some(space_fingerprint(quotas)?)
+    };
+    Ok(())
+}
+
+const SYNTHESIZED_ETC_FILES: [&str; 4] = ["passwd", "group", "hostname", "hosts"];
+
+fn write_synthesized_etc() -> Result<()> {
+    // Programs that look up the current user, e.g. the JVM or Python's getpass, fail when the uid is
+    // not listed in /etc/passwd, which is usually the case for images not built with sunwalker-box
+    // in mind
+    let host_name = sandbox::HOST_NAME;
+    let domain_name = sandbox::DOMAIN_NAME;
+    let contents = [
+        format!(
+            "root:x:{}:{}:root:/root:/bin/sh\nuser:x:{}:{}:user:/space:/bin/sh\nnobody:x:{}:{}:\
+             nobody:/nonexistent:/usr/sbin/nologin\n",
+            ids::INTERNAL_ROOT_UID,
+            ids::INTERNAL_ROOT_GID,
+            ids::INTERNAL_USER_UID,
+            ids::INTERNAL_USER_GID,
+            ids::NOBODY_UID,
+            ids::NOGRP_GID,
+        ),
+        format!(
+            "root:x:{}:\nuser:x:{}:\nnogroup:x:{}:\n",
+            ids::INTERNAL_ROOT_GID,
+            ids::INTERNAL_USER_GID,
+            ids::NOGRP_GID,
+        ),
+        format!("{host_name}\n"),
+        format!(
+            "127.0.0.1\tlocalhost\n::1\tlocalhost\n127.0.1.1\t{host_name}.{domain_name} \
+             {host_name}\n"
+        ),
+    ];
+
+    for (name, contents) in SYNTHESIZED_ETC_FILES.iter().zip(contents) {
+        write_synthesized_etc_file(name, &contents)?;
+    }
+    Ok(())
+}
+
+fn write_synthesized_etc_file(name: &str, contents: &str) -> Result<()> {
+    std::fs::create_dir_all("/synthesized-etc").context("Failed to mkdir /synthesized-etc")?;
+    let path = format!("/synthesized-etc/{name}");
+    std::fs::write(&path, contents).with_context(|| format!("Failed to write {path}"))?;
+    std::os::unix::fs::chown(
+        &path,
+        Some(ids::EXTERNAL_ROOT_UID),
+        Some(ids::EXTERNAL_ROOT_GID),
+    )
+    .with_context(|| format!("Failed to chown {path}"))?;
+    std::fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o644))
+        .with_context(|| format!("Failed to chmod {path}"))?;
+    Ok(())
+}
+
+fn mount_synthesized_etc_file(name: &str) -> Result<()> {
+    let path = resolve_abs_box_root(format!("/etc/{name}"))?;
+    // A file can only be mounted over an existing file, and the root is read-only
+    if !std::fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_file()) {
+        return Ok(());
+    }
+    let source = format!("/synthesized-etc/{name}");
+    system::bind_mount(&source, &path)
+        .with_context(|| format!("Failed to bind-mount {source} to {path:?}"))?;
+    system::bind_mount_opt("none", &path, system::MS_REMOUNT | system::MS_RDONLY)
+        .with_context(|| format!("Failed to remount {path:?} read-only"))?;
+    Ok(())
+}
+
+fn apply_mount_flags(mount_flags: &MountFlags) -> Result<()> {
+    let path = format!("/newroot{}", mount_flags.path);
+    let metadata =
+        std::fs::symlink_metadata(&path).with_context(|| format!("Failed to stat {path}"))?;
+    if !metadata.is_dir() {
+        bail!("Cannot set mount flags of {path}: not a directory");
+    }
+
+    // The directory might be a part of a bigger mount, e.g. with --overlay-root, in which case it
+    // has to become a mount of its own first. Such mounts are unmounted on reset along with other
+    // non-whitelisted mounts, so this is repeated each time.
+    if !list_child_mounts("/newroot/")?.contains(&path) {
+        system::bind_mount_opt(&path, &path, system::MS_REC)
+            .with_context(|| format!("Failed to bind-mount {path} onto itself"))?;
+    }
+
+    // Remounting resets the flags that are not passed, so keep the current ones
+    let current = nix::sys::statvfs::statvfs(path.as_str())
+        .with_context(|| format!("Failed to statvfs {path}"))?
+        .flags();
+    let mut flags = mount_flags.flags;
+    for (statvfs_flag, mount_flag) in [
+        (nix::sys::statvfs::FsFlags::ST_RDONLY, system::MS_RDONLY),
+        (nix::sys::statvfs::FsFlags::ST_NOSUID, system::MS_NOSUID),
+        (nix::sys::statvfs::FsFlags::ST_NODEV, system::MS_NODEV),
+        (nix::sys::statvfs::FsFlags::ST_NOEXEC, system::MS_NOEXEC),
+    ] {
+        if current.contains(statvfs_flag) {
+            flags |= mount_flag;
+        }
+    }
+    system::bind_mount_opt("none", &path, system::MS_REMOUNT | flags)
+        .with_context(|| format!("Failed to remount {path} with new flags"))?;
+    Ok(())
+}
+
+pub fn unmount_fuse() -> Result<()> {
+    // This is called from inside the box, so the paths are relative to /newroot
+    let file = std::fs::File::open("/proc/self/mounts")
+        .context("Failed to open /proc/self/mounts for reading")?;
+
+    let mut paths = Vec::new();
+    for line in std::io::BufReader::new(file).lines() {
+        let line = line.context("Failed to read /proc/self/mounts")?;
+        let mut it = line.split(' ');
+        it.next().context("Invalid format of /proc/self/mounts")?;
+        let target_path = it.next().context("Invalid format of /proc/self/mounts")?;
+        let fs_type = it.next().context("Invalid format of /proc/self/mounts")?;
+        // fuse, fuseblk, and fuse.<subtype>
+        if fs_type.starts_with("fuse") {
+            paths.push(target_path.to_string());
+        }
+    }
+
+    // The daemons might be dead already, so don't wait for them
+    for path in paths.into_iter().rev() {
+        system::umount_opt(&path, system::MNT_DETACH)
+            .with_context(|| format!("Failed to unmount {path}"))?;
+    }
+    Ok(())
+}
+
+fn mask_path(path: &str) -> Result<()> {
+    let path_abs = resolve_abs_box_root(path)?;
+    let metadata = match std::fs::symlink_metadata(&path_abs) {
+        Ok(metadata) => metadata,
+        // Nothing to hide
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to stat {path_abs:?}")),
+    };
+    let source = if metadata.is_dir() {
+        "/emptydir"
+    } else {
+        "/emptyfile"
+    };
+    system::bind_mount(source, &path_abs)
+        .with_context(|| format!("Failed to mask {path_abs:?}"))?;
+    system::bind_mount_opt("none", &path_abs, system::MS_REMOUNT | system::MS_RDONLY)
+        .with_context(|| format!("Failed to remount {path_abs:?} read-only"))?;
+    Ok(())
+}
+
+fn mount_writable_root(state: &RootfsState, quotas: &DiskQuotas, size: u64) -> Result<()> {
+    // The overlays have just been unmounted, so their upper layers are unused
+    if let Err(e) = system::umount("/rootfs-upper") {
+        if e.kind() != ErrorKind::InvalidInput && e.kind() != ErrorKind::NotFound {
+            return Err(e).context("Failed to unmount /rootfs-upper");
+        }
+    }
+    std::fs::create_dir_all("/rootfs-upper").context("Failed to mkdir /rootfs-upper")?;
+    system::mount(
+        "none",
+        "/rootfs-upper",
+        "tmpfs",
+        system::MS_NOSUID | system::MS_NODEV,
+        Some(format!("size={size},nr_inodes={}", quotas.max_inodes).as_ref()),
+    )
+    .context("Failed to mount tmpfs on /rootfs-upper")?;
+
+    for (name, sources) in &state.writable_dirs {
+        let upper = format!("/rootfs-upper/{name}/upper");
+        let work = format!("/rootfs-upper/{name}/work");
+        for path in [&upper, &work] {
+            std::fs::create_dir_all(path).with_context(|| format!("Failed to mkdir {path}"))?;
+        }
+        prepare_overlay_upper(&upper, &sources[0])?;
+
+        let mut lower = Vec::with_capacity(sources.len());
+        for source in sources {
+            lower.push(source.to_str().context("Path to root is not UTF-8")?);
+        }
+        let target = format!("/newroot/{name}");
+        system::mount(
+            "overlay",
+            &target,
+            "overlay",
+            0,
+            Some(&format!(
+                "lowerdir={},upperdir={upper},workdir={work}",
+                lower.join(":")
+            )),
+        )
+        .with_context(|| format!("Failed to mount overlay on {target}"))?;
+    }
+    Ok(())
+}
+
+// The lowest /space is a tmpfs, and each snapshot adds an overlay on top
+const MAX_SNAPSHOT_DEPTH: usize = 2;
+
+pub fn snapshot_space(state: &mut RootfsState) -> Result<()> {
+    // The kernel refuses to stack overlayfs more than FILESYSTEM_MAX_STACK_DEPTH levels deep, and
+    // it would only find out when /space is mounted over the snapshot, leaving the box broken
+    if state.snapshot_depth >= MAX_SNAPSHOT_DEPTH {
+        bail!("Snapshots cannot be nested more than {MAX_SNAPSHOT_DEPTH} levels deep");
+    }
+
+    // The snapshot shares the filesystem with the current /space, so the caller has to reset the
+    // box right after this, so that the snapshot is never modified. /tmp and /dev/shm are going to
+    // be cleaned up anyway, so drop them now rather than carry them along
+    for path in ["/newroot/space/.shm", "/newroot/space/.tmp"] {
+        std::fs::remove_dir_all(path).with_context(|| format!("Failed to rm -r {path}"))?;
+    }
+    state.space_fingerprint = None;
+
+    // Clone the mount without the child mounts, which are not going to be preserved
+    let mount_fd = system::open_tree("/newroot/space", system::OPEN_TREE_CLONE)
+        .context("Failed to clone /newroot/space")?;
+    system::mount_setattr(&mount_fd, 0, system::MOUNT_ATTR_RDONLY, 0, None)
+        .context("Failed to make the snapshot read-only")?;
+
+    // The previous snapshot may still be used as a lower layer of the current /space, which is
+    // fine, because the kernel holds a reference to it
+    let depth = state.snapshot_depth + 1;
+    drop_snapshot(state)?;
+    std::fs::create_dir_all("/space-snapshot").context("Failed to mkdir /space-snapshot")?;
+    system::move_mount(&mount_fd, "/space-snapshot")
+        .context("Failed to attach snapshot to /space-snapshot")?;
+    state.snapshot_depth = depth;
+    Ok(())
+}
+
+pub fn drop_snapshot(state: &mut RootfsState) -> Result<()> {
+    if state.snapshot_depth > 0 {
+        system::umount_opt("/space-snapshot", system::MNT_DETACH)
+            .context("Failed to unmount /space-snapshot")?;
+        state.snapshot_depth = 0;
+    }
+    Ok(())
+}
+
+fn mount_space_over_snapshot(quotas: &DiskQuotas) -> Result<()> {
+    // Only the modifications are stored in the upper layer, so the quota applies to them alone
+    std::fs::create_dir_all("/space-upper").context("Failed to mkdir /space-upper")?;
+    if let Err(e) = system::umount_opt("/space-upper", system::MNT_DETACH) {
+        if e.kind() != ErrorKind::InvalidInput {
+            return Err(e).context("Failed to unmount /space-upper");
         }
     }
+    system::mount(
+        "none",
+        "/space-upper",
+        "tmpfs",
+        system::MS_NOSUID,
+        Some(format!("size={},nr_inodes={}", quotas.space, quotas.max_inodes).as_ref()),
+    )
+    .context("Failed to mount tmpfs on /space-upper")?;
+    for path in ["/space-upper/upper", "/space-upper/work"] {
+        std::fs::create_dir(path).with_context(|| format!("Failed to mkdir {path}"))?;
+    }
+    prepare_overlay_upper("/space-upper/upper", "/space-snapshot")?;
 
+    system::mount(
+        "overlay",
+        "/newroot/space",
+        "overlay",
+        system::MS_NOSUID,
+        Some("lowerdir=/space-snapshot,upperdir=/space-upper/upper,workdir=/space-upper/work"),
+    )
+    .context("Failed to mount overlay on /newroot/space")?;
     Ok(())
 }
 
-fn list_child_mounts(prefix: &str) -> Result<Vec<String>> {
+// The root of an overlay inherits the attributes of the upper directory, so it's made to look like
+// the lower one
+pub fn prepare_overlay_upper(upper: impl AsRef<Path>, like: impl AsRef<Path>) -> Result<()> {
+    let upper = upper.as_ref();
+    let like = like.as_ref();
+    let metadata = std::fs::metadata(like).with_context(|| format!("Failed to stat {like:?}"))?;
+    std::os::unix::fs::chown(upper, Some(metadata.uid()), Some(metadata.gid()))
+        .with_context(|| format!("Failed to chown {upper:?}"))?;
+    std::fs::set_permissions(upper, metadata.permissions())
+        .with_context(|| format!("Failed to chmod {upper:?}"))?;
+    Ok(())
+}
+
+pub fn list_child_mounts(prefix: &str) -> Result<Vec<String>> {
     let file = std::fs::File::open("/proc/self/mounts")
         .context("Failed to open /proc/self/mounts for reading")?;
 
@@ -300,6 +1222,22 @@ pub fn resolve_abs_box_root<P: AsRef<Path>>(path: P) -> std::io::Result<PathBuf>
     resolve_abs(path.as_ref(), b"/newroot", b"/newroot/space".to_vec(), 0)
 }
 
+// Same as resolve_abs_box_root, but doesn't resolve the last component if it's a symlink, so that
+// the link itself can be removed or moved
+pub fn resolve_abs_box_root_nofollow<P: AsRef<Path>>(path: P) -> std::io::Result<PathBuf> {
+    let path = path.as_ref();
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(file_name)) => Ok(resolve_abs_box_root(parent)?.join(file_name)),
+        _ => resolve_abs_box_root(path),
+    }
+}
+
+// Resolves a path relative to an arbitrary directory, treating it as the root
+pub fn resolve_abs_in<P: AsRef<Path>>(root: &Path, path: P) -> std::io::Result<PathBuf> {
+    let root = root.as_os_str().as_bytes();
+    resolve_abs(path.as_ref(), root, root.to_vec(), 0)
+}
+
 pub fn resolve_abs_old_root<P: AsRef<Path>>(path: P) -> std::io::Result<PathBuf> {
     resolve_abs(path.as_ref(), b"/oldroot", b"/oldroot".to_vec(), 0)
 }
@@ -1,18 +1,20 @@
-use crate::linux::{cgroups, ipc, rootfs, timens, tracing, userns};
-use anyhow::{bail, Context, Result};
+use crate::linux::{cgroups, ipc, landlock, rootfs, seccomp, sha256, timens, tracing, userns};
+use anyhow::{anyhow, bail, Context, Result};
 use multiprocessing::Object;
 use nix::{
-    errno, libc,
+    errno, fcntl, libc,
     libc::pid_t,
-    sys::{epoll, memfd, ptrace, signal, signalfd, wait},
+    sys::{epoll, memfd, ptrace, resource, signal, signalfd, wait},
     unistd,
     unistd::Pid,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
 use std::fs::File;
-use std::io::Write;
+use std::io::{ErrorKind, Read, Write};
 use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
 pub struct Runner {
@@ -21,38 +23,382 @@ pub struct Runner {
     sigfd: signalfd::SignalFd,
     epollfd: OwnedFd,
     exec_wrapper: File,
+    // Signals the controller asks to deliver to the main process of a program, or of all programs
+    // if the index is None, come in; events of the run go out
+    run_channel: multiprocessing::Duplex<String, RunControl>,
+}
+
+// epoll event data
+const SIGFD_EVENT: u64 = 0;
+const RUN_CHANNEL_EVENT: u64 = 1;
+
+// Requests of the controller to the run in progress. The program is an index into the run group,
+// None stands for all programs.
+#[derive(Object)]
+pub enum RunControl {
+    // Sends a signal to the main process
+    Signal(Option<usize>, i32),
+    // Freezes or thaws all processes
+    Freeze(Option<usize>, bool),
+}
+
+#[derive(Object)]
+pub enum Stream {
+    File(String),
+    // An end of a pipe connecting programs that run together
+    Pipe(String),
+}
+
+// Resource limits of the program, each set as both the soft and the hard limit. None keeps the
+// limit of the box. RLIMIT_FSIZE and RLIMIT_STACK are output_limit and stack_limit.
+#[derive(Default, Object)]
+pub struct Rlimits {
+    pub nofile: Option<libc::rlim_t>,
+    pub nproc: Option<libc::rlim_t>,
+    pub core: Option<libc::rlim_t>,
+    pub memlock: Option<libc::rlim_t>,
+    pub msgqueue: Option<libc::rlim_t>,
+}
+
+impl Rlimits {
+    fn apply(&self) -> Result<()> {
+        for (resource, name, limit) in [
+            (
+                resource::Resource::RLIMIT_NOFILE,
+                "RLIMIT_NOFILE",
+                self.nofile,
+            ),
+            (resource::Resource::RLIMIT_NPROC, "RLIMIT_NPROC", self.nproc),
+            (resource::Resource::RLIMIT_CORE, "RLIMIT_CORE", self.core),
+            (
+                resource::Resource::RLIMIT_MEMLOCK,
+                "RLIMIT_MEMLOCK",
+                self.memlock,
+            ),
+            (
+                resource::Resource::RLIMIT_MSGQUEUE,
+                "RLIMIT_MSGQUEUE",
+                self.msgqueue,
+            ),
+        ] {
+            if let Some(limit) = limit {
+                resource::setrlimit(resource, limit, limit)
+                    .with_context(|| format!("Failed to set {name}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Core dumps of crashing processes are written into the box for the judge to retrieve. The
+// kernel writes them according to the core_pattern of the host, which the box can't change, so
+// only patterns that name files the manager can find are supported.
+#[derive(Object)]
+pub struct CoreDumpOptions {
+    // At most this many bytes of each dump are written, enforced with RLIMIT_CORE
+    pub limit: u64,
+    // core_pattern, with ".%p" appended if the kernel does that due to core_uses_pid
+    pub pattern: String,
+}
+
+// A netlink socket was created or an abstract Unix socket was named
+pub struct SocketAttempt {
+    pub pid: pid_t,
+    pub syscall: u32,
+    pub target: SocketTarget,
+    pub denied: bool,
+}
+
+pub enum SocketTarget {
+    // The name without the leading NUL, None if the kernel is asked to pick one
+    AbstractUnix(Option<Vec<u8>>),
+    // NETLINK_ROUTE, NETLINK_KOBJECT_UEVENT, etc.
+    Netlink(i32),
+}
+
+pub struct CoreDump {
+    pub pid: pid_t,
+    // A path inside the box
+    pub path: String,
+    pub size: u64,
+    pub truncated: bool,
+}
+
+impl CoreDumpOptions {
+    pub fn new(limit: u64) -> Result<Self> {
+        if limit == 0 {
+            bail!("Core dump limit must be positive");
+        }
+        let mut pattern = std::fs::read_to_string("/proc/sys/kernel/core_pattern")
+            .context("Failed to read /proc/sys/kernel/core_pattern")?
+            .trim_end_matches('\n')
+            .to_string();
+        if pattern.starts_with('|') {
+            bail!(
+                "Core dumps are piped to {pattern} on this host, so they can't be captured in the \
+                 box. Set kernel.core_pattern to a path, e.g. core, to use core_dump."
+            );
+        }
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            if c == '%' {
+                match chars.next() {
+                    Some('%' | 'p' | 'e' | 'h') => {}
+                    specifier => bail!(
+                        "Unsupported specifier %{} in kernel.core_pattern {pattern}",
+                        specifier.unwrap_or(' ')
+                    ),
+                }
+            }
+        }
+        let uses_pid = std::fs::read_to_string("/proc/sys/kernel/core_uses_pid")
+            .context("Failed to read /proc/sys/kernel/core_uses_pid")?;
+        if uses_pid.trim() != "0" && !pattern.contains("%p") {
+            pattern.push_str(".%p");
+        }
+        Ok(Self { limit, pattern })
+    }
+
+    // Expands the pattern the way the kernel does for the given thread, which must not have exited
+    // yet. The PID is that of the thread group as seen from the box.
+    fn path_of(&self, tgid: Pid, tid: Pid) -> Result<String> {
+        let mut path = String::new();
+        let mut chars = self.pattern.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                path.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('p') => path.push_str(&tgid.to_string()),
+                Some('e') => path.push_str(
+                    std::fs::read_to_string(format!("/proc/{tid}/comm"))
+                        .context("Failed to read /proc/pid/comm")?
+                        .trim_end_matches('\n'),
+                ),
+                // The box has its own UTS namespace, so this is the hostname the program sees
+                Some('h') => path.push_str(
+                    &unistd::gethostname()
+                        .context("Failed to get hostname")?
+                        .to_string_lossy(),
+                ),
+                _ => path.push('%'),
+            }
+        }
+        // A relative path is relative to the working directory at the moment of the crash
+        if !path.starts_with('/') {
+            let cwd = std::fs::read_link(format!("/proc/{tid}/cwd"))
+                .context("Failed to read /proc/pid/cwd")?;
+            path = cwd.join(path).to_string_lossy().into_owned();
+        }
+        Ok(path)
+    }
+}
+
+// Executables the program may not run, e.g. the system compiler
+#[derive(Object)]
+pub enum ExecRule {
+    // A path inside the box with symlinks resolved
+    Path(String),
+    // The hex-encoded SHA-256 of the file
+    Hash(String),
 }
 
 #[derive(Object)]
 pub struct Options {
     pub argv: Vec<String>,
-    pub stdin: String,
-    pub stdout: String,
-    pub stderr: String,
+    // What the program sees as argv[0], argv[0] itself is the path to the executable
+    pub argv0: Option<String>,
+    // A path inside the box, /space by default
+    pub cwd: Option<String>,
+    pub stdin: Stream,
+    pub stdout: Stream,
+    pub stderr: Stream,
     pub real_time_limit: Option<Duration>,
     pub cpu_time_limit: Option<Duration>,
     pub idleness_time_limit: Option<Duration>,
     pub memory_limit: Option<usize>,
+    // memory.swap.max, swapping is disabled by default
+    pub swap_limit: usize,
+    pub io_limit: Option<cgroups::IoLimit>,
     pub processes_limit: Option<usize>,
+    // The maximum size of the files the program writes, enforced with RLIMIT_FSIZE
+    pub output_limit: Option<usize>,
+    // The maximum number of bytes sent and received over the network, enforced by polling
+    pub network_limit: Option<u64>,
+    // RLIMIT_STACK, RLIM_INFINITY if unlimited. The stack still counts towards the memory limit
+    pub stack_limit: Option<libc::rlim_t>,
+    pub rlimits: Rlimits,
+    // A subset of the cores of the box, all of them by default
+    pub cpus: Option<Vec<u64>>,
+    // The fraction of a core the program may use, enforced by throttling
+    pub cpu_quota: Option<f64>,
+    // Whether to allow transparent hugepages, the policy of the host by default
+    pub transparent_hugepages: Option<bool>,
+    // Ambient capabilities the program keeps, all are dropped by default
+    pub capabilities: Vec<u32>,
+    // Paths the program may access, enforced with Landlock
+    pub landlock: Option<landlock::Ruleset>,
+    // Stacked on top of the filter every program gets
+    pub seccomp_profile: Option<seccomp::Profile>,
+    // Syscalls stopped by the manager, reported as events and possibly overridden
+    pub intercept: Vec<seccomp::InterceptRule>,
+    // Syscalls counted by the manager
+    pub syscall_audit: Option<seccomp::SyscallAudit>,
+    pub socket_isolation: Option<seccomp::SocketIsolation>,
+    // What the program sees as CLOCK_REALTIME instead of the time of the host
+    pub realtime: Option<timens::RealTime>,
+    pub exec_deny: Vec<ExecRule>,
+    pub core_dump: Option<CoreDumpOptions>,
+    // Whether to hand the program over to a debugger instead of tracing it
+    pub debug: bool,
     pub env: Option<HashMap<String, String>>,
+    // A file passed to the program at the given descriptor number
+    pub scratch_file: Option<(i32, File)>,
+    // Files sent by the judge, passed to the program at the given descriptor numbers
+    pub inherited_fds: Vec<(i32, File)>,
+    // The slave of a pseudoterminal, which becomes the standard streams and the controlling
+    // terminal of the program
+    pub tty: Option<File>,
 }
 
-#[derive(PartialEq, Eq)]
+// The limit verdicts carry the limit that was exceeded
+#[derive(Debug, PartialEq, Eq)]
 pub enum Verdict {
     ExitCode(i32),
     Signaled(i32),
-    CPUTimeLimitExceeded,
-    RealTimeLimitExceeded,
-    IdlenessTimeLimitExceeded,
-    MemoryLimitExceeded,
+    // Killed by seccomp for a forbidden syscall, as opposed to receiving SIGSYS from someone
+    SecurityViolation,
+    CPUTimeLimitExceeded(Duration),
+    RealTimeLimitExceeded(Duration),
+    IdlenessTimeLimitExceeded(Duration),
+    // None if the OOM killer was triggered by the limits of the box itself rather than the program
+    MemoryLimitExceeded(Option<usize>),
+    OutputLimitExceeded(usize),
+    NetworkLimitExceeded(u64),
+}
+
+impl Verdict {
+    // The name of the option whose limit was exceeded, if any
+    pub fn exceeded_limit(&self) -> Option<&'static str> {
+        match self {
+            Verdict::CPUTimeLimitExceeded(_) => Some("cpu_time_limit"),
+            Verdict::RealTimeLimitExceeded(_) => Some("real_time_limit"),
+            Verdict::IdlenessTimeLimitExceeded(_) => Some("idleness_time_limit"),
+            Verdict::MemoryLimitExceeded(_) => Some("memory_limit"),
+            Verdict::OutputLimitExceeded(_) => Some("output_limit"),
+            Verdict::NetworkLimitExceeded(_) => Some("network_limit"),
+            _ => None,
+        }
+    }
 }
 
 pub struct RunResults {
     pub verdict: Verdict,
     pub real_time: Duration,
     pub cpu_time: Duration,
+    // The two components of cpu_time
+    pub user_time: Duration,
+    pub system_time: Duration,
     pub idleness_time: Duration,
     pub memory: usize,
+    // "memory.peak" or "sampling", see the README
+    pub memory_source: &'static str,
+    pub swap: usize,
+    // From block devices
+    pub io_read: u64,
+    pub io_written: u64,
+    // The threads of the main process, in the order they terminated
+    pub threads: Vec<ThreadTimes>,
+    // Programs typically crash when they fail to start a process, so this tells a fork bomb from
+    // a bug
+    pub processes_limit_hit: bool,
+    // How many times each syscall was made by the processes of the program, if they are audited
+    pub syscalls: Option<HashMap<u32, u64>>,
+    // The core dumps written by the processes of the program, if they are captured
+    pub core_dumps: Option<Vec<CoreDump>>,
+    // Set if the main process was killed by a signal that could be intercepted, i.e. not SIGKILL
+    pub crash: Option<CrashReport>,
+    // Present if the box has a network interface other than lo
+    pub network: Option<NetworkUsage>,
+    // The sockets subject to socket_isolation, if it's enabled
+    pub socket_attempts: Option<Vec<SocketAttempt>>,
+}
+
+// Traffic over the interfaces of the box other than lo, i.e. tap0 of slirp4netns or eth0 of a veth
+// pair. The counters belong to the network namespace, so they're shared by the programs of a group.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NetworkUsage {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+}
+
+impl NetworkUsage {
+    // None if there are no interfaces but lo. /proc/net describes the network namespace of the
+    // reader, i.e. that of the box.
+    fn read() -> Result<Option<Self>> {
+        let dev =
+            std::fs::read_to_string("/proc/net/dev").context("Failed to read /proc/net/dev")?;
+        let mut usage = None;
+        // Two lines of headers, then "  name: rx_bytes rx_packets ... (8 fields) tx_bytes tx_packets
+        // ..."
+        for line in dev.lines().skip(2) {
+            let (name, counters) = line
+                .split_once(':')
+                .context("Invalid format of /proc/net/dev")?;
+            if name.trim() == "lo" {
+                continue;
+            }
+            let counters = counters
+                .split_whitespace()
+                .map(|counter| counter.parse::<u64>())
+                .collect::<Result<Vec<u64>, _>>()
+                .context("Invalid format of /proc/net/dev")?;
+            if counters.len() < 10 {
+                bail!("Invalid format of /proc/net/dev");
+            }
+            let usage: &mut Self = usage.get_or_insert_with(Self::default);
+            usage.bytes_received += counters[0];
+            usage.packets_received += counters[1];
+            usage.bytes_sent += counters[8];
+            usage.packets_sent += counters[9];
+        }
+        Ok(usage)
+    }
+
+    fn since(&self, baseline: &Self) -> Self {
+        Self {
+            bytes_sent: self.bytes_sent.saturating_sub(baseline.bytes_sent),
+            bytes_received: self.bytes_received.saturating_sub(baseline.bytes_received),
+            packets_sent: self.packets_sent.saturating_sub(baseline.packets_sent),
+            packets_received: self
+                .packets_received
+                .saturating_sub(baseline.packets_received),
+        }
+    }
+
+    fn bytes(&self) -> u64 {
+        self.bytes_sent + self.bytes_received
+    }
+}
+
+// The signal that killed the main process, as seen when it was about to be delivered
+pub struct CrashReport {
+    pub signal: i32,
+    pub code: i32,
+    // The faulting address, for the signals generated by faults
+    pub address: Option<usize>,
+    pub tid: pid_t,
+    // At the moment the fault was raised, i.e. rip points at the faulting instruction
+    pub registers: libc::user_regs_struct,
+}
+
+pub struct ThreadTimes {
+    pub tid: pid_t,
+    pub user_time: Duration,
+    pub system_time: Duration,
 }
 
 #[derive(PartialEq, Eq)]
@@ -64,28 +410,81 @@ enum ProcessState {
 
 struct ProcessInfo {
     state: ProcessState,
+    // The index of the program the process belongs to, None if its parent is unknown
+    program: Option<usize>,
 }
 
-struct SingleRun<'a> {
-    runner: &'a mut Runner,
+// One of the programs that are run together
+struct Program {
     options: Options,
     results: RunResults,
     box_cgroup: Option<cgroups::BoxCgroup>,
     has_peak: bool,
+    // How many cores' worth of CPU time the program can use per second
+    parallelism: f64,
     main_pid: Pid,
+    // Refers to the main process even after it's reaped and its PID is reused by another process of
+    // the box
+    pidfd: Option<OwnedFd>,
     start_time: Option<Instant>,
+    // The faked CLOCK_REALTIME at start_time, if realtime is passed
+    realtime_start: Option<i64>,
+    // Set once the main process terminates or a limit is exceeded
+    finished: bool,
+    // Whether SIGSYS was delivered to the main process normally. seccomp kills the process without
+    // a signal-delivery-stop, so this tells the two apart.
+    sigsys_delivered: bool,
+    // Whether a process was killed for executing a denied executable
+    exec_denied: bool,
+    // The last signal delivered to the main process
+    last_signal: Option<CrashReport>,
+    // Frozen programs are not charged real time
+    paused_since: Option<Instant>,
+    paused_for: Duration,
+    // The stdout file, until the program writes to it, if events are to be emitted
+    stdout_file: Option<File>,
+    // RLIMIT_FSIZE doesn't apply to pipes, so their output is counted as it's passed on
+    output_relays: Vec<OutputRelay>,
+}
+
+struct OutputRelay {
+    state: Arc<Mutex<RelayState>>,
+    // The pipe the output goes to, until the relay is started
+    destination: Option<File>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+struct RelayState {
+    // The pipe the program writes to, closed once the relay stops
+    source: Option<File>,
+    // How many bytes have been read from the source
+    passed: u64,
+}
+
+struct SingleRun<'a> {
+    runner: &'a mut Runner,
+    programs: Vec<Program>,
+    // Whether to emit events as the run progresses
+    events: bool,
     processes: HashMap<Pid, ProcessInfo>,
     tsc_shift: u64,
     sem_next_id: isize,
     msg_next_id: isize,
     shm_next_id: isize,
+    // The counters at the start of the run, if the box has a network
+    network_baseline: Option<NetworkUsage>,
 }
 
 impl Runner {
-    pub fn new(proc_cgroup: cgroups::ProcCgroup) -> Result<Self> {
+    pub fn new(
+        proc_cgroup: cgroups::ProcCgroup,
+        run_channel: multiprocessing::Duplex<String, RunControl>,
+        time_offsets: timens::Offsets,
+    ) -> Result<Self> {
         // Mount procfs and enter the sandboxed root
         rootfs::configure_rootfs().context("Failed to configure rootfs")?;
-        let timens_controller = timens::TimeNsController::new().context("Failed to adjust time")?;
+        let timens_controller =
+            timens::TimeNsController::new(time_offsets).context("Failed to adjust time")?;
         userns::enter_user_namespace().context("Failed to unshare user namespace")?;
         rootfs::enter_rootfs().context("Failed to enter rootfs")?;
 
@@ -109,9 +508,24 @@ impl Runner {
             epollfd.as_raw_fd(),
             epoll::EpollOp::EpollCtlAdd,
             sigfd.as_raw_fd(),
-            &mut epoll::EpollEvent::new(epoll::EpollFlags::EPOLLIN, 0),
+            &mut epoll::EpollEvent::new(epoll::EpollFlags::EPOLLIN, SIGFD_EVENT),
         )
         .context("Failed to configure epoll")?;
+        epoll::epoll_ctl(
+            epollfd.as_raw_fd(),
+            epoll::EpollOp::EpollCtlAdd,
+            run_channel.as_raw_fd(),
+            &mut epoll::EpollEvent::new(epoll::EpollFlags::EPOLLIN, RUN_CHANNEL_EVENT),
+        )
+        .context("Failed to configure epoll")?;
+
+        // Events are dropped rather than stall the run if the controller doesn't keep up with them.
+        // Signals are only received when the channel is known to be readable.
+        fcntl::fcntl(
+            run_channel.as_raw_fd(),
+            fcntl::FcntlArg::F_SETFL(fcntl::OFlag::O_NONBLOCK),
+        )
+        .context("Failed to make run channel non-blocking")?;
 
         let mut exec_wrapper = unsafe {
             File::from_raw_fd(
@@ -132,57 +546,222 @@ impl Runner {
             sigfd,
             epollfd,
             exec_wrapper,
+            run_channel,
         })
     }
 
-    pub fn run(&mut self, options: Options) -> Result<RunResults> {
+    // Runs several programs concurrently, each in its own cgroup and with its own limits
+    pub fn run(&mut self, programs: Vec<Options>, events: bool) -> Result<Vec<RunResults>> {
+        let _span = ::tracing::info_span!("run", programs = programs.len()).entered();
+
+        // Signals meant for the previous run might have arrived after it finished
+        while nix::poll::poll(
+            &mut [nix::poll::PollFd::new(
+                self.run_channel.as_raw_fd(),
+                nix::poll::PollFlags::POLLIN,
+            )],
+            0,
+        )
+        .context("Failed to poll run channel")?
+            != 0
+        {
+            self.run_channel
+                .recv()
+                .context("Failed to receive signal")?
+                .context("Controller terminated")?;
+        }
+
+        let network_baseline = NetworkUsage::read()?;
+        if network_baseline.is_none()
+            && programs
+                .iter()
+                .any(|options| options.network_limit.is_some())
+        {
+            bail!("'network_limit' requires a network, i.e. --slirp4netns or --veth-bridge");
+        }
+
         let mut single_run = SingleRun {
             runner: self,
-            options,
-            results: RunResults {
-                verdict: Verdict::ExitCode(0),
-                real_time: Duration::ZERO,
-                cpu_time: Duration::ZERO,
-                idleness_time: Duration::ZERO,
-                memory: 0,
-            },
-            box_cgroup: None,
-            has_peak: false,
-            main_pid: Pid::from_raw(0),
-            start_time: None,
+            programs: programs
+                .into_iter()
+                .map(|options| {
+                    let syscalls = options.syscall_audit.is_some().then(HashMap::new);
+                    let core_dumps = options.core_dump.is_some().then(Vec::new);
+                    let socket_attempts = options.socket_isolation.is_some().then(Vec::new);
+                    Program {
+                        options,
+                        results: RunResults {
+                            verdict: Verdict::ExitCode(0),
+                            real_time: Duration::ZERO,
+                            cpu_time: Duration::ZERO,
+                            user_time: Duration::ZERO,
+                            system_time: Duration::ZERO,
+                            idleness_time: Duration::ZERO,
+                            memory: 0,
+                            memory_source: "memory.peak",
+                            swap: 0,
+                            io_read: 0,
+                            io_written: 0,
+                            threads: Vec::new(),
+                            processes_limit_hit: false,
+                            syscalls,
+                            core_dumps,
+                            crash: None,
+                            network: network_baseline.map(|_| NetworkUsage::default()),
+                            socket_attempts,
+                        },
+                        box_cgroup: None,
+                        has_peak: false,
+                        parallelism: 1.0,
+                        main_pid: Pid::from_raw(0),
+                        pidfd: None,
+                        start_time: None,
+                        realtime_start: None,
+                        finished: false,
+                        sigsys_delivered: false,
+                        exec_denied: false,
+                        last_signal: None,
+                        paused_since: None,
+                        paused_for: Duration::ZERO,
+                        stdout_file: None,
+                        output_relays: Vec::new(),
+                    }
+                })
+                .collect(),
+            events,
             processes: HashMap::new(),
             tsc_shift: rand::random::<u64>(),
             sem_next_id: 0,
             msg_next_id: 0,
             shm_next_id: 0,
+            network_baseline,
         };
         single_run.run()?;
-        Ok(single_run.results)
+        Ok(single_run
+            .programs
+            .into_iter()
+            .map(|program| program.results)
+            .collect())
     }
 }
 
-impl SingleRun<'_> {
-    fn open_standard_streams(&self) -> Result<[File; 3]> {
-        let stdin = File::open(&self.options.stdin).context("Failed to open stdin file")?;
-        let stdout = File::options()
+// The read and write ends of the pipes, by name
+type Pipes = HashMap<String, (File, File)>;
+
+fn open_output_stream(stream: &Stream, pipes: &Pipes) -> Result<File> {
+    match stream {
+        Stream::File(path) => Ok(File::options()
             .write(true)
             .create(true)
             .truncate(true)
-            .open(&self.options.stdout)
+            .open(path)?),
+        Stream::Pipe(name) => Ok(pipes[name].1.try_clone()?),
+    }
+}
+
+impl Program {
+    fn open_standard_streams(&self, pipes: &Pipes) -> Result<[File; 3]> {
+        if let Some(tty) = &self.options.tty {
+            let clone = || tty.try_clone().context("Failed to clone pseudoterminal");
+            return Ok([clone()?, clone()?, clone()?]);
+        }
+        let stdin = match &self.options.stdin {
+            Stream::File(path) => File::open(path),
+            Stream::Pipe(name) => pipes[name].0.try_clone(),
+        }
+        .context("Failed to open stdin file")?;
+        let stdout = open_output_stream(&self.options.stdout, pipes)
             .context("Failed to open stdout file")?;
-        let stderr = File::options()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.options.stderr)
-            .context("Failed to open stderr file")?;
+        // Opening the file twice would give the streams separate offsets, so that they'd overwrite
+        // each other rather than interleave, as with 2>&1
+        let stderr = match (&self.options.stdout, &self.options.stderr) {
+            (Stream::File(stdout_path), Stream::File(stderr_path))
+                if stdout_path == stderr_path =>
+            {
+                stdout.try_clone().context("Failed to clone stdout file")?
+            }
+            _ => open_output_stream(&self.options.stderr, pipes)
+                .context("Failed to open stderr file")?,
+        };
         Ok([stdin, stdout, stderr])
     }
 
-    fn create_box_cgroup(&mut self) -> Result<()> {
-        let box_cgroup = self
-            .runner
-            .proc_cgroup
+    fn relay_if_limited(&mut self, is_pipe: bool, file: File) -> Result<File> {
+        if !is_pipe || self.options.output_limit.is_none() || self.options.tty.is_some() {
+            return Ok(file);
+        }
+        let (read_end, write_end) =
+            unistd::pipe2(fcntl::OFlag::O_CLOEXEC).context("Failed to create pipe")?;
+        let (source, sink) = unsafe { (File::from_raw_fd(read_end), File::from_raw_fd(write_end)) };
+        fcntl::fcntl(
+            source.as_raw_fd(),
+            fcntl::FcntlArg::F_SETFL(fcntl::OFlag::O_NONBLOCK),
+        )
+        .context("Failed to make pipe non-blocking")?;
+        self.output_relays.push(OutputRelay {
+            state: Arc::new(Mutex::new(RelayState {
+                source: Some(source),
+                passed: 0,
+            })),
+            destination: Some(file),
+            thread: None,
+        });
+        Ok(sink)
+    }
+
+    // Threads are only started once all programs are forked
+    fn start_output_relays(&mut self) -> Result<()> {
+        let Some(limit) = self.options.output_limit else {
+            return Ok(());
+        };
+        for relay in &mut self.output_relays {
+            let state = relay.state.clone();
+            let destination = relay.destination.take().unwrap();
+            let pidfd = self
+                .pidfd
+                .as_ref()
+                .unwrap()
+                .try_clone()
+                .context("Failed to clone pidfd")?;
+            relay.thread = Some(
+                std::thread::Builder::new()
+                    .name("output-relay".to_string())
+                    .spawn(move || relay_output(state, destination, limit as u64, pidfd))
+                    .context("Failed to start output relay")?,
+            );
+        }
+        Ok(())
+    }
+
+    fn is_exceeding_output_limit(&self) -> Result<bool> {
+        let Some(limit) = self.options.output_limit else {
+            return Ok(false);
+        };
+        for relay in &self.output_relays {
+            let state = relay.state.lock().unwrap();
+            // The program might have exited right after writing, before the relay got to the data
+            let pending = match &state.source {
+                Some(source) => {
+                    let mut pending: libc::c_int = 0;
+                    if unsafe { libc::ioctl(source.as_raw_fd(), libc::FIONREAD, &mut pending) }
+                        == -1
+                    {
+                        return Err(std::io::Error::last_os_error())
+                            .context("Failed to get the size of output in pipe");
+                    }
+                    pending as u64
+                }
+                None => 0,
+            };
+            if state.passed + pending > limit as u64 {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn create_box_cgroup(&mut self, proc_cgroup: &cgroups::ProcCgroup) -> Result<()> {
+        let box_cgroup = proc_cgroup
             .create_box_cgroup()
             .context("Failed to create user cgroup")?;
         if let Some(memory_limit) = self.options.memory_limit {
@@ -190,36 +769,87 @@ impl SingleRun<'_> {
                 .set_memory_limit(memory_limit)
                 .context("Failed to apply memory limit")?;
         }
+        box_cgroup
+            .set_swap_limit(self.options.swap_limit)
+            .context("Failed to apply swap limit")?;
+        if let Some(io_limit) = &self.options.io_limit {
+            box_cgroup
+                .set_io_limit(io_limit)
+                .context("Failed to apply I/O limit")?;
+        }
         if let Some(processes_limit) = self.options.processes_limit {
             box_cgroup
                 .set_processes_limit(processes_limit)
                 .context("Failed to apply processes limit")?;
         }
+        if let Some(cpus) = &self.options.cpus {
+            box_cgroup
+                .set_cpus(cpus)
+                .context("Failed to pin to CPUs, they have to be cores of the box")?;
+        }
+        if let Some(cpu_quota) = self.options.cpu_quota {
+            box_cgroup
+                .set_cpu_quota(cpu_quota)
+                .context("Failed to apply CPU quota")?;
+        }
+        self.parallelism = box_cgroup.get_cpu_count()? as f64;
+        if let Some(cpu_quota) = self.options.cpu_quota {
+            self.parallelism = self.parallelism.min(cpu_quota);
+        }
         self.box_cgroup = Some(box_cgroup);
         Ok(())
     }
 
-    fn start_worker(&mut self) -> Result<()> {
-        let [stdin, stdout, stderr] = self.open_standard_streams()?;
+    fn start_worker(&mut self, runner: &Runner, pipes: &Pipes, events: bool) -> Result<()> {
+        let [stdin, stdout, stderr] = self.open_standard_streams(pipes)?;
+        let stdout =
+            self.relay_if_limited(matches!(self.options.stdout, Stream::Pipe(_)), stdout)?;
+        let stderr =
+            self.relay_if_limited(matches!(self.options.stderr, Stream::Pipe(_)), stderr)?;
+        if events && matches!(self.options.stdout, Stream::File(_)) {
+            self.stdout_file = Some(stdout.try_clone().context("Failed to clone stdout file")?);
+        }
 
         // Start process, redirecting standard streams and configuring ITIMER_PROF
         let (theirs, mut ours) = multiprocessing::channel().context("Failed to create a pipe")?;
         let user_process = executor_worker
             .spawn(
                 self.options.argv.clone(),
+                self.options.argv0.clone(),
+                self.options.cwd.clone(),
                 self.options.env.clone(),
                 stdin,
                 stdout,
                 stderr,
                 theirs,
                 self.options.cpu_time_limit,
-                self.runner
+                self.options.output_limit,
+                self.options.stack_limit,
+                std::mem::take(&mut self.options.rlimits),
+                self.options.transparent_hugepages,
+                std::mem::take(&mut self.options.capabilities),
+                self.options.landlock.take(),
+                self.options.seccomp_profile.take(),
+                seccomp::Profile::for_tracing(
+                    &self.options.intercept,
+                    self.options.syscall_audit.as_ref(),
+                    self.options.socket_isolation.as_ref(),
+                    self.options.realtime.is_some(),
+                ),
+                std::mem::take(&mut self.options.inherited_fds)
+                    .into_iter()
+                    .chain(self.options.scratch_file.take())
+                    .collect(),
+                self.options.tty.is_some(),
+                runner
                     .exec_wrapper
                     .try_clone()
                     .context("Failed to clone exec_wrapper")?,
             )
             .context("Failed to spawn the child")?;
         self.main_pid = Pid::from_raw(user_process.id());
+        // The PID can't be reused until the child is reaped, so it's sure to be the right process
+        self.pidfd = Some(pidfd_open(self.main_pid)?);
 
         // The child will either exit or trigger SIGTRAP on execve() to exec_wrapper due to ptrace
         let wait_status =
@@ -241,7 +871,7 @@ impl SingleRun<'_> {
         };
 
         // Apply cgroup limits
-        self.create_box_cgroup()?;
+        self.create_box_cgroup(&runner.proc_cgroup)?;
         self.box_cgroup
             .as_mut()
             .unwrap()
@@ -271,17 +901,22 @@ impl SingleRun<'_> {
         }
     }
 
-    fn compute_wait_timeout_ms(&self) -> i32 {
+    fn compute_wait_timeout(&self) -> Duration {
         let mut timeout = Duration::MAX;
 
+        // A frozen program can't exceed any limits
+        if self.paused_since.is_some() {
+            return timeout;
+        }
+
         if let Some(real_time_limit) = self.options.real_time_limit {
             timeout = timeout.min(real_time_limit - self.results.real_time);
         }
 
         // The connection between real time and CPU time is complicated. On the one hand, a process
         // can sleep, which does not count towards CPU time, so it can be as low as it gets.
-        // Secondly, multithreaded applications can use several cores (TODO: add opt-in support for
-        // that), and that means CPU time may exceed real time. The inequality seems to be
+        // Secondly, multithreaded applications can use several cores if the box has extra cores,
+        // and that means CPU time may exceed real time. The inequality seems to be
         //     0 <= cpu_time <= real_time * n_cores,
         // so a process cannot exceed its CPU time limit during
         //     cpu_time_left / n_cores
@@ -303,8 +938,10 @@ impl SingleRun<'_> {
         // spin lock, and allows SIGPROF to fire just at the right moment under normal
         // circumstances.
         if let Some(cpu_time_limit) = self.options.cpu_time_limit {
-            timeout =
-                timeout.min(cpu_time_limit - self.results.cpu_time + Duration::from_millis(50));
+            timeout = timeout.min(
+                (cpu_time_limit - self.results.cpu_time).div_f64(self.parallelism)
+                    + Duration::from_millis(50),
+            );
         }
 
         // Similarly, a process cannot exceed its idleness time limit during idleness_time_left
@@ -319,21 +956,12 @@ impl SingleRun<'_> {
         }
 
         // Old kernels don't reveal memory.peak, so the only way to get memory usage stats is to use
-        // polling
-        if !self.has_peak {
+        // polling. The same applies to network usage.
+        if !self.has_peak || self.options.network_limit.is_some() {
             timeout = Duration::from_millis(50);
         }
 
-        if timeout == Duration::MAX {
-            -1
-        } else {
-            // Old kernels don't support very large timeouts
-            timeout
-                .as_millis()
-                .try_into()
-                .unwrap_or(i32::MAX)
-                .min(1000000)
-        }
+        timeout
     }
 
     fn is_exceeding_limits(&self) -> bool {
@@ -352,29 +980,32 @@ impl SingleRun<'_> {
                 .options
                 .memory_limit
                 .is_some_and(|limit| self.results.memory > limit)
+            || self.is_exceeding_network_limit()
+    }
+
+    fn is_exceeding_network_limit(&self) -> bool {
+        self.options.network_limit.is_some_and(|limit| {
+            self.results
+                .network
+                .is_some_and(|network| network.bytes() > limit)
+        })
     }
 
     fn compute_verdict(&self, wait_status: wait::WaitStatus) -> Result<Verdict> {
-        if self
-            .options
-            .cpu_time_limit
-            .is_some_and(|limit| self.results.cpu_time > limit)
-        {
-            return Ok(Verdict::CPUTimeLimitExceeded);
+        if let Some(limit) = self.options.cpu_time_limit {
+            if self.results.cpu_time > limit {
+                return Ok(Verdict::CPUTimeLimitExceeded(limit));
+            }
         }
-        if self
-            .options
-            .real_time_limit
-            .is_some_and(|limit| self.results.real_time > limit)
-        {
-            return Ok(Verdict::RealTimeLimitExceeded);
+        if let Some(limit) = self.options.real_time_limit {
+            if self.results.real_time > limit {
+                return Ok(Verdict::RealTimeLimitExceeded(limit));
+            }
         }
-        if self
-            .options
-            .idleness_time_limit
-            .is_some_and(|limit| self.results.idleness_time > limit)
-        {
-            return Ok(Verdict::IdlenessTimeLimitExceeded);
+        if let Some(limit) = self.options.idleness_time_limit {
+            if self.results.idleness_time > limit {
+                return Ok(Verdict::IdlenessTimeLimitExceeded(limit));
+            }
         }
         if self.box_cgroup.as_ref().unwrap().was_oom_killed()?
             || self
@@ -382,24 +1013,141 @@ impl SingleRun<'_> {
                 .memory_limit
                 .is_some_and(|limit| self.results.memory > limit)
         {
-            return Ok(Verdict::MemoryLimitExceeded);
+            return Ok(Verdict::MemoryLimitExceeded(self.options.memory_limit));
+        }
+        if self.is_exceeding_network_limit() {
+            return Ok(Verdict::NetworkLimitExceeded(
+                self.options.network_limit.unwrap(),
+            ));
+        }
+        if self.is_exceeding_output_limit()? {
+            return Ok(Verdict::OutputLimitExceeded(
+                self.options.output_limit.unwrap(),
+            ));
+        }
+        // The kernel sends SIGXFSZ when a write would exceed RLIMIT_FSIZE. If the program ignores
+        // the signal, the write fails with EFBIG instead, which is the program's problem.
+        if let (wait::WaitStatus::Signaled(_, signal::Signal::SIGXFSZ, _), Some(limit)) =
+            (wait_status, self.options.output_limit)
+        {
+            return Ok(Verdict::OutputLimitExceeded(limit));
+        }
+        if self.exec_denied {
+            return Ok(Verdict::SecurityViolation);
         }
         match wait_status {
             wait::WaitStatus::Exited(_, exit_code) => Ok(Verdict::ExitCode(exit_code)),
-            wait::WaitStatus::Signaled(_, signal, _) => {
-                if signal == signal::Signal::SIGPROF {
-                    Ok(Verdict::CPUTimeLimitExceeded)
-                } else {
-                    Ok(Verdict::Signaled(signal as i32))
+            wait::WaitStatus::Signaled(_, signal, _) => match (signal, self.options.cpu_time_limit)
+            {
+                (signal::Signal::SIGPROF, Some(limit)) => Ok(Verdict::CPUTimeLimitExceeded(limit)),
+                (signal::Signal::SIGSYS, _) if !self.sigsys_delivered => {
+                    Ok(Verdict::SecurityViolation)
                 }
-            }
+                _ => Ok(Verdict::Signaled(signal as i32)),
+            },
             _ => {
                 bail!("waitpid returned unexpected status: {wait_status:?}");
             }
         }
     }
 
+    fn set_frozen(&mut self, frozen: bool) -> Result<()> {
+        if frozen == self.paused_since.is_some() {
+            return Ok(());
+        }
+        // Update the metrics first, so that the time until the freeze is accounted for
+        self.update_metrics()?;
+        self.box_cgroup
+            .as_ref()
+            .unwrap()
+            .set_frozen(frozen)
+            .context("Failed to freeze user cgroup")?;
+        if frozen {
+            self.paused_since = Some(Instant::now());
+        } else if let Some(paused_since) = self.paused_since.take() {
+            self.paused_for += paused_since.elapsed();
+        }
+        Ok(())
+    }
+
+    fn update_metrics(&mut self) -> Result<()> {
+        let cpu_stats = self.box_cgroup.as_mut().unwrap().get_cpu_stats()?;
+        self.results.cpu_time = cpu_stats.total;
+        self.results.user_time = cpu_stats.user;
+        self.results.system_time = cpu_stats.system;
+        let paused_for = self.paused_for
+            + self
+                .paused_since
+                .map_or(Duration::ZERO, |paused_since| paused_since.elapsed());
+        self.results.real_time = self
+            .start_time
+            .unwrap()
+            .elapsed()
+            .saturating_sub(paused_for);
+        self.results.idleness_time = self.results.real_time.saturating_sub(self.results.cpu_time);
+        if !self.has_peak {
+            self.results.memory = self
+                .results
+                .memory
+                .max(self.box_cgroup.as_mut().unwrap().get_memory_total()?);
+        }
+        self.results.swap = self
+            .results
+            .swap
+            .max(self.box_cgroup.as_ref().unwrap().get_swap_usage()?);
+        (self.results.io_read, self.results.io_written) =
+            self.box_cgroup.as_ref().unwrap().get_io_stats()?;
+        Ok(())
+    }
+
+    fn finish(&mut self, wait_status: wait::WaitStatus) -> Result<()> {
+        if self.has_peak {
+            self.results.memory = self.results.memory.max(
+                self.box_cgroup
+                    .as_mut()
+                    .unwrap()
+                    .get_memory_peak()?
+                    .context("memory.peak is unexpectedly unavailable")?,
+            );
+        }
+        self.results.swap = self
+            .results
+            .swap
+            .max(self.box_cgroup.as_ref().unwrap().get_swap_usage()?);
+        (self.results.io_read, self.results.io_written) =
+            self.box_cgroup.as_ref().unwrap().get_io_stats()?;
+
+        self.results.verdict = self.compute_verdict(wait_status)?;
+        // A handled signal is followed by the one that did kill the process, if any
+        if let Verdict::Signaled(signal) = self.results.verdict {
+            self.results.crash = self
+                .last_signal
+                .take()
+                .filter(|report| report.signal == signal);
+        }
+        self.results.processes_limit_hit = self
+            .box_cgroup
+            .as_ref()
+            .unwrap()
+            .was_processes_limit_hit()?;
+        self.finished = true;
+
+        // The other programs keep running, so the leftover processes have to be killed right away
+        self.box_cgroup
+            .as_mut()
+            .unwrap()
+            .kill()
+            .context("Failed to kill user cgroup")?;
+        Ok(())
+    }
+}
+
+impl SingleRun<'_> {
     fn wait_for_event(&mut self) -> Result<wait::WaitStatus> {
+        if let Some(wait_status) = self.reap_exited_main_process()? {
+            return Ok(wait_status);
+        }
+
         let wait_status = wait::waitpid(
             None,
             Some(wait::WaitPidFlag::__WALL | wait::WaitPidFlag::WNOHANG),
@@ -409,7 +1157,33 @@ impl SingleRun<'_> {
             return Ok(wait_status);
         }
 
-        let timeout_ms = self.compute_wait_timeout_ms();
+        let timeout = self
+            .programs
+            .iter()
+            .filter(|program| !program.finished)
+            .map(|program| program.compute_wait_timeout())
+            .min()
+            .unwrap_or(Duration::MAX);
+        // The first output has to be noticed reasonably quickly
+        let timeout = if self
+            .programs
+            .iter()
+            .any(|program| !program.finished && program.stdout_file.is_some())
+        {
+            timeout.min(Duration::from_millis(100))
+        } else {
+            timeout
+        };
+        let timeout_ms = if timeout == Duration::MAX {
+            -1
+        } else {
+            // Old kernels don't support very large timeouts
+            timeout
+                .as_millis()
+                .try_into()
+                .unwrap_or(i32::MAX)
+                .min(1000000)
+        };
         let mut events = [epoll::EpollEvent::empty()];
         let n_events = epoll::epoll_wait(
             self.runner.epollfd.as_raw_fd(),
@@ -420,6 +1194,38 @@ impl SingleRun<'_> {
 
         match n_events {
             0 => Ok(wait::WaitStatus::StillAlive),
+            1 if events[0].data() == RUN_CHANNEL_EVENT => {
+                let control = self
+                    .runner
+                    .run_channel
+                    .recv()
+                    .context("Failed to receive signal")?
+                    .context("Controller terminated")?;
+                match control {
+                    RunControl::Signal(index, signal) => {
+                        let signal = signal::Signal::try_from(signal).context("Invalid signal")?;
+                        for (i, program) in self.programs.iter().enumerate() {
+                            if program.finished || index.is_some_and(|index| index != i) {
+                                continue;
+                            }
+                            match pidfd_send_signal(program.pidfd.as_ref().unwrap(), signal) {
+                                // The process might have just terminated
+                                Ok(()) | Err(errno::Errno::ESRCH) => {}
+                                Err(e) => Err(e).context("Failed to send signal")?,
+                            }
+                        }
+                    }
+                    RunControl::Freeze(index, frozen) => {
+                        for (i, program) in self.programs.iter_mut().enumerate() {
+                            if program.finished || index.is_some_and(|index| index != i) {
+                                continue;
+                            }
+                            program.set_frozen(frozen)?;
+                        }
+                    }
+                }
+                Ok(wait::WaitStatus::StillAlive)
+            }
             1 => {
                 while self
                     .runner
@@ -440,17 +1246,60 @@ impl SingleRun<'_> {
         }
     }
 
-    fn update_metrics(&mut self) -> Result<()> {
-        let cpu_stats = self.box_cgroup.as_mut().unwrap().get_cpu_stats()?;
-        self.results.cpu_time = cpu_stats.total;
-        self.results.real_time = self.start_time.unwrap().elapsed();
-        self.results.idleness_time = self.results.real_time.saturating_sub(self.results.cpu_time);
-        if !self.has_peak {
-            self.results.memory = self
-                .results
-                .memory
-                .max(self.box_cgroup.as_mut().unwrap().get_memory_total()?);
+    // The termination of a main process decides the verdict, so it's handled before the events of
+    // other processes. Otherwise, it might be queued behind lots of them, e.g. during a fork bomb,
+    // and the program would be charged real time for them, or even hit a limit after exiting.
+    fn reap_exited_main_process(&mut self) -> Result<Option<wait::WaitStatus>> {
+        let mut fds = Vec::new();
+        let mut pids = Vec::new();
+        for program in &self.programs {
+            if let (false, Some(pidfd)) = (program.finished, &program.pidfd) {
+                fds.push(nix::poll::PollFd::new(
+                    pidfd.as_raw_fd(),
+                    nix::poll::PollFlags::POLLIN,
+                ));
+                pids.push(program.main_pid);
+            }
+        }
+        // A pidfd is readable once the whole thread group has terminated
+        if nix::poll::poll(&mut fds, 0).context("Failed to poll pidfds")? == 0 {
+            return Ok(None);
+        }
+        for (fd, pid) in fds.iter().zip(pids) {
+            if fd.revents().is_some_and(|revents| !revents.is_empty()) {
+                let wait_status = wait::waitpid(
+                    pid,
+                    Some(wait::WaitPidFlag::__WALL | wait::WaitPidFlag::WNOHANG),
+                )
+                .context("Failed to waitpid for process")?;
+                if wait_status != wait::WaitStatus::StillAlive {
+                    return Ok(Some(wait_status));
+                }
+            }
         }
+        Ok(None)
+    }
+
+    // A process can only have one tracer, so the box lets go of the main process, leaving it
+    // stopped at the first instruction of the program for the debugger to attach to. Its children
+    // are not traced by the box either, and the syscalls the box emulates fail with ENOSYS.
+    fn detach_for_debugger(&mut self, i: usize) -> Result<()> {
+        let program = &self.programs[i];
+        ptrace::detach(program.main_pid, Some(signal::Signal::SIGSTOP))
+            .context("Failed to detach from the program")?;
+        let cgroup = program
+            .box_cgroup
+            .as_ref()
+            .unwrap()
+            .path(&self.runner.proc_cgroup);
+        let pid = program.main_pid.as_raw();
+        ::tracing::info!(program = i, pid, cgroup, "Waiting for a debugger");
+        self.emit(json::object! {
+            event: "debug",
+            program: i,
+            pid: pid,
+            cgroup: cgroup,
+        });
         Ok(())
     }
 
@@ -476,6 +1325,43 @@ impl SingleRun<'_> {
             .context("Failed to get syscall info")?;
         let syscall_info = unsafe { syscall_info.u.seccomp };
 
+        let is_socket = syscall_info.ret_data == seccomp::SOCKET_TRACE_DATA as u32;
+        let is_clock = syscall_info.ret_data == seccomp::CLOCK_TRACE_DATA as u32;
+        if syscall_info.ret_data == seccomp::INTERCEPT_TRACE_DATA as u32
+            || syscall_info.ret_data == seccomp::AUDIT_TRACE_DATA as u32
+            || is_socket
+            || is_clock
+        {
+            self.count_syscall(pid, syscall_info.nr as u32);
+        }
+
+        if is_socket && self.check_socket(pid, &syscall_info)? {
+            return self.emulate_syscall_result(pid, -libc::EACCES as i64);
+        }
+
+        // The socket and clock rules take precedence in the filter, so intercepted sockets and
+        // clocks come in tagged as such
+        if syscall_info.ret_data == seccomp::INTERCEPT_TRACE_DATA as u32
+            || ((is_socket || is_clock) && self.is_intercepted(pid, syscall_info.nr as u32))
+        {
+            match self.intercept_syscall(pid, &syscall_info)? {
+                // The syscall might still be one of those emulated below
+                seccomp::InterceptAction::Allow => {}
+                seccomp::InterceptAction::Errno(errno) => {
+                    return self.emulate_syscall_result(pid, -(errno as i64));
+                }
+                seccomp::InterceptAction::Return(value) => {
+                    return self.emulate_syscall_result(pid, value);
+                }
+            }
+        }
+
+        if is_clock {
+            if let Some(result) = self.fake_realtime(pid, &syscall_info) {
+                return self.emulate_syscall_result(pid, result);
+            }
+        }
+
         match syscall_info.nr as i64 {
             // We could theoretically let next_id stay 0 forever, but the present implementation
             // mirrors the original behavior and might be somewhat more efficient.
@@ -533,6 +1419,226 @@ impl SingleRun<'_> {
         Ok(())
     }
 
+    fn count_syscall(&mut self, pid: Pid, syscall: u32) {
+        let Some(i) = self.processes.get(&pid).and_then(|process| process.program) else {
+            return;
+        };
+        let program = &mut self.programs[i];
+        if let (Some(audit), Some(syscalls)) = (
+            &program.options.syscall_audit,
+            &mut program.results.syscalls,
+        ) {
+            if audit.covers(syscall) {
+                *syscalls.entry(syscall).or_insert(0) += 1;
+            }
+        }
+    }
+
+    fn is_intercepted(&self, pid: Pid, syscall: u32) -> bool {
+        self.processes
+            .get(&pid)
+            .and_then(|process| process.program)
+            .is_some_and(|i| {
+                self.programs[i]
+                    .options
+                    .intercept
+                    .iter()
+                    .any(|rule| rule.syscall == syscall)
+            })
+    }
+
+    // Returns whether to deny the syscall. The address is read before the syscall is executed, so
+    // like with intercept, a multithreaded program can replace it in the meantime; this is an
+    // audit, which also makes the usual ways of using these sockets fail.
+    fn check_socket(
+        &mut self,
+        pid: Pid,
+        syscall_info: &tracing::ptrace_syscall_info_seccomp,
+    ) -> Result<bool> {
+        let Some(i) = self.processes.get(&pid).and_then(|process| process.program) else {
+            return Ok(false);
+        };
+        let Some(isolation) = self.programs[i].options.socket_isolation else {
+            return Ok(false);
+        };
+        let args = syscall_info.args;
+        let traced_process = tracing::TracedProcess::new(pid);
+
+        let (target, policy) = match syscall_info.nr as i64 {
+            libc::SYS_socket => {
+                if args[0] != libc::AF_NETLINK as u64 {
+                    return Ok(false);
+                }
+                (SocketTarget::Netlink(args[2] as i32), isolation.netlink)
+            }
+            nr => {
+                // The address and its length, wherever the syscall takes them from
+                let (address, address_len) = match nr {
+                    libc::SYS_bind | libc::SYS_connect => (args[1], args[2]),
+                    libc::SYS_sendto => (args[4], args[5]),
+                    libc::SYS_sendmsg => {
+                        // msg_name and msg_namelen come first in struct msghdr
+                        let Ok(header) = traced_process.read_bytes(args[1] as usize, 12) else {
+                            return Ok(false);
+                        };
+                        if header.len() < 12 {
+                            return Ok(false);
+                        }
+                        (
+                            u64::from_ne_bytes(header[..8].try_into().unwrap()),
+                            u32::from_ne_bytes(header[8..12].try_into().unwrap()) as u64,
+                        )
+                    }
+                    _ => return Ok(false),
+                };
+                if address == 0 || address_len < 2 {
+                    return Ok(false);
+                }
+                let max_len = std::mem::size_of::<libc::sockaddr_un>();
+                let Ok(address) =
+                    traced_process.read_bytes(address as usize, (address_len as usize).min(max_len))
+                else {
+                    return Ok(false);
+                };
+                if address.len() < 2
+                    || u16::from_ne_bytes([address[0], address[1]]) != libc::AF_UNIX as u16
+                {
+                    return Ok(false);
+                }
+                let name = match address.get(2) {
+                    // Binding to an empty address picks a random abstract name
+                    None if nr == libc::SYS_bind => None,
+                    Some(0) => Some(address[3..].to_vec()),
+                    _ => return Ok(false),
+                };
+                (SocketTarget::AbstractUnix(name), isolation.abstract_unix)
+            }
+        };
+        if policy == seccomp::SocketPolicy::Allow {
+            return Ok(false);
+        }
+
+        let denied = policy == seccomp::SocketPolicy::Deny;
+        if let Some(attempts) = &mut self.programs[i].results.socket_attempts {
+            attempts.push(SocketAttempt {
+                pid: pid.as_raw(),
+                syscall: syscall_info.nr as u32,
+                target,
+                denied,
+            });
+        }
+        Ok(denied)
+    }
+
+    // Returns the result of the syscall if it reads CLOCK_REALTIME and the program fakes it
+    fn fake_realtime(
+        &self,
+        pid: Pid,
+        syscall_info: &tracing::ptrace_syscall_info_seccomp,
+    ) -> Option<i64> {
+        let program = &self.programs[self.processes.get(&pid)?.program?];
+        let realtime = program.options.realtime?;
+        let now = realtime.now(program.realtime_start?, program.start_time?.elapsed());
+        let (seconds, nanoseconds) = (now.div_euclid(1_000_000_000), now.rem_euclid(1_000_000_000));
+
+        let args = syscall_info.args;
+        let traced_process = tracing::TracedProcess::new(pid);
+        let write = |address: u64, fields: [i64; 2]| -> bool {
+            let mut data = fields[0].to_ne_bytes().to_vec();
+            data.extend_from_slice(&fields[1].to_ne_bytes());
+            traced_process.write_bytes(address as usize, &data).is_ok()
+        };
+        let result = match syscall_info.nr as i64 {
+            libc::SYS_clock_gettime => {
+                if !timens::REALTIME_CLOCKS.contains(&(args[0] as libc::clockid_t)) {
+                    return None;
+                }
+                if write(args[1], [seconds, nanoseconds]) {
+                    0
+                } else {
+                    -libc::EFAULT as i64
+                }
+            }
+            libc::SYS_gettimeofday => {
+                // The timezone is obsolete, and is UTC unless the host sets it explicitly
+                if (args[0] != 0 && !write(args[0], [seconds, nanoseconds / 1000]))
+                    || (args[1] != 0
+                        && traced_process
+                            .write_bytes(args[1] as usize, &[0; 8])
+                            .is_err())
+                {
+                    -libc::EFAULT as i64
+                } else {
+                    0
+                }
+            }
+            libc::SYS_time => {
+                if args[0] != 0
+                    && traced_process
+                        .write_bytes(args[0] as usize, &seconds.to_ne_bytes())
+                        .is_err()
+                {
+                    -libc::EFAULT as i64
+                } else {
+                    seconds
+                }
+            }
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    // The path is read before the syscall is executed, so a multithreaded program can replace it in
+    // the meantime. Path rules are thus good for auditing and stubbing, but not for enforcement,
+    // which is what seccomp_profile and the layout of the filesystem are for.
+    fn intercept_syscall(
+        &mut self,
+        pid: Pid,
+        syscall_info: &tracing::ptrace_syscall_info_seccomp,
+    ) -> Result<seccomp::InterceptAction> {
+        let Some(i) = self.processes.get(&pid).and_then(|process| process.program) else {
+            return Ok(seccomp::InterceptAction::Allow);
+        };
+        let syscall = syscall_info.nr as u32;
+
+        let path = seccomp::path_argument(syscall).map(|index| {
+            tracing::TracedProcess::new(pid)
+                .read_c_string(syscall_info.args[index] as usize, libc::PATH_MAX as usize)
+                .map(|path| String::from_utf8_lossy(&path).into_owned())
+                .unwrap_or_default()
+        });
+
+        let action = self.programs[i]
+            .options
+            .intercept
+            .iter()
+            .find(|rule| {
+                rule.syscall == syscall
+                    && rule.path_prefix.as_ref().map_or(true, |prefix| {
+                        path.as_ref().is_some_and(|path| path.starts_with(prefix))
+                    })
+            })
+            .map_or(seccomp::InterceptAction::Allow, |rule| rule.action);
+
+        let name = seccomp::syscall_name(syscall).map_or_else(|| syscall.to_string(), String::from);
+        let action_json = match action {
+            seccomp::InterceptAction::Allow => json::JsonValue::from("allow"),
+            seccomp::InterceptAction::Errno(errno) => json::object! { errno: errno },
+            seccomp::InterceptAction::Return(value) => json::object! { "return": value },
+        };
+        self.emit(json::object! {
+            event: "syscall",
+            program: i,
+            pid: pid.as_raw(),
+            syscall: name,
+            args: syscall_info.args.to_vec(),
+            path: path,
+            action: action_json,
+        });
+
+        Ok(action)
+    }
+
     fn emulate_syscall_result_errno(&mut self, pid: Pid, mut result: i64) -> Result<()> {
         if result == -1 {
             result = -errno::errno() as i64;
@@ -553,7 +1659,7 @@ impl SingleRun<'_> {
         Ok(())
     }
 
-    fn handle_sigsegv(&self, pid: Pid) -> Result<()> {
+    fn handle_sigsegv(&mut self, pid: Pid) -> Result<()> {
         let traced_process = tracing::TracedProcess::new(pid);
 
         let info = traced_process.get_signal_info()?;
@@ -599,17 +1705,50 @@ impl SingleRun<'_> {
             }
         }
 
+        self.record_signal(pid, &info)?;
         traced_process.resume_signal(signal::Signal::SIGSEGV)?;
         Ok(())
     }
 
-    fn _handle_event(&mut self, wait_status: wait::WaitStatus) -> Result<bool> {
+    fn record_signal(&mut self, tid: Pid, info: &libc::siginfo_t) -> Result<()> {
+        let Some(i) = self.processes.get(&tid).and_then(|process| process.program) else {
+            return Ok(());
+        };
+        let program = &mut self.programs[i];
+        // Only the main process decides the verdict
+        if !Path::new(&format!("/proc/{}/task/{tid}", program.main_pid)).exists() {
+            return Ok(());
+        }
+        let fault = [
+            signal::Signal::SIGSEGV,
+            signal::Signal::SIGBUS,
+            signal::Signal::SIGILL,
+            signal::Signal::SIGFPE,
+            signal::Signal::SIGTRAP,
+        ]
+        .into_iter()
+        .any(|signal| signal as i32 == info.si_signo);
+        program.last_signal = Some(CrashReport {
+            signal: info.si_signo,
+            code: info.si_code,
+            address: fault.then(|| unsafe { info.si_addr() as usize }),
+            tid: tid.as_raw(),
+            registers: tracing::TracedProcess::new(tid).get_registers()?,
+        });
+        Ok(())
+    }
+
+    fn _handle_event(&mut self, wait_status: wait::WaitStatus) -> Result<Option<usize>> {
         match wait_status {
             wait::WaitStatus::StillAlive => {}
 
             wait::WaitStatus::Exited(pid, _) | wait::WaitStatus::Signaled(pid, _, _) => {
-                if pid == self.main_pid {
-                    return Ok(true);
+                if let Some(i) = self
+                    .programs
+                    .iter()
+                    .position(|program| program.main_pid == pid && !program.finished)
+                {
+                    return Ok(Some(i));
                 }
             }
 
@@ -623,7 +1762,7 @@ impl SingleRun<'_> {
                                 process.state = ProcessState::Alive;
                                 self.on_after_fork(pid)?;
                                 traced_process.resume()?;
-                                return Ok(false);
+                                return Ok(None);
                             }
                         }
                         signal::Signal::SIGTRAP => {
@@ -633,17 +1772,32 @@ impl SingleRun<'_> {
                                 regs.rax = ret as u64;
                                 traced_process.set_registers(regs)?;
                                 traced_process.resume()?;
-                                return Ok(false);
+                                return Ok(None);
                             }
                         }
                         _ => {}
                     }
                 }
 
+                if signal == signal::Signal::SIGSYS {
+                    if let Some(program) = self
+                        .programs
+                        .iter_mut()
+                        .find(|program| program.main_pid == pid)
+                    {
+                        program.sigsys_delivered = true;
+                    }
+                }
+
                 // This conditional is an optimization
                 if signal == signal::Signal::SIGSEGV {
                     self.handle_sigsegv(pid)?;
-                    return Ok(false);
+                    return Ok(None);
+                }
+
+                // Group-stops look the same but have no signal information
+                if let Ok(info) = traced_process.get_signal_info() {
+                    self.record_signal(pid, &info)?;
                 }
 
                 traced_process.resume_signal(signal)?;
@@ -657,27 +1811,56 @@ impl SingleRun<'_> {
                     || event == ptrace::Event::PTRACE_EVENT_CLONE as i32
                 {
                     let child_pid = Pid::from_raw(traced_process.get_event_msg()? as pid_t);
+                    let program = self.processes.get(&pid).and_then(|process| process.program);
                     self.processes.insert(
                         child_pid,
                         ProcessInfo {
                             state: ProcessState::JustStarted,
+                            program,
                         },
                     );
+                    self.emit(json::object! {
+                        event: "spawned",
+                        program: program,
+                        pid: child_pid.as_raw(),
+                    });
                 } else if event == ptrace::Event::PTRACE_EVENT_EXIT as i32 {
-                    self.processes.remove(&pid);
+                    if let Some(process) = self.processes.remove(&pid) {
+                        if let Some(i) = process.program {
+                            self.record_core_dump(i, pid)?;
+                            self.record_thread_times(i, pid)?;
+                            if pid == self.programs[i].main_pid && !self.programs[i].has_peak {
+                                self.record_max_rss(i)?;
+                            }
+                        }
+                        self.emit(json::object! {
+                            event: "exited",
+                            program: process.program,
+                            pid: pid.as_raw(),
+                        });
+                    }
                 } else if event == ptrace::Event::PTRACE_EVENT_EXEC as i32 {
                     let old_pid = Pid::from_raw(traced_process.get_event_msg()? as pid_t);
-                    self.processes.remove(&old_pid);
+                    let program = self
+                        .processes
+                        .remove(&old_pid)
+                        .and_then(|process| process.program);
                     self.processes.insert(
                         pid,
                         ProcessInfo {
                             state: ProcessState::Alive,
+                            program,
                         },
                     );
                     self.on_after_execve(pid)?;
+                    if let Some(i) = program {
+                        if self.deny_exec(i, pid)? {
+                            return Ok(None);
+                        }
+                    }
                 } else if event == ptrace::Event::PTRACE_EVENT_SECCOMP as i32 {
                     self.on_seccomp(pid)?;
-                    return Ok(false);
+                    return Ok(None);
                 }
 
                 traced_process.resume()?;
@@ -688,27 +1871,177 @@ impl SingleRun<'_> {
             }
         }
 
-        Ok(false)
+        Ok(None)
+    }
+
+    // Called at the exit stop of a thread, after the kernel has written the dump if it's the thread
+    // that received the signal, but before the working directory is released
+    fn record_core_dump(&mut self, i: usize, tid: Pid) -> Result<()> {
+        let program = &mut self.programs[i];
+        let (Some(options), Some(core_dumps)) =
+            (&program.options.core_dump, &mut program.results.core_dumps)
+        else {
+            return Ok(());
+        };
+        let wait_status = tracing::TracedProcess::new(tid).get_event_msg()?;
+        if wait_status & 0x80 == 0 {
+            return Ok(());
+        }
+
+        let status = std::fs::read_to_string(format!("/proc/{tid}/status"))
+            .context("Failed to read /proc/pid/status")?;
+        let tgid: pid_t = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Tgid:"))
+            .and_then(|tgid| tgid.trim().parse().ok())
+            .context("Invalid /proc/pid/status")?;
+        // Several threads of the process may exit with the core dump flag set
+        if core_dumps.iter().any(|core_dump| core_dump.pid == tgid) {
+            return Ok(());
+        }
+
+        let path = options.path_of(Pid::from_raw(tgid), tid)?;
+        let size = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            // The kernel may fail to create the file, e.g. if the directory is read-only
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to stat {path}")),
+        };
+        core_dumps.push(CoreDump {
+            pid: tgid,
+            path,
+            size,
+            truncated: size >= options.limit,
+        });
+        Ok(())
+    }
+
+    // The thread is stopped before exiting, so its stats are still available, but only until it's
+    // reaped
+    fn record_thread_times(&mut self, i: usize, tid: Pid) -> Result<()> {
+        let program = &mut self.programs[i];
+        let stat =
+            match std::fs::read_to_string(format!("/proc/{}/task/{tid}/stat", program.main_pid)) {
+                Ok(stat) => stat,
+                // Not a thread of the main process
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e).context("Failed to read /proc/pid/task/tid/stat"),
+            };
+        // The command name may contain spaces and parentheses, so skip past the last parenthesis.
+        // utime and stime are the 12th and the 13th fields after it, in clock ticks.
+        let fields: Vec<&str> = stat
+            .rsplit_once(')')
+            .context("Invalid /proc/pid/task/tid/stat")?
+            .1
+            .split_whitespace()
+            .collect();
+        let ticks_per_second = unistd::sysconf(unistd::SysconfVar::CLK_TCK)
+            .context("Failed to get clock ticks per second")?
+            .context("Clock ticks per second are unknown")? as f64;
+        let field = |index: usize| -> Result<Duration> {
+            let ticks: u64 = fields
+                .get(index)
+                .and_then(|field| field.parse().ok())
+                .context("Invalid /proc/pid/task/tid/stat")?;
+            Ok(Duration::from_secs_f64(ticks as f64 / ticks_per_second))
+        };
+        program.results.threads.push(ThreadTimes {
+            tid: tid.as_raw(),
+            user_time: field(11)?,
+            system_time: field(12)?,
+        });
+        Ok(())
+    }
+
+    // Sampling misses short spikes, so without memory.peak, the peak RSS of the main process is
+    // taken into account too. This has to be done before the process is reaped.
+    // The process is stopped right after execve, before the new executable has run any code, so
+    // killing it enforces the rules. Returns whether the process was killed.
+    fn deny_exec(&mut self, i: usize, pid: Pid) -> Result<bool> {
+        let rules = &self.programs[i].options.exec_deny;
+        if rules.is_empty() {
+            return Ok(false);
+        }
+        let exe = format!("/proc/{pid}/exe");
+        let path = std::fs::read_link(&exe).with_context(|| format!("Failed to readlink {exe}"))?;
+        let path = path.to_string_lossy();
+
+        let mut denied = rules
+            .iter()
+            .any(|rule| matches!(rule, ExecRule::Path(denied) if *denied == path));
+        if !denied && rules.iter().any(|rule| matches!(rule, ExecRule::Hash(_))) {
+            let mut file = File::open(&exe).with_context(|| format!("Failed to open {exe}"))?;
+            let mut hasher = sha256::Sha256::new();
+            std::io::copy(&mut file, &mut hasher).context("Failed to hash executable")?;
+            let digest: String = hasher
+                .finish()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            denied = rules
+                .iter()
+                .any(|rule| matches!(rule, ExecRule::Hash(denied) if *denied == digest));
+        }
+        if !denied {
+            return Ok(false);
+        }
+
+        signal::kill(pid, signal::Signal::SIGKILL).context("Failed to kill process")?;
+        self.programs[i].exec_denied = true;
+        let path = path.into_owned();
+        self.emit(json::object! {
+            event: "exec_denied",
+            program: i,
+            pid: pid.as_raw(),
+            path: path,
+        });
+        Ok(true)
+    }
+
+    fn record_max_rss(&mut self, i: usize) -> Result<()> {
+        let program = &mut self.programs[i];
+        let status = match std::fs::read_to_string(format!("/proc/{}/status", program.main_pid)) {
+            Ok(status) => status,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).context("Failed to read /proc/pid/status"),
+        };
+        // Absent for kernel threads and processes that have already released their memory
+        let Some(line) = status.lines().find(|line| line.starts_with("VmHWM:")) else {
+            return Ok(());
+        };
+        let max_rss: usize = line
+            .trim_start_matches("VmHWM:")
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .context("Invalid /proc/pid/status format")?;
+        program.results.memory = program.results.memory.max(max_rss * 1024);
+        Ok(())
     }
 
-    fn handle_event(&mut self, wait_status: wait::WaitStatus) -> Result<bool> {
+    // Returns the index of the program whose main process has terminated, if any
+    fn handle_event(&mut self, wait_status: wait::WaitStatus) -> Result<Option<usize>> {
         // ptrace often reports ESRCH if the process is killed before we notice that
         let res = self._handle_event(wait_status);
         if let Err(ref e) = res {
             // Not the nicest solution, certainly
             if let Some(errno::Errno::ESRCH) = e.root_cause().downcast_ref::<errno::Errno>() {
-                return Ok(false);
+                return Ok(None);
             }
         }
         res
     }
 
     fn cleanup(&mut self) -> Result<()> {
-        self.box_cgroup
-            .as_mut()
-            .unwrap()
-            .kill()
-            .context("Failed to kill user cgroup")?;
+        for program in &mut self.programs {
+            // Finished programs have been killed already
+            if !program.finished {
+                if let Some(box_cgroup) = program.box_cgroup.as_mut() {
+                    box_cgroup.kill().context("Failed to kill user cgroup")?;
+                }
+            }
+        }
 
         // We don't really care what happens after, but we have to waitpid() anyway
         loop {
@@ -721,112 +2054,388 @@ impl SingleRun<'_> {
             }
         }
 
-        self.box_cgroup
-            .take()
-            .unwrap()
-            .destroy()
-            .context("Failed to destroy user cgroup")?;
+        // The programs are dead, so the relays get EOF or EPIPE
+        for program in &mut self.programs {
+            for relay in std::mem::take(&mut program.output_relays) {
+                if let Some(thread) = relay.thread {
+                    thread
+                        .join()
+                        .map_err(|_| anyhow!("Output relay panicked"))?;
+                }
+            }
+        }
+
+        for program in &mut self.programs {
+            if let Some(box_cgroup) = program.box_cgroup.take() {
+                box_cgroup
+                    .destroy()
+                    .context("Failed to destroy user cgroup")?;
+            }
+        }
 
         Ok(())
     }
 
+    fn finish_program(&mut self, i: usize, wait_status: wait::WaitStatus) -> Result<()> {
+        // The program might have written something just before terminating
+        self.check_output()?;
+        self.programs[i].finish(wait_status)?;
+        ::tracing::debug!(
+            program = i,
+            verdict = ?self.programs[i].results.verdict,
+            "Program finished"
+        );
+        if let Some(limit) = self.programs[i].results.verdict.exceeded_limit() {
+            self.emit(json::object! {
+                event: "limit_exceeded",
+                program: i,
+                limit: limit,
+            });
+        }
+        Ok(())
+    }
+
+    fn update_network_usage(&mut self) -> Result<()> {
+        let Some(baseline) = self.network_baseline else {
+            return Ok(());
+        };
+        let usage = NetworkUsage::read()?
+            .context("Network interfaces disappeared")?
+            .since(&baseline);
+        for program in &mut self.programs {
+            if !program.finished {
+                program.results.network = Some(usage);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_output(&mut self) -> Result<()> {
+        for i in 0..self.programs.len() {
+            let Some(stdout) = &self.programs[i].stdout_file else {
+                continue;
+            };
+            if stdout
+                .metadata()
+                .context("Failed to stat stdout file")?
+                .len()
+                > 0
+            {
+                self.programs[i].stdout_file = None;
+                self.emit(json::object! { event: "output", program: i });
+            }
+        }
+        Ok(())
+    }
+
+    // Events are dropped if the controller doesn't read them fast enough, e.g. during a fork bomb,
+    // or if it's gone, which is noticed elsewhere anyway
+    fn emit(&mut self, event: json::JsonValue) {
+        ::tracing::trace!(event = %event.dump(), "Run event");
+        if self.events {
+            let _ = self.runner.run_channel.send(&event.dump());
+        }
+    }
+
+    fn create_pipes(&self) -> Result<Pipes> {
+        let mut readers = HashSet::new();
+        let mut writers = HashSet::new();
+        for program in &self.programs {
+            if let Stream::Pipe(name) = &program.options.stdin {
+                readers.insert(name);
+            }
+            for stream in [&program.options.stdout, &program.options.stderr] {
+                if let Stream::Pipe(name) = stream {
+                    writers.insert(name);
+                }
+            }
+        }
+        if let Some(name) = readers.symmetric_difference(&writers).next() {
+            bail!("Pipe {name} must be both read and written to");
+        }
+
+        let mut pipes = Pipes::new();
+        for name in readers {
+            let (read_end, write_end) =
+                unistd::pipe2(fcntl::OFlag::O_CLOEXEC).context("Failed to create pipe")?;
+            pipes.insert(name.clone(), unsafe {
+                (File::from_raw_fd(read_end), File::from_raw_fd(write_end))
+            });
+        }
+        Ok(pipes)
+    }
+
     pub fn run(&mut self) -> Result<()> {
         // This is because no matter however small exec_wrapper is, the kernel is going to
         // preallocate stack anyway. Moreover, the stack ulimit is silently increased to at least
         // 128 KiB (ARG_MAX, to be precise), so the memory usage is going to be at least 128 KiB,
         // at least if the kernel is not patched. In practice, the minimal enforced limit is
         // slightly higher because of vdso, vvar, and other special pages.
-        if let Some(memory_limit) = self.options.memory_limit {
-            if memory_limit < 43 * 4096 {
-                bail!("Memory limit lower than 172 KiB cannot be enforced");
+        for program in &self.programs {
+            if let Some(memory_limit) = program.options.memory_limit {
+                if memory_limit < 43 * 4096 {
+                    bail!("Memory limit lower than 172 KiB cannot be enforced");
+                }
             }
         }
 
+        let pipes = self.create_pipes()?;
+
         self.runner
             .timens_controller
             .reset_system_time_for_children()
             .context("Failed to virtualize boot time")?;
 
-        self.start_worker()?;
+        // All programs are started before any of them is resumed, so that the ones started first
+        // don't get a head start
+        let result: Result<()> = try {
+            for program in &mut self.programs {
+                program.start_worker(self.runner, &pipes, self.events)?;
+            }
+        };
+        // Our copies of the pipes would prevent the readers from ever getting EOF
+        drop(pipes);
+        let result = result.and_then(|()| {
+            for program in &mut self.programs {
+                program.start_output_relays()?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            self.cleanup()?;
+            return Err(e);
+        }
 
-        self.has_peak = self
-            .box_cgroup
-            .as_mut()
-            .unwrap()
-            .get_memory_peak()?
-            .is_some();
+        for i in 0..self.programs.len() {
+            let program = &mut self.programs[i];
+            program.has_peak = program
+                .box_cgroup
+                .as_mut()
+                .unwrap()
+                .get_memory_peak()?
+                .is_some();
+            if !program.has_peak {
+                program.results.memory_source = "sampling";
+            }
 
-        // execve has just happened
-        self.start_time = Some(Instant::now());
+            // execve has just happened
+            program.start_time = Some(Instant::now());
+            if let Some(realtime) = program.options.realtime {
+                program.realtime_start = Some(realtime.at_start(timens::host_realtime()?));
+            }
 
-        let traced_process = tracing::TracedProcess::new(self.main_pid);
-        self.on_after_fork(self.main_pid)?;
-        self.on_after_execve(self.main_pid)?;
-        traced_process.resume()?;
+            let main_pid = program.main_pid;
+            let traced_process = tracing::TracedProcess::new(main_pid);
+            self.on_after_fork(main_pid)?;
+            self.on_after_execve(main_pid)?;
+            if self.programs[i].options.debug {
+                self.detach_for_debugger(i)?;
+            } else if !self.deny_exec(i, main_pid)? {
+                traced_process.resume()?;
+            }
 
-        self.processes.insert(
-            self.main_pid,
-            ProcessInfo {
-                state: ProcessState::Alive,
-            },
-        );
+            self.processes.insert(
+                main_pid,
+                ProcessInfo {
+                    state: ProcessState::Alive,
+                    program: Some(i),
+                },
+            );
+            self.emit(json::object! {
+                event: "spawned",
+                program: i,
+                pid: main_pid.as_raw(),
+            });
+        }
 
-        let mut wait_status = wait::WaitStatus::StillAlive;
-        while !self.is_exceeding_limits() {
-            wait_status = self.wait_for_event()?;
-            if self.handle_event(wait_status)? {
+        loop {
+            for i in 0..self.programs.len() {
+                if !self.programs[i].finished && self.programs[i].is_exceeding_limits() {
+                    self.finish_program(i, wait::WaitStatus::StillAlive)?;
+                }
+            }
+            if self.programs.iter().all(|program| program.finished) {
                 break;
             }
-            self.update_metrics()?;
-        }
 
-        if self.has_peak {
-            self.results.memory = self.results.memory.max(
-                self.box_cgroup
-                    .as_mut()
-                    .unwrap()
-                    .get_memory_peak()?
-                    .context("memory.peak is unexpectedly unavailable")?,
-            );
+            let wait_status = self.wait_for_event()?;
+            if let Some(i) = self.handle_event(wait_status)? {
+                self.finish_program(i, wait_status)?;
+            }
+            for program in &mut self.programs {
+                if !program.finished {
+                    program.update_metrics()?;
+                }
+            }
+            self.update_network_usage()?;
+            self.check_output()?;
         }
 
-        self.results.verdict = self.compute_verdict(wait_status)?;
+        self.cleanup()
+    }
+}
 
-        self.cleanup()?;
+fn pidfd_open(pid: Pid) -> Result<OwnedFd> {
+    // pidfd_open sets O_CLOEXEC by default
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid.as_raw(), 0) };
+    if pidfd == -1 {
+        return Err(std::io::Error::last_os_error()).context("pidfd_open failed");
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(pidfd as i32) })
+}
 
-        Ok(())
+fn pidfd_send_signal(pidfd: &OwnedFd, signal: signal::Signal) -> nix::Result<()> {
+    errno::Errno::result(unsafe {
+        libc::syscall(
+            libc::SYS_pidfd_send_signal,
+            pidfd.as_raw_fd(),
+            signal as i32,
+            std::ptr::null::<libc::siginfo_t>(),
+            0,
+        )
+    })
+    .map(drop)
+}
+
+// Passes the output of a program on to a pipe, counting it. The source is only read with the lock
+// held, so the bytes are always either counted or still in the pipe. Once the limit is exceeded, the
+// output is truncated to the limit and the program is killed.
+fn relay_output(state: Arc<Mutex<RelayState>>, mut destination: File, limit: u64, pidfd: OwnedFd) {
+    let mut buf = vec![0; 65536];
+    loop {
+        // Only this thread closes the source, so the descriptor stays valid
+        let fd = state.lock().unwrap().source.as_ref().unwrap().as_raw_fd();
+        let _ = nix::poll::poll(
+            &mut [nix::poll::PollFd::new(fd, nix::poll::PollFlags::POLLIN)],
+            -1,
+        );
+        let (n_read, n_allowed) = {
+            let mut state = state.lock().unwrap();
+            let n_read = match state.source.as_mut().unwrap().read(&mut buf) {
+                Ok(0) => break,
+                Ok(n_read) => n_read,
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) => {
+                    continue
+                }
+                Err(_) => break,
+            };
+            let n_allowed = limit.saturating_sub(state.passed).min(n_read as u64) as usize;
+            state.passed += n_read as u64;
+            (n_read, n_allowed)
+        };
+        // If the readers are gone, closing the source gives the program SIGPIPE, as it would get
+        // without the relay
+        if destination.write_all(&buf[..n_allowed]).is_err() {
+            break;
+        }
+        if n_allowed < n_read {
+            let _ = pidfd_send_signal(&pidfd, signal::Signal::SIGKILL);
+            break;
+        }
     }
+    state.lock().unwrap().source = None;
+}
+
+fn duplicate_fd_above(fd: &impl AsRawFd, min_fd: i32) -> Result<i32> {
+    fcntl::fcntl(fd.as_raw_fd(), fcntl::FcntlArg::F_DUPFD_CLOEXEC(min_fd + 1))
+        .context("Failed to duplicate file descriptor")
 }
 
 #[multiprocessing::entrypoint]
 fn executor_worker(
     argv: Vec<String>,
+    argv0: Option<String>,
+    cwd: Option<String>,
     env: Option<HashMap<String, String>>,
     stdin: File,
     stdout: File,
     stderr: File,
     mut pipe: multiprocessing::Sender<String>,
     cpu_time_limit: Option<Duration>,
-    exec_wrapper: File,
+    output_limit: Option<usize>,
+    stack_limit: Option<libc::rlim_t>,
+    rlimits: Rlimits,
+    transparent_hugepages: Option<bool>,
+    capabilities: Vec<u32>,
+    landlock: Option<landlock::Ruleset>,
+    seccomp_profile: Option<seccomp::Profile>,
+    intercept_filter: Option<seccomp::Profile>,
+    // Files to be passed to the program at the given descriptor numbers
+    extra_fds: Vec<(i32, File)>,
+    controlling_tty: bool,
+    mut exec_wrapper: File,
 ) {
     let result: Result<()> = try {
+        // Collapsing pages into hugepages stalls the program for milliseconds at unpredictable
+        // moments. The flag is inherited by children and preserved across execve.
+        if let Some(transparent_hugepages) = transparent_hugepages {
+            if unsafe {
+                libc::prctl(
+                    libc::PR_SET_THP_DISABLE,
+                    (!transparent_hugepages) as libc::c_ulong,
+                    0,
+                    0,
+                    0,
+                )
+            } == -1
+            {
+                Err(std::io::Error::last_os_error())
+                    .context("Failed to configure transparent hugepages")?;
+            }
+        }
+
         tracing::apply_seccomp_filter().context("Failed to apply seccomp filter")?;
 
-        userns::drop_privileges().context("Failed to drop privileges")?;
+        userns::drop_privileges(&capabilities).context("Failed to drop privileges")?;
 
         // We want to disable rdtsc. Turns out, ld.so always calls rdtsc when it starts and keeps
         // using it as if it's always available. Bummer. This means we'll have to simulate rdtsc.
         timens::disable_rdtsc().context("Failed to disable rdtsc")?;
 
-        std::env::set_current_dir("/space").context("Failed to chdir to /space")?;
+        let cwd = cwd.as_deref().unwrap_or("/space");
+        std::env::set_current_dir(cwd).with_context(|| format!("Failed to chdir to {cwd}"))?;
 
         unistd::dup2(stdin.as_raw_fd(), libc::STDIN_FILENO).context("dup2 for stdin failed")?;
         unistd::dup2(stdout.as_raw_fd(), libc::STDOUT_FILENO).context("dup2 for stdout failed")?;
         unistd::dup2(stderr.as_raw_fd(), libc::STDERR_FILENO).context("dup2 for stderr failed")?;
 
-        let mut args = Vec::with_capacity(argv.len() + 1);
+        if controlling_tty {
+            // A new session is needed to acquire a controlling terminal, which is what makes job
+            // control and /dev/tty work
+            unistd::setsid().context("Failed to setsid")?;
+            if unsafe { libc::ioctl(libc::STDIN_FILENO, libc::TIOCSCTTY, 0) } == -1 {
+                Err(std::io::Error::last_os_error())
+                    .context("Failed to set controlling terminal")?;
+            }
+        }
+
+        if let Some(max_fd) = extra_fds.iter().map(|(fd, _)| *fd).max() {
+            // dup2 silently closes whatever occupies the target descriptor, so move the descriptors
+            // we still need above all the targets first. This also guarantees that dup2 is never a
+            // no-op, which wouldn't reset FD_CLOEXEC.
+            exec_wrapper = unsafe { File::from_raw_fd(duplicate_fd_above(&exec_wrapper, max_fd)?) };
+            pipe =
+                unsafe { multiprocessing::Sender::from_raw_fd(duplicate_fd_above(&pipe, max_fd)?) };
+            let mut moved = Vec::with_capacity(extra_fds.len());
+            for (fd, file) in extra_fds {
+                moved.push((fd, unsafe {
+                    File::from_raw_fd(duplicate_fd_above(&file, max_fd)?)
+                }));
+            }
+            // The copies are CLOEXEC and are closed on drop anyway
+            for (fd, file) in moved {
+                unistd::dup2(file.as_raw_fd(), fd)
+                    .with_context(|| format!("dup2 for descriptor {fd} failed"))?;
+            }
+        }
+
+        // exec_wrapper executes args[1] with argv args[2..]
+        let mut args = Vec::with_capacity(argv.len() + 2);
         args.push(CString::new("exec_wrapper")?);
-        for arg in argv {
+        args.push(CString::new(argv[0].as_bytes()).context("Argument contains null character")?);
+        let argv0 = argv0.unwrap_or_else(|| argv[0].clone());
+        args.push(CString::new(argv0.into_bytes()).context("Argument contains null character")?);
+        for arg in argv.into_iter().skip(1) {
             args.push(CString::new(arg.into_bytes()).context("Argument contains null character")?);
         }
 
@@ -881,10 +2490,54 @@ fn executor_worker(
             }
         }
 
+        if let Some(output_limit) = output_limit {
+            // Applies to all files, not just stdout and stderr, but not to pipes. Writing to stdout
+            // redirected to a pipe is limited by the reader anyway.
+            let limit = output_limit as libc::rlim_t;
+            resource::setrlimit(resource::Resource::RLIMIT_FSIZE, limit, limit)
+                .context("Failed to set output limit")?;
+        }
+        if let Some(stack_limit) = stack_limit {
+            // The kernel lays out the address space according to the limit at the time of execve
+            resource::setrlimit(resource::Resource::RLIMIT_STACK, stack_limit, stack_limit)
+                .context("Failed to set stack limit")?;
+        }
+        // Raising a limit above the hard limit of the box fails, as that requires CAP_SYS_RESOURCE
+        // in the host
+        rlimits.apply()?;
+
         ptrace::traceme().context("Failed to ptrace(PTRACE_TRACEME)")?;
 
         // We don't need to reset signals because we didn't configure them inside executor_worker()
 
+        // Without CAP_SYS_ADMIN, which drop_privileges() took away, Landlock rulesets and seccomp
+        // filters can only be applied by processes that can't gain privileges via execve. As a
+        // side effect, setuid and setgid bits are ignored in such runs.
+        if (landlock.is_some() || seccomp_profile.is_some() || intercept_filter.is_some())
+            && unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } == -1
+        {
+            Err(std::io::Error::last_os_error()).context("Failed to set no_new_privs")?;
+        }
+
+        // Applied before seccomp, as the profile might forbid the Landlock syscalls
+        if let Some(landlock) = landlock {
+            landlock
+                .apply(exec_wrapper.as_raw_fd())
+                .context("Failed to apply Landlock ruleset")?;
+        }
+        // The profile might forbid the syscalls used above, so it's applied as late as possible
+        if let Some(profile) = seccomp_profile {
+            profile.apply().context("Failed to apply seccomp profile")?;
+        }
+        // Installed last: when several filters return SECCOMP_RET_TRACE, e.g. this one and the
+        // default filter for System V IPC, the kernel reports the data of the most recently
+        // installed one, which tells the tracer why the syscall was stopped
+        if let Some(filter) = intercept_filter {
+            filter
+                .apply()
+                .context("Failed to apply seccomp interception filter")?;
+        }
+
         // If we executed the user program directly, we wouldn't be able to catch the right moment
         // to add the process to the cgroup. If we did that too early, sunwalker's memory usage
         // would be included. If we did that too late, the kernel might have loaded too big an
@@ -1,9 +1,10 @@
-use crate::linux::system;
+use crate::linux::{network, system};
 use anyhow::{bail, Context, Result};
 use nix::{
     libc,
     libc::{c_char, CLONE_NEWNET, CLONE_NEWUTS, CLONE_SYSVSEM},
 };
+use std::path::Path;
 
 pub fn sanity_checks() -> Result<()> {
     // suid_dumpable = 1 means PR_SET_DUMPABLE does not trigger automatically on setuid, which is
@@ -40,14 +41,27 @@ pub fn sanity_checks() -> Result<()> {
     Ok(())
 }
 
-pub fn unshare_persistent_namespaces() -> Result<()> {
+pub const DOMAIN_NAME: &str = "sunwalker";
+pub const HOST_NAME: &str = "box";
+
+pub fn unshare_persistent_namespaces(
+    loopback: bool,
+    outbound: Option<&network::OutboundOptions>,
+    veth: Option<&network::VethOptions>,
+) -> Result<Option<network::Slirp>> {
+    // The pair has to be created in the network namespace of the host to be attached to the bridge
+    let created_veth = veth
+        .map(network::create_veth)
+        .transpose()
+        .context("Failed to create veth pair")?;
+
     if unsafe { libc::unshare(CLONE_NEWUTS | CLONE_SYSVSEM | CLONE_NEWNET) } != 0 {
         return Err(std::io::Error::last_os_error()).context("Failed to unshare namespaces");
     }
 
     // Configure UTS namespace
-    let domain_name = "sunwalker";
-    let host_name = "box";
+    let domain_name = DOMAIN_NAME;
+    let host_name = HOST_NAME;
     if unsafe { libc::setdomainname(domain_name.as_ptr() as *const c_char, domain_name.len()) }
         == -1
     {
@@ -79,14 +93,90 @@ pub fn unshare_persistent_namespaces() -> Result<()> {
     // It would still have to be re-created, though, and that takes precious time, 50 ms for me. And
     // then there is a problem with IPv6--::1 cannot be assigned to anything but lo due to a quirk
     // in the interpretation of the IPv6 RFC by the Linux kernel.
-
-    // Bring lo down
+    //
+    // So lo is only brought up on request, e.g. for problems where the solution talks to an
+    // interactor over TCP, and then its statistics are shared by the runs of the box. slirp4netns
+    // brings lo up itself, so its state is set afterwards; it has to stay up for the stub resolver.
+    let slirp = outbound
+        .map(network::start_outbound)
+        .transpose()
+        .context("Failed to set up outbound networking")?;
+    if let (Some(created_veth), Some(veth)) = (created_veth, veth) {
+        created_veth
+            .move_to_box(veth)
+            .context("Failed to set up veth")?;
+    }
     interfaces::Interface::get_by_name("lo")
         .context("Failed to get lo interface")?
         .context("lo interface is missing")?
-        .set_up(false)
-        .context("Failed to bring lo down")?;
+        .set_up(loopback || outbound.is_some())
+        .with_context(|| {
+            format!(
+                "Failed to bring lo {}",
+                if loopback || outbound.is_some() {
+                    "up"
+                } else {
+                    "down"
+                }
+            )
+        })?;
 
+    Ok(slirp)
+}
+
+// File manipulation for the judge, so that images don't have to contain coreutils. All paths are
+// resolved already, so symlinks are never followed here
+
+pub fn remove_path(path: &Path, recursive: bool) -> Result<()> {
+    let metadata =
+        std::fs::symlink_metadata(path).with_context(|| format!("Failed to stat {path:?}"))?;
+    if !metadata.is_dir() {
+        std::fs::remove_file(path).with_context(|| format!("Failed to rm {path:?}"))
+    } else if recursive {
+        std::fs::remove_dir_all(path).with_context(|| format!("Failed to rm -r {path:?}"))
+    } else {
+        std::fs::remove_dir(path).with_context(|| format!("Failed to rmdir {path:?}"))
+    }
+}
+
+pub fn move_path(from: &Path, to: &Path) -> Result<()> {
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if e.raw_os_error() == Some(libc::EXDEV) => {
+            // Different mounts, e.g. a bind mount and /space
+            copy_path(from, to, true)?;
+            remove_path(from, true)
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to mv {from:?} {to:?}")),
+    }
+}
+
+pub fn copy_path(from: &Path, to: &Path, recursive: bool) -> Result<()> {
+    let metadata =
+        std::fs::symlink_metadata(from).with_context(|| format!("Failed to stat {from:?}"))?;
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        if !recursive {
+            bail!("{from:?} is a directory");
+        }
+        std::fs::create_dir(to).with_context(|| format!("Failed to mkdir {to:?}"))?;
+        for entry in std::fs::read_dir(from).with_context(|| format!("Failed to read {from:?}"))? {
+            let entry = entry.with_context(|| format!("Failed to read {from:?}"))?;
+            copy_path(&entry.path(), &to.join(entry.file_name()), true)?;
+        }
+        std::fs::set_permissions(to, metadata.permissions())
+            .with_context(|| format!("Failed to chmod {to:?}"))?;
+    } else if file_type.is_symlink() {
+        let target =
+            std::fs::read_link(from).with_context(|| format!("Failed to readlink {from:?}"))?;
+        std::os::unix::fs::symlink(&target, to)
+            .with_context(|| format!("Failed to ln -s {target:?} {to:?}"))?;
+    } else if file_type.is_file() {
+        // std::fs::copy copies permissions too
+        std::fs::copy(from, to).with_context(|| format!("Failed to cp {from:?} {to:?}"))?;
+    } else {
+        bail!("{from:?} is neither a file, a directory, nor a symlink");
+    }
     Ok(())
 }
 
@@ -95,7 +185,8 @@ pub fn reset_persistent_namespaces() -> Result<()> {
     // the programs, so we only really need to care about information stored by the kernel
     // internally. This includes statistics, which we nullify by bringing lo down, port occupation
     // statuses, and perhaps something else. Luckily, it seems like we don't have to do anything at
-    // all.
+    // all. With --loopback, the statistics of lo and the connections in TIME_WAIT do survive the
+    // reset, which only tells a program how much the previous ones have talked.
     //
     // Many address families can only be used if a capable interface is available, and the only
     // interface we provide, lo, is down. Management protocols, such as AF_KEY, AF_NETLINK,
@@ -144,12 +235,16 @@ pub fn enter_working_area() -> Result<()> {
     Ok(())
 }
 
-pub fn create_dev_copy() -> Result<()> {
+pub fn create_dev_copy(allow_fuse: bool) -> Result<()> {
     std::fs::create_dir("/dev").context("Failed to mkdir /dev")?;
 
-    for name in [
+    let mut names = vec![
         "null", "full", "zero", "urandom", "random", "stdin", "stdout", "stderr", "tty", "fd",
-    ] {
+    ];
+    if allow_fuse {
+        names.push("fuse");
+    }
+    for name in names {
         let source = if name == "random" {
             "/oldroot/dev/urandom".to_string() // prevent entropy depletion
         } else {
@@ -0,0 +1,994 @@
+use anyhow::{bail, Context, Result};
+use multiprocessing::Object;
+use nix::libc;
+
+// Profiles are seccomp filters stacked on top of the one every program gets, for deployments that
+// require defense in depth beyond namespaces, or for problems that forbid e.g. spawning processes.
+// The kernel applies the strictest of the actions the filters return, so a profile can only take
+// permissions away; in particular, the syscalls the box emulates are still traced unless the
+// profile denies them. Killed programs get the SecurityViolation verdict, as usual.
+
+#[derive(Clone, Copy, Object)]
+pub enum Action {
+    Allow,
+    Errno(u16),
+    Kill,
+    // Stops the process for the tracer, the data tells why
+    Trace(u16),
+}
+
+// Interception lets the judge see and override the syscalls of the program, e.g. to audit which
+// files it opens or to stub out sockets. The syscalls are stopped by the tracer like the ones the
+// box emulates, and the filter tags them, so that the two are told apart.
+#[derive(Clone, Object)]
+pub struct InterceptRule {
+    pub syscall: u32,
+    // The rule only applies if the path the syscall operates on starts with this
+    pub path_prefix: Option<String>,
+    pub action: InterceptAction,
+}
+
+#[derive(Clone, Copy, Object)]
+pub enum InterceptAction {
+    // Lets the syscall proceed, so that it's only reported
+    Allow,
+    // Fails the syscall without executing it
+    Errno(u16),
+    // Returns the value without executing the syscall
+    Return(i64),
+}
+
+// The audit counts the syscalls of the program by name, e.g. to check that a solution doesn't
+// spawn threads. Only the syscalls that are counted are stopped.
+#[derive(Clone, Object)]
+pub enum SyscallAudit {
+    All,
+    Only(Vec<u32>),
+}
+
+impl SyscallAudit {
+    pub fn covers(&self, syscall: u32) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(syscalls) => syscalls.contains(&syscall),
+        }
+    }
+}
+
+// Sockets that aren't files, and thus aren't confined by the mount namespace and Landlock.
+// Abstract Unix sockets are shared by everything in the network namespace of the box, e.g. by the
+// programs of a group, which can thus talk to each other behind the back of the judge. Netlink
+// sockets talk to the kernel, which e.g. broadcasts the uevents of the host to them, as the network
+// namespace of the box belongs to the user namespace of the host.
+#[derive(Clone, Copy, Object)]
+pub struct SocketIsolation {
+    pub abstract_unix: SocketPolicy,
+    pub netlink: SocketPolicy,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Object)]
+pub enum SocketPolicy {
+    Allow,
+    // Lets the syscall proceed, so that it's only reported
+    Report,
+    // Fails the syscall with EACCES and reports it
+    Deny,
+}
+
+// The syscalls that create netlink sockets or name abstract Unix sockets
+pub const SOCKET_SYSCALLS: [i64; 5] = [
+    libc::SYS_socket,
+    libc::SYS_bind,
+    libc::SYS_connect,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+];
+
+pub const INTERCEPT_TRACE_DATA: u16 = 1;
+pub const AUDIT_TRACE_DATA: u16 = 2;
+pub const SOCKET_TRACE_DATA: u16 = 3;
+pub const CLOCK_TRACE_DATA: u16 = 4;
+
+// The syscalls that read CLOCK_REALTIME, for the box to fake it
+const CLOCK_SYSCALLS: [i64; 3] = [
+    libc::SYS_clock_gettime,
+    libc::SYS_gettimeofday,
+    libc::SYS_time,
+];
+
+// The box uses them to start the program before the tracer can handle the stops
+const UNTRACEABLE_SYSCALLS: [i64; 3] = [libc::SYS_execve, libc::SYS_execveat, libc::SYS_exit];
+
+#[derive(Clone, Object)]
+pub struct Rule {
+    pub syscall: u32,
+    // The rule doesn't apply if the first argument is equal to this, e.g. to AF_UNIX for socket
+    pub unless_arg0: Option<u32>,
+    pub action: Action,
+}
+
+#[derive(Clone, Object)]
+pub struct Profile {
+    // The first matching rule wins
+    pub rules: Vec<Rule>,
+    pub default_action: Action,
+}
+
+// What a statically or dynamically linked program needs to compute something and print it
+const STRICT_SYSCALLS: &[&str] = &[
+    "read",
+    "write",
+    "readv",
+    "writev",
+    "pread64",
+    "pwrite64",
+    "lseek",
+    "open",
+    "openat",
+    "close",
+    "stat",
+    "fstat",
+    "lstat",
+    "newfstatat",
+    "statx",
+    "access",
+    "faccessat",
+    "faccessat2",
+    "readlink",
+    "readlinkat",
+    "getcwd",
+    "fcntl",
+    "ioctl",
+    "dup",
+    "dup2",
+    "dup3",
+    "mmap",
+    "munmap",
+    "mremap",
+    "mprotect",
+    "madvise",
+    "brk",
+    "rt_sigaction",
+    "rt_sigprocmask",
+    "rt_sigreturn",
+    "sigaltstack",
+    "futex",
+    "sched_yield",
+    "sched_getaffinity",
+    "nanosleep",
+    "clock_nanosleep",
+    "clock_gettime",
+    "clock_getres",
+    "gettimeofday",
+    "time",
+    "getpid",
+    "gettid",
+    "getuid",
+    "geteuid",
+    "getgid",
+    "getegid",
+    "uname",
+    "sysinfo",
+    "getrandom",
+    "getrlimit",
+    "prlimit64",
+    "getrusage",
+    "arch_prctl",
+    "set_tid_address",
+    "set_robust_list",
+    "rseq",
+    "execve",
+    "execveat",
+    "exit",
+    "exit_group",
+];
+
+impl Profile {
+    // Either the name of a built-in profile or a profile in JSON, see README. None stands for the
+    // default profile, which doesn't add a filter at all.
+    pub fn parse(arg: &json::JsonValue) -> Result<Option<Self>> {
+        if let Some(name) = arg.as_str() {
+            return match name {
+                "default" => Ok(None),
+                "strict" => Ok(Some(Self {
+                    rules: STRICT_SYSCALLS
+                        .iter()
+                        .map(|name| {
+                            Ok(Rule {
+                                syscall: syscall_number(name)?,
+                                unless_arg0: None,
+                                action: Action::Allow,
+                            })
+                        })
+                        .collect::<Result<_>>()?,
+                    default_action: Action::Kill,
+                })),
+                // There is no network in the box anyway, so this is about sockets of other
+                // families, which are a common attack surface. io_uring can create sockets too.
+                "no-network" => Ok(Some(Self {
+                    rules: vec![
+                        Rule {
+                            syscall: syscall_number("socket")?,
+                            unless_arg0: Some(libc::AF_UNIX as u32),
+                            action: Action::Errno(libc::EACCES as u16),
+                        },
+                        Rule {
+                            syscall: syscall_number("io_uring_setup")?,
+                            unless_arg0: None,
+                            action: Action::Errno(libc::EPERM as u16),
+                        },
+                    ],
+                    default_action: Action::Allow,
+                })),
+                // Threads are processes too, as far as the kernel is concerned
+                "no-clone" => Ok(Some(Self {
+                    rules: ["fork", "vfork", "clone", "clone3"]
+                        .iter()
+                        .map(|name| {
+                            Ok(Rule {
+                                syscall: syscall_number(name)?,
+                                unless_arg0: None,
+                                action: Action::Errno(libc::EPERM as u16),
+                            })
+                        })
+                        .collect::<Result<_>>()?,
+                    default_action: Action::Allow,
+                })),
+                _ => bail!("Unknown seccomp profile {name}"),
+            };
+        }
+
+        if !arg.is_object() {
+            bail!("Expected the name of a profile or an object");
+        }
+        let default_action = if arg["default"].is_null() {
+            Action::Allow
+        } else {
+            parse_action(&arg["default"]).context("Invalid 'default' action")?
+        };
+        let mut rules = Vec::new();
+        if !arg["rules"].is_null() {
+            if !arg["rules"].is_array() {
+                bail!("Invalid 'rules'");
+            }
+            for rule in arg["rules"].members() {
+                let action = parse_action(&rule["action"]).context("Invalid 'action' of rule")?;
+                if !rule["syscalls"].is_array() {
+                    bail!("Invalid 'syscalls' of rule");
+                }
+                for syscall in rule["syscalls"].members() {
+                    let syscall = match syscall.as_str() {
+                        Some(name) => syscall_number(name)?,
+                        None => syscall.as_u32().context("Invalid syscall")?,
+                    };
+                    rules.push(Rule {
+                        syscall,
+                        unless_arg0: None,
+                        action,
+                    });
+                }
+            }
+        }
+        Ok(Some(Self {
+            rules,
+            default_action,
+        }))
+    }
+
+    fn compile(&self) -> Vec<libc::sock_filter> {
+        let mut filter = vec![
+            load(SECCOMP_DATA_ARCH),
+            jump(BPF_JEQ, AUDIT_ARCH_X86_64, 1, 0),
+            ret(Action::Kill),
+            load(SECCOMP_DATA_NR),
+            // x32 syscalls are the same syscalls with a bit set, they mustn't slip through
+            jump(BPF_JGE, X32_SYSCALL_BIT, 0, 1),
+            ret(Action::Kill),
+        ];
+        for rule in &self.rules {
+            match rule.unless_arg0 {
+                None => {
+                    filter.push(jump(BPF_JEQ, rule.syscall, 0, 1));
+                    filter.push(ret(rule.action));
+                }
+                Some(arg0) => {
+                    filter.push(jump(BPF_JEQ, rule.syscall, 0, 4));
+                    filter.push(load(SECCOMP_DATA_ARG0));
+                    filter.push(jump(BPF_JEQ, arg0, 0, 1));
+                    filter.push(ret(Action::Allow));
+                    filter.push(ret(rule.action));
+                }
+            }
+        }
+        filter.push(ret(self.default_action));
+        filter
+    }
+
+    // A single filter handles both, as the kernel only reports the data of one of the filters that
+    // return the same action
+    pub fn for_tracing(
+        intercept: &[InterceptRule],
+        audit: Option<&SyscallAudit>,
+        sockets: Option<&SocketIsolation>,
+        fake_realtime: bool,
+    ) -> Option<Self> {
+        if intercept.is_empty() && audit.is_none() && sockets.is_none() && !fake_realtime {
+            return None;
+        }
+        let mut syscalls: Vec<u32> = intercept.iter().map(|rule| rule.syscall).collect();
+        syscalls.sort();
+        syscalls.dedup();
+        let mut rules: Vec<Rule> = syscalls
+            .into_iter()
+            .map(|syscall| Rule {
+                syscall,
+                unless_arg0: None,
+                action: Action::Trace(INTERCEPT_TRACE_DATA),
+            })
+            .collect();
+        let mut default_action = Action::Allow;
+        match audit {
+            None => {}
+            Some(SyscallAudit::All) => {
+                rules.extend(UNTRACEABLE_SYSCALLS.into_iter().map(|syscall| Rule {
+                    syscall: syscall as u32,
+                    unless_arg0: None,
+                    action: Action::Allow,
+                }));
+                default_action = Action::Trace(AUDIT_TRACE_DATA);
+            }
+            Some(SyscallAudit::Only(syscalls)) => {
+                rules.extend(syscalls.iter().map(|&syscall| Rule {
+                    syscall,
+                    unless_arg0: None,
+                    action: Action::Trace(AUDIT_TRACE_DATA),
+                }));
+            }
+        }
+        // These come first, so that the tracer always knows to check them, and applies the other
+        // rules to them itself. The addresses are in memory, which seccomp can't look at.
+        if let Some(sockets) = sockets {
+            let mut syscalls = Vec::new();
+            if sockets.netlink != SocketPolicy::Allow {
+                syscalls.push(libc::SYS_socket);
+            }
+            if sockets.abstract_unix != SocketPolicy::Allow {
+                syscalls.extend(&SOCKET_SYSCALLS[1..]);
+            }
+            rules.splice(
+                0..0,
+                syscalls.into_iter().map(|syscall| Rule {
+                    syscall: syscall as u32,
+                    unless_arg0: None,
+                    action: Action::Trace(SOCKET_TRACE_DATA),
+                }),
+            );
+        }
+        // Likewise, clock_gettime only reads CLOCK_REALTIME for some of the clocks
+        if fake_realtime {
+            rules.splice(
+                0..0,
+                CLOCK_SYSCALLS.into_iter().map(|syscall| Rule {
+                    syscall: syscall as u32,
+                    unless_arg0: None,
+                    action: Action::Trace(CLOCK_TRACE_DATA),
+                }),
+            );
+        }
+        Some(Self {
+            rules,
+            default_action,
+        })
+    }
+
+    // Applies to the calling thread and whatever it executes. Must be called as late as possible,
+    // as the profile might forbid the syscalls used to set up the program.
+    pub fn apply(&self) -> Result<()> {
+        let filter = self.compile();
+        let prog = libc::sock_fprog {
+            len: filter
+                .len()
+                .try_into()
+                .context("The seccomp profile is too long")?,
+            filter: filter.as_ptr() as *mut libc::sock_filter,
+        };
+        if unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                SECCOMP_SET_MODE_FILTER,
+                libc::SECCOMP_FILTER_FLAG_SPEC_ALLOW,
+                &prog as *const libc::sock_fprog,
+            )
+        } == -1
+        {
+            Err(std::io::Error::last_os_error())?;
+        }
+        Ok(())
+    }
+}
+
+// Either true for all syscalls or a list of syscalls
+pub fn parse_syscall_audit(arg: &json::JsonValue) -> Result<Option<SyscallAudit>> {
+    if let Some(enabled) = arg.as_bool() {
+        return Ok(enabled.then_some(SyscallAudit::All));
+    }
+    if !arg.is_array() {
+        bail!("Expected a boolean or an array of syscalls");
+    }
+    let mut syscalls = Vec::new();
+    for syscall in arg.members() {
+        let syscall = match syscall.as_str() {
+            Some(name) => syscall_number(name)?,
+            None => syscall.as_u32().context("Invalid syscall")?,
+        };
+        if UNTRACEABLE_SYSCALLS.contains(&(syscall as i64)) {
+            bail!("execve, execveat, and exit cannot be audited");
+        }
+        syscalls.push(syscall);
+    }
+    Ok(Some(SyscallAudit::Only(syscalls)))
+}
+
+// {"abstract_unix": "deny", "netlink": "report"}, the omitted kinds are allowed
+pub fn parse_socket_isolation(arg: &json::JsonValue) -> Result<Option<SocketIsolation>> {
+    if !arg.is_object() {
+        bail!("Expected an object");
+    }
+    let parse_policy = |key: &str| -> Result<SocketPolicy> {
+        match arg[key].as_str() {
+            None if arg[key].is_null() => Ok(SocketPolicy::Allow),
+            Some("allow") => Ok(SocketPolicy::Allow),
+            Some("report") => Ok(SocketPolicy::Report),
+            Some("deny") => Ok(SocketPolicy::Deny),
+            _ => bail!("Invalid '{key}', expected \"allow\", \"report\", or \"deny\""),
+        }
+    };
+    let isolation = SocketIsolation {
+        abstract_unix: parse_policy("abstract_unix")?,
+        netlink: parse_policy("netlink")?,
+    };
+    if isolation.abstract_unix == SocketPolicy::Allow && isolation.netlink == SocketPolicy::Allow {
+        return Ok(None);
+    }
+    Ok(Some(isolation))
+}
+
+pub fn parse_intercept_rules(arg: &json::JsonValue) -> Result<Vec<InterceptRule>> {
+    if !arg.is_array() {
+        bail!("Expected an array of rules");
+    }
+    let mut rules = Vec::new();
+    for rule in arg.members() {
+        let action = if rule["action"].is_null() || rule["action"] == "allow" {
+            InterceptAction::Allow
+        } else if !rule["action"]["errno"].is_null() {
+            InterceptAction::Errno(
+                rule["action"]["errno"]
+                    .as_u16()
+                    .context("Invalid 'errno'")?,
+            )
+        } else if !rule["action"]["return"].is_null() {
+            InterceptAction::Return(
+                rule["action"]["return"]
+                    .as_i64()
+                    .context("Invalid 'return'")?,
+            )
+        } else {
+            bail!("Expected \"allow\", {{\"errno\": ...}}, or {{\"return\": ...}}");
+        };
+        let path_prefix = if rule["path_prefix"].is_null() {
+            None
+        } else {
+            Some(
+                rule["path_prefix"]
+                    .as_str()
+                    .context("Invalid 'path_prefix'")?
+                    .to_string(),
+            )
+        };
+        if !rule["syscalls"].is_array() {
+            bail!("Invalid 'syscalls' of rule");
+        }
+        for syscall in rule["syscalls"].members() {
+            let syscall = match syscall.as_str() {
+                Some(name) => syscall_number(name)?,
+                None => syscall.as_u32().context("Invalid syscall")?,
+            };
+            if UNTRACEABLE_SYSCALLS.contains(&(syscall as i64)) {
+                bail!("execve, execveat, and exit cannot be intercepted");
+            }
+            if path_prefix.is_some() && path_argument(syscall).is_none() {
+                bail!(
+                    "Syscall {} does not take a path",
+                    syscall_name(syscall).unwrap_or("?")
+                );
+            }
+            rules.push(InterceptRule {
+                syscall,
+                path_prefix: path_prefix.clone(),
+                action,
+            });
+        }
+    }
+    Ok(rules)
+}
+
+// The index of the argument of the syscall that is a path, if any
+pub fn path_argument(syscall: u32) -> Option<usize> {
+    match syscall as i64 {
+        libc::SYS_open
+        | libc::SYS_stat
+        | libc::SYS_lstat
+        | libc::SYS_access
+        | libc::SYS_readlink
+        | libc::SYS_truncate
+        | libc::SYS_chdir
+        | libc::SYS_mkdir
+        | libc::SYS_rmdir
+        | libc::SYS_unlink
+        | libc::SYS_rename
+        | libc::SYS_creat
+        | libc::SYS_chmod
+        | libc::SYS_chown
+        | libc::SYS_lchown
+        | libc::SYS_symlink
+        | libc::SYS_link => Some(0),
+        libc::SYS_openat
+        | libc::SYS_openat2
+        | libc::SYS_newfstatat
+        | libc::SYS_statx
+        | libc::SYS_faccessat
+        | libc::SYS_faccessat2
+        | libc::SYS_readlinkat
+        | libc::SYS_mkdirat
+        | libc::SYS_unlinkat
+        | libc::SYS_renameat
+        | libc::SYS_renameat2
+        | libc::SYS_fchmodat
+        | libc::SYS_fchownat
+        | libc::SYS_linkat => Some(1),
+        _ => None,
+    }
+}
+
+fn parse_action(arg: &json::JsonValue) -> Result<Action> {
+    if arg == "allow" {
+        Ok(Action::Allow)
+    } else if arg == "kill" {
+        Ok(Action::Kill)
+    } else if !arg["errno"].is_null() {
+        Ok(Action::Errno(
+            arg["errno"].as_u16().context("Invalid 'errno'")?,
+        ))
+    } else {
+        bail!("Expected \"allow\", \"kill\", or {{\"errno\": ...}}");
+    }
+}
+
+const SECCOMP_SET_MODE_FILTER: libc::c_uint = 1;
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x80000000;
+const SECCOMP_RET_ERRNO: u32 = 0x00050000;
+const SECCOMP_RET_TRACE: u32 = 0x7ff00000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff0000;
+
+const AUDIT_ARCH_X86_64: u32 = 0xc000003e;
+const X32_SYSCALL_BIT: u32 = 0x40000000;
+
+// Offsets in struct seccomp_data
+const SECCOMP_DATA_NR: u32 = 0;
+const SECCOMP_DATA_ARCH: u32 = 4;
+// The lower half of the first argument, as x86-64 is little-endian
+const SECCOMP_DATA_ARG0: u32 = 16;
+
+const BPF_LD_W_ABS: u16 = 0x20;
+const BPF_JEQ: u16 = 0x15;
+const BPF_JGE: u16 = 0x35;
+const BPF_RET_K: u16 = 0x06;
+
+fn load(offset: u32) -> libc::sock_filter {
+    libc::sock_filter {
+        code: BPF_LD_W_ABS,
+        jt: 0,
+        jf: 0,
+        k: offset,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+fn ret(action: Action) -> libc::sock_filter {
+    let k = match action {
+        Action::Allow => SECCOMP_RET_ALLOW,
+        Action::Errno(errno) => SECCOMP_RET_ERRNO | errno as u32,
+        Action::Kill => SECCOMP_RET_KILL_PROCESS,
+        Action::Trace(data) => SECCOMP_RET_TRACE | data as u32,
+    };
+    libc::sock_filter {
+        code: BPF_RET_K,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+pub fn syscall_number(name: &str) -> Result<u32> {
+    SYSCALLS
+        .iter()
+        .find(|(syscall, _)| *syscall == name)
+        .map(|(_, number)| *number)
+        .with_context(|| format!("Unknown syscall {name}"))
+}
+
+pub fn syscall_name(number: u32) -> Option<&'static str> {
+    SYSCALLS
+        .iter()
+        .find(|(_, syscall)| *syscall == number)
+        .map(|(name, _)| *name)
+}
+
+// x86-64 only, as the box doesn't support other architectures
+const SYSCALLS: &[(&str, u32)] = &[
+    ("read", 0),
+    ("write", 1),
+    ("open", 2),
+    ("close", 3),
+    ("stat", 4),
+    ("fstat", 5),
+    ("lstat", 6),
+    ("poll", 7),
+    ("lseek", 8),
+    ("mmap", 9),
+    ("mprotect", 10),
+    ("munmap", 11),
+    ("brk", 12),
+    ("rt_sigaction", 13),
+    ("rt_sigprocmask", 14),
+    ("rt_sigreturn", 15),
+    ("ioctl", 16),
+    ("pread64", 17),
+    ("pwrite64", 18),
+    ("readv", 19),
+    ("writev", 20),
+    ("access", 21),
+    ("pipe", 22),
+    ("select", 23),
+    ("sched_yield", 24),
+    ("mremap", 25),
+    ("msync", 26),
+    ("mincore", 27),
+    ("madvise", 28),
+    ("shmget", 29),
+    ("shmat", 30),
+    ("shmctl", 31),
+    ("dup", 32),
+    ("dup2", 33),
+    ("pause", 34),
+    ("nanosleep", 35),
+    ("getitimer", 36),
+    ("alarm", 37),
+    ("setitimer", 38),
+    ("getpid", 39),
+    ("sendfile", 40),
+    ("socket", 41),
+    ("connect", 42),
+    ("accept", 43),
+    ("sendto", 44),
+    ("recvfrom", 45),
+    ("sendmsg", 46),
+    ("recvmsg", 47),
+    ("shutdown", 48),
+    ("bind", 49),
+    ("listen", 50),
+    ("getsockname", 51),
+    ("getpeername", 52),
+    ("socketpair", 53),
+    ("setsockopt", 54),
+    ("getsockopt", 55),
+    ("clone", 56),
+    ("fork", 57),
+    ("vfork", 58),
+    ("execve", 59),
+    ("exit", 60),
+    ("wait4", 61),
+    ("kill", 62),
+    ("uname", 63),
+    ("semget", 64),
+    ("semop", 65),
+    ("semctl", 66),
+    ("shmdt", 67),
+    ("msgget", 68),
+    ("msgsnd", 69),
+    ("msgrcv", 70),
+    ("msgctl", 71),
+    ("fcntl", 72),
+    ("flock", 73),
+    ("fsync", 74),
+    ("fdatasync", 75),
+    ("truncate", 76),
+    ("ftruncate", 77),
+    ("getdents", 78),
+    ("getcwd", 79),
+    ("chdir", 80),
+    ("fchdir", 81),
+    ("rename", 82),
+    ("mkdir", 83),
+    ("rmdir", 84),
+    ("creat", 85),
+    ("link", 86),
+    ("unlink", 87),
+    ("symlink", 88),
+    ("readlink", 89),
+    ("chmod", 90),
+    ("fchmod", 91),
+    ("chown", 92),
+    ("fchown", 93),
+    ("lchown", 94),
+    ("umask", 95),
+    ("gettimeofday", 96),
+    ("getrlimit", 97),
+    ("getrusage", 98),
+    ("sysinfo", 99),
+    ("times", 100),
+    ("ptrace", 101),
+    ("getuid", 102),
+    ("syslog", 103),
+    ("getgid", 104),
+    ("setuid", 105),
+    ("setgid", 106),
+    ("geteuid", 107),
+    ("getegid", 108),
+    ("setpgid", 109),
+    ("getppid", 110),
+    ("getpgrp", 111),
+    ("setsid", 112),
+    ("setreuid", 113),
+    ("setregid", 114),
+    ("getgroups", 115),
+    ("setgroups", 116),
+    ("setresuid", 117),
+    ("getresuid", 118),
+    ("setresgid", 119),
+    ("getresgid", 120),
+    ("getpgid", 121),
+    ("setfsuid", 122),
+    ("setfsgid", 123),
+    ("getsid", 124),
+    ("capget", 125),
+    ("capset", 126),
+    ("rt_sigpending", 127),
+    ("rt_sigtimedwait", 128),
+    ("rt_sigqueueinfo", 129),
+    ("rt_sigsuspend", 130),
+    ("sigaltstack", 131),
+    ("utime", 132),
+    ("mknod", 133),
+    ("uselib", 134),
+    ("personality", 135),
+    ("ustat", 136),
+    ("statfs", 137),
+    ("fstatfs", 138),
+    ("sysfs", 139),
+    ("getpriority", 140),
+    ("setpriority", 141),
+    ("sched_setparam", 142),
+    ("sched_getparam", 143),
+    ("sched_setscheduler", 144),
+    ("sched_getscheduler", 145),
+    ("sched_get_priority_max", 146),
+    ("sched_get_priority_min", 147),
+    ("sched_rr_get_interval", 148),
+    ("mlock", 149),
+    ("munlock", 150),
+    ("mlockall", 151),
+    ("munlockall", 152),
+    ("vhangup", 153),
+    ("modify_ldt", 154),
+    ("pivot_root", 155),
+    ("_sysctl", 156),
+    ("prctl", 157),
+    ("arch_prctl", 158),
+    ("adjtimex", 159),
+    ("setrlimit", 160),
+    ("chroot", 161),
+    ("sync", 162),
+    ("acct", 163),
+    ("settimeofday", 164),
+    ("mount", 165),
+    ("umount2", 166),
+    ("swapon", 167),
+    ("swapoff", 168),
+    ("reboot", 169),
+    ("sethostname", 170),
+    ("setdomainname", 171),
+    ("iopl", 172),
+    ("ioperm", 173),
+    ("create_module", 174),
+    ("init_module", 175),
+    ("delete_module", 176),
+    ("get_kernel_syms", 177),
+    ("query_module", 178),
+    ("quotactl", 179),
+    ("nfsservctl", 180),
+    ("getpmsg", 181),
+    ("putpmsg", 182),
+    ("afs_syscall", 183),
+    ("tuxcall", 184),
+    ("security", 185),
+    ("gettid", 186),
+    ("readahead", 187),
+    ("setxattr", 188),
+    ("lsetxattr", 189),
+    ("fsetxattr", 190),
+    ("getxattr", 191),
+    ("lgetxattr", 192),
+    ("fgetxattr", 193),
+    ("listxattr", 194),
+    ("llistxattr", 195),
+    ("flistxattr", 196),
+    ("removexattr", 197),
+    ("lremovexattr", 198),
+    ("fremovexattr", 199),
+    ("tkill", 200),
+    ("time", 201),
+    ("futex", 202),
+    ("sched_setaffinity", 203),
+    ("sched_getaffinity", 204),
+    ("set_thread_area", 205),
+    ("io_setup", 206),
+    ("io_destroy", 207),
+    ("io_getevents", 208),
+    ("io_submit", 209),
+    ("io_cancel", 210),
+    ("get_thread_area", 211),
+    ("lookup_dcookie", 212),
+    ("epoll_create", 213),
+    ("epoll_ctl_old", 214),
+    ("epoll_wait_old", 215),
+    ("remap_file_pages", 216),
+    ("getdents64", 217),
+    ("set_tid_address", 218),
+    ("restart_syscall", 219),
+    ("semtimedop", 220),
+    ("fadvise64", 221),
+    ("timer_create", 222),
+    ("timer_settime", 223),
+    ("timer_gettime", 224),
+    ("timer_getoverrun", 225),
+    ("timer_delete", 226),
+    ("clock_settime", 227),
+    ("clock_gettime", 228),
+    ("clock_getres", 229),
+    ("clock_nanosleep", 230),
+    ("exit_group", 231),
+    ("epoll_wait", 232),
+    ("epoll_ctl", 233),
+    ("tgkill", 234),
+    ("utimes", 235),
+    ("vserver", 236),
+    ("mbind", 237),
+    ("set_mempolicy", 238),
+    ("get_mempolicy", 239),
+    ("mq_open", 240),
+    ("mq_unlink", 241),
+    ("mq_timedsend", 242),
+    ("mq_timedreceive", 243),
+    ("mq_notify", 244),
+    ("mq_getsetattr", 245),
+    ("kexec_load", 246),
+    ("waitid", 247),
+    ("add_key", 248),
+    ("request_key", 249),
+    ("keyctl", 250),
+    ("ioprio_set", 251),
+    ("ioprio_get", 252),
+    ("inotify_init", 253),
+    ("inotify_add_watch", 254),
+    ("inotify_rm_watch", 255),
+    ("migrate_pages", 256),
+    ("openat", 257),
+    ("mkdirat", 258),
+    ("mknodat", 259),
+    ("fchownat", 260),
+    ("futimesat", 261),
+    ("newfstatat", 262),
+    ("unlinkat", 263),
+    ("renameat", 264),
+    ("linkat", 265),
+    ("symlinkat", 266),
+    ("readlinkat", 267),
+    ("fchmodat", 268),
+    ("faccessat", 269),
+    ("pselect6", 270),
+    ("ppoll", 271),
+    ("unshare", 272),
+    ("set_robust_list", 273),
+    ("get_robust_list", 274),
+    ("splice", 275),
+    ("tee", 276),
+    ("sync_file_range", 277),
+    ("vmsplice", 278),
+    ("move_pages", 279),
+    ("utimensat", 280),
+    ("epoll_pwait", 281),
+    ("signalfd", 282),
+    ("timerfd_create", 283),
+    ("eventfd", 284),
+    ("fallocate", 285),
+    ("timerfd_settime", 286),
+    ("timerfd_gettime", 287),
+    ("accept4", 288),
+    ("signalfd4", 289),
+    ("eventfd2", 290),
+    ("epoll_create1", 291),
+    ("dup3", 292),
+    ("pipe2", 293),
+    ("inotify_init1", 294),
+    ("preadv", 295),
+    ("pwritev", 296),
+    ("rt_tgsigqueueinfo", 297),
+    ("perf_event_open", 298),
+    ("recvmmsg", 299),
+    ("fanotify_init", 300),
+    ("fanotify_mark", 301),
+    ("prlimit64", 302),
+    ("name_to_handle_at", 303),
+    ("open_by_handle_at", 304),
+    ("clock_adjtime", 305),
+    ("syncfs", 306),
+    ("sendmmsg", 307),
+    ("setns", 308),
+    ("getcpu", 309),
+    ("process_vm_readv", 310),
+    ("process_vm_writev", 311),
+    ("kcmp", 312),
+    ("finit_module", 313),
+    ("sched_setattr", 314),
+    ("sched_getattr", 315),
+    ("renameat2", 316),
+    ("seccomp", 317),
+    ("getrandom", 318),
+    ("memfd_create", 319),
+    ("kexec_file_load", 320),
+    ("bpf", 321),
+    ("execveat", 322),
+    ("userfaultfd", 323),
+    ("membarrier", 324),
+    ("mlock2", 325),
+    ("copy_file_range", 326),
+    ("preadv2", 327),
+    ("pwritev2", 328),
+    ("pkey_mprotect", 329),
+    ("pkey_alloc", 330),
+    ("pkey_free", 331),
+    ("statx", 332),
+    ("rseq", 334),
+    ("pidfd_send_signal", 424),
+    ("io_uring_setup", 425),
+    ("io_uring_enter", 426),
+    ("io_uring_register", 427),
+    ("open_tree", 428),
+    ("move_mount", 429),
+    ("fsopen", 430),
+    ("fsconfig", 431),
+    ("fsmount", 432),
+    ("fspick", 433),
+    ("pidfd_open", 434),
+    ("clone3", 435),
+    ("close_range", 436),
+    ("openat2", 437),
+    ("pidfd_getfd", 438),
+    ("faccessat2", 439),
+    ("process_madvise", 440),
+    ("epoll_pwait2", 441),
+    ("mount_setattr", 442),
+    ("quotactl_fd", 443),
+    ("landlock_create_ruleset", 444),
+    ("landlock_add_rule", 445),
+    ("landlock_restrict_self", 446),
+    ("memfd_secret", 447),
+    ("process_mrelease", 448),
+    ("futex_waitv", 449),
+    ("set_mempolicy_home_node", 450),
+];
@@ -0,0 +1,171 @@
+use crate::linux::{api, sandbox};
+use anyhow::{anyhow, bail, Context, Result};
+use nix::{libc, sys::wait, unistd};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::FromRawFd;
+
+// Probes the kernel for the features the box relies on, so that a misconfigured host is diagnosed
+// up front rather than by a cryptic error in the middle of `start`. The report is a JSON object
+// mapping check names to {"ok": true} or {"ok": false, "error": "..."}.
+
+const CLONE_NEWTIME: libc::c_int = 0x80;
+
+pub fn run(core: Option<u64>) -> Result<bool> {
+    let mut checks: Vec<(&str, Result<()>)> = vec![
+        ("root", check_root()),
+        ("sanity", sandbox::sanity_checks()),
+        ("cgroup_controllers", check_cgroup_controllers()),
+        ("pidfd", check_pidfd()),
+        ("seccomp", check_seccomp()),
+        ("overlayfs", check_filesystem("overlay")),
+        ("squashfs", check_filesystem("squashfs")),
+        ("loop_devices", check_exists("/dev/loop-control")),
+    ];
+    for (name, flag) in [
+        ("user_namespace", libc::CLONE_NEWUSER),
+        ("pid_namespace", libc::CLONE_NEWPID),
+        ("mount_namespace", libc::CLONE_NEWNS),
+        ("ipc_namespace", libc::CLONE_NEWIPC),
+        ("uts_namespace", libc::CLONE_NEWUTS),
+        ("net_namespace", libc::CLONE_NEWNET),
+        ("time_namespace", CLONE_NEWTIME),
+    ] {
+        checks.push((name, in_child(|| check_unshare(flag))));
+    }
+    // This is the only check that involves a real box, so it needs an isolated core
+    if let Some(core) = core {
+        checks.push(("box_lifecycle", check_box_lifecycle(core)));
+    }
+
+    let mut report = json::JsonValue::new_object();
+    let mut all_ok = true;
+    for (name, result) in checks {
+        report[name] = match result {
+            Ok(()) => json::object! { ok: true },
+            Err(e) => {
+                all_ok = false;
+                json::object! { ok: false, error: format!("{e:?}") }
+            }
+        };
+    }
+    println!("{}", report.pretty(2));
+    Ok(all_ok)
+}
+
+fn check_root() -> Result<()> {
+    if !unistd::geteuid().is_root() {
+        bail!("The box has to be started as root");
+    }
+    Ok(())
+}
+
+fn check_cgroup_controllers() -> Result<()> {
+    let controllers = std::fs::read_to_string("/sys/fs/cgroup/cgroup.controllers")
+        .context("Failed to read /sys/fs/cgroup/cgroup.controllers")?;
+    let controllers: Vec<&str> = controllers.split_whitespace().collect();
+    for controller in ["cpu", "cpuset", "memory", "pids"] {
+        if !controllers.contains(&controller) {
+            bail!("cgroup controller {controller} is unavailable");
+        }
+    }
+    Ok(())
+}
+
+fn check_pidfd() -> Result<()> {
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, unistd::getpid().as_raw(), 0) };
+    if pidfd == -1 {
+        return Err(std::io::Error::last_os_error()).context("pidfd_open failed");
+    }
+    unistd::close(pidfd as i32).context("Failed to close pidfd")?;
+    Ok(())
+}
+
+fn check_seccomp() -> Result<()> {
+    // Fails with EINVAL if the kernel is built without seccomp
+    if unsafe { libc::prctl(libc::PR_GET_SECCOMP) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("prctl(PR_GET_SECCOMP) failed");
+    }
+    Ok(())
+}
+
+fn check_filesystem(name: &str) -> Result<()> {
+    let filesystems =
+        std::fs::read_to_string("/proc/filesystems").context("Failed to read /proc/filesystems")?;
+    if !filesystems
+        .lines()
+        .any(|line| line.split_whitespace().last() == Some(name))
+    {
+        bail!(
+            "{name} is not supported by the kernel, or its module is not loaded (try modprobe \
+             {name})"
+        );
+    }
+    Ok(())
+}
+
+fn check_exists(path: &str) -> Result<()> {
+    if !std::path::Path::new(path).exists() {
+        bail!("{path} does not exist");
+    }
+    Ok(())
+}
+
+fn check_unshare(flag: libc::c_int) -> Result<()> {
+    if unsafe { libc::unshare(flag) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("unshare() failed");
+    }
+    Ok(())
+}
+
+// Runs the probe in a forked child, so that the changes it makes don't affect us
+fn in_child(probe: impl FnOnce() -> Result<()>) -> Result<()> {
+    let (read_end, write_end) =
+        unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC).context("Failed to create pipe")?;
+    let (mut read_end, mut write_end) =
+        unsafe { (File::from_raw_fd(read_end), File::from_raw_fd(write_end)) };
+
+    // No threads have been started yet, so forking is safe
+    match unsafe { unistd::fork() }.context("Failed to fork")? {
+        unistd::ForkResult::Child => {
+            drop(read_end);
+            let code = match probe() {
+                Ok(()) => 0,
+                Err(e) => {
+                    let _ = write_end.write_all(format!("{e:?}").as_bytes());
+                    1
+                }
+            };
+            unsafe { libc::_exit(code) };
+        }
+        unistd::ForkResult::Parent { child } => {
+            drop(write_end);
+            let mut error = String::new();
+            read_end
+                .read_to_string(&mut error)
+                .context("Failed to read from child")?;
+            match wait::waitpid(child, None).context("Failed to waitpid for child")? {
+                wait::WaitStatus::Exited(_, 0) => Ok(()),
+                wait::WaitStatus::Exited(_, _) => Err(anyhow!("{error}")),
+                status => bail!("Child terminated with {status:?}"),
+            }
+        }
+    }
+}
+
+fn check_box_lifecycle(core: u64) -> Result<()> {
+    let executable = std::env::current_exe().context("Failed to locate the executable")?;
+    let mut sandbox = api::Box::new(api::Config::new(executable, core))?;
+    for _ in 0..2 {
+        let result = sandbox.run(&api::RunSpec::new(
+            vec!["/bin/true".to_string()],
+            std::time::Duration::from_secs(1),
+            1,
+        ))?;
+        if result.verdict != api::Verdict::ExitCode(0) {
+            bail!("/bin/true finished with {:?}", result.verdict);
+        }
+        sandbox.reset()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use anyhow::{bail, Context, Result};
+use nix::libc;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::{fs::FileExt, io::AsRawFd};
+
+// Sparse files are transferred as a list of data extents, i.e. (offset, length) pairs, followed by
+// the contents of the extents. Holes are implied by the gaps between them.
+
+pub fn data_extents(file: &File, start: u64, end: u64) -> Result<Vec<(u64, u64)>> {
+    let mut extents = Vec::new();
+    let mut offset = start;
+    while offset < end {
+        let data_start = match seek(file, offset, libc::SEEK_DATA)? {
+            Some(data_start) if data_start < end => data_start,
+            // Only a hole is left
+            _ => break,
+        };
+        let data_end = seek(file, data_start, libc::SEEK_HOLE)?
+            .unwrap_or(end)
+            .min(end);
+        extents.push((data_start, data_end - data_start));
+        offset = data_end;
+    }
+    Ok(extents)
+}
+
+fn seek(file: &File, offset: u64, whence: libc::c_int) -> Result<Option<u64>> {
+    let offset = offset.try_into().context("Too big offset")?;
+    let res = unsafe { libc::lseek(file.as_raw_fd(), offset, whence) };
+    if res == -1 {
+        let e = std::io::Error::last_os_error();
+        if e.raw_os_error() == Some(libc::ENXIO) {
+            // We're past the last extent of the given kind
+            return Ok(None);
+        }
+        return Err(e).context("Failed to seek");
+    }
+    Ok(Some(res as u64))
+}
+
+pub fn extents_to_json(extents: &[(u64, u64)]) -> json::JsonValue {
+    extents
+        .iter()
+        .map(|&(offset, len)| json::array![offset, len])
+        .collect::<Vec<_>>()
+        .into()
+}
+
+pub fn extents_from_json(value: &json::JsonValue, file_len: u64) -> Result<Vec<(u64, u64)>> {
+    let json::JsonValue::Array(array) = value else {
+        bail!("Invalid 'extents' argument");
+    };
+    let mut extents = Vec::with_capacity(array.len());
+    for extent in array {
+        let offset = extent[0].as_u64().context("Invalid 'extents' argument")?;
+        let len = extent[1].as_u64().context("Invalid 'extents' argument")?;
+        if offset.checked_add(len).map_or(true, |end| end > file_len) {
+            bail!("Extent out of file bounds");
+        }
+        extents.push((offset, len));
+    }
+    Ok(extents)
+}
+
+// Reads the contents of the extents one after another
+pub struct ExtentReader {
+    file: File,
+    extents: std::vec::IntoIter<(u64, u64)>,
+    current: (u64, u64),
+}
+
+impl ExtentReader {
+    pub fn new(file: File, extents: Vec<(u64, u64)>) -> Self {
+        Self {
+            file,
+            extents: extents.into_iter(),
+            current: (0, 0),
+        }
+    }
+}
+
+impl Read for ExtentReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.current.1 == 0 {
+            match self.extents.next() {
+                Some(extent) => self.current = extent,
+                None => return Ok(0),
+            }
+        }
+        let (offset, len) = self.current;
+        let n = buf.len().min(len.try_into().unwrap_or(usize::MAX));
+        let n_read = self.file.read_at(&mut buf[..n], offset)?;
+        if n_read == 0 {
+            // The file was truncated in the meantime, fill the rest with zeroes
+            buf[..n].fill(0);
+            self.current = (offset + n as u64, len - n as u64);
+            return Ok(n);
+        }
+        self.current = (offset + n_read as u64, len - n_read as u64);
+        Ok(n_read)
+    }
+}
+
+pub fn write_extents(
+    file: &mut File,
+    extents: &[(u64, u64)],
+    payload: &mut impl Read,
+) -> Result<()> {
+    for &(offset, len) in extents {
+        file.seek(SeekFrom::Start(offset))
+            .context("Failed to seek")?;
+        let n_written =
+            std::io::copy(&mut payload.by_ref().take(len), file).context("Failed to write file")?;
+        if n_written < len {
+            bail!("Unexpected EOF in payload");
+        }
+    }
+    file.flush().context("Failed to write file")?;
+    Ok(())
+}
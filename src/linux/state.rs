@@ -0,0 +1,198 @@
+use crate::linux::cgroups;
+use anyhow::{bail, Context, Result};
+use nix::{errno, sys::signal, unistd::Pid};
+use std::io::Write;
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// A box cleans up after itself when it terminates normally, and the reaper destroys the cgroup if
+// the controller dies. If all the processes of the box are killed at once though, e.g. by the OOM
+// killer or by a service manager, the cgroup and the socket are left behind. The state file
+// records what has to be removed in this case.
+
+pub struct State {
+    // The controller, identified by PID and start time, as PIDs are reused
+    pub pid: i32,
+    pub start_time: u64,
+    pub core: u64,
+    pub proc_cgroup: String,
+    pub listen: Option<String>,
+}
+
+fn state_path(state_dir: impl AsRef<Path>) -> PathBuf {
+    state_dir.as_ref().join("state.json")
+}
+
+impl State {
+    pub fn of_self(core: u64, proc_cgroup: String, listen: Option<String>) -> Result<Self> {
+        let pid = nix::unistd::getpid().as_raw();
+        Ok(Self {
+            pid,
+            start_time: process_start_time(pid)?.context("Failed to get own start time")?,
+            core,
+            proc_cgroup,
+            listen,
+        })
+    }
+
+    pub fn load(state_dir: &str) -> Result<Option<Self>> {
+        let path = state_path(state_dir);
+        let state = match std::fs::read_to_string(&path) {
+            Ok(state) => state,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {path:?}")),
+        };
+        let state: Result<Self> = try {
+            let state = json::parse(&state)?;
+            Self {
+                pid: state["pid"].as_i32().context("Invalid 'pid'")?,
+                start_time: state["start_time"]
+                    .as_u64()
+                    .context("Invalid 'start_time'")?,
+                core: state["core"].as_u64().context("Invalid 'core'")?,
+                proc_cgroup: state["proc_cgroup"]
+                    .as_str()
+                    .context("Invalid 'proc_cgroup'")?
+                    .to_string(),
+                listen: if state["listen"].is_null() {
+                    None
+                } else {
+                    Some(
+                        state["listen"]
+                            .as_str()
+                            .context("Invalid 'listen'")?
+                            .to_string(),
+                    )
+                },
+            }
+        };
+        state
+            .map(Some)
+            .with_context(|| format!("Invalid state file {path:?}"))
+    }
+
+    // The box saves its state after entering its root, so it passes the path via the old root
+    pub fn save(&self, state_dir: impl AsRef<Path>) -> Result<()> {
+        let state_dir = state_dir.as_ref();
+        std::fs::create_dir_all(state_dir)
+            .with_context(|| format!("Failed to mkdir {state_dir:?}"))?;
+        let state = json::object! {
+            pid: self.pid,
+            start_time: self.start_time,
+            core: self.core,
+            proc_cgroup: self.proc_cgroup.as_str(),
+            listen: self.listen.as_deref(),
+        };
+        // Written atomically, so that a crash doesn't leave a truncated file
+        let path = state_path(state_dir);
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, state.dump())
+            .with_context(|| format!("Failed to write {tmp_path:?}"))?;
+        std::fs::rename(&tmp_path, &path)
+            .with_context(|| format!("Failed to rename {tmp_path:?} to {path:?}"))
+    }
+
+    pub fn is_running(&self) -> Result<bool> {
+        Ok(process_start_time(self.pid)? == Some(self.start_time))
+    }
+}
+
+pub fn remove(state_dir: impl AsRef<Path>) -> Result<()> {
+    let path = state_path(state_dir);
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to remove {path:?}")),
+    }
+}
+
+// Kills the box if it's still running and removes its leftovers
+pub fn cleanup(state_dir: &str) -> Result<()> {
+    let Some(state) = State::load(state_dir)? else {
+        return Ok(());
+    };
+
+    if state.is_running()? {
+        // The reaper notices the death of the controller and terminates the rest of the box
+        match signal::kill(Pid::from_raw(state.pid), signal::Signal::SIGKILL) {
+            Ok(()) | Err(errno::Errno::ESRCH) => {}
+            Err(e) => return Err(e).context("Failed to kill the box"),
+        }
+        let mut times = 0;
+        while state.is_running()? {
+            if times == 100 {
+                bail!("The box is still running after SIGKILL");
+            }
+            std::thread::sleep(Duration::from_millis(50));
+            times += 1;
+        }
+    }
+
+    cgroups::remove_proc_cgroup(state.core, &state.proc_cgroup)
+        .with_context(|| format!("Failed to remove cgroup proc-{}", state.proc_cgroup))?;
+    if let Some(path) = &state.listen {
+        match std::fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to remove {path}")),
+        }
+    }
+    remove(state_dir)
+}
+
+// Relays stdin and stdout to the --listen socket of a running box, so that a judge that has been
+// restarted can drive the box it started before. The box only outlives the judge if something else
+// keeps its stdin open, e.g. a service manager. File descriptors are not relayed.
+pub fn resume(state_dir: &str) -> Result<()> {
+    let state = State::load(state_dir)?
+        .with_context(|| format!("No box has been started with state directory {state_dir}"))?;
+    if !state.is_running()? {
+        bail!("The box has terminated, use cleanup to remove its leftovers");
+    }
+    let path = state
+        .listen
+        .context("The box was started without --listen, so there is nothing to attach to")?;
+    let stream =
+        UnixStream::connect(&path).with_context(|| format!("Failed to connect to {path}"))?;
+
+    let mut stream_in = stream.try_clone().context("Failed to clone socket")?;
+    std::thread::spawn(move || {
+        if let Err(e) = std::io::copy(&mut std::io::stdin().lock(), &mut stream_in) {
+            ::tracing::warn!("Failed to relay stdin: {e}");
+        }
+        // Let the box know no more commands are coming, so that it closes the connection once it
+        // has replied to the last one
+        let _ = stream_in.shutdown(Shutdown::Write);
+    });
+
+    let mut stdout = std::io::stdout().lock();
+    std::io::copy(&mut &stream, &mut stdout).context("Failed to relay stdout")?;
+    stdout.flush().context("Failed to flush stdout")
+}
+
+// None if there is no such process or it's a zombie
+fn process_start_time(pid: i32) -> Result<Option<u64>> {
+    let stat = match std::fs::read_to_string(format!("/proc/{pid}/stat")) {
+        Ok(stat) => stat,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read /proc/{pid}/stat")),
+    };
+    // The command name may contain spaces and parentheses, so skip past the last parenthesis. The
+    // state is the first field after it, and the start time is the 20th.
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .context("Invalid /proc/pid/stat")?
+        .1
+        .split_whitespace()
+        .collect();
+    if fields.first() == Some(&"Z") {
+        return Ok(None);
+    }
+    let start_time = fields
+        .get(19)
+        .and_then(|field| field.parse().ok())
+        .context("Invalid /proc/pid/stat")?;
+    Ok(Some(start_time))
+}
@@ -1,6 +1,6 @@
 use nix::{
     libc,
-    libc::{c_int, c_ulong, c_void},
+    libc::{c_int, c_uint, c_ulong, c_void},
 };
 
 pub use nix::libc::{
@@ -12,10 +12,64 @@ pub use nix::libc::{
 
 use std::ffi::CString;
 use std::io::{Error, ErrorKind, Result};
-use std::os::unix::ffi::OsStrExt;
+use std::os::unix::{
+    ffi::OsStrExt,
+    io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+};
 use std::path::Path;
 use std::ptr::null;
 
+// Not exported by libc yet
+pub const OPEN_TREE_CLONE: c_uint = 1;
+pub const AT_RECURSIVE: c_uint = 0x8000;
+const MOVE_MOUNT_F_EMPTY_PATH: c_uint = 0x4;
+
+const FICLONE: c_ulong = 0x40049409;
+
+const LOOP_CTL_GET_FREE: c_ulong = 0x4c82;
+const LOOP_CONFIGURE: c_ulong = 0x4c0a;
+const LO_FLAGS_READ_ONLY: u32 = 1;
+const LO_FLAGS_AUTOCLEAR: u32 = 4;
+
+pub const MOUNT_ATTR_RDONLY: u64 = 0x1;
+pub const MOUNT_ATTR_NOSUID: u64 = 0x2;
+pub const MOUNT_ATTR_NODEV: u64 = 0x4;
+pub const MOUNT_ATTR_NOEXEC: u64 = 0x8;
+pub const MOUNT_ATTR_IDMAP: u64 = 0x100000;
+
+#[repr(C)]
+struct mount_attr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+#[repr(C)]
+struct loop_info64 {
+    lo_device: u64,
+    lo_inode: u64,
+    lo_rdevice: u64,
+    lo_offset: u64,
+    lo_sizelimit: u64,
+    lo_number: u32,
+    lo_encrypt_type: u32,
+    lo_encrypt_key_size: u32,
+    lo_flags: u32,
+    lo_file_name: [u8; 64],
+    lo_crypt_name: [u8; 64],
+    lo_encrypt_key: [u8; 32],
+    lo_init: [u64; 2],
+}
+
+#[repr(C)]
+struct loop_config {
+    fd: u32,
+    block_size: u32,
+    info: loop_info64,
+    reserved: [u64; 8],
+}
+
 pub fn to_cstring(data: &[u8]) -> Result<CString> {
     CString::new(data)
         .map_err(|e| Error::new(ErrorKind::InvalidData, format!("CString::new failed: {e}")))
@@ -84,3 +138,107 @@ pub fn umount_opt<P: AsRef<Path>>(path: P, flags: c_int) -> Result<()> {
 pub fn umount<P: AsRef<Path>>(path: P) -> Result<()> {
     umount_opt(path, 0)
 }
+
+pub fn open_tree<P: AsRef<Path>>(path: P, flags: c_uint) -> Result<OwnedFd> {
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_open_tree,
+            libc::AT_FDCWD,
+            to_cstring(path.as_ref().as_os_str().as_bytes())?.as_ptr(),
+            flags | libc::O_CLOEXEC as c_uint,
+        )
+    };
+    if fd == -1 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+    }
+}
+
+pub fn mount_setattr(
+    mount_fd: &OwnedFd,
+    flags: c_uint,
+    attr_set: u64,
+    propagation: u64,
+    userns_fd: Option<&OwnedFd>,
+) -> Result<()> {
+    let attr = mount_attr {
+        attr_set,
+        attr_clr: 0,
+        propagation,
+        userns_fd: userns_fd.map_or(0, |fd| fd.as_raw_fd() as u64),
+    };
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            mount_fd.as_raw_fd(),
+            b"\0".as_ptr(),
+            flags | libc::AT_EMPTY_PATH as c_uint,
+            &attr as *const mount_attr,
+            std::mem::size_of::<mount_attr>(),
+        )
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+pub fn move_mount<P: AsRef<Path>>(mount_fd: &OwnedFd, target: P) -> Result<()> {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_move_mount,
+            mount_fd.as_raw_fd(),
+            b"\0".as_ptr(),
+            libc::AT_FDCWD,
+            to_cstring(target.as_ref().as_os_str().as_bytes())?.as_ptr(),
+            MOVE_MOUNT_F_EMPTY_PATH,
+        )
+    };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+pub fn reflink(source: &std::fs::File, target: &std::fs::File) -> Result<()> {
+    if unsafe { libc::ioctl(target.as_raw_fd(), FICLONE as _, source.as_raw_fd()) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+// Attaches a file to a free loop device in read-only mode. The device is detached automatically
+// once the last reference to it, e.g. a mount, is gone
+pub fn attach_loop_device(loop_control: &Path, image: &std::fs::File) -> Result<(u32, OwnedFd)> {
+    let control = std::fs::File::open(loop_control)?;
+    let dev_dir = loop_control.parent().unwrap_or(Path::new("/dev"));
+    loop {
+        let number = unsafe { libc::ioctl(control.as_raw_fd(), LOOP_CTL_GET_FREE as _) };
+        if number == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let device: OwnedFd = std::fs::File::open(dev_dir.join(format!("loop{number}")))?.into();
+        let mut config: loop_config = unsafe { std::mem::zeroed() };
+        config.fd = image.as_raw_fd() as u32;
+        config.info.lo_flags = LO_FLAGS_READ_ONLY | LO_FLAGS_AUTOCLEAR;
+        if unsafe {
+            libc::ioctl(
+                device.as_raw_fd(),
+                LOOP_CONFIGURE as _,
+                &config as *const loop_config,
+            )
+        } == 0
+        {
+            return Ok((number as u32, device));
+        }
+        let e = std::io::Error::last_os_error();
+        if e.raw_os_error() != Some(libc::EBUSY) {
+            return Err(e);
+        }
+        // Someone else has grabbed the device in the meantime
+    }
+}
@@ -1,19 +1,139 @@
-use anyhow::{Context, Result};
-use nix::{libc, libc::c_int, time::ClockId};
+use anyhow::{bail, Context, Result};
+use multiprocessing::Object;
+use nix::{libc, libc::c_int, sys::time::TimeSpec, time::ClockId};
 use std::fs::File;
 use std::io::{Seek, Write};
+use std::time::Duration;
 
 const CLONE_NEWTIME: c_int = 0x80;
 
+// What the clocks of the box read when a run starts. The host uptime is never visible to the box,
+// so the offsets are only worth changing if the programs expect the system to have been up for a
+// while.
+#[derive(Clone, Copy, Object)]
+pub struct Offsets {
+    // CLOCK_MONOTONIC and its RAW and COARSE variants
+    pub monotonic: Duration,
+    // CLOCK_BOOTTIME and CLOCK_BOOTTIME_ALARM
+    pub boottime: Duration,
+}
+
+impl Offsets {
+    pub fn new(monotonic: Option<f64>, boottime: Option<f64>) -> Result<Self> {
+        let monotonic = Duration::try_from_secs_f64(monotonic.unwrap_or(0.0))
+            .context("Invalid monotonic offset")?;
+        let boottime = match boottime {
+            Some(boottime) => {
+                Duration::try_from_secs_f64(boottime).context("Invalid boottime offset")?
+            }
+            None => monotonic,
+        };
+        // The time spent in suspend is counted by CLOCK_BOOTTIME only
+        if boottime < monotonic {
+            bail!("Boottime offset must not be less than monotonic offset");
+        }
+        Ok(Self {
+            monotonic,
+            boottime,
+        })
+    }
+}
+
+// Time namespaces don't cover CLOCK_REALTIME, so the box fakes it by emulating the syscalls that read
+// it, which the programs use because vDSO is disabled. All values are in nanoseconds since the Unix
+// epoch.
+#[derive(Clone, Copy, Object)]
+pub struct RealTime {
+    pub origin: RealTimeOrigin,
+    // Whether the clock stays at its value at the start of the run rather than advance
+    pub frozen: bool,
+}
+
+#[derive(Clone, Copy, Object)]
+pub enum RealTimeOrigin {
+    // The value at the start of the run
+    Start(i64),
+    // Added to the time of the host
+    Offset(i64),
+}
+
+// The clocks that follow CLOCK_REALTIME. The box doesn't know the TAI offset, so CLOCK_TAI reads
+// the same as CLOCK_REALTIME.
+pub const REALTIME_CLOCKS: [libc::clockid_t; 4] = [
+    libc::CLOCK_REALTIME,
+    libc::CLOCK_REALTIME_COARSE,
+    libc::CLOCK_REALTIME_ALARM,
+    libc::CLOCK_TAI,
+];
+
+impl RealTime {
+    // {"start": SECONDS} or {"offset": SECONDS}, optionally with "frozen": true
+    pub fn parse(arg: &json::JsonValue) -> Result<Self> {
+        if !arg.is_object() {
+            bail!("Expected an object");
+        }
+        let nanoseconds = |key: &str| -> Result<i64> {
+            let seconds = arg[key]
+                .as_f64()
+                .with_context(|| format!("Invalid '{key}'"))?;
+            let nanoseconds = seconds * 1e9;
+            if !nanoseconds.is_finite() || nanoseconds.abs() >= i64::MAX as f64 {
+                bail!("'{key}' is out of range");
+            }
+            Ok(nanoseconds as i64)
+        };
+        let origin = match (arg["start"].is_null(), arg["offset"].is_null()) {
+            (false, true) => RealTimeOrigin::Start(nanoseconds("start")?),
+            (true, false) => RealTimeOrigin::Offset(nanoseconds("offset")?),
+            _ => bail!("Expected exactly one of 'start' and 'offset'"),
+        };
+        let frozen = if arg["frozen"].is_null() {
+            false
+        } else {
+            arg["frozen"].as_bool().context("Invalid 'frozen'")?
+        };
+        Ok(Self { origin, frozen })
+    }
+
+    // The value at the start of the run, given the time of the host then
+    pub fn at_start(&self, host: i64) -> i64 {
+        match self.origin {
+            RealTimeOrigin::Start(start) => start,
+            RealTimeOrigin::Offset(offset) => host.saturating_add(offset),
+        }
+    }
+
+    // The clock advances with CLOCK_MONOTONIC rather than the clock of the host, so that adjustments
+    // of the latter don't show through
+    pub fn now(&self, start: i64, elapsed: Duration) -> i64 {
+        if self.frozen {
+            start
+        } else {
+            start.saturating_add(elapsed.as_nanos().try_into().unwrap_or(i64::MAX))
+        }
+    }
+}
+
+pub fn host_realtime() -> Result<i64> {
+    let now = ClockId::CLOCK_REALTIME
+        .now()
+        .context("Failed to get wall-clock time")?;
+    Ok(now.tv_sec() * 1_000_000_000 + now.tv_nsec())
+}
+
 pub struct TimeNsController {
     timens_offsets: File,
+    offsets: Offsets,
 }
 
 impl TimeNsController {
-    pub fn new() -> Result<Self> {
+    pub fn new(offsets: Offsets) -> Result<Self> {
         let timens_offsets = File::create("/newroot/proc/self/timens_offsets")
             .context("Failed to open /newroot/proc/self/timens_offsets for writing")?;
-        Ok(Self { timens_offsets })
+        Ok(Self {
+            timens_offsets,
+            offsets,
+        })
     }
 
     pub fn reset_system_time_for_children(&mut self) -> Result<()> {
@@ -27,21 +147,23 @@ impl TimeNsController {
         // We want to avoid negative times (should not be a problem with sane programs, but not all
         // of them are), so choose the offset so that all the clocks are positive but stay
         // approximately fixed across runs.
-        let monotonic_offset = -[
-            ClockId::CLOCK_MONOTONIC,
-            ClockId::CLOCK_MONOTONIC_RAW,
-            ClockId::CLOCK_MONOTONIC_COARSE,
-        ]
-        .map(|clock_id| clock_id.now().context("Failed to get monotonic time"))
-        .into_iter()
-        .collect::<Result<Vec<_>>>()?
-        .into_iter()
-        .min()
-        .unwrap();
-
-        let boottime_offset = -ClockId::CLOCK_BOOTTIME
-            .now()
-            .context("Failed to get time since boot")?;
+        let monotonic_offset = TimeSpec::from_duration(self.offsets.monotonic)
+            - [
+                ClockId::CLOCK_MONOTONIC,
+                ClockId::CLOCK_MONOTONIC_RAW,
+                ClockId::CLOCK_MONOTONIC_COARSE,
+            ]
+            .map(|clock_id| clock_id.now().context("Failed to get monotonic time"))
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .min()
+            .unwrap();
+
+        let boottime_offset = TimeSpec::from_duration(self.offsets.boottime)
+            - ClockId::CLOCK_BOOTTIME
+                .now()
+                .context("Failed to get time since boot")?;
 
         self.timens_offsets
             .rewind()
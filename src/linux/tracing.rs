@@ -1,8 +1,8 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use nix::{
     libc,
     libc::{c_uint, c_void},
-    sys::{ptrace, signal},
+    sys::{ptrace, signal, uio},
     unistd::Pid,
 };
 
@@ -86,6 +86,64 @@ impl TracedProcess {
             .context("Failed to read word")
     }
 
+    // Reads a NUL-terminated string of at most max_len bytes, stopping early at unmapped memory
+    pub fn read_c_string(&self, address: usize, max_len: usize) -> Result<Vec<u8>> {
+        const PAGE_SIZE: usize = 4096;
+        let mut string = Vec::new();
+        let mut address = address;
+        while string.len() < max_len {
+            // Reading page by page avoids failing on a string near the end of a mapping
+            let len = (PAGE_SIZE - address % PAGE_SIZE).min(max_len - string.len());
+            let mut buf = vec![0u8; len];
+            let n_read = uio::process_vm_readv(
+                self.pid,
+                &mut [std::io::IoSliceMut::new(&mut buf)],
+                &[uio::RemoteIoVec { base: address, len }],
+            )
+            .context("Failed to read string")?;
+            if let Some(nul) = buf[..n_read].iter().position(|&c| c == 0) {
+                string.extend_from_slice(&buf[..nul]);
+                return Ok(string);
+            }
+            string.extend_from_slice(&buf[..n_read]);
+            if n_read < len {
+                break;
+            }
+            address += len;
+        }
+        Ok(string)
+    }
+
+    pub fn read_bytes(&self, address: usize, len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        let n_read = uio::process_vm_readv(
+            self.pid,
+            &mut [std::io::IoSliceMut::new(&mut buf)],
+            &[uio::RemoteIoVec { base: address, len }],
+        )
+        .context("Failed to read memory")?;
+        buf.truncate(n_read);
+        Ok(buf)
+    }
+
+    // Fails unless the whole buffer is written, which process_vm_writev only does if the memory is
+    // writable
+    pub fn write_bytes(&self, address: usize, data: &[u8]) -> Result<()> {
+        let n_written = uio::process_vm_writev(
+            self.pid,
+            &[std::io::IoSlice::new(data)],
+            &[uio::RemoteIoVec {
+                base: address,
+                len: data.len(),
+            }],
+        )
+        .context("Failed to write memory")?;
+        if n_written < data.len() {
+            bail!("Failed to write memory");
+        }
+        Ok(())
+    }
+
     pub unsafe fn write_word(&self, address: usize, value: usize) -> Result<()> {
         ptrace::write(self.pid, address as *mut c_void, value as *mut c_void)
             .context("Failed to write word")
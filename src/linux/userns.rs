@@ -1,6 +1,11 @@
 use crate::linux::ids::*;
 use anyhow::{Context, Result};
-use nix::{libc, libc::CLONE_NEWUSER, unistd};
+use nix::{
+    libc,
+    libc::{gid_t, uid_t, CLONE_NEWUSER},
+    unistd,
+};
+use std::os::unix::io::OwnedFd;
 
 pub fn enter_user_namespace() -> Result<()> {
     // Start a subprocess which will give us the right uid_map and gid_map
@@ -55,7 +60,95 @@ fn configure_ns(mut rx: multiprocessing::Receiver<()>) {
     .expect("Failed to fill gid_map");
 }
 
-pub fn drop_privileges() -> Result<()> {
+pub fn create_idmap(uid: uid_t, gid: gid_t) -> Result<OwnedFd> {
+    // Idmapped mounts take the mapping from a user namespace, and the only way to get a user
+    // namespace with a custom mapping is to put a process into it
+    let (mut channel, child_channel) =
+        multiprocessing::duplex::<(), ()>().context("Failed to create channel")?;
+    let mut child = hold_idmap
+        .spawn(child_channel)
+        .context("Failed to start child")?;
+
+    channel
+        .recv()
+        .context("Failed to recv")?
+        .context("Child terminated too early")?;
+
+    // Map the owner of the files to the sandboxed user
+    let pid = child.id();
+    std::fs::write(
+        format!("/proc/{pid}/uid_map"),
+        format!("{EXTERNAL_USER_UID} {uid} 1\n"),
+    )
+    .context("Failed to fill uid_map")?;
+    std::fs::write(
+        format!("/proc/{pid}/gid_map"),
+        format!("{EXTERNAL_USER_GID} {gid} 1\n"),
+    )
+    .context("Failed to fill gid_map")?;
+
+    let userns = std::fs::File::open(format!("/proc/{pid}/ns/user"))
+        .context("Failed to open user namespace of child")?;
+
+    channel.send(&()).context("Failed to release child")?;
+    child.join().context("Child didn't terminate gracefully")?;
+
+    Ok(userns.into())
+}
+
+#[multiprocessing::entrypoint]
+fn hold_idmap(mut channel: multiprocessing::Duplex<(), ()>) {
+    if unsafe { libc::unshare(CLONE_NEWUSER) } != 0 {
+        panic!("unshare() failed: {}", std::io::Error::last_os_error());
+    }
+    channel.send(&()).expect("Failed to send");
+    channel.recv().expect("Failed to recv");
+}
+
+// Capabilities a program may keep, e.g. for debugger-based tasks. The capabilities only apply to
+// what the user namespace of the box owns, i.e. its processes, files, and IPC, so most of them
+// would hand the program control over the box: CAP_KILL could stop the manager, CAP_SETUID could
+// make the program the in-box root, and CAP_DAC_READ_SEARCH could read the files of the host root,
+// which is mapped into the box. Those in this list are safe. CAP_SYS_PTRACE, in particular, can't
+// be used to attach to the manager, which is not dumpable, and whose memory belongs to the host.
+const ALLOWED_CAPABILITIES: &[(&str, u32)] = &[("CAP_SYS_PTRACE", 19)];
+
+pub fn parse_capability(name: &str) -> Result<u32> {
+    ALLOWED_CAPABILITIES
+        .iter()
+        .find(|(allowed_name, _)| *allowed_name == name)
+        .map(|(_, capability)| *capability)
+        .with_context(|| {
+            format!(
+                "Capability {name} is unknown or not allowed, only {} are",
+                ALLOWED_CAPABILITIES
+                    .iter()
+                    .map(|(name, _)| *name)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+const LINUX_CAPABILITY_VERSION_3: u32 = 0x20080522;
+
+#[repr(C)]
+struct cap_user_header {
+    version: u32,
+    pid: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct cap_user_data {
+    effective: u32,
+    permitted: u32,
+    inheritable: u32,
+}
+
+// The capabilities are made ambient, so that they survive execve of the program, whose binary
+// has no file capabilities, and are inherited by its children
+pub fn drop_privileges(capabilities: &[u32]) -> Result<()> {
     // Calling setuid() resets the "dumpable" attribute of the calling process, which in turn
     // disables ptracing and makes its /proc/<pid> subdirectory root-owned, which guarantees that a
     // malicious program cannot mess with the process except by sending signals to it. For the short
@@ -63,6 +156,9 @@ pub fn drop_privileges() -> Result<()> {
     // in some other cases though, e.g. if we called drop_privileges() in a manager process, because
     // that would allow the child to send SIGSTOP to circumvent time limit, or to send SIGKILL,
     // which would confuse the system.
+    if !capabilities.is_empty() && unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1, 0, 0, 0) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to set keepcaps");
+    }
     unistd::setgroups(&[unistd::Gid::from_raw(INTERNAL_USER_GID)])
         .context("Failed to setgroups")?;
     if unsafe { libc::setgid(INTERNAL_USER_GID) } != 0 {
@@ -71,5 +167,41 @@ pub fn drop_privileges() -> Result<()> {
     if unsafe { libc::setuid(INTERNAL_USER_UID) } != 0 {
         return Err(std::io::Error::last_os_error()).context("Failed to setuid");
     }
+    if capabilities.is_empty() {
+        return Ok(());
+    }
+
+    // setuid() kept the permitted set due to keepcaps, so narrow it down to the requested
+    // capabilities. They have to be inheritable to be raised in the ambient set.
+    let mut data = [cap_user_data::default(); 2];
+    for &capability in capabilities {
+        let mask = 1 << (capability % 32);
+        let data = &mut data[capability as usize / 32];
+        data.effective |= mask;
+        data.permitted |= mask;
+        data.inheritable |= mask;
+    }
+    let mut header = cap_user_header {
+        version: LINUX_CAPABILITY_VERSION_3,
+        pid: 0,
+    };
+    if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } == -1 {
+        return Err(std::io::Error::last_os_error()).context("Failed to capset");
+    }
+    for &capability in capabilities {
+        if unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_RAISE,
+                capability as libc::c_ulong,
+                0,
+                0,
+            )
+        } == -1
+        {
+            return Err(std::io::Error::last_os_error())
+                .context("Failed to raise ambient capability");
+        }
+    }
     Ok(())
 }
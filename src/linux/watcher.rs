@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use nix::{
+    libc,
+    poll::{poll, PollFd, PollFlags},
+};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::os::unix::{
+    ffi::OsStrExt,
+    io::{AsRawFd, FromRawFd, OwnedFd},
+};
+use std::path::Path;
+use std::time::Duration;
+
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_MODIFY
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE
+    | libc::IN_DELETE_SELF;
+
+const EVENT_NAMES: [(u32, &str); 7] = [
+    (libc::IN_CREATE, "create"),
+    (libc::IN_MODIFY, "modify"),
+    (libc::IN_CLOSE_WRITE, "close_write"),
+    (libc::IN_MOVED_FROM, "moved_from"),
+    (libc::IN_MOVED_TO, "moved_to"),
+    (libc::IN_DELETE, "delete"),
+    (libc::IN_DELETE_SELF, "delete_self"),
+];
+
+pub struct Watcher {
+    inotify: OwnedFd,
+    // Watch descriptor -> in-box path
+    paths: HashMap<i32, String>,
+}
+
+impl Watcher {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            inotify: create_inotify().context("Failed to create inotify")?,
+            paths: HashMap::new(),
+        })
+    }
+
+    pub fn add(&mut self, path: &Path, box_path: String) -> Result<i32> {
+        let wd = add_watch(&self.inotify, path)?;
+        self.paths.insert(wd, box_path);
+        Ok(wd)
+    }
+
+    pub fn poll_events(&mut self, timeout: Duration) -> Result<json::JsonValue> {
+        let mut events = json::JsonValue::new_array();
+
+        let mut fds = [PollFd::new(self.inotify.as_raw_fd(), PollFlags::POLLIN)];
+        let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+        if poll(&mut fds, timeout_ms).context("Failed to poll inotify")? == 0 {
+            return Ok(events);
+        }
+
+        let mut buf = vec![0u8; 65536];
+        loop {
+            let n_read = unsafe {
+                libc::read(
+                    self.inotify.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n_read == -1 {
+                let e = std::io::Error::last_os_error();
+                if e.kind() == std::io::ErrorKind::WouldBlock {
+                    break;
+                }
+                return Err(e).context("Failed to read inotify events");
+            }
+            self.parse_events(&buf[..n_read as usize], &mut events)?;
+        }
+
+        Ok(events)
+    }
+
+    fn parse_events(&mut self, mut data: &[u8], events: &mut json::JsonValue) -> Result<()> {
+        let header_size = std::mem::size_of::<libc::inotify_event>();
+        while data.len() >= header_size {
+            let event: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(data.as_ptr() as *const libc::inotify_event) };
+            let name_len = event.len as usize;
+            let name = &data[header_size..header_size + name_len];
+            data = &data[header_size + name_len..];
+
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                events.push(json::object! { path: null, events: ["overflow"] })?;
+                continue;
+            }
+
+            let Some(dir_path) = self.paths.get(&event.wd) else {
+                continue;
+            };
+            let mut path = dir_path.clone();
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            if !name.is_empty() {
+                path = Path::new(&path)
+                    .join(OsStr::from_bytes(name))
+                    .to_string_lossy()
+                    .into_owned();
+            }
+
+            let names: Vec<&str> = EVENT_NAMES
+                .iter()
+                .filter(|(mask, _)| event.mask & mask != 0)
+                .map(|(_, name)| *name)
+                .collect();
+            if !names.is_empty() {
+                events.push(json::object! { path: path, events: names })?;
+            }
+
+            if event.mask & libc::IN_IGNORED != 0 {
+                // The watched file was removed
+                self.paths.remove(&event.wd);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn create_inotify() -> Result<OwnedFd> {
+    let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if fd == -1 {
+        return Err(std::io::Error::last_os_error()).context("inotify_init1() failed");
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+pub fn add_watch(inotify: &OwnedFd, path: &Path) -> Result<i32> {
+    let c_path =
+        std::ffi::CString::new(path.as_os_str().as_bytes()).context("Path contains a null byte")?;
+    let wd = unsafe {
+        libc::inotify_add_watch(
+            inotify.as_raw_fd(),
+            c_path.as_ptr(),
+            WATCH_MASK | libc::IN_DONT_FOLLOW,
+        )
+    };
+    if wd == -1 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to watch {path:?}"));
+    }
+    Ok(wd)
+}
@@ -0,0 +1,178 @@
+use crate::linux::api;
+use anyhow::{Context, Result};
+use pyo3::prelude::*;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// Python bindings for the library API. Built with `make python`, which produces sunwalker_box.so
+// that can be imported as `import sunwalker_box`.
+
+#[pyclass(name = "Box", module = "sunwalker_box")]
+struct PyBox {
+    inner: api::Box,
+}
+
+#[pymethods]
+impl PyBox {
+    #[new]
+    #[pyo3(signature = (executable, core, root = "/".to_string(), quota_space = 32 * 1024 * 1024, quota_inodes = 1024, extra_args = Vec::new()))]
+    fn new(
+        py: Python,
+        executable: String,
+        core: u64,
+        root: String,
+        quota_space: u64,
+        quota_inodes: u64,
+        extra_args: Vec<String>,
+    ) -> Result<Self> {
+        let mut config = api::Config::new(executable, core);
+        config.root = root;
+        config.quota_space = quota_space;
+        config.quota_inodes = quota_inodes;
+        config.extra_args = extra_args;
+        let inner = py.allow_threads(|| api::Box::new(config))?;
+        Ok(Self { inner })
+    }
+
+    // Time limits are in seconds, as elsewhere in the protocol
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (argv, cpu_time_limit, processes_limit, stdin = None, stdout = None, stderr = None, real_time_limit = None, idleness_time_limit = None, memory_limit = None, swap_limit = None, output_limit = None, stack_limit = None, env = None, argv0 = None, cwd = None))]
+    fn run(
+        &mut self,
+        py: Python,
+        argv: Vec<String>,
+        cpu_time_limit: f64,
+        processes_limit: u64,
+        stdin: Option<String>,
+        stdout: Option<String>,
+        stderr: Option<String>,
+        real_time_limit: Option<f64>,
+        idleness_time_limit: Option<f64>,
+        memory_limit: Option<u64>,
+        swap_limit: Option<u64>,
+        output_limit: Option<u64>,
+        stack_limit: Option<u64>,
+        env: Option<HashMap<String, String>>,
+        argv0: Option<String>,
+        cwd: Option<String>,
+    ) -> Result<PyRunResult> {
+        let mut spec =
+            api::RunSpec::new(argv, duration_from_secs(cpu_time_limit)?, processes_limit);
+        spec.stdin = stdin;
+        spec.stdout = stdout;
+        spec.stderr = stderr;
+        spec.real_time_limit = real_time_limit.map(duration_from_secs).transpose()?;
+        spec.idleness_time_limit = idleness_time_limit.map(duration_from_secs).transpose()?;
+        spec.memory_limit = memory_limit;
+        spec.swap_limit = swap_limit;
+        spec.output_limit = output_limit;
+        spec.stack_limit = stack_limit;
+        spec.env = env;
+        spec.argv0 = argv0;
+        spec.cwd = cwd;
+        let result = py.allow_threads(|| self.inner.run(&spec))?;
+        Ok(result.into())
+    }
+
+    fn reset(&mut self, py: Python) -> Result<()> {
+        py.allow_threads(|| self.inner.reset())
+    }
+
+    fn mkdir(&mut self, py: Python, path: String) -> Result<()> {
+        py.allow_threads(|| self.inner.mkdir(&path))
+    }
+
+    fn write_file(&mut self, py: Python, path: String, content: Vec<u8>) -> Result<()> {
+        py.allow_threads(|| self.inner.write_file(&path, &content))
+    }
+
+    fn read_file(&mut self, py: Python, path: String) -> Result<PyObject> {
+        let content = py.allow_threads(|| self.inner.read_file(&path))?;
+        Ok(pyo3::types::PyBytes::new(py, &content).into())
+    }
+
+    #[pyo3(signature = (external, internal, ro = false))]
+    fn bind(&mut self, py: Python, external: String, internal: String, ro: bool) -> Result<()> {
+        py.allow_threads(|| self.inner.bind(&external, &internal, ro))
+    }
+
+    // The argument and the result are JSON strings, use json.dumps/json.loads
+    #[pyo3(signature = (command, argument = "null".to_string()))]
+    fn command(&mut self, py: Python, command: String, argument: String) -> Result<String> {
+        let argument = json::parse(&argument)?;
+        let result = py.allow_threads(|| self.inner.command(&command, argument))?;
+        Ok(result.dump())
+    }
+}
+
+#[pyclass(name = "RunResult", module = "sunwalker_box")]
+struct PyRunResult {
+    // One of "ExitCode", "Signaled", "SecurityViolation", "CPUTimeLimitExceeded", "RealTimeLimitExceeded",
+    // "IdlenessTimeLimitExceeded", "MemoryLimitExceeded", "OutputLimitExceeded",
+    // "NetworkLimitExceeded"
+    #[pyo3(get)]
+    verdict: &'static str,
+    #[pyo3(get)]
+    exit_code: Option<i32>,
+    #[pyo3(get)]
+    signal: Option<i32>,
+    #[pyo3(get)]
+    real_time: f64,
+    #[pyo3(get)]
+    cpu_time: f64,
+    #[pyo3(get)]
+    user_time: f64,
+    #[pyo3(get)]
+    system_time: f64,
+    #[pyo3(get)]
+    idleness_time: f64,
+    #[pyo3(get)]
+    memory: u64,
+    #[pyo3(get)]
+    memory_source: String,
+    #[pyo3(get)]
+    swap: u64,
+    #[pyo3(get)]
+    processes_limit_hit: bool,
+}
+
+impl From<api::RunResult> for PyRunResult {
+    fn from(result: api::RunResult) -> Self {
+        let (verdict, exit_code, signal) = match result.verdict {
+            api::Verdict::ExitCode(code) => ("ExitCode", Some(code), None),
+            api::Verdict::Signaled(signal) => ("Signaled", None, Some(signal)),
+            api::Verdict::SecurityViolation => ("SecurityViolation", None, None),
+            api::Verdict::CPUTimeLimitExceeded => ("CPUTimeLimitExceeded", None, None),
+            api::Verdict::RealTimeLimitExceeded => ("RealTimeLimitExceeded", None, None),
+            api::Verdict::IdlenessTimeLimitExceeded => ("IdlenessTimeLimitExceeded", None, None),
+            api::Verdict::MemoryLimitExceeded => ("MemoryLimitExceeded", None, None),
+            api::Verdict::OutputLimitExceeded => ("OutputLimitExceeded", None, None),
+            api::Verdict::NetworkLimitExceeded => ("NetworkLimitExceeded", None, None),
+        };
+        Self {
+            verdict,
+            exit_code,
+            signal,
+            real_time: result.real_time.as_secs_f64(),
+            cpu_time: result.cpu_time.as_secs_f64(),
+            user_time: result.user_time.as_secs_f64(),
+            system_time: result.system_time.as_secs_f64(),
+            idleness_time: result.idleness_time.as_secs_f64(),
+            memory: result.memory,
+            memory_source: result.memory_source,
+            swap: result.swap,
+            processes_limit_hit: result.processes_limit_hit,
+        }
+    }
+}
+
+fn duration_from_secs(secs: f64) -> Result<Duration> {
+    Duration::try_from_secs_f64(secs).context("Invalid time limit")
+}
+
+#[pymodule]
+fn sunwalker_box(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyBox>()?;
+    m.add_class::<PyRunResult>()?;
+    Ok(())
+}